@@ -0,0 +1,269 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use bytes::Bytes;
+use rand::RngCore;
+use serde::Deserialize;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+use tracing::Instrument;
+
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::shutdown::{Download, Transfers};
+use crate::stats::{ActiveTransfer, Stats};
+
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+	/// NCSA Common Log Format.
+	#[default]
+	Common,
+	/// Common Log Format followed by the referer and user agent, as Apache and nginx write it.
+	Combined,
+	/// Structured `tracing` events, written as one JSON object per line together with the
+	/// server's other logs.
+	Json,
+	Off,
+}
+
+/// What is known about a request once its response has started; the byte count is only known
+/// once the body has been sent.
+struct Entry {
+	id: String,
+	started: Instant,
+	time: chrono::DateTime<chrono::Local>,
+	remote: Option<String>,
+	method: String,
+	path: String,
+	version: String,
+	status: u16,
+	bytes: u64,
+	referer: Option<String>,
+	user_agent: Option<String>,
+}
+
+/// Target of the `tracing` events describing finished requests.
+pub const TARGET: &str = "http_server_rs::access";
+
+impl Entry {
+	/// Emits the entry as a structured event, which the subscriber shows on stdout for the `json`
+	/// format and sends to `--log-file` regardless, and as a plain line for the text formats.
+	fn write(&self, format: LogFormat) {
+		tracing::info!(
+			target: TARGET,
+			request_id = %self.id,
+			peer = self.remote.as_deref(),
+			method = %self.method,
+			path = %self.path,
+			version = %self.version,
+			status = self.status,
+			bytes = self.bytes,
+			latency_ms = self.started.elapsed().as_secs_f64() * 1000.0,
+			referer = self.referer.as_deref(),
+			user_agent = self.user_agent.as_deref(),
+			"request",
+		);
+
+		let line = match format {
+			LogFormat::Common => self.common(),
+			LogFormat::Combined => format!(
+				"{} {:?} {:?}",
+				self.common(),
+				self.referer.as_deref().unwrap_or("-"),
+				self.user_agent.as_deref().unwrap_or("-"),
+			),
+			LogFormat::Json | LogFormat::Off => return,
+		};
+		// Logging must never take the server down, e.g. when stdout is a closed pipe.
+		let _ = writeln!(std::io::stdout().lock(), "{line}");
+	}
+
+	fn common(&self) -> String {
+		format!(
+			"{} - - [{}] {:?} {} {}",
+			self.remote.as_deref().unwrap_or("-"),
+			self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+			format!("{} {} {}", self.method, self.path, self.version),
+			self.status,
+			self.bytes,
+		)
+	}
+}
+
+/// Response body that counts what it sends and writes the log entry (and updates the metrics) once
+/// it is done, whether the body finished or the client went away.
+struct LoggedBody {
+	body: BoxBody,
+	entry: Entry,
+	format: LogFormat,
+	metrics: Option<web::Data<Metrics>>,
+	transfer: Option<ActiveTransfer>,
+	transfers: Option<web::Data<Transfers>>,
+	/// Set once the body has ended, rather than been dropped part way through.
+	complete: bool,
+	/// Whether the body is a whole file, counted as downloaded once complete.
+	download: bool,
+}
+
+impl MessageBody for LoggedBody {
+	type Error = Box<dyn std::error::Error>;
+
+	fn size(&self) -> BodySize {
+		self.body.size()
+	}
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+		let this = self.get_mut();
+		let chunk = ready!(Pin::new(&mut this.body).poll_next(cx));
+		match &chunk {
+			Some(Ok(bytes)) => {
+				this.entry.bytes += bytes.len() as u64;
+				if let Some(transfer) = &this.transfer {
+					transfer.add_sent(bytes.len() as u64);
+				}
+				if let Some(transfers) = &this.transfers {
+					transfers.add_sent(bytes.len() as u64);
+				}
+			},
+			None => this.complete = true,
+			Some(Err(_)) => {},
+		}
+		Poll::Ready(chunk)
+	}
+}
+
+impl Drop for LoggedBody {
+	fn drop(&mut self) {
+		if let Some(metrics) = &self.metrics {
+			metrics.record_sent(self.entry.bytes);
+		}
+		if let Some(transfers) = &self.transfers {
+			// Bodies known to be empty are not always read to their end.
+			let empty = matches!(self.body.size(), BodySize::None | BodySize::Sized(0));
+			transfers.finish(self.complete || empty);
+			if self.complete && self.download {
+				transfers.downloaded();
+			}
+		}
+		self.entry.write(self.format);
+	}
+}
+
+fn header_value(req: &ServiceRequest, name: header::HeaderName) -> Option<String> {
+	req.headers()
+		.get(name)
+		.and_then(|v| v.to_str().ok())
+		.map(str::to_string)
+}
+
+/// The request target with the value of any `?token=` parameter masked, so access logs do not
+/// leak credentials.
+fn logged_path(req: &ServiceRequest) -> String {
+	let path = req.path();
+	let query = req.query_string();
+	if query.is_empty() {
+		return path.to_string();
+	}
+	let query: Vec<String> = query
+		.split('&')
+		.map(|pair| match pair.split_once('=') {
+			Some(("token", _)) => "token=***".to_string(),
+			_ => pair.to_string(),
+		})
+		.collect();
+	format!("{path}?{}", query.join("&"))
+}
+
+const REQUEST_ID: header::HeaderName = header::HeaderName::from_static("x-request-id");
+/// Longest incoming `X-Request-Id` that is passed on rather than replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifies one request across the response headers and every log line written for it.
+/// Handlers and hooks can read it from the request extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Keeps the id a proxy or client already assigned when it is sane, so one request can be traced
+/// through several services, and makes up a new one otherwise.
+fn request_id(req: &ServiceRequest) -> String {
+	let incoming = req
+		.headers()
+		.get(&REQUEST_ID)
+		.and_then(|v| v.to_str().ok())
+		.filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+		.filter(|id| id.bytes().all(|b| b.is_ascii_graphic()));
+	if let Some(id) = incoming {
+		return id.to_string();
+	}
+
+	let mut bytes = [0u8; 8];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Middleware tagging each request with an id, returned as `X-Request-Id` and attached to all
+/// events logged while handling it, and logging the request in the configured `--log-format`.
+pub async fn log_requests(
+	req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+	let format = req
+		.app_data::<web::Data<Config>>()
+		.map_or(LogFormat::default(), |config| config.log_format);
+	let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+	let stats = req.app_data::<web::Data<Stats>>().cloned();
+	let transfers = req.app_data::<web::Data<Transfers>>().cloned();
+
+	let id = request_id(&req);
+	req.extensions_mut().insert(RequestId(id.clone()));
+	let started = Instant::now();
+	let time = chrono::Local::now();
+	let remote = req.peer_addr().map(|addr| addr.ip().to_string());
+	let method = req.method().to_string();
+	let path = logged_path(&req);
+	let version = format!("{:?}", req.version());
+	let referer = header_value(&req, header::REFERER);
+	let user_agent = header_value(&req, header::USER_AGENT);
+
+	let span = tracing::info_span!("request", request_id = %id, peer = remote.as_deref());
+	let mut res = next.call(req).instrument(span).await?;
+	if let Ok(value) = HeaderValue::from_str(&id) {
+		res.headers_mut().insert(REQUEST_ID, value);
+	}
+	let status = res.status().as_u16();
+	let download = method == "GET" && status == 200 && res.request().extensions().contains::<Download>();
+	if let Some(metrics) = &metrics {
+		metrics.record_response(status);
+	}
+	let transfer = stats.map(|stats| Stats::start(stats, &method, &path, remote.as_deref(), status));
+	if let Some(transfers) = &transfers {
+		transfers.start();
+	}
+	Ok(res.map_body(|_, body| LoggedBody {
+		body: body.boxed(),
+		entry: Entry {
+			id,
+			started,
+			time,
+			remote,
+			method,
+			path,
+			version,
+			status,
+			bytes: 0,
+			referer,
+			user_agent,
+		},
+		format,
+		metrics,
+		transfer,
+		transfers,
+		complete: false,
+		download,
+	}))
+}
@@ -0,0 +1,255 @@
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::Path;
+
+use crate::checksum::ChecksumCache;
+use crate::config::{normalize_prefix, ByteSize, Config, Mount};
+use crate::reload::{Reloader, Settings};
+use crate::stats::Stats;
+
+/// What of the configuration `/_admin/api` reports and changes, as requests see it.
+#[derive(Serialize)]
+struct Effective<'a> {
+	root: &'a Path,
+	route_prefix: &'a str,
+	read_only: bool,
+	allow_upload: bool,
+	allow_delete: bool,
+	webdav: bool,
+	rate_limit: Option<f64>,
+	rate_limit_burst: u32,
+	limit_rate: Option<u64>,
+	limit_rate_total: Option<u64>,
+	/// The names of the users, whose passwords are not given out.
+	users: Vec<&'a str>,
+	ignore: &'a [String],
+	mounts: Vec<EffectiveMount<'a>>,
+}
+
+#[derive(Serialize)]
+struct EffectiveMount<'a> {
+	host: Option<&'a str>,
+	path: &'a str,
+	root: &'a Path,
+	allow_upload: bool,
+	allow_delete: bool,
+	users: Vec<&'a str>,
+}
+
+fn users(config: &Config) -> Vec<&str> {
+	config
+		.auth
+		.iter()
+		.map(|entry| entry.split_once(':').map_or(entry.as_str(), |(user, _)| user))
+		.collect()
+}
+
+fn effective(settings: &Settings) -> HttpResponse {
+	let config = &settings.config;
+	HttpResponse::Ok().json(Effective {
+		root: &config.root,
+		route_prefix: &config.route_prefix,
+		read_only: settings.read_only,
+		allow_upload: config.allow_upload,
+		allow_delete: config.allow_delete,
+		webdav: config.webdav,
+		rate_limit: config.rate_limit,
+		rate_limit_burst: config.rate_limit_burst,
+		limit_rate: config.limit_rate.map(|rate| rate.0),
+		limit_rate_total: config.limit_rate_total.map(|rate| rate.0),
+		users: users(config),
+		ignore: &config.ignore,
+		mounts: settings
+			.mounts
+			.0
+			.iter()
+			.map(|mount| EffectiveMount {
+				host: mount.host.as_deref(),
+				path: &mount.config.route_prefix,
+				root: &mount.config.root,
+				allow_upload: mount.config.allow_upload,
+				allow_delete: mount.config.allow_delete,
+				users: users(&mount.config),
+			})
+			.collect(),
+	})
+}
+
+/// Answers with the effective configuration once `update` has been applied, or what it failed
+/// with, leaving the settings as they were. It is applied on the blocking pool, as building the
+/// settings reads files and waits for any other update to finish.
+async fn respond(
+	reloader: web::Data<Reloader>,
+	update: impl FnOnce(&Settings) -> std::io::Result<Settings> + Send + 'static,
+) -> HttpResponse {
+	let result = match web::block(move || reloader.update(update)).await {
+		Ok(result) => result,
+		Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+	};
+	match result {
+		Ok(settings) => {
+			tracing::info!("Updated the settings through the admin API");
+			effective(&settings)
+		},
+		Err(e) => match e.kind() {
+			std::io::ErrorKind::NotFound => HttpResponse::NotFound().body(e.to_string()),
+			std::io::ErrorKind::AlreadyExists => HttpResponse::Conflict().body(e.to_string()),
+			_ => HttpResponse::UnprocessableEntity().body(e.to_string()),
+		},
+	}
+}
+
+fn disabled() -> HttpResponse {
+	HttpResponse::NotFound().body("The admin dashboard is disabled")
+}
+
+fn invalid(message: String) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+/// Tells a field given as `null`, which is `Some(None)`, from one left out.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+	Option::deserialize(deserializer).map(Some)
+}
+
+/// The settings a `PATCH` changes, each left as it is when not given, and turned off by `null`
+/// where it is optional.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Changes {
+	read_only: Option<bool>,
+	#[serde(default, deserialize_with = "present")]
+	rate_limit: Option<Option<f64>>,
+	rate_limit_burst: Option<u32>,
+	#[serde(default, deserialize_with = "present")]
+	limit_rate: Option<Option<ByteSize>>,
+	#[serde(default, deserialize_with = "present")]
+	limit_rate_total: Option<Option<ByteSize>>,
+}
+
+#[derive(Deserialize)]
+struct MountPath {
+	path: String,
+}
+
+#[get("/_admin/api")]
+async fn show_settings(stats: Option<web::Data<Stats>>, reloader: web::Data<Reloader>) -> HttpResponse {
+	if stats.is_none() {
+		return disabled();
+	}
+	effective(&reloader.current())
+}
+
+/// Toggles read-only mode and adjusts rate limits.
+#[patch("/_admin/api")]
+async fn change_settings(
+	stats: Option<web::Data<Stats>>,
+	reloader: web::Data<Reloader>,
+	changes: web::Json<Changes>,
+) -> HttpResponse {
+	if stats.is_none() {
+		return disabled();
+	}
+	let changes = changes.into_inner();
+	respond(reloader, move |settings| {
+		let changed = settings.changed(|config| {
+			if let Some(rate) = changes.rate_limit {
+				if let Some(rate) = rate.filter(|rate| !rate.is_finite() || *rate <= 0.0) {
+					return Err(invalid(format!("Invalid request rate limit {rate}")));
+				}
+				config.rate_limit = rate;
+			}
+			if let Some(burst) = changes.rate_limit_burst {
+				config.rate_limit_burst = burst;
+			}
+			for (limit, change) in [
+				(&mut config.limit_rate, changes.limit_rate),
+				(&mut config.limit_rate_total, changes.limit_rate_total),
+			] {
+				if let Some(rate) = change {
+					if let Some(rate) = rate.filter(|rate| rate.0 == 0) {
+						return Err(invalid(format!("Invalid rate limit {}", rate.0)));
+					}
+					*limit = rate;
+				}
+			}
+			Ok(())
+		})?;
+		match changes.read_only {
+			Some(read_only) => changed.with_read_only(read_only),
+			None => Ok(changed),
+		}
+	})
+	.await
+}
+
+/// Serves another directory, as `--mount` does.
+#[post("/_admin/api/mounts")]
+async fn add_mount(
+	stats: Option<web::Data<Stats>>,
+	reloader: web::Data<Reloader>,
+	mount: web::Json<Mount>,
+) -> HttpResponse {
+	if stats.is_none() {
+		return disabled();
+	}
+	let mount = mount.into_inner();
+	respond(reloader, move |settings| {
+		settings.changed(|config| {
+			if let Some(entry) = mount.auth.iter().find(|entry| !entry.contains(':')) {
+				return Err(invalid(format!("Invalid auth entry {entry:?}, expected user:pass")));
+			}
+			let path = normalize_prefix(&mount.path);
+			if config.mounts.iter().any(|other| normalize_prefix(&other.path) == path) {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::AlreadyExists,
+					format!("Already serving a mount at {}", mount.path),
+				));
+			}
+			config.mounts.push(mount);
+			Ok(())
+		})
+	})
+	.await
+}
+
+/// Stops serving the mount at `?path=`.
+#[delete("/_admin/api/mounts")]
+async fn remove_mount(
+	stats: Option<web::Data<Stats>>,
+	reloader: web::Data<Reloader>,
+	query: web::Query<MountPath>,
+) -> HttpResponse {
+	if stats.is_none() {
+		return disabled();
+	}
+	let path = normalize_prefix(&query.path);
+	respond(reloader, move |settings| {
+		settings.changed(|config| {
+			let before = config.mounts.len();
+			config.mounts.retain(|mount| normalize_prefix(&mount.path) != path);
+			if config.mounts.len() == before {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::NotFound,
+					format!("No mount at {}", query.path),
+				));
+			}
+			Ok(())
+		})
+	})
+	.await
+}
+
+/// Forgets the checksums worked out so far and reads the `.gitignore` and error pages again.
+#[post("/_admin/api/invalidate")]
+async fn invalidate(
+	stats: Option<web::Data<Stats>>,
+	reloader: web::Data<Reloader>,
+	checksums: web::Data<ChecksumCache>,
+) -> HttpResponse {
+	if stats.is_none() {
+		return disabled();
+	}
+	checksums.clear();
+	respond(reloader, |settings| settings.changed(|_| Ok(()))).await
+}
@@ -0,0 +1,362 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::helpers::{dir_entry, entry_order, mime_type, resolve_path, visible_entries, within_root, Visibility};
+use crate::index::ContentIndex;
+use crate::structs::DirEntry;
+
+const DEFAULT_TREE_DEPTH: usize = 3;
+const MAX_TREE_DEPTH: usize = 32;
+const DEFAULT_TREE_LIMIT: usize = 1000;
+const MAX_TREE_LIMIT: usize = 10_000;
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+const MAX_SEARCH_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct TreeQuery {
+	#[serde(default)]
+	path: String,
+	/// How many levels below `path` to descend; `0` lists only its direct entries.
+	depth: Option<usize>,
+	/// Maximum number of entries in the whole tree.
+	limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct TreeNode {
+	#[serde(flatten)]
+	entry: DirEntry,
+	/// Entries of a directory, absent once the depth limit is reached.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	children: Option<Vec<TreeNode>>,
+}
+
+#[derive(Serialize)]
+struct Tree {
+	path: String,
+	/// Set when the entry limit cut the walk short.
+	truncated: bool,
+	children: Vec<TreeNode>,
+}
+
+/// Number of entries a tree may still take, and whether any had to be left out.
+struct Budget {
+	left: usize,
+	exhausted: bool,
+}
+
+/// Collects the entries below `dir`, recursing `depth` more levels and spending one unit of
+/// `budget` per entry. Symlinks are reported but never descended into.
+async fn build_tree(
+	root: &Path,
+	dir: &Path,
+	depth: usize,
+	budget: &mut Budget,
+	visibility: &Visibility,
+	parallelism: usize,
+) -> std::io::Result<Vec<TreeNode>> {
+	let mut nodes = Vec::new();
+
+	for (entry, metadata) in visible_entries(dir, visibility, parallelism).await? {
+		if budget.left == 0 {
+			budget.exhausted = true;
+			break;
+		}
+		budget.left -= 1;
+
+		let children = if metadata.is_dir() && depth > 0 {
+			Some(
+				Box::pin(build_tree(
+					root,
+					&entry.path(),
+					depth - 1,
+					budget,
+					visibility,
+					parallelism,
+				))
+				.await?,
+			)
+		} else {
+			None
+		};
+		nodes.push(TreeNode {
+			entry: dir_entry(root, &entry.path(), &metadata),
+			children,
+		});
+	}
+
+	nodes.sort_by(|a, b| entry_order(&a.entry, &b.entry));
+	Ok(nodes)
+}
+
+#[get("/_api/tree")]
+async fn tree(req: HttpRequest, query: web::Query<TreeQuery>, config: web::Data<Config>) -> Result<HttpResponse> {
+	if !config.dirlist {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	let relative = query.path.trim_start_matches('/');
+	let Some(dir) = resolve_path(&config.root, Path::new(relative)) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	if !dir.is_dir() || !within_root(&config, &dir) || !visibility.allows(Path::new(relative)) {
+		return Ok(HttpResponse::NotFound().body("Directory not found"));
+	}
+
+	let depth = query.depth.unwrap_or(DEFAULT_TREE_DEPTH).min(MAX_TREE_DEPTH);
+	let mut budget = Budget {
+		left: query.limit.unwrap_or(DEFAULT_TREE_LIMIT).min(MAX_TREE_LIMIT),
+		exhausted: false,
+	};
+	let children = build_tree(
+		&config.root,
+		&dir,
+		depth,
+		&mut budget,
+		&visibility,
+		config.stat_parallelism,
+	)
+	.await
+	.map_err(ErrorInternalServerError)?;
+
+	Ok(HttpResponse::Ok().json(Tree {
+		path: relative.to_string(),
+		truncated: budget.exhausted,
+		children,
+	}))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+	q: String,
+	/// Directory to search under, relative to the root.
+	#[serde(default)]
+	path: String,
+	limit: Option<usize>,
+	/// Search file contents through the index instead of matching names.
+	#[serde(default)]
+	content: bool,
+}
+
+#[derive(Serialize)]
+struct SearchResults {
+	/// Set when more entries matched than the limit allowed.
+	truncated: bool,
+	results: Vec<DirEntry>,
+}
+
+/// How a search query is matched against file names, always ignoring case.
+enum NameMatcher {
+	Glob(GlobMatcher),
+	Substring(String),
+}
+
+impl NameMatcher {
+	/// Queries containing glob metacharacters are treated as globs, anything else as a substring.
+	fn new(query: &str) -> Result<Self, globset::Error> {
+		if query.contains(['*', '?', '[', '{']) {
+			let glob = GlobBuilder::new(query).case_insensitive(true).build()?;
+			Ok(Self::Glob(glob.compile_matcher()))
+		} else {
+			Ok(Self::Substring(query.to_lowercase()))
+		}
+	}
+
+	fn matches(&self, name: &str) -> bool {
+		match self {
+			Self::Glob(glob) => glob.is_match(name),
+			Self::Substring(needle) => name.to_lowercase().contains(needle),
+		}
+	}
+}
+
+/// Looks `query` up in the content index, keeping the best `limit` hits below `dir`.
+async fn search_contents(
+	config: &Config,
+	index: &ContentIndex,
+	dir: &Path,
+	query: &str,
+	limit: usize,
+	visibility: &Visibility,
+) -> Result<SearchResults> {
+	let hits = index
+		.search(query, MAX_SEARCH_LIMIT)
+		.map_err(ErrorInternalServerError)?;
+
+	let mut results = Vec::new();
+	let mut truncated = false;
+	for hit in hits {
+		let path = config.root.join(&hit);
+		if !path.starts_with(dir) || !visibility.allows(Path::new(&hit)) {
+			continue;
+		}
+		// The index may briefly lag behind files that were just removed.
+		let Ok(metadata) = tokio::fs::symlink_metadata(&path).await else {
+			continue;
+		};
+		if results.len() == limit {
+			truncated = true;
+			break;
+		}
+		results.push(dir_entry(&config.root, &path, &metadata));
+	}
+
+	Ok(SearchResults { truncated, results })
+}
+
+#[get("/_api/search")]
+async fn search(
+	req: HttpRequest,
+	query: web::Query<SearchQuery>,
+	config: web::Data<Config>,
+	index: Option<web::Data<ContentIndex>>,
+) -> Result<HttpResponse> {
+	if !config.dirlist {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	let relative = Path::new(query.path.trim_start_matches('/'));
+	let Some(dir) = resolve_path(&config.root, relative) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	if !dir.is_dir() || !within_root(&config, &dir) || !visibility.allows(relative) {
+		return Ok(HttpResponse::NotFound().body("Directory not found"));
+	}
+	if query.q.is_empty() {
+		return Ok(HttpResponse::BadRequest().body("Missing search query"));
+	}
+
+	let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).min(MAX_SEARCH_LIMIT);
+	if query.content {
+		let Some(index) = index else {
+			return Ok(HttpResponse::BadRequest().body("Content search requires --index-content"));
+		};
+		let results = search_contents(&config, &index, &dir, &query.q, limit, &visibility).await?;
+		return Ok(HttpResponse::Ok().json(results));
+	}
+
+	let matcher = match NameMatcher::new(&query.q) {
+		Ok(matcher) => matcher,
+		Err(e) => return Ok(HttpResponse::BadRequest().body(format!("Invalid pattern: {e}"))),
+	};
+
+	let mut results = Vec::new();
+	let mut truncated = false;
+	let mut pending = vec![dir];
+
+	// Symlinks can match by name but are not descended into, as in the tree API.
+	'walk: while let Some(dir) = pending.pop() {
+		let Ok(entries) = visible_entries(&dir, &visibility, config.stat_parallelism).await else {
+			continue;
+		};
+		for (entry, metadata) in entries {
+			if metadata.is_dir() {
+				pending.push(entry.path());
+			}
+			if !matcher.matches(&entry.file_name().to_string_lossy()) {
+				continue;
+			}
+			if results.len() == limit {
+				truncated = true;
+				break 'walk;
+			}
+			results.push(dir_entry(&config.root, &entry.path(), &metadata));
+		}
+	}
+
+	results.sort_by_key(|entry| entry.path.to_lowercase());
+	Ok(HttpResponse::Ok().json(SearchResults { truncated, results }))
+}
+
+#[derive(Serialize)]
+struct Stat {
+	name: String,
+	path: String,
+	is_dir: bool,
+	is_symlink: bool,
+	/// Where a symlink points, as stored in the link.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	target: Option<String>,
+	size: u64,
+	mime: Option<String>,
+	modified: Option<chrono::DateTime<chrono::Local>>,
+	/// Last status change on unix, creation time elsewhere.
+	ctime: Option<chrono::DateTime<chrono::Local>>,
+	/// Unix permission bits in octal, e.g. `"0644"`.
+	permissions: Option<String>,
+	readonly: bool,
+}
+
+#[cfg(unix)]
+fn change_time(metadata: &std::fs::Metadata) -> Option<chrono::DateTime<chrono::Local>> {
+	use std::os::unix::fs::MetadataExt;
+	let utc = chrono::DateTime::from_timestamp(metadata.ctime(), u32::try_from(metadata.ctime_nsec()).ok()?)?;
+	Some(utc.into())
+}
+
+#[cfg(not(unix))]
+fn change_time(metadata: &std::fs::Metadata) -> Option<chrono::DateTime<chrono::Local>> {
+	metadata.created().ok().map(Into::into)
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &std::fs::Metadata) -> Option<String> {
+	use std::os::unix::fs::PermissionsExt;
+	Some(format!("{:04o}", metadata.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn permission_bits(_: &std::fs::Metadata) -> Option<String> {
+	None
+}
+
+#[get("/_api/stat/{path:.*}")]
+async fn stat(req: HttpRequest, path: web::Path<String>, config: web::Data<Config>) -> Result<HttpResponse> {
+	if !config.dirlist {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	let relative = path.trim_matches('/');
+	let Some(target) = resolve_path(&config.root, Path::new(relative)) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	if !within_root(&config, &target) || !Visibility::of(&config, &req).allows(Path::new(relative)) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	let Ok(link) = tokio::fs::symlink_metadata(&target).await else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+
+	let is_symlink = link.file_type().is_symlink();
+	// Symlinks describe what they point at, falling back to the link itself when dangling.
+	let metadata = if is_symlink {
+		tokio::fs::metadata(&target).await.unwrap_or(link)
+	} else {
+		link
+	};
+	let link_target = if is_symlink {
+		tokio::fs::read_link(&target)
+			.await
+			.ok()
+			.map(|t| t.to_string_lossy().into_owned())
+	} else {
+		None
+	};
+
+	Ok(HttpResponse::Ok().json(Stat {
+		name: target.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+		path: relative.to_string(),
+		is_dir: metadata.is_dir(),
+		is_symlink,
+		target: link_target,
+		size: metadata.len(),
+		mime: (!metadata.is_dir()).then(|| mime_type(&config, &target).to_string()),
+		modified: metadata.modified().ok().map(Into::into),
+		ctime: change_time(&metadata),
+		permissions: permission_bits(&metadata),
+		readonly: metadata.permissions().readonly(),
+	}))
+}
@@ -0,0 +1,278 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{post, web, HttpRequest, HttpResponse, Result};
+use async_compression::tokio::write::GzipEncoder;
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt as _, DuplexStream};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::config::Config;
+use crate::helpers::{attachment, resolve_path, sanitize_filename, visible_entries, within_root, Visibility};
+
+/// Size of the in-memory pipe between the archive writer and the response body.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Name of the folder an archive of `dir` unpacks into, falling back to a generic one for the root
+/// itself.
+fn archive_stem(dir: &Path) -> String {
+	dir.file_name()
+		.map_or_else(|| "archive".into(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Already-compressed formats gain nothing from deflate, so they are stored as-is.
+fn compression_for(path: &Path) -> Compression {
+	let mime = mime_guess::from_path(path).first_or_octet_stream();
+	let compressed = matches!(mime.type_().as_str(), "image" | "audio" | "video")
+		|| matches!(
+			mime.subtype().as_str(),
+			"zip" | "gzip" | "x-bzip2" | "x-xz" | "x-7z-compressed" | "vnd.rar" | "zstd"
+		);
+	if compressed {
+		Compression::Stored
+	} else {
+		Compression::Deflate
+	}
+}
+
+fn zip_date(metadata: &std::fs::Metadata) -> ZipDateTime {
+	let modified = metadata.modified().unwrap_or_else(|_| std::time::SystemTime::now());
+	ZipDateTime::from_chrono(&chrono::DateTime::<chrono::Utc>::from(modified))
+}
+
+/// One file or directory reached while walking the tree being archived.
+struct Entry {
+	path: PathBuf,
+	/// Slash-separated name inside the archive.
+	name: String,
+	metadata: std::fs::Metadata,
+}
+
+/// Depth-first walk over the visible entries below a directory, yielding each directory before its
+/// contents. Symlinks are followed only while they stay inside `root`, if there is one, and each
+/// directory is entered once so links pointing back up the tree cannot loop forever.
+struct Walk {
+	root: Option<PathBuf>,
+	visibility: Visibility,
+	visited: HashSet<PathBuf>,
+	pending: Vec<(PathBuf, String)>,
+	queue: Vec<Entry>,
+	/// Entries of a directory stat'ed at once.
+	parallelism: usize,
+}
+
+impl Walk {
+	/// Walks `dir` itself, nested under a folder named after it.
+	async fn new(root: Option<&Path>, dir: &Path, visibility: Visibility, parallelism: usize) -> std::io::Result<Self> {
+		Self::over(
+			root,
+			vec![(dir.to_path_buf(), archive_stem(dir))],
+			visibility,
+			parallelism,
+		)
+		.await
+	}
+
+	/// Walks each of `tops`, placed at the top level of the archive under the given names.
+	async fn over(
+		root: Option<&Path>,
+		tops: Vec<(PathBuf, String)>,
+		visibility: Visibility,
+		parallelism: usize,
+	) -> std::io::Result<Self> {
+		let mut queue = Vec::with_capacity(tops.len());
+		for (path, name) in tops.into_iter().rev() {
+			let metadata = tokio::fs::metadata(&path).await?;
+			queue.push(Entry { path, name, metadata });
+		}
+		Ok(Self {
+			root: root.map(Path::to_path_buf),
+			visibility,
+			visited: HashSet::new(),
+			pending: Vec::new(),
+			queue,
+			parallelism,
+		})
+	}
+
+	async fn next(&mut self) -> std::io::Result<Option<Entry>> {
+		loop {
+			if let Some(entry) = self.queue.pop() {
+				if entry.metadata.is_dir() {
+					if !self.visited.insert(tokio::fs::canonicalize(&entry.path).await?) {
+						continue;
+					}
+					self.pending.push((entry.path.clone(), entry.name.clone()));
+				}
+				return Ok(Some(entry));
+			}
+
+			let Some((dir, prefix)) = self.pending.pop() else {
+				return Ok(None);
+			};
+			for (entry, _) in visible_entries(&dir, &self.visibility, self.parallelism).await? {
+				let path = entry.path();
+				let Ok(target) = tokio::fs::canonicalize(&path).await else {
+					continue;
+				};
+				if self.root.as_ref().is_some_and(|root| !target.starts_with(root)) {
+					continue;
+				}
+
+				self.queue.push(Entry {
+					name: format!("{prefix}/{}", entry.file_name().to_string_lossy()),
+					metadata: tokio::fs::metadata(&path).await?,
+					path,
+				});
+			}
+		}
+	}
+}
+
+/// Pipes the archive produced by `write` into a streamed response, building it while the client
+/// downloads so nothing is staged on disk or held in memory beyond a small pipe buffer.
+fn stream_archive<F, Fut>(filename: String, content_type: &str, write: F) -> HttpResponse
+where
+	F: FnOnce(DuplexStream) -> Fut,
+	Fut: Future<Output = std::io::Result<()>> + 'static,
+{
+	let (writer, reader) = tokio::io::duplex(PIPE_SIZE);
+
+	// A failure half-way through can no longer change the status code; dropping the writer ends
+	// the body early and the client is left with a truncated archive it will reject.
+	let task = write(writer);
+	actix_web::rt::spawn(async move {
+		let _ = task.await;
+	});
+
+	HttpResponse::Ok()
+		.content_type(content_type)
+		.insert_header(attachment(&filename))
+		.streaming(ReaderStream::new(reader))
+}
+
+/// The directory archives must not leave through symlinks, `None` when they may be followed
+/// anywhere.
+fn confinement(config: &Config) -> Option<PathBuf> {
+	(!config.follow_symlinks).then(|| config.root.clone())
+}
+
+/// Streams `dir` as a zip archive, nested under a folder named after it.
+pub fn zip_response(config: &Config, dir: &Path, visibility: Visibility) -> HttpResponse {
+	let filename = format!("{}.zip", archive_stem(dir));
+	let (root, dir, parallelism) = (confinement(config), dir.to_path_buf(), config.stat_parallelism);
+	stream_archive(filename, "application/zip", |writer| async move {
+		write_zip(Walk::new(root.as_deref(), &dir, visibility, parallelism).await?, writer).await
+	})
+}
+
+async fn write_zip(mut walk: Walk, writer: impl AsyncWrite + Unpin) -> std::io::Result<()> {
+	let mut zip = ZipFileWriter::with_tokio(writer);
+
+	while let Some(entry) = walk.next().await? {
+		if entry.metadata.is_dir() {
+			let builder = ZipEntryBuilder::new(format!("{}/", entry.name).into(), Compression::Stored)
+				.last_modification_date(zip_date(&entry.metadata));
+			zip.write_entry_whole(builder, &[])
+				.await
+				.map_err(std::io::Error::other)?;
+			continue;
+		}
+
+		let builder = ZipEntryBuilder::new(entry.name.into(), compression_for(&entry.path))
+			.last_modification_date(zip_date(&entry.metadata));
+		let mut file = tokio::fs::File::open(&entry.path).await?.compat();
+		let mut entry_writer = zip.write_entry_stream(builder).await.map_err(std::io::Error::other)?;
+		futures::io::copy(&mut file, &mut entry_writer).await?;
+		entry_writer.close().await.map_err(std::io::Error::other)?;
+	}
+
+	let mut writer = zip.close().await.map_err(std::io::Error::other)?.into_inner();
+	writer.shutdown().await
+}
+
+/// Streams `dir` as a gzip-compressed tarball. Unlike zip, tar keeps unix permissions and needs no
+/// central directory, so memory use stays constant however large the tree is.
+pub fn tar_gz_response(config: &Config, dir: &Path, visibility: Visibility) -> HttpResponse {
+	let filename = format!("{}.tar.gz", archive_stem(dir));
+	let (root, dir, parallelism) = (confinement(config), dir.to_path_buf(), config.stat_parallelism);
+	stream_archive(filename, "application/gzip", |writer| async move {
+		write_tar_gz(Walk::new(root.as_deref(), &dir, visibility, parallelism).await?, writer).await
+	})
+}
+
+async fn write_tar_gz(mut walk: Walk, writer: DuplexStream) -> std::io::Result<()> {
+	let mut tar = tokio_tar::Builder::new(GzipEncoder::new(writer));
+
+	while let Some(entry) = walk.next().await? {
+		if entry.metadata.is_dir() {
+			tar.append_dir(&entry.name, &entry.path).await?;
+		} else {
+			let mut file = tokio::fs::File::open(&entry.path).await?;
+			tar.append_file(&entry.name, &mut file).await?;
+		}
+	}
+
+	tar.into_inner().await?.shutdown().await
+}
+
+/// Streams a zip of just the entries ticked in a listing. The form carries the listing's `dir` and
+/// one `entry` field per selected name, which is why it is read as raw pairs rather than a struct.
+#[allow(clippy::future_not_send)]
+#[post("/_api/archive")]
+async fn download_selection(
+	req: HttpRequest,
+	form: web::Form<Vec<(String, String)>>,
+	config: web::Data<Config>,
+) -> Result<HttpResponse> {
+	let mut dir = String::new();
+	let mut names = Vec::new();
+	for (key, value) in form.into_inner() {
+		match key.as_str() {
+			"dir" => dir = value,
+			"entry" => names.push(value),
+			_ => (),
+		}
+	}
+
+	let relative = Path::new(dir.trim_start_matches('/'));
+	let Some(dir) = resolve_path(&config.root, relative) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	if !dir.is_dir() || !within_root(&config, &dir) || !visibility.allows(relative) {
+		return Ok(HttpResponse::NotFound().body("Directory not found"));
+	}
+	if names.is_empty() {
+		return Ok(HttpResponse::BadRequest().body("Nothing selected"));
+	}
+
+	let mut tops = Vec::with_capacity(names.len());
+	for name in names {
+		if sanitize_filename(&name).as_deref() != Some(&*name) {
+			return Ok(HttpResponse::BadRequest().body("Invalid entry name"));
+		}
+		let path = dir.join(&name);
+		let hidden = !visibility.allows(&relative.join(&name)) || (!config.dirlist && path.is_dir());
+		if !within_root(&config, &path) || hidden {
+			return Ok(HttpResponse::NotFound().body(format!("{name} not found")));
+		}
+		tops.push((path, name));
+	}
+
+	let walk = Walk::over(
+		confinement(&config).as_deref(),
+		tops,
+		visibility,
+		config.stat_parallelism,
+	)
+	.await
+	.map_err(ErrorInternalServerError)?;
+	let filename = format!("{}.zip", archive_stem(&dir));
+	Ok(stream_archive(filename, "application/zip", |writer| {
+		write_zip(walk, writer)
+	}))
+}
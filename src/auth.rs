@@ -0,0 +1,268 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use base64::prelude::{Engine, BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD};
+use rand::RngCore;
+
+use crate::bans::Bans;
+use crate::config::Config;
+use crate::helpers::{client_ip, unprefixed};
+use crate::share::{self, ShareCheck};
+use crate::{health, metrics, mounts};
+
+const REALM: &str = "http-server-rs";
+const TOKEN_COOKIE: &str = "http_server_token";
+
+/// Generates an unguessable token suitable for `--token` when none was supplied.
+pub fn generate_token() -> String {
+	let mut bytes = [0u8; 24];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// A `Cookie` header value without the token cookie, for passing on to proxied servers and CGI
+/// scripts, which get no credentials of this server; `None` when nothing else is left.
+pub fn without_token_cookie(value: &str) -> Option<String> {
+	let rest: Vec<&str> = value
+		.split(';')
+		.map(str::trim)
+		.filter(|pair| !pair.is_empty() && pair.split('=').next().map(str::trim) != Some(TOKEN_COOKIE))
+		.collect();
+	(!rest.is_empty()).then(|| rest.join("; "))
+}
+
+/// Compares two byte strings without short-circuiting on the first mismatch.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Decodes an `Authorization: Basic ...` header value into its `user:pass` form.
+fn basic_credentials(req: &ServiceRequest) -> Option<Vec<u8>> {
+	let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+	let (scheme, encoded) = value.split_once(' ')?;
+	if !scheme.eq_ignore_ascii_case("basic") {
+		return None;
+	}
+	BASE64_STANDARD.decode(encoded.trim()).ok()
+}
+
+/// Extracts the token from an `Authorization: Bearer ...` header.
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+	let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+	let (scheme, token) = value.split_once(' ')?;
+	scheme.eq_ignore_ascii_case("bearer").then(|| token.trim().to_string())
+}
+
+/// Extracts the token from a `?token=...` query parameter.
+fn query_token(req: &ServiceRequest) -> Option<String> {
+	web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+		.ok()?
+		.into_inner()
+		.into_iter()
+		.find_map(|(key, value)| (key == "token").then_some(value))
+}
+
+enum Authorized {
+	/// Either no authentication is configured or the request carried valid credentials.
+	Yes,
+	/// The token was supplied in the query string; it is remembered in a cookie so links in the
+	/// listing keep working without carrying `?token=` around.
+	ViaQueryToken,
+	No,
+}
+
+/// Whether `req` is for the metrics endpoint while it has credentials of its own.
+fn uses_metrics_auth(config: &Config, req: &ServiceRequest) -> bool {
+	config.metrics_auth.is_some() && unprefixed(config, req.path()) == Some(metrics::PATH)
+}
+
+fn authorize(config: &Config, req: &ServiceRequest) -> Authorized {
+	// Scrapers get their own credentials so they need no access to the files.
+	if let Some(expected) = config.metrics_auth.as_ref().filter(|_| uses_metrics_auth(config, req)) {
+		return match basic_credentials(req) {
+			Some(credentials) if constant_time_eq(expected.as_bytes(), &credentials) => Authorized::Yes,
+			_ => Authorized::No,
+		};
+	}
+
+	let health_check = unprefixed(config, req.path()).is_some_and(|path| health::PATHS.contains(&path));
+	if config.auth.is_empty() && config.token.is_none() || health_check {
+		return Authorized::Yes;
+	}
+
+	// A valid share link grants reading exactly the path it was signed for, and nothing else:
+	// writing to it takes credentials like any other change.
+	let reading = matches!(*req.method(), Method::GET | Method::HEAD);
+	if reading && share::verify(config, req.request()) == ShareCheck::Valid {
+		return Authorized::Yes;
+	}
+
+	if let Some(credentials) = basic_credentials(req) {
+		if config
+			.auth
+			.iter()
+			.any(|allowed| constant_time_eq(allowed.as_bytes(), &credentials))
+		{
+			return Authorized::Yes;
+		}
+	}
+
+	if let Some(expected) = &config.token {
+		let matches = |token: &str| constant_time_eq(expected.as_bytes(), token.as_bytes());
+		if bearer_token(req).is_some_and(|token| matches(&token))
+			|| req.cookie(TOKEN_COOKIE).is_some_and(|cookie| matches(cookie.value()))
+		{
+			return Authorized::Yes;
+		}
+		if query_token(req).is_some_and(|token| matches(&token)) {
+			return Authorized::ViaQueryToken;
+		}
+	}
+
+	Authorized::No
+}
+
+/// Whether the request tried to authenticate, as opposed to not having been challenged yet. The
+/// token cookie is left out: it goes stale whenever the server restarts with a new token, and
+/// every request of a page would count against the client.
+fn presented_credentials(req: &ServiceRequest) -> bool {
+	req.headers().contains_key(header::AUTHORIZATION) || query_token(req).is_some()
+}
+
+fn unauthorized(config: &Config, req: &ServiceRequest) -> HttpResponse {
+	let metrics_only = uses_metrics_auth(config, req);
+	let mut response = HttpResponse::Unauthorized();
+	if !config.auth.is_empty() || metrics_only {
+		response.append_header((
+			header::WWW_AUTHENTICATE,
+			format!("Basic realm=\"{REALM}\", charset=\"UTF-8\""),
+		));
+	}
+	if config.token.is_some() && !metrics_only {
+		response.append_header((header::WWW_AUTHENTICATE, format!("Bearer realm=\"{REALM}\"")));
+	}
+	response.body("Unauthorized")
+}
+
+pub async fn require_auth(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let Some(config) = mounts::request_config(&req) else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+
+	let bans = req
+		.app_data::<web::Data<Bans>>()
+		.zip(client_ip(req.request(), config.trusted_proxies))
+		.map(|(bans, ip)| (bans.clone(), ip));
+	if let Some(left) = bans.as_ref().and_then(|(bans, ip)| bans.banned_for(*ip)) {
+		let response = HttpResponse::Forbidden()
+			.insert_header((header::RETRY_AFTER, left.as_secs().max(1).to_string()))
+			.body("Banned after too many failed authentication attempts");
+		return Ok(req.into_response(response).map_into_right_body());
+	}
+
+	match authorize(&config, &req) {
+		Authorized::Yes => Ok(next.call(req).await?.map_into_left_body()),
+		Authorized::ViaQueryToken => {
+			let mut res = next.call(req).await?;
+			if let Some(token) = &config.token {
+				let cookie = Cookie::build(TOKEN_COOKIE, token.clone())
+					.path("/")
+					.http_only(true)
+					.same_site(SameSite::Strict)
+					.finish();
+				res.response_mut().add_cookie(&cookie)?;
+			}
+			Ok(res.map_into_left_body())
+		},
+		Authorized::No => {
+			if let Some((bans, ip)) = bans.filter(|_| presented_credentials(&req)) {
+				bans.record_failure(ip);
+			}
+			let response = unauthorized(&config, &req);
+			Ok(req.into_response(response).map_into_right_body())
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use actix_web::test::TestRequest;
+
+	fn config() -> Config {
+		Config {
+			auth: vec!["u:p".to_string()],
+			share_secret: "secret".to_string(),
+			..Config::default()
+		}
+	}
+
+	/// A request for `a.txt` carrying a share link's valid signature.
+	fn shared(method: Method) -> TestRequest {
+		let expires = share::now() + 3600;
+		let sig = share::signature("secret", "/a.txt", expires);
+		TestRequest::default()
+			.method(method)
+			.uri(&format!("/a.txt?expires={expires}&sig={sig}"))
+	}
+
+	#[test]
+	fn share_links_only_grant_reading() {
+		let config = config();
+		assert!(matches!(
+			authorize(&config, &shared(Method::GET).to_srv_request()),
+			Authorized::Yes
+		));
+		assert!(matches!(
+			authorize(&config, &shared(Method::HEAD).to_srv_request()),
+			Authorized::Yes
+		));
+		for method in [
+			Method::PUT,
+			Method::DELETE,
+			Method::POST,
+			Method::from_bytes(b"MOVE").unwrap(),
+		] {
+			assert!(matches!(
+				authorize(&config, &shared(method).to_srv_request()),
+				Authorized::No
+			));
+		}
+	}
+
+	#[actix_web::test]
+	async fn writes_through_share_links_are_unauthorized() {
+		use actix_web::{middleware, test, App, HttpResponse};
+
+		let app = test::init_service(
+			App::new()
+				.app_data(web::Data::new(config()))
+				.wrap(middleware::from_fn(require_auth))
+				.default_service(web::to(HttpResponse::NoContent)),
+		)
+		.await;
+		for method in [Method::PUT, Method::DELETE] {
+			let res = test::call_service(&app, shared(method).to_request()).await;
+			assert_eq!(res.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+		}
+	}
+
+	#[test]
+	fn token_cookie_is_left_out() {
+		assert_eq!(
+			without_token_cookie("a=1; http_server_token=secret; b=2").as_deref(),
+			Some("a=1; b=2")
+		);
+		assert_eq!(without_token_cookie("http_server_token=secret"), None);
+		assert_eq!(
+			without_token_cookie("http_server_tokens=x").as_deref(),
+			Some("http_server_tokens=x")
+		);
+	}
+}
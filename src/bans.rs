@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a ban lasts when `--ban-time` reaches further than `Instant` can count.
+const FOREVER: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Failed authentication attempts of each client and who is banned until when, shared by all
+/// workers. Failures are forgotten once a ban's length has passed since the first of them, so an
+/// occasional typo never adds up to a ban.
+pub struct Bans {
+	threshold: u32,
+	duration: Duration,
+	failures: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+	banned: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl Bans {
+	pub fn new(threshold: u32, duration: Duration) -> Self {
+		Self {
+			threshold: threshold.max(1),
+			duration,
+			failures: Mutex::new(HashMap::new()),
+			banned: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// How long `ip` remains banned, if it is.
+	pub fn banned_for(&self, ip: IpAddr) -> Option<Duration> {
+		let mut banned = self.banned.lock().unwrap();
+		let until = *banned.get(&ip)?;
+		let left = until.saturating_duration_since(Instant::now());
+		if left.is_zero() {
+			banned.remove(&ip);
+			tracing::info!("Ban on {ip} expired");
+			return None;
+		}
+		Some(left)
+	}
+
+	/// Counts a failed attempt from `ip`, banning it once it reaches the threshold.
+	pub fn record_failure(&self, ip: IpAddr) {
+		let now = Instant::now();
+		let mut failures = self.failures.lock().unwrap();
+		failures.retain(|_, (_, first)| now.duration_since(*first) < self.duration);
+		let (count, _) = failures.entry(ip).or_insert((0, now));
+		*count += 1;
+		tracing::debug!("Failed authentication attempt {count} from {ip}");
+		if *count < self.threshold {
+			return;
+		}
+
+		let count = *count;
+		failures.remove(&ip);
+		drop(failures);
+		let mut banned = self.banned.lock().unwrap();
+		banned.retain(|_, until| *until > now);
+		banned.insert(ip, now.checked_add(self.duration).unwrap_or(now + FOREVER));
+		tracing::warn!(
+			"Banning {ip} for {}s after {count} failed authentication attempts",
+			self.duration.as_secs()
+		);
+	}
+}
@@ -0,0 +1,30 @@
+use bytes::BytesMut;
+use std::sync::Mutex;
+
+/// Idle buffers kept for reuse; beyond this they are freed, so a burst of streams does not pin
+/// its peak memory for good.
+const MAX_POOLED: usize = 64;
+
+/// Read buffers left behind by finished streams, shared by all workers so that many clients
+/// streaming at once do not each keep allocating their own.
+static POOL: Mutex<Vec<BytesMut>> = Mutex::new(Vec::new());
+
+/// A buffer with room for at least `capacity` bytes, reused from the pool when there is one.
+pub fn take(capacity: usize) -> BytesMut {
+	let mut buffer = POOL.lock().unwrap().pop().unwrap_or_default();
+	buffer.reserve(capacity);
+	buffer
+}
+
+/// Returns a buffer to the pool. Chunks split off it that are still being sent keep their part
+/// of it alive; the next [`BytesMut::reserve`] reclaims the allocation once they are gone.
+pub fn give_back(mut buffer: BytesMut) {
+	buffer.clear();
+	if buffer.capacity() == 0 {
+		return;
+	}
+	let mut pool = POOL.lock().unwrap();
+	if pool.len() < MAX_POOLED {
+		pool.push(buffer);
+	}
+}
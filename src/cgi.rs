@@ -0,0 +1,373 @@
+use actix_web::body::SizedStream;
+use actix_web::error::{ErrorGatewayTimeout, ErrorInternalServerError, ErrorPayloadTooLarge};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::rt::time::{timeout, Instant};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use base64::prelude::*;
+use bytes::{Bytes, BytesMut};
+use percent_encoding::percent_decode_str;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdout, Command};
+
+use crate::auth;
+use crate::config::{normalize_prefix, Config, MAX_BUFFERED_BODY};
+use crate::helpers::{safe_component, within_root, Visibility};
+
+/// Scripts printing more than this before the blank line ending their headers are taken to have
+/// printed none.
+const MAX_HEADERS: usize = 64 * 1024;
+
+/// Scripts still running this long after they were started are killed.
+const MAX_RUNTIME: Duration = Duration::from_secs(300);
+
+/// The `--cgi` directory.
+pub struct Cgi {
+	/// The path its scripts are requested beneath, with the route prefix.
+	pub path: String,
+	dir: PathBuf,
+}
+
+impl Cgi {
+	/// The directory of `config`, or `None` when it has none.
+	pub fn from_config(config: &Config) -> std::io::Result<Option<Self>> {
+		let Some(dir) = &config.cgi_dir else {
+			return Ok(None);
+		};
+		let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+		let path = normalize_prefix(&dir.to_string_lossy())
+			.filter(|path| !path.is_empty())
+			.ok_or_else(|| invalid(format!("Invalid CGI directory {}", dir.display())))?;
+		let canonical = config
+			.root
+			.join(path.trim_start_matches('/'))
+			.canonicalize()
+			.map_err(|e| std::io::Error::new(e.kind(), format!("Cannot run scripts in {}: {e}", dir.display())))?;
+		if !canonical.starts_with(&config.root) || !canonical.is_dir() {
+			return Err(invalid(format!(
+				"{} is not a directory beneath the root",
+				dir.display()
+			)));
+		}
+		Ok(Some(Self {
+			path: format!("{}{path}", config.route_prefix),
+			dir: canonical,
+		}))
+	}
+
+	/// Splits `rest`, the request path below `self.path`, into the script it names and the
+	/// `PATH_INFO` following it.
+	fn locate(&self, rest: &str) -> Option<(PathBuf, String)> {
+		let segments = rest
+			.split('/')
+			.filter(|segment| !segment.is_empty())
+			.map(|segment| percent_decode_str(segment).decode_utf8().ok())
+			.collect::<Option<Vec<_>>>()?;
+		let mut script = self.dir.clone();
+		for (i, segment) in segments.iter().enumerate() {
+			if matches!(segment.as_ref(), "." | "..")
+				|| segment.contains(['/', '\\', '\0'])
+				|| !safe_component(OsStr::new(segment.as_ref()))
+			{
+				return None;
+			}
+			script.push(segment.as_ref());
+			if std::fs::metadata(&script).ok()?.is_file() {
+				let path_info = segments[i + 1..].iter().map(|segment| format!("/{segment}")).collect();
+				return Some((script, path_info));
+			}
+		}
+		None
+	}
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> bool {
+	use std::os::unix::fs::PermissionsExt;
+	std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn executable(_: &Path) -> bool {
+	true
+}
+
+/// The meta-variables of RFC 3875, section 4.1, along with the `HTTP_*` ones for the request's
+/// headers and those common scripts such as `php-cgi` expect.
+fn environment(
+	req: &HttpRequest,
+	config: &Config,
+	cgi: &Cgi,
+	script: &Path,
+	path_info: &str,
+	content_length: usize,
+) -> Vec<(String, String)> {
+	let info = req.connection_info();
+	let script_name = script
+		.strip_prefix(&cgi.dir)
+		.unwrap_or(script)
+		.components()
+		.map(|component| format!("/{}", component.as_os_str().to_string_lossy()))
+		.collect::<String>();
+	let mut env = vec![
+		("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string()),
+		(
+			"SERVER_SOFTWARE".to_string(),
+			concat!("http-server-rs/", env!("CARGO_PKG_VERSION")).to_string(),
+		),
+		("SERVER_PROTOCOL".to_string(), format!("{:?}", req.version())),
+		(
+			"SERVER_NAME".to_string(),
+			crate::helpers::hostname(info.host()).to_string(),
+		),
+		(
+			"SERVER_PORT".to_string(),
+			req.app_config().local_addr().port().to_string(),
+		),
+		("REQUEST_METHOD".to_string(), req.method().to_string()),
+		("REQUEST_URI".to_string(), req.uri().to_string()),
+		("QUERY_STRING".to_string(), req.query_string().to_string()),
+		("SCRIPT_NAME".to_string(), format!("{}{script_name}", cgi.path)),
+		("SCRIPT_FILENAME".to_string(), script.display().to_string()),
+		("DOCUMENT_ROOT".to_string(), config.root.display().to_string()),
+		("PATH_INFO".to_string(), path_info.to_string()),
+		// Checked by php-cgi, which otherwise refuses to run as a CGI script.
+		("REDIRECT_STATUS".to_string(), "200".to_string()),
+	];
+	if !path_info.is_empty() {
+		env.push((
+			"PATH_TRANSLATED".to_string(),
+			config
+				.root
+				.join(path_info.trim_start_matches('/'))
+				.display()
+				.to_string(),
+		));
+	}
+	if let Some(peer) = req.peer_addr() {
+		env.push(("REMOTE_ADDR".to_string(), peer.ip().to_canonical().to_string()));
+		env.push(("REMOTE_PORT".to_string(), peer.port().to_string()));
+	}
+	if info.scheme() == "https" {
+		env.push(("HTTPS".to_string(), "on".to_string()));
+	}
+	if content_length > 0 {
+		env.push(("CONTENT_LENGTH".to_string(), content_length.to_string()));
+	}
+	if let Some(content_type) = req.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+		env.push(("CONTENT_TYPE".to_string(), content_type.to_string()));
+	}
+	// The credentials themselves are kept from the script, only the user it was run for is told,
+	// and so is the token cookie below.
+	let user = req
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.split_once(' '))
+		.filter(|(scheme, _)| !config.auth.is_empty() && scheme.eq_ignore_ascii_case("basic"))
+		.and_then(|(_, encoded)| BASE64_STANDARD.decode(encoded.trim()).ok())
+		.and_then(|decoded| Some(String::from_utf8(decoded).ok()?.split_once(':')?.0.to_string()));
+	if let Some(user) = user {
+		env.push(("AUTH_TYPE".to_string(), "Basic".to_string()));
+		env.push(("REMOTE_USER".to_string(), user));
+	}
+
+	for name in req.headers().keys() {
+		// A `Proxy` header would become the `HTTP_PROXY` many HTTP clients take for their proxy.
+		if [header::AUTHORIZATION, header::CONTENT_TYPE, header::CONTENT_LENGTH].contains(name) || name == "proxy" {
+			continue;
+		}
+		// Several `Cookie` headers are one list of cookies, joined as the other headers are not.
+		let separator = if name == header::COOKIE { "; " } else { ", " };
+		let mut value = req
+			.headers()
+			.get_all(name)
+			.filter_map(|v| v.to_str().ok())
+			.collect::<Vec<_>>()
+			.join(separator);
+		if name == header::COOKIE {
+			let Some(cookies) = auth::without_token_cookie(&value) else {
+				continue;
+			};
+			value = cookies;
+		}
+		env.push((
+			format!("HTTP_{}", name.as_str().to_ascii_uppercase().replace('-', "_")),
+			value,
+		));
+	}
+	env
+}
+
+/// Reads the header lines a script prints before its body, up to the blank line ending them, or
+/// `None` when it prints no such thing.
+async fn read_headers(stdout: &mut (impl AsyncBufRead + Unpin)) -> Option<Vec<(String, String)>> {
+	let mut headers = Vec::new();
+	let mut read = 0;
+	let mut line = String::new();
+	loop {
+		line.clear();
+		let n = stdout.read_line(&mut line).await.ok()?;
+		read += n;
+		if n == 0 || read > MAX_HEADERS {
+			return None;
+		}
+		let line = line.trim_end_matches(['\r', '\n']);
+		if line.is_empty() {
+			return Some(headers);
+		}
+		let (name, value) = line.split_once(':')?;
+		headers.push((name.trim().to_string(), value.trim().to_string()));
+	}
+}
+
+/// The rest of what a script prints once its headers are read, ending as the running script is
+/// killed: when the response is dropped, such as by the client going away, or when `deadline`
+/// passes.
+fn output(
+	stdout: BufReader<ChildStdout>,
+	child: Child,
+	deadline: Instant,
+) -> impl futures::Stream<Item = std::io::Result<Bytes>> {
+	futures::stream::unfold(Some((stdout, child)), move |state| async move {
+		let (mut stdout, child) = state?;
+		let mut buf = BytesMut::with_capacity(8 * 1024);
+		match timeout(
+			deadline.saturating_duration_since(Instant::now()),
+			stdout.read_buf(&mut buf),
+		)
+		.await
+		{
+			Ok(Ok(0)) => None,
+			Ok(Ok(_)) => Some((Ok(buf.freeze()), Some((stdout, child)))),
+			Ok(Err(e)) => Some((Err(e), None)),
+			Err(_) => Some((
+				Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "Script ran too long")),
+				None,
+			)),
+		}
+	})
+}
+
+/// Runs the script a request beneath the `--cgi` directory names, as RFC 3875 describes: the
+/// request becomes its environment and standard input, and what it prints the response, with a
+/// `Status` header for its status. Anything it writes to standard error is logged. The script
+/// is killed once the client goes away or it has run for [`MAX_RUNTIME`], and its input is read
+/// in full first, up to `--max-upload-size` or else [`MAX_BUFFERED_BODY`].
+pub async fn run(
+	req: HttpRequest,
+	payload: web::Payload,
+	cgi: web::Data<Cgi>,
+	config: web::Data<Config>,
+) -> Result<HttpResponse, Error> {
+	let rest = req.path().strip_prefix(&cgi.path).unwrap_or_default();
+	let Some((script, path_info)) = cgi.locate(rest) else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+	let relative = script.strip_prefix(&config.root).unwrap_or(&script);
+	if !Visibility::of(&config, &req).allows(relative) || !within_root(&config, &script) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	if !executable(&script) {
+		return Ok(HttpResponse::Forbidden().body("Script is not executable"));
+	}
+
+	let limit = config.max_upload_size.map_or(MAX_BUFFERED_BODY, |max| max.0);
+	let body = payload
+		.to_bytes_limited(usize::try_from(limit).unwrap_or(usize::MAX))
+		.await
+		.map_err(ErrorPayloadTooLarge)??;
+
+	let mut command = Command::new(&script);
+	command
+		.env_clear()
+		.envs(environment(&req, &config, &cgi, &script, &path_info, body.len()))
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true);
+	if let Some(path) = std::env::var_os("PATH") {
+		command.env("PATH", path);
+	}
+	if let Some(dir) = script.parent() {
+		command.current_dir(dir);
+	}
+	let deadline = Instant::now() + MAX_RUNTIME;
+	let mut child = command.spawn().map_err(|e| {
+		tracing::warn!("Failed to run {}: {e}", script.display());
+		ErrorInternalServerError("Failed to run script")
+	})?;
+
+	let (Some(mut stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take())
+	else {
+		return Err(ErrorInternalServerError("Failed to run script"));
+	};
+	// Written alongside reading the output, as a script may start printing before it has read
+	// all of its input.
+	actix_web::rt::spawn(async move {
+		let _ = stdin.write_all(&body).await;
+	});
+	let name = script.display().to_string();
+	actix_web::rt::spawn(async move {
+		let mut lines = BufReader::new(stderr).lines();
+		while let Ok(Some(line)) = lines.next_line().await {
+			tracing::warn!("{name}: {line}");
+		}
+	});
+
+	let mut stdout = BufReader::new(stdout);
+	let Ok(headers) = timeout(
+		deadline.saturating_duration_since(Instant::now()),
+		read_headers(&mut stdout),
+	)
+	.await
+	else {
+		tracing::warn!("{} printed no headers in time", script.display());
+		return Err(ErrorGatewayTimeout("Script ran too long"));
+	};
+	let Some(headers) = headers else {
+		tracing::warn!("{} printed no headers", script.display());
+		return Err(ErrorInternalServerError("Script printed no headers"));
+	};
+
+	let mut status = None;
+	let mut redirects = false;
+	let mut length = None;
+	let mut response = HttpResponse::Ok();
+	for (name, value) in headers {
+		if name.eq_ignore_ascii_case("status") {
+			status = Some(
+				value
+					.split_whitespace()
+					.next()
+					.and_then(|code| code.parse::<u16>().ok())
+					.and_then(|code| StatusCode::from_u16(code).ok())
+					.ok_or_else(|| ErrorInternalServerError("Script printed an invalid status"))?,
+			);
+			continue;
+		}
+		if name.eq_ignore_ascii_case("content-length") {
+			length = value.parse::<u64>().ok();
+			continue;
+		}
+		let (Ok(header_name), Ok(header_value)) = (HeaderName::try_from(name.as_str()), HeaderValue::try_from(value))
+		else {
+			tracing::warn!("{} printed an invalid header {name:?}", script.display());
+			continue;
+		};
+		redirects |= header_name == header::LOCATION;
+		response.append_header((header_name, header_value));
+	}
+	// A `Location` without a status of its own is one of RFC 3875's client redirects.
+	response.status(status.unwrap_or(if redirects { StatusCode::FOUND } else { StatusCode::OK }));
+
+	let body = output(stdout, child, deadline);
+	Ok(match length {
+		Some(length) => response.body(SizedStream::new(length, body)),
+		None => response.streaming(body),
+	})
+}
@@ -0,0 +1,66 @@
+use mime_guess::mime::{self, Mime};
+use std::io::Read;
+use std::path::Path;
+
+use crate::config::Config;
+
+/// How much of a text file is looked at to tell its encoding.
+const SNIFF_LEN: usize = 4096;
+
+/// Whether responses of type `mime` are text a browser would decode with a charset.
+fn is_text(mime: &Mime) -> bool {
+	mime.type_() == mime::TEXT
+		|| matches!(mime.subtype().as_str(), "javascript" | "json" | "xml")
+		|| mime
+			.suffix()
+			.is_some_and(|suffix| matches!(suffix.as_str(), "xml" | "json"))
+}
+
+/// Tells the encoding of text from its first bytes: a byte order mark if there is one, then
+/// UTF-16 by how its zero bytes fall, or whether it is UTF-8, and Latin-1 when it is neither.
+fn sniff(bytes: &[u8], truncated: bool) -> &'static str {
+	match bytes {
+		[0xEF, 0xBB, 0xBF, ..] => return "utf-8",
+		[0xFF, 0xFE, ..] => return "utf-16le",
+		[0xFE, 0xFF, ..] => return "utf-16be",
+		_ => (),
+	}
+	// Zero bytes are valid UTF-8 but all but absent from text, unless it is UTF-16.
+	let zeros = |offset: usize| bytes.iter().skip(offset).step_by(2).filter(|&&b| b == 0).count();
+	let (even, odd) = (zeros(0), zeros(1));
+	let quarter = bytes.len() / 8;
+	if odd > quarter && even == 0 {
+		return "utf-16le";
+	}
+	if even > quarter && odd == 0 {
+		return "utf-16be";
+	}
+	match std::str::from_utf8(bytes) {
+		Ok(_) => "utf-8",
+		// A character cut off where the sample ends.
+		Err(e) if truncated && e.error_len().is_none() => "utf-8",
+		Err(_) => "iso-8859-1",
+	}
+}
+
+/// `mime` for the file at `path`, with the charset of the text in it: the `--charset` if given,
+/// or else the one sniffed from its start. Other types, and those naming a charset already, are
+/// returned as they are.
+pub fn with_charset(config: &Config, path: &Path, mime: Mime) -> Mime {
+	if !is_text(&mime) || mime.get_param(mime::CHARSET).is_some() {
+		return mime;
+	}
+	let charset = match &config.charset {
+		Some(charset) => charset.as_str(),
+		None => {
+			let mut sample = Vec::with_capacity(SNIFF_LEN);
+			let Ok(read) =
+				std::fs::File::open(path).and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut sample))
+			else {
+				return mime;
+			};
+			sniff(&sample, read == SNIFF_LEN)
+		},
+	};
+	format!("{mime}; charset={charset}").parse().unwrap_or(mime)
+}
@@ -0,0 +1,114 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{HttpResponse, Result};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Digests kept before the cache is emptied and starts over.
+const MAX_CACHED: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+	Sha256,
+	Md5,
+	Blake3,
+}
+
+impl Algorithm {
+	pub fn parse(name: &str) -> Option<Self> {
+		match name.to_ascii_lowercase().as_str() {
+			"sha256" => Some(Self::Sha256),
+			"md5" => Some(Self::Md5),
+			"blake3" => Some(Self::Blake3),
+			_ => None,
+		}
+	}
+}
+
+/// Identifies one version of a file; any write changes the mtime or size and so misses the cache.
+#[derive(PartialEq, Eq, Hash)]
+struct Key {
+	path: PathBuf,
+	modified: SystemTime,
+	size: u64,
+	algorithm: Algorithm,
+}
+
+/// Digests of recently hashed files.
+#[derive(Default)]
+pub struct ChecksumCache {
+	digests: Mutex<HashMap<Key, String>>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hash_file(path: &Path, algorithm: Algorithm) -> std::io::Result<String> {
+	let mut file = std::fs::File::open(path)?;
+	Ok(match algorithm {
+		Algorithm::Sha256 => {
+			let mut hasher = Sha256::new();
+			std::io::copy(&mut file, &mut hasher)?;
+			hex(&hasher.finalize())
+		},
+		Algorithm::Md5 => {
+			let mut hasher = Md5::new();
+			std::io::copy(&mut file, &mut hasher)?;
+			hex(&hasher.finalize())
+		},
+		Algorithm::Blake3 => {
+			let mut hasher = blake3::Hasher::new();
+			std::io::copy(&mut file, &mut hasher)?;
+			hasher.finalize().to_hex().to_string()
+		},
+	})
+}
+
+impl ChecksumCache {
+	/// Returns the digest of `path`, hashing it on a blocking thread unless this exact version of
+	/// the file was hashed before.
+	pub async fn digest(&self, path: &Path, algorithm: Algorithm) -> std::io::Result<String> {
+		let metadata = tokio::fs::metadata(path).await?;
+		let key = Key {
+			path: path.to_path_buf(),
+			modified: metadata.modified()?,
+			size: metadata.len(),
+			algorithm,
+		};
+		if let Some(digest) = self.digests.lock().unwrap().get(&key) {
+			return Ok(digest.clone());
+		}
+
+		let owned = path.to_path_buf();
+		let digest = actix_web::rt::task::spawn_blocking(move || hash_file(&owned, algorithm))
+			.await
+			.map_err(std::io::Error::other)??;
+
+		let mut digests = self.digests.lock().unwrap();
+		if digests.len() >= MAX_CACHED {
+			digests.clear();
+		}
+		digests.insert(key, digest.clone());
+		Ok(digest)
+	}
+
+	/// Forgets every digest, as if no file had been hashed yet.
+	pub fn clear(&self) {
+		self.digests.lock().unwrap().clear();
+	}
+}
+
+/// Answers `?hash=<algorithm>` for the file at `path` with its hex digest.
+pub async fn response(cache: &ChecksumCache, path: &Path, algorithm: &str) -> Result<HttpResponse> {
+	let Some(algorithm) = Algorithm::parse(algorithm) else {
+		return Ok(HttpResponse::BadRequest().body("Unsupported hash, expected sha256, md5 or blake3"));
+	};
+	let digest = cache.digest(path, algorithm).await.map_err(ErrorInternalServerError)?;
+	Ok(HttpResponse::Ok()
+		.content_type("text/plain")
+		.body(format!("{digest}\n")))
+}
@@ -0,0 +1,40 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Programs putting what they read on the clipboard, with their arguments, tried in turn.
+const PROGRAMS: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+	&[("pbcopy", &[])]
+} else if cfg!(windows) {
+	&[("clip", &[])]
+} else {
+	&[
+		("wl-copy", &[]),
+		("xclip", &["-selection", "clipboard"]),
+		("xsel", &["--clipboard", "--input"]),
+	]
+};
+
+/// Puts `text` on the clipboard through the first of the platform's clipboard programs that is
+/// installed and succeeds.
+pub fn copy(text: &str) -> std::io::Result<()> {
+	for (program, args) in PROGRAMS {
+		let Ok(mut child) = Command::new(program)
+			.args(*args)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn()
+		else {
+			continue;
+		};
+		let written = child.stdin.take().map(|mut stdin| stdin.write_all(text.as_bytes()));
+		if child.wait()?.success() && matches!(written, Some(Ok(()))) {
+			return Ok(());
+		}
+	}
+	let names: Vec<_> = PROGRAMS.iter().map(|(program, _)| *program).collect();
+	Err(std::io::Error::new(
+		std::io::ErrorKind::NotFound,
+		format!("none of {} is available", names.join(", ")),
+	))
+}
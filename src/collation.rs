@@ -0,0 +1,67 @@
+use icu_normalizer::DecomposingNormalizer;
+use std::cmp::Ordering;
+
+/// Whether `c` is one of the combining marks NFD splits accents off letters into.
+const fn is_combining(c: char) -> bool {
+	matches!(
+		c,
+		'\u{300}'..='\u{36f}' | '\u{1ab0}'..='\u{1aff}' | '\u{1dc0}'..='\u{1dff}' | '\u{20d0}'..='\u{20ff}' | '\u{fe20}'..='\u{fe2f}'
+	)
+}
+
+/// `name` lowercased and with the accents taken off its letters, as names are compared for
+/// sorting before telling apart those that only differ in those.
+fn fold(name: &str) -> Vec<char> {
+	const NFD: DecomposingNormalizer = DecomposingNormalizer::new_nfd();
+	NFD.normalize(name)
+		.chars()
+		.filter(|&c| !is_combining(c))
+		.flat_map(char::to_lowercase)
+		.collect()
+}
+
+/// The end of the run of digits starting at `start`.
+fn digits_end(chars: &[char], start: usize) -> usize {
+	chars[start..]
+		.iter()
+		.position(|c| !c.is_ascii_digit())
+		.map_or(chars.len(), |len| start + len)
+}
+
+/// Compares `a` and `b` character by character, except for runs of digits, compared by the
+/// numbers they spell.
+fn compare_numbers_by_value(a: &[char], b: &[char]) -> Ordering {
+	let (mut i, mut j) = (0, 0);
+	while i < a.len() && j < b.len() {
+		if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+			let (a_end, b_end) = (digits_end(a, i), digits_end(b, j));
+			let a_number = &a[i..a_end];
+			let b_number = &b[j..b_end];
+			let a_number = &a_number[a_number.iter().take_while(|&&c| c == '0').count()..];
+			let b_number = &b_number[b_number.iter().take_while(|&&c| c == '0').count()..];
+			let order = a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(b_number));
+			if order != Ordering::Equal {
+				return order;
+			}
+			(i, j) = (a_end, b_end);
+			continue;
+		}
+		match a[i].cmp(&b[j]) {
+			Ordering::Equal => (i, j) = (i + 1, j + 1),
+			order => return order,
+		}
+	}
+	(a.len() - i).cmp(&(b.len() - j))
+}
+
+/// Orders names as people read them: regardless of case and accents, so `Émile` sits next to
+/// `emile`, and with numbers compared by value, so `file2.png` comes before `file10.png`. Names
+/// only differing in those are then ordered by their characters.
+pub fn natural(a: &str, b: &str) -> Ordering {
+	compare_numbers_by_value(&fold(a), &fold(b)).then_with(|| a.cmp(b))
+}
+
+/// Orders names case-insensitively, character by character.
+pub fn lexicographic(a: &str, b: &str) -> Ordering {
+	a.to_lowercase().cmp(&b.to_lowercase())
+}
@@ -0,0 +1,404 @@
+use clap::{Arg, ArgAction, Command, ValueHint};
+use std::fmt::Write;
+
+use crate::structs::Shell;
+
+/// The script completing the options and subcommands of `command` in `shell`.
+pub fn script(shell: Shell, mut command: Command) -> String {
+	command.build();
+	match shell {
+		Shell::Bash => bash(&command),
+		Shell::Zsh => zsh(&command),
+		Shell::Fish => fish(&command),
+	}
+}
+
+/// How the values an argument takes are completed.
+enum Values {
+	None,
+	Any,
+	Paths,
+	Directories,
+	OneOf(Vec<String>),
+}
+
+fn values(arg: &Arg) -> Values {
+	if !arg.get_action().takes_values() {
+		return Values::None;
+	}
+	let possible: Vec<_> = arg
+		.get_possible_values()
+		.iter()
+		.filter(|value| !value.is_hide_set())
+		.map(|value| value.get_name().to_string())
+		.collect();
+	if !possible.is_empty() {
+		return Values::OneOf(possible);
+	}
+	match arg.get_value_hint() {
+		ValueHint::AnyPath | ValueHint::FilePath | ValueHint::ExecutablePath => Values::Paths,
+		ValueHint::DirPath => Values::Directories,
+		_ => Values::Any,
+	}
+}
+
+fn options(command: &Command) -> impl Iterator<Item = &Arg> {
+	command
+		.get_arguments()
+		.filter(|arg| !arg.is_hide_set() && !arg.is_positional())
+}
+
+fn positionals(command: &Command) -> impl Iterator<Item = &Arg> {
+	command.get_positionals().filter(|arg| !arg.is_hide_set())
+}
+
+/// The subcommands of `command`, leaving out `help`, which is no use to complete for.
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+	command
+		.get_subcommands()
+		.filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+/// The first line of what `--help` says about `arg`, without the default it mentions.
+fn help(arg: &Arg) -> String {
+	let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+	let line = help.lines().next().unwrap_or_default();
+	line.split(" [default").next().unwrap_or_default().trim().to_string()
+}
+
+fn about(command: &Command) -> String {
+	command.get_about().map(ToString::to_string).unwrap_or_default()
+}
+
+/// The `--long` and `-s` names of `arg`, with its aliases.
+fn flags(arg: &Arg) -> Vec<String> {
+	let mut flags: Vec<_> = arg
+		.get_long_and_visible_aliases()
+		.into_iter()
+		.flatten()
+		.map(|long| format!("--{long}"))
+		.collect();
+	flags.extend(
+		arg.get_short_and_visible_aliases()
+			.into_iter()
+			.flatten()
+			.map(|short| format!("-{short}")),
+	);
+	flags
+}
+
+/// The bash completing the options, subcommands and first positional argument of `command`.
+fn bash_command(out: &mut String, command: &Command) {
+	let mut taking_values = String::new();
+	for arg in options(command) {
+		let compgen = match values(arg) {
+			Values::None => continue,
+			Values::Any => String::new(),
+			Values::Paths => "\t\t\t\tCOMPREPLY=($(compgen -f -- \"$cur\"))\n".to_string(),
+			Values::Directories => "\t\t\t\tCOMPREPLY=($(compgen -d -- \"$cur\"))\n".to_string(),
+			Values::OneOf(possible) => {
+				format!(
+					"\t\t\t\tCOMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+					possible.join(" ")
+				)
+			},
+		};
+		let _ = writeln!(
+			taking_values,
+			"\t\t\t{})\n{compgen}\t\t\t\treturn\n\t\t\t\t;;",
+			flags(arg).join("|")
+		);
+	}
+	if !taking_values.is_empty() {
+		let _ = write!(out, "\t\tcase \"$prev\" in\n{taking_values}\t\tesac\n");
+	}
+	let words: Vec<_> = options(command).flat_map(flags).collect();
+	let _ = writeln!(
+		out,
+		"\t\tif [[ \"$cur\" == -* ]]; then\n\t\t\tCOMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n\t\t\treturn\n\t\tfi",
+		words.join(" ")
+	);
+	if subcommands(command).next().is_some() {
+		let names: Vec<_> = subcommands(command).map(Command::get_name).collect();
+		let _ = writeln!(
+			out,
+			"\t\tif [[ -z \"$subcommand\" ]]; then\n\t\t\tCOMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n\t\tfi",
+			names.join(" ")
+		);
+	}
+	for arg in positionals(command).take(1) {
+		match values(arg) {
+			Values::Paths => out.push_str("\t\tCOMPREPLY+=($(compgen -f -- \"$cur\"))\n"),
+			Values::Directories => out.push_str("\t\tCOMPREPLY+=($(compgen -d -- \"$cur\"))\n"),
+			Values::OneOf(possible) => {
+				let _ = writeln!(
+					out,
+					"\t\tCOMPREPLY+=($(compgen -W \"{}\" -- \"$cur\"))",
+					possible.join(" ")
+				);
+			},
+			Values::None | Values::Any => {},
+		}
+	}
+}
+
+fn bash(command: &Command) -> String {
+	let name = command.get_name();
+	let function = format!("_{}", name.replace('-', "_"));
+	let names: Vec<_> = subcommands(command).map(Command::get_name).collect();
+	let mut out = format!(
+		"{function}() {{\n\tlocal cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n\
+		 \tlocal subcommand=\"\" i\n\tfor ((i = 1; i < COMP_CWORD; i++)); do\n\t\tcase \"${{COMP_WORDS[i]}}\" in\n\
+		 \t\t\t{})\n\t\t\t\tsubcommand=\"${{COMP_WORDS[i]}}\"\n\t\t\t\tbreak\n\t\t\t\t;;\n\t\tesac\n\tdone\n\
+		 \tCOMPREPLY=()\n\tcase \"$subcommand\" in\n",
+		names.join("|")
+	);
+	for subcommand in subcommands(command) {
+		let _ = writeln!(out, "\t{})", subcommand.get_name());
+		bash_command(&mut out, subcommand);
+		out.push_str("\t\t;;\n");
+	}
+	out.push_str("\t*)\n");
+	bash_command(&mut out, command);
+	out.push_str("\t\t;;\n\tesac\n}\n\n");
+	let _ = writeln!(out, "complete -F {function} -o filenames {name}");
+	out
+}
+
+/// `text` for putting between single quotes in zsh, where brackets and colons also separate
+/// the parts of an `_arguments` spec.
+fn zsh_quote(text: &str) -> String {
+	text.replace('\'', "'\\''")
+		.replace('[', "\\[")
+		.replace(']', "\\]")
+		.replace(':', "\\:")
+}
+
+fn zsh_action(values: Values) -> String {
+	match values {
+		Values::None | Values::Any => String::new(),
+		Values::Paths => "_files".to_string(),
+		Values::Directories => "_files -/".to_string(),
+		Values::OneOf(possible) => format!("({})", possible.join(" ")),
+	}
+}
+
+/// The `_arguments` specs of the options and positional arguments of `command`.
+fn zsh_specs(command: &Command) -> Vec<String> {
+	let mut specs = Vec::new();
+	for arg in options(command) {
+		let repeated = matches!(arg.get_action(), ArgAction::Append | ArgAction::Count);
+		let flags = flags(arg);
+		let takes_values = arg.get_action().takes_values();
+		let names: Vec<_> = flags
+			.iter()
+			.map(|flag| match (takes_values, flag.starts_with("--")) {
+				(false, _) => flag.clone(),
+				(true, true) => format!("{flag}="),
+				(true, false) => format!("{flag}+"),
+			})
+			.collect();
+		let exclusive = match (repeated, flags.len()) {
+			(true, _) => "*".to_string(),
+			(false, 1) => String::new(),
+			(false, _) => format!("({})", flags.join(" ")),
+		};
+		let mut spec = format!("[{}]", zsh_quote(&help(arg)));
+		if takes_values {
+			let value_name = arg
+				.get_value_names()
+				.and_then(|names| names.first())
+				.map_or_else(|| arg.get_id().to_string().to_uppercase(), ToString::to_string);
+			let _ = write!(spec, ":{}:{}", zsh_quote(&value_name), zsh_action(values(arg)));
+		}
+		specs.extend(names.iter().map(|name| format!("'{exclusive}{name}{spec}'")));
+	}
+	for (i, arg) in positionals(command).enumerate() {
+		specs.push(format!(
+			"'{}:{}:{}'",
+			i + 1,
+			zsh_quote(&help(arg)),
+			zsh_action(values(arg))
+		));
+	}
+	specs
+}
+
+fn zsh(command: &Command) -> String {
+	let name = command.get_name();
+	let function = format!("_{}", name.replace('-', "_"));
+	let mut out =
+		format!("#compdef {name}\n\n{function}() {{\n\tlocal context state state_descr line\n\ttypeset -A opt_args\n");
+	let mut specs = zsh_specs(command);
+	// The first word is a subcommand or the directory to serve.
+	specs.retain(|spec| !spec.starts_with("'1:"));
+	specs.push("'1: :->first'".to_string());
+	specs.push("'*::: :->rest'".to_string());
+	let _ = writeln!(out, "\t_arguments -s -S -C \\\n\t\t{}\n", specs.join(" \\\n\t\t"));
+	let commands: Vec<_> = subcommands(command)
+		.map(|subcommand| format!("'{}:{}'", subcommand.get_name(), zsh_quote(&about(subcommand))))
+		.collect();
+	let _ = writeln!(
+		out,
+		"\tcase $state in\n\tfirst)\n\t\tlocal -a subcommands=(\n\t\t\t{}\n\t\t)",
+		commands.join("\n\t\t\t")
+	);
+	out.push_str("\t\t_describe -t subcommands subcommand subcommands");
+	if let Some(arg) = positionals(command).next() {
+		let _ = write!(out, "\n\t\t{}", zsh_action(values(arg)));
+	}
+	// The subcommand's own `_arguments` takes it for the command name.
+	out.push_str(
+		"\n\t\t;;\n\trest)\n\t\twords=($line[1] \"${words[@]}\")\n\t\t(( CURRENT += 1 ))\n\t\tcase $line[1] in\n",
+	);
+	for subcommand in subcommands(command) {
+		let specs = zsh_specs(subcommand);
+		let _ = writeln!(out, "\t\t{})", subcommand.get_name());
+		if !specs.is_empty() {
+			let _ = writeln!(
+				out,
+				"\t\t\t_arguments -s -S \\\n\t\t\t\t{}",
+				specs.join(" \\\n\t\t\t\t")
+			);
+		}
+		out.push_str("\t\t\t;;\n");
+	}
+	let _ = write!(out, "\t\tesac\n\t\t;;\n\tesac\n}}\n\n{function} \"$@\"\n");
+	out
+}
+
+fn fish_quote(text: &str) -> String {
+	format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// The `complete` lines of what `command` takes, where `condition` holds.
+fn fish_command(out: &mut String, name: &str, condition: &str, command: &Command) {
+	for arg in options(command) {
+		let mut line = format!("complete -c {name} -n {}", fish_quote(condition));
+		for long in arg.get_long_and_visible_aliases().into_iter().flatten() {
+			let _ = write!(line, " -l {long}");
+		}
+		for short in arg.get_short_and_visible_aliases().into_iter().flatten() {
+			let _ = write!(line, " -s {short}");
+		}
+		match values(arg) {
+			Values::None => {},
+			Values::Any => line.push_str(" -x"),
+			Values::Paths => line.push_str(" -r -F"),
+			Values::Directories => line.push_str(" -x -a '(__fish_complete_directories)'"),
+			Values::OneOf(possible) => {
+				let _ = write!(line, " -x -a {}", fish_quote(&possible.join(" ")));
+			},
+		}
+		let help = help(arg);
+		if !help.is_empty() {
+			let _ = write!(line, " -d {}", fish_quote(&help));
+		}
+		let _ = writeln!(out, "{line}");
+	}
+	if let Some(arg) = positionals(command).next() {
+		let arguments = match values(arg) {
+			Values::OneOf(possible) => Some(fish_quote(&possible.join(" "))),
+			Values::Directories => Some("'(__fish_complete_directories)'".to_string()),
+			_ => None,
+		};
+		if let Some(arguments) = arguments {
+			let _ = writeln!(out, "complete -c {name} -n {} -f -a {arguments}", fish_quote(condition));
+		}
+	}
+}
+
+fn fish(command: &Command) -> String {
+	let name = command.get_name();
+	let mut out = String::new();
+	fish_command(&mut out, name, "__fish_use_subcommand", command);
+	for subcommand in subcommands(command) {
+		let _ = writeln!(
+			out,
+			"complete -c {name} -n '__fish_use_subcommand' -f -a {} -d {}",
+			subcommand.get_name(),
+			fish_quote(&about(subcommand))
+		);
+	}
+	for subcommand in subcommands(command) {
+		let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+		fish_command(&mut out, name, &condition, subcommand);
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::structs::Cli;
+	use clap::CommandFactory;
+
+	/// The visible long flags of the command and of each subcommand, with the subcommand's name.
+	fn long_flags() -> Vec<(Option<String>, String)> {
+		let mut command = Cli::command();
+		command.build();
+		let mut flags: Vec<_> = options(&command)
+			.flat_map(|arg| arg.get_long_and_visible_aliases().into_iter().flatten())
+			.map(|long| (None, long.to_string()))
+			.collect();
+		for subcommand in subcommands(&command) {
+			flags.extend(
+				options(subcommand)
+					.flat_map(|arg| arg.get_long_and_visible_aliases().into_iter().flatten())
+					.map(|long| (Some(subcommand.get_name().to_string()), long.to_string())),
+			);
+		}
+		flags
+	}
+
+	fn words(script: &str) -> Vec<&str> {
+		script
+			.split(|c: char| c.is_whitespace() || "'\"|()=[*".contains(c))
+			.collect()
+	}
+
+	#[test]
+	fn bash_and_zsh_complete_every_long_flag() {
+		let flags = long_flags();
+		assert!(flags.iter().any(|(_, long)| long == "port"));
+		for shell in [Shell::Bash, Shell::Zsh] {
+			let script = script(shell, Cli::command());
+			let words = words(&script);
+			for (_, long) in &flags {
+				assert!(words.contains(&format!("--{long}").as_str()), "--{long} in {shell:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn fish_completes_every_long_flag_under_its_subcommand() {
+		let script = script(Shell::Fish, Cli::command());
+		for (subcommand, long) in long_flags() {
+			let condition = match &subcommand {
+				Some(name) => format!("'__fish_seen_subcommand_from {name}'"),
+				None => "'__fish_use_subcommand'".to_string(),
+			};
+			let found = script
+				.lines()
+				.filter(|line| line.contains(&condition))
+				.any(|line| words(line).windows(2).any(|pair| pair == ["-l", long.as_str()]));
+			assert!(found, "--{long} of {subcommand:?} in fish");
+		}
+	}
+
+	#[test]
+	fn every_subcommand_is_offered() {
+		let mut command = Cli::command();
+		command.build();
+		for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+			let script = script(shell, Cli::command());
+			for subcommand in subcommands(&command) {
+				assert!(
+					words(&script).contains(&subcommand.get_name()),
+					"{} in {shell:?}",
+					subcommand.get_name()
+				);
+			}
+		}
+	}
+}
@@ -0,0 +1,252 @@
+use actix_web::body::{BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, AcceptEncoding, ContentEncoding, Encoding, HeaderValue, Preference, Quality};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use bytes::Bytes;
+use serde::Deserialize;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use crate::config::Config;
+
+/// Bodies known to be smaller than this are sent as they are, as compressing them saves little
+/// or even makes them larger.
+const MIN_SIZE: u64 = 256;
+
+/// Content types skipped by default because they are compressed already.
+pub const ALREADY_COMPRESSED: &[&str] = &[
+	"image/jpeg",
+	"image/png",
+	"image/gif",
+	"image/webp",
+	"image/avif",
+	"video/*",
+	"audio/*",
+	"font/woff",
+	"font/woff2",
+	"application/zip",
+	"application/gzip",
+	"application/x-gzip",
+	"application/x-bzip2",
+	"application/x-xz",
+	"application/zstd",
+	"application/x-7z-compressed",
+	"application/vnd.rar",
+	"application/x-rar-compressed",
+];
+
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+	Gzip,
+	#[value(name = "br")]
+	#[serde(rename = "br")]
+	Brotli,
+	Zstd,
+}
+
+impl Algorithm {
+	pub const ALL: [Self; 3] = [Self::Brotli, Self::Zstd, Self::Gzip];
+
+	const fn encoding(self) -> ContentEncoding {
+		match self {
+			Self::Gzip => ContentEncoding::Gzip,
+			Self::Brotli => ContentEncoding::Brotli,
+			Self::Zstd => ContentEncoding::Zstd,
+		}
+	}
+
+	/// The given level clamped to what the algorithm supports, or the fast level used when none
+	/// is set.
+	fn level(self, level: Option<u32>) -> u32 {
+		let (min, max, default) = match self {
+			Self::Gzip => (0, 9, 1),
+			Self::Brotli => (0, 11, 3),
+			Self::Zstd => (1, 22, 3),
+		};
+		level.map_or(default, |level| level.clamp(min, max))
+	}
+}
+
+enum Encoder {
+	Gzip(flate2::write::GzEncoder<Vec<u8>>),
+	Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+	Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+	fn new(algorithm: Algorithm, level: Option<u32>) -> std::io::Result<Self> {
+		let level = algorithm.level(level);
+		Ok(match algorithm {
+			Algorithm::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+				Vec::new(),
+				flate2::Compression::new(level),
+			)),
+			Algorithm::Brotli => Self::Brotli(Box::new(brotli::CompressorWriter::new(
+				Vec::new(),
+				32 * 1024,
+				level,
+				22,
+			))),
+			Algorithm::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(
+				Vec::new(),
+				level.try_into().unwrap_or(3),
+			)?),
+		})
+	}
+
+	/// Compresses `data`, returning whatever output the encoder has ready so far.
+	fn write(&mut self, data: &[u8]) -> std::io::Result<Bytes> {
+		let out = match self {
+			Self::Gzip(encoder) => {
+				encoder.write_all(data)?;
+				encoder.get_mut()
+			},
+			Self::Brotli(encoder) => {
+				encoder.write_all(data)?;
+				encoder.get_mut()
+			},
+			Self::Zstd(encoder) => {
+				encoder.write_all(data)?;
+				encoder.get_mut()
+			},
+		};
+		Ok(std::mem::take(out).into())
+	}
+
+	fn finish(self) -> std::io::Result<Bytes> {
+		let out = match self {
+			Self::Gzip(encoder) => encoder.finish()?,
+			Self::Brotli(encoder) => encoder.into_inner(),
+			Self::Zstd(encoder) => encoder.finish()?,
+		};
+		Ok(out.into())
+	}
+}
+
+/// A body compressed chunk by chunk as it is sent.
+pub struct Compressed {
+	body: BoxBody,
+	encoder: Option<Encoder>,
+}
+
+impl MessageBody for Compressed {
+	type Error = Box<dyn std::error::Error>;
+
+	fn size(&self) -> BodySize {
+		BodySize::Stream
+	}
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+		let this = self.get_mut();
+		loop {
+			let Some(encoder) = this.encoder.as_mut() else {
+				return Poll::Ready(None);
+			};
+			let out = match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+				Some(Ok(chunk)) => encoder.write(&chunk),
+				Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+				None => this.encoder.take().map_or(Ok(Bytes::new()), Encoder::finish),
+			};
+			match out {
+				// Encoders hold on to small inputs, and an empty chunk would end the response.
+				Ok(out) if out.is_empty() => (),
+				Ok(out) => return Poll::Ready(Some(Ok(out))),
+				Err(e) => return Poll::Ready(Some(Err(e.into()))),
+			}
+		}
+	}
+}
+
+/// Whether `content_type` matches one of `patterns`, which are either exact types or a `type/*`.
+fn excluded(content_type: &str, patterns: &[String]) -> bool {
+	let essence = content_type.split(';').next().unwrap_or_default().trim();
+	patterns.iter().any(|pattern| match pattern.strip_suffix("/*") {
+		Some(kind) => essence.split('/').next().is_some_and(|k| k.eq_ignore_ascii_case(kind)),
+		None => essence.eq_ignore_ascii_case(pattern),
+	})
+}
+
+/// How much the client wants `encoding`, from its own entry or failing that from `*`.
+fn quality(accept: &AcceptEncoding, encoding: &Encoding) -> Option<Quality> {
+	let specific = accept
+		.iter()
+		.find(|item| matches!(&item.item, Preference::Specific(e) if e == encoding));
+	let any = || accept.iter().find(|item| item.item == Preference::Any);
+	specific.or_else(any).map(|item| item.quality)
+}
+
+/// The enabled algorithm the client ranks highest, ties going to the one listed first in the
+/// configuration; `None` when it accepts none of them.
+fn negotiate(req: &ServiceRequest, algorithms: &[Algorithm]) -> Option<Algorithm> {
+	let accept = req.get_header::<AcceptEncoding>()?;
+	let mut best: Option<(Algorithm, Quality)> = None;
+	for &algorithm in algorithms {
+		let Some(quality) = quality(&accept, &Encoding::Known(algorithm.encoding())) else {
+			continue;
+		};
+		if quality > Quality::ZERO && best.is_none_or(|(_, best)| quality > best) {
+			best = Some((algorithm, quality));
+		}
+	}
+	best.map(|(algorithm, _)| algorithm)
+}
+
+/// Middleware compressing responses with the algorithm the client prefers among those enabled.
+/// Partial and already encoded responses are left alone, as are the content types excluded in
+/// the configuration.
+pub async fn compress(
+	req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<Compressed, BoxBody>>, Error> {
+	let config = req.app_data::<web::Data<Config>>().cloned();
+	let algorithm = config
+		.as_ref()
+		.filter(|_| req.method() != Method::HEAD)
+		.and_then(|config| negotiate(&req, &config.compress_algorithms));
+
+	let res = next.call(req).await?;
+	let (Some(config), Some(algorithm)) = (config, algorithm) else {
+		return Ok(res.map_body(|_, body| EitherBody::right(body.boxed())));
+	};
+
+	let status = res.status();
+	let skip = matches!(
+		status,
+		StatusCode::PARTIAL_CONTENT | StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+	) || status.is_informational()
+		|| res.headers().contains_key(header::CONTENT_ENCODING)
+		|| res
+			.headers()
+			.get(header::CONTENT_TYPE)
+			.and_then(|v| v.to_str().ok())
+			.is_some_and(|content_type| excluded(content_type, &config.compress_exclude))
+		|| match res.response().body().size() {
+			BodySize::None => true,
+			BodySize::Sized(size) => size < MIN_SIZE,
+			BodySize::Stream => false,
+		};
+	let encoder = if skip {
+		None
+	} else {
+		Encoder::new(algorithm, config.compress_level).ok()
+	};
+
+	Ok(res.map_body(|head, body| match encoder {
+		Some(encoder) => {
+			head.headers_mut()
+				.insert(header::CONTENT_ENCODING, algorithm.encoding().to_header_value());
+			head.headers_mut()
+				.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+			head.headers_mut().remove(header::CONTENT_LENGTH);
+			EitherBody::left(Compressed {
+				body: body.boxed(),
+				encoder: Some(encoder),
+			})
+		},
+		None => EitherBody::right(body.boxed()),
+	}))
+}
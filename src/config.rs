@@ -0,0 +1,1106 @@
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::access_log::LogFormat;
+use crate::compression::{Algorithm, ALREADY_COMPRESSED};
+use crate::logging::LogRotation;
+use crate::structs::{Args, ListingOrder, Theme};
+
+/// A byte count, written either as a plain integer or with a binary `K`/`M`/`G`/`T` suffix
+/// (e.g. `512M`).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "ByteSizeRepr")]
+pub struct ByteSize(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ByteSizeRepr {
+	Bytes(u64),
+	Text(String),
+}
+
+impl TryFrom<ByteSizeRepr> for ByteSize {
+	type Error = String;
+
+	fn try_from(repr: ByteSizeRepr) -> Result<Self, Self::Error> {
+		match repr {
+			ByteSizeRepr::Bytes(bytes) => Ok(Self(bytes)),
+			ByteSizeRepr::Text(text) => text.parse(),
+		}
+	}
+}
+
+impl FromStr for ByteSize {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+		let (digits, suffix) = s.split_at(split);
+		let value: u64 = digits.parse().map_err(|_| format!("Invalid size {s:?}"))?;
+
+		let suffix = suffix.trim().to_ascii_uppercase();
+		let shift = match suffix.trim_end_matches('B').trim_end_matches('I') {
+			"" => 0,
+			"K" => 10,
+			"M" => 20,
+			"G" => 30,
+			"T" => 40,
+			_ => return Err(format!("Invalid size suffix in {s:?}")),
+		};
+
+		value
+			.checked_mul(1 << shift)
+			.map(Self)
+			.ok_or_else(|| format!("Size {s:?} is too large"))
+	}
+}
+
+/// A duration in seconds, written either as a plain integer or with an `s`/`m`/`h`/`d` suffix
+/// (e.g. `30m`).
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "SecondsRepr")]
+pub struct Seconds(pub u64);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecondsRepr {
+	Seconds(u64),
+	Text(String),
+}
+
+impl TryFrom<SecondsRepr> for Seconds {
+	type Error = String;
+
+	fn try_from(repr: SecondsRepr) -> Result<Self, Self::Error> {
+		match repr {
+			SecondsRepr::Seconds(seconds) => Ok(Self(seconds)),
+			SecondsRepr::Text(text) => text.parse(),
+		}
+	}
+}
+
+impl FromStr for Seconds {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+		let (digits, suffix) = s.split_at(split);
+		let value: u64 = digits.parse().map_err(|_| format!("Invalid duration {s:?}"))?;
+
+		let unit = match suffix.trim().to_ascii_lowercase().as_str() {
+			"" | "s" => 1,
+			"m" => 60,
+			"h" => 60 * 60,
+			"d" => 24 * 60 * 60,
+			_ => return Err(format!("Invalid duration suffix in {s:?}")),
+		};
+
+		value
+			.checked_mul(unit)
+			.map(Self)
+			.ok_or_else(|| format!("Duration {s:?} is too long"))
+	}
+}
+
+impl Seconds {
+	pub const fn duration(self) -> std::time::Duration {
+		std::time::Duration::from_secs(self.0)
+	}
+}
+
+/// A configuration file with every setting commented out, as `gen-config` prints it.
+pub const TEMPLATE: &str = include_str!("../templates/config.toml");
+
+/// Largest request body read in full before it is passed on, to proxied servers and CGI
+/// scripts, when `max_upload_size` sets no limit of its own.
+pub const MAX_BUFFERED_BODY: u64 = 64 * 1024 * 1024;
+
+/// The `Content-Security-Policy` sent with `--security-headers` unless another is given. Inline
+/// styles stay allowed for the listing's stylesheet and the video player.
+pub const DEFAULT_CSP: &str = "default-src 'self'; img-src 'self' data: blob:; media-src 'self' blob:; \
+	font-src 'self' data:; style-src 'self' 'unsafe-inline'; worker-src 'self' blob:; object-src 'none'; \
+	base-uri 'self'; frame-ancestors 'none'";
+
+/// An address or CIDR block (e.g. `192.168.1.0/24`), as given to `--allow-ip` and `--deny-ip`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct IpRange(pub IpNet);
+
+impl IpRange {
+	pub fn contains(&self, ip: &IpAddr) -> bool {
+		self.0.contains(ip)
+	}
+}
+
+impl TryFrom<String> for IpRange {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl FromStr for IpRange {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		s.parse::<IpNet>()
+			.or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+			.map(|net| Self(net.trunc()))
+			.map_err(|_| format!("Invalid address or CIDR block {s:?}"))
+	}
+}
+
+/// An HTTP error status, `400` to `599`, keying the pages given to `--error-page`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct ErrorStatus(pub u16);
+
+impl TryFrom<String> for ErrorStatus {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+impl FromStr for ErrorStatus {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.trim()
+			.parse()
+			.ok()
+			.filter(|code| (400..600).contains(code))
+			.map(Self)
+			.ok_or_else(|| format!("Invalid error status {s:?}, expected 400 to 599"))
+	}
+}
+
+/// Parses a `STATUS=FILE` error page mapping as given to `--error-page`.
+pub fn parse_error_page(s: &str) -> Result<(ErrorStatus, PathBuf), String> {
+	let (status, file) = s
+		.split_once('=')
+		.ok_or_else(|| format!("Expected STATUS=FILE, got {s:?}"))?;
+	Ok((status.parse()?, PathBuf::from(file)))
+}
+
+/// A directory served beneath a URL path of its own, next to the root, as given to `--mount`
+/// in the form `PATH=DIR[,read-only][,auth=USER:PASS]...`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Mount {
+	/// Such as `/music`.
+	pub path: String,
+	pub root: PathBuf,
+	/// Refuses uploads and deletions here even when they are allowed elsewhere.
+	#[serde(default)]
+	pub read_only: bool,
+	/// `user:pass` entries replacing the server's own credentials here.
+	#[serde(default)]
+	pub auth: Vec<String>,
+}
+
+impl FromStr for Mount {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (path, rest) = s
+			.split_once('=')
+			.ok_or_else(|| format!("Expected PATH=DIR, got {s:?}"))?;
+		let mut options = rest.split(',');
+		let mut mount = Self {
+			path: path.to_string(),
+			root: PathBuf::from(options.next().unwrap_or_default()),
+			read_only: false,
+			auth: Vec::new(),
+		};
+		for option in options {
+			match option.split_once('=') {
+				None if option == "read-only" => mount.read_only = true,
+				Some(("auth", entry)) => mount.auth.push(entry.to_string()),
+				_ => return Err(format!("Unknown mount option {option:?}")),
+			}
+		}
+		Ok(mount)
+	}
+}
+
+/// An address and port listened on, as given to `--listen` in the form `[SCHEME://]ADDR:PORT`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Listener {
+	/// Such as `0.0.0.0:8080` or `[::1]:8443`.
+	pub address: std::net::SocketAddr,
+	/// Whether connections are made over TLS; when unset, exactly when TLS is configured.
+	#[serde(default)]
+	pub tls: Option<bool>,
+}
+
+impl FromStr for Listener {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (tls, address) = match s.split_once("://") {
+			Some(("http", address)) => (Some(false), address),
+			Some(("https", address)) => (Some(true), address),
+			Some((scheme, _)) => return Err(format!("Expected http or https, got {scheme:?}")),
+			None => (None, s),
+		};
+		let address = address
+			.trim_end_matches('/')
+			.parse()
+			.map_err(|_| format!("Expected ADDR:PORT, got {address:?}"))?;
+		Ok(Self { address, tls })
+	}
+}
+
+/// A path forwarded to another server, as given to `--proxy` in the form `PATH=URL`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Proxy {
+	/// Such as `/api`.
+	pub path: String,
+	/// Such as `http://localhost:3000`. Requests keep their path unless it has one of its own,
+	/// which then takes the place of `path`.
+	pub upstream: String,
+}
+
+impl FromStr for Proxy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (path, upstream) = s
+			.split_once('=')
+			.ok_or_else(|| format!("Expected PATH=URL, got {s:?}"))?;
+		Ok(Self {
+			path: path.to_string(),
+			upstream: upstream.to_string(),
+		})
+	}
+}
+
+/// A header added to responses, as given to `--header` in the form `[GLOB ]Name: value`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CustomHeader {
+	/// Limits the header to request paths matching this glob, which like an `--ignore` pattern
+	/// matches file names at any depth unless it contains a slash.
+	#[serde(default)]
+	pub path: Option<String>,
+	pub name: String,
+	pub value: String,
+}
+
+impl FromStr for CustomHeader {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (left, value) = s
+			.split_once(':')
+			.ok_or_else(|| format!("Expected [GLOB ]Name: value, got {s:?}"))?;
+		let (path, name) = match left.trim().rsplit_once(char::is_whitespace) {
+			Some((path, name)) => (Some(path.trim().to_string()), name),
+			None => (None, left.trim()),
+		};
+		Ok(Self {
+			path,
+			name: name.to_string(),
+			value: value.trim().to_string(),
+		})
+	}
+}
+
+/// A `[[redirects]]` rule sending requests for `from` to `to`. A trailing `*` on `from` matches
+/// the rest of the path, which then takes the place of a trailing `*` on `to`.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Redirect {
+	pub from: String,
+	pub to: String,
+	/// One of `301`, `302`, `303`, `307` or `308`.
+	#[serde(default = "Redirect::default_status")]
+	pub status: u16,
+}
+
+impl Redirect {
+	const fn default_status() -> u16 {
+		301
+	}
+}
+
+/// A `[[rewrites]]` rule serving paths matching the regex `pattern` as if `to` had been asked
+/// for, with `$1` and the like replaced by what the groups captured.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Rewrite {
+	pub pattern: String,
+	pub to: String,
+}
+
+/// Brings a URL prefix into the form `/files`, which links are built from by appending `/path`,
+/// with the empty string standing for no prefix. `None` when it has characters that would need
+/// escaping.
+pub fn normalize_prefix(prefix: &str) -> Option<String> {
+	let prefix = prefix.trim().trim_end_matches('/');
+	if !prefix.chars().all(|c| c.is_ascii_alphanumeric() || "-._~/".contains(c)) {
+		return None;
+	}
+	Some(if prefix.is_empty() || prefix.starts_with('/') {
+		prefix.to_string()
+	} else {
+		format!("/{prefix}")
+	})
+}
+
+/// Parses an `EXT=TYPE` content type mapping as given to `--mime-map`.
+pub fn parse_mime_mapping(s: &str) -> Result<(String, String), String> {
+	let (extension, mime) = s
+		.split_once('=')
+		.ok_or_else(|| format!("Expected EXT=TYPE, got {s:?}"))?;
+	let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+	if extension.is_empty() {
+		return Err(format!("Missing extension in {s:?}"));
+	}
+	Ok((extension, mime.trim().to_string()))
+}
+
+/// Parses permissions given in octal, such as `660`.
+pub fn parse_mode(s: &str) -> Result<u32, String> {
+	u32::from_str_radix(s.trim_start_matches("0o"), 8)
+		.ok()
+		.filter(|mode| *mode <= 0o7777)
+		.ok_or_else(|| format!("Expected octal permissions such as 660, got {s:?}"))
+}
+
+/// Parses a `DIR=SIZE` quota specification as given to `--quota`.
+pub fn parse_quota(s: &str) -> Result<(PathBuf, ByteSize), String> {
+	let (dir, size) = s
+		.split_once('=')
+		.ok_or_else(|| format!("Expected DIR=SIZE, got {s:?}"))?;
+	Ok((PathBuf::from(dir.trim_matches('/')), size.parse()?))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	pub root: PathBuf,
+	pub port: u16,
+	/// How many ports above `port` are tried in turn when it is in use; none when 0, the default.
+	/// `port` 0 listens on any that is free.
+	pub port_retry: u16,
+	/// Addresses listened on, each on `port`; only localhost when empty. `::` takes IPv4
+	/// connections as well unless `0.0.0.0` is also given.
+	pub bind: Vec<IpAddr>,
+	/// Listened on instead of `port` at the `bind` addresses when given, such as one address for
+	/// plain HTTP and another for HTTPS.
+	pub listen: Vec<Listener>,
+	/// Whether plain HTTP listeners also take HTTP/2 from clients that start with it (h2c with
+	/// prior knowledge; `Upgrade: h2c` is answered over HTTP/1.1). The Unix socket never does.
+	pub h2c: bool,
+	/// Whether HTTPS listeners offer HTTP/2 through ALPN. Without it, every listener speaks
+	/// HTTP/1.1 only.
+	pub http2: bool,
+	/// A Unix socket listened on for a reverse proxy on the same machine, in place of `port`
+	/// unless `bind` or `listen` are also given.
+	pub unix_socket: Option<PathBuf>,
+	/// Permissions the socket is created with, such as `0o660` to let the proxy's group in.
+	pub unix_socket_mode: Option<u32>,
+	/// Whether the server is advertised on the local network over mDNS, as `_http._tcp` or
+	/// `_https._tcp` at `<host name>.local`.
+	pub mdns: bool,
+	/// The instance name it is advertised under; the host name when unset.
+	pub mdns_name: Option<String>,
+	/// Whether a QR code of each URL others on the network can open is printed at startup, for
+	/// a phone to scan.
+	pub qr: bool,
+	/// Whether the URL others on the network can open, or else the first one listened at, is put
+	/// on the clipboard at startup.
+	pub copy_url: bool,
+	/// How long responses being sent may take to finish once the server is told to stop, by
+	/// Ctrl+C or SIGTERM, before they are cut off.
+	pub shutdown_seconds: u64,
+	/// How long the server runs before stopping by itself, for sharing sessions that should not
+	/// outlive their purpose.
+	pub timeout: Option<Seconds>,
+	/// How long the server may go without requests before stopping by itself. Time spent sending
+	/// a response does not count.
+	pub exit_after_idle: Option<Seconds>,
+	/// How many files may be downloaded in full before the server stops by itself, such as 1 to
+	/// hand over a single file.
+	pub max_downloads: Option<u64>,
+	/// Worker threads handling connections; one per CPU core when unset.
+	pub workers: Option<usize>,
+	/// How long an idle connection is kept open for another request; 0 closes each after its
+	/// response. 5 seconds when unset.
+	pub keep_alive: Option<Seconds>,
+	/// How long a client may take to send a request's headers before it is answered with
+	/// `408 Request Timeout`; 0 waits indefinitely. 5 seconds when unset.
+	pub client_request_timeout: Option<Seconds>,
+	/// How long a connection is given to close cleanly once its last response is sent. 1 second
+	/// when unset.
+	pub client_disconnect_timeout: Option<Seconds>,
+	pub tls_cert: Option<PathBuf>,
+	pub tls_key: Option<PathBuf>,
+	pub tls_self_signed: bool,
+	pub acme: Vec<String>,
+	pub acme_email: Vec<String>,
+	pub acme_cache: Option<PathBuf>,
+	pub acme_staging: bool,
+	/// Sends `Strict-Transport-Security` over HTTPS, telling browsers to use nothing else for
+	/// `hsts_max_age` seconds.
+	pub hsts: bool,
+	pub hsts_max_age: u64,
+	pub hsts_include_subdomains: bool,
+	/// Plain HTTP port redirecting every request to HTTPS.
+	pub redirect_http_port: Option<u16>,
+	pub auth: Vec<String>,
+	pub token: Option<String>,
+	pub share_secret: String,
+	/// Serves symlinks wherever they point; otherwise only those resolving beneath `root` are.
+	pub follow_symlinks: bool,
+	/// Lists and serves dotfiles, which are otherwise treated as if they did not exist.
+	pub hidden: bool,
+	/// Patterns, written as in a `.gitignore`, of paths kept from clients.
+	pub ignore: Vec<String>,
+	/// Also keeps what the root's `.gitignore` lists from clients.
+	pub use_gitignore: bool,
+	/// Served in place of the listing of a directory containing one of them, the first found
+	/// winning; directories are always listed when this is empty.
+	pub index_files: Vec<String>,
+	/// Answers requests for missing paths without an extension with the root's index file, so
+	/// a single-page app can route them itself.
+	pub spa: bool,
+	/// Lists directories without an index file. Otherwise they, and the tree and search APIs,
+	/// are answered as if they did not exist.
+	pub dirlist: bool,
+	/// Files shown to browsers instead of the built-in page for each error status. They are read
+	/// at startup and need not lie beneath `root`.
+	pub error_pages: HashMap<ErrorStatus, PathBuf>,
+	/// Content types by lowercase extension, without the dot, taking the place of the built-in
+	/// guesses.
+	pub mime_types: HashMap<String, String>,
+	/// An `/etc/mime.types`-style file read into `mime_types` at startup, whose entries yield to
+	/// those given there directly.
+	pub mime_types_file: Option<PathBuf>,
+	/// Sent for files without an extension instead of `application/octet-stream`.
+	pub default_mime_type: Option<String>,
+	/// Sent with text responses instead of the charset sniffed from the start of each file.
+	pub charset: Option<String>,
+	/// Extensions, without the dot, of files always sent as attachments rather than shown, as
+	/// with `?download`.
+	pub force_download: Vec<String>,
+	/// Path every route is mounted beneath, such as `/files`; the server's own root when empty.
+	pub route_prefix: String,
+	/// Further directories, each served beneath its own path inside the route prefix.
+	pub mounts: Vec<Mount>,
+	/// Roots served instead of `root`, and without the mounts, to requests for each host name;
+	/// requests for any other host get `root`.
+	pub hosts: HashMap<String, PathBuf>,
+	/// Paths beneath the route prefix handed to other servers rather than served from the root.
+	pub proxies: Vec<Proxy>,
+	/// Checked in order against each request's path, before `rewrites`; the first match answers.
+	pub redirects: Vec<Redirect>,
+	/// Applied to each request's path before it is routed; only the first match is.
+	pub rewrites: Vec<Rewrite>,
+	pub allow_upload: bool,
+	pub allow_delete: bool,
+	pub webdav: bool,
+	/// Directory beneath the root, such as `cgi-bin`, whose files are run as CGI scripts rather
+	/// than served.
+	pub cgi_dir: Option<PathBuf>,
+	pub max_upload_size: Option<ByteSize>,
+	/// Maximum total size of each listed directory (relative to the root), enforced on upload.
+	pub quotas: HashMap<PathBuf, ByteSize>,
+	pub index_content: bool,
+	pub index_dir: Option<PathBuf>,
+	pub log_format: LogFormat,
+	/// `tracing` filter directives such as `info` or `http_server_rs=debug`; `RUST_LOG` applies
+	/// when unset.
+	pub log_level: Option<String>,
+	pub log_file: Option<PathBuf>,
+	pub log_rotate: LogRotation,
+	/// Number of rotated log files kept next to `log_file`.
+	pub log_keep: usize,
+	pub metrics: bool,
+	pub admin: bool,
+	pub server_timing: bool,
+	/// Serve the whole file rather than `416` when none of the requested ranges can be satisfied,
+	/// for clients that cannot cope with the error.
+	pub lenient_ranges: bool,
+	/// Size of each read when streaming a file.
+	pub chunk_size: ByteSize,
+	/// Chunks read ahead of the one being sent, so the disk and the network are busy at the same
+	/// time; `0` reads only once the previous chunk is gone.
+	pub read_ahead: usize,
+	/// Entries per listing page when the request does not pick a `?limit=`; listings are whole
+	/// when unset.
+	pub page_size: Option<usize>,
+	/// Order of listings the request does not pick one for with `?sort=`.
+	pub sort: ListingOrder,
+	/// Palette of listings, whose light or dark variant each browser picks.
+	pub theme: Theme,
+	/// Directory whose `directory.html` and `error.html`, where present, are rendered instead of
+	/// the built-in pages.
+	pub template_dir: Option<PathBuf>,
+	/// Served at `/_static/custom.css` and `/_static/custom.js`, which listings load after their
+	/// own styles and scripts.
+	pub custom_css: Option<PathBuf>,
+	pub custom_js: Option<PathBuf>,
+	/// Directory entries whose metadata is read concurrently when listing or walking a directory.
+	pub stat_parallelism: usize,
+	pub compress: bool,
+	/// Offered to clients in this order of preference when they rank several equally.
+	pub compress_algorithms: Vec<Algorithm>,
+	/// Applied to every algorithm, clamped to its range; each one's fast default when unset.
+	pub compress_level: Option<u32>,
+	/// Content types sent uncompressed, either exact or as `type/*`.
+	pub compress_exclude: Vec<String>,
+	/// Files of at least this size are sent from a memory map rather than read in chunks, when they
+	/// are on a read-only filesystem, as a truncation under the map would crash the server.
+	pub mmap_min_size: Option<ByteSize>,
+	/// Bytes per second each response is sent at, at most.
+	pub limit_rate: Option<ByteSize>,
+	/// Bytes per second shared between all responses being sent.
+	pub limit_rate_total: Option<ByteSize>,
+	/// Requests per second allowed from each client IP on average.
+	pub rate_limit: Option<f64>,
+	/// Requests a client IP may make in a burst above `rate_limit`.
+	pub rate_limit_burst: u32,
+	/// Connections each client IP may have open at once.
+	pub max_connections_per_ip: Option<usize>,
+	/// Clients allowed in; anyone may connect when empty.
+	pub allow_ip: Vec<IpRange>,
+	/// Clients turned away even when `allow_ip` lets them in.
+	pub deny_ip: Vec<IpRange>,
+	/// Host names requests may be sent to, so pages on other sites cannot reach the server through
+	/// DNS rebinding. `*` allows any, and a name with a leading dot its subdomains as well. When
+	/// empty, `localhost`, the address listened on, `hosts`, the `acme` domains and the `mdns` name
+	/// are allowed.
+	pub allowed_hosts: Vec<String>,
+	/// Reverse proxies in front of the server whose `X-Forwarded-For` is believed when telling
+	/// who the client is; `0` ignores the header.
+	pub trusted_proxies: usize,
+	/// Failed authentication attempts after which a client is banned; nobody is when unset.
+	pub ban_after: Option<u32>,
+	/// How long bans last, which is also how long failed attempts are remembered.
+	pub ban_seconds: u64,
+	/// Adds `nosniff`, `X-Frame-Options`, `Referrer-Policy` and `content_security_policy` to
+	/// responses that do not set them.
+	pub security_headers: bool,
+	pub content_security_policy: String,
+	/// Set on every response, or those for paths matching their glob, replacing any the
+	/// response has of the same name.
+	pub headers: Vec<CustomHeader>,
+	/// `user:pass` required for the metrics endpoint instead of the regular credentials.
+	pub metrics_auth: Option<String>,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			root: PathBuf::from("."),
+			port: 8080,
+			port_retry: 0,
+			bind: Vec::new(),
+			listen: Vec::new(),
+			h2c: false,
+			http2: true,
+			unix_socket: None,
+			unix_socket_mode: None,
+			mdns: false,
+			mdns_name: None,
+			qr: false,
+			copy_url: false,
+			shutdown_seconds: 30,
+			timeout: None,
+			exit_after_idle: None,
+			max_downloads: None,
+			workers: None,
+			keep_alive: None,
+			client_request_timeout: None,
+			client_disconnect_timeout: None,
+			tls_cert: None,
+			tls_key: None,
+			tls_self_signed: false,
+			acme: Vec::new(),
+			acme_email: Vec::new(),
+			acme_cache: None,
+			acme_staging: false,
+			hsts: false,
+			hsts_max_age: 31_536_000,
+			hsts_include_subdomains: false,
+			redirect_http_port: None,
+			auth: Vec::new(),
+			token: None,
+			share_secret: String::new(),
+			follow_symlinks: false,
+			hidden: false,
+			ignore: Vec::new(),
+			use_gitignore: false,
+			index_files: vec!["index.html".to_string()],
+			spa: false,
+			dirlist: true,
+			error_pages: HashMap::new(),
+			mime_types: HashMap::new(),
+			mime_types_file: None,
+			default_mime_type: None,
+			charset: None,
+			force_download: Vec::new(),
+			route_prefix: String::new(),
+			mounts: Vec::new(),
+			hosts: HashMap::new(),
+			proxies: Vec::new(),
+			redirects: Vec::new(),
+			rewrites: Vec::new(),
+			allow_upload: false,
+			allow_delete: false,
+			webdav: false,
+			cgi_dir: None,
+			max_upload_size: None,
+			quotas: HashMap::new(),
+			index_content: false,
+			index_dir: None,
+			log_format: LogFormat::default(),
+			log_level: None,
+			log_file: None,
+			log_rotate: LogRotation::default(),
+			log_keep: 7,
+			metrics: false,
+			admin: false,
+			server_timing: false,
+			lenient_ranges: false,
+			chunk_size: ByteSize(crate::CHUNK_SIZE as u64),
+			read_ahead: 1,
+			mmap_min_size: None,
+			limit_rate: None,
+			limit_rate_total: None,
+			rate_limit: None,
+			rate_limit_burst: 20,
+			max_connections_per_ip: None,
+			allow_ip: Vec::new(),
+			deny_ip: Vec::new(),
+			allowed_hosts: Vec::new(),
+			trusted_proxies: 0,
+			ban_after: None,
+			ban_seconds: 600,
+			security_headers: false,
+			content_security_policy: DEFAULT_CSP.to_string(),
+			headers: Vec::new(),
+			page_size: None,
+			sort: ListingOrder::default(),
+			theme: Theme::default(),
+			template_dir: None,
+			custom_css: None,
+			custom_js: None,
+			stat_parallelism: 16,
+			compress: true,
+			compress_algorithms: Algorithm::ALL.to_vec(),
+			compress_level: None,
+			compress_exclude: ALREADY_COMPRESSED.iter().map(|s| (*s).to_string()).collect(),
+			metrics_auth: None,
+		}
+	}
+}
+
+impl Config {
+	pub fn load(path: &Path) -> std::io::Result<Self> {
+		let contents = std::fs::read_to_string(path)?;
+		toml::from_str(&contents).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+	}
+
+	/// Reads `mime_types_file` into `mime_types` and checks that every content type, and the
+	/// charset, parses, so lookups need not.
+	pub fn load_mime_types(&mut self) -> std::io::Result<()> {
+		self.mime_types = std::mem::take(&mut self.mime_types)
+			.into_iter()
+			.map(|(extension, mime)| (extension.trim_start_matches('.').to_ascii_lowercase(), mime))
+			.collect();
+		if let Some(path) = &self.mime_types_file {
+			let contents = std::fs::read_to_string(path).map_err(|e| {
+				std::io::Error::new(e.kind(), format!("Failed to read MIME types {}: {e}", path.display()))
+			})?;
+			for line in contents.lines() {
+				let mut fields = line.split('#').next().unwrap_or_default().split_whitespace();
+				let Some(mime) = fields.next() else {
+					continue;
+				};
+				for extension in fields {
+					self.mime_types
+						.entry(extension.to_ascii_lowercase())
+						.or_insert_with(|| mime.to_string());
+				}
+			}
+		}
+
+		if let Some(mime) = self
+			.mime_types
+			.values()
+			.chain(&self.default_mime_type)
+			.find(|mime| mime.parse::<mime_guess::Mime>().is_err())
+		{
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid content type {mime:?}"),
+			));
+		}
+		if let Some(charset) = self.charset.as_ref().filter(|charset| {
+			format!("text/plain; charset={charset}")
+				.parse::<mime_guess::Mime>()
+				.is_err()
+		}) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid charset {charset:?}"),
+			));
+		}
+		Ok(())
+	}
+
+	/// Builds the effective configuration: the config file (if any) provides the base values and
+	/// any flag given on the command line takes precedence.
+	pub fn from_args(args: &Args) -> std::io::Result<Self> {
+		let mut config = match &args.config {
+			Some(path) => Self::load(path)?,
+			None => Self::default(),
+		};
+
+		if let Some(directory) = &args.directory {
+			config.root.clone_from(directory);
+		}
+		if let Some(port) = args.port {
+			config.port = port;
+		}
+		if let Some(retries) = args.port_retry {
+			config.port_retry = retries;
+		}
+		if !args.bind.is_empty() {
+			config.bind.clone_from(&args.bind);
+		}
+		config.listen.extend(args.listen.iter().copied());
+		if args.h2c {
+			config.h2c = true;
+		}
+		if args.no_http2 {
+			config.http2 = false;
+		}
+		if let Some(path) = &args.unix_socket {
+			config.unix_socket = Some(path.clone());
+		}
+		if let Some(mode) = args.unix_socket_mode {
+			config.unix_socket_mode = Some(mode);
+		}
+		if args.mdns {
+			config.mdns = true;
+		}
+		if let Some(name) = &args.mdns_name {
+			config.mdns_name = Some(name.clone());
+		}
+		if args.qr {
+			config.qr = true;
+		}
+		if args.copy_url {
+			config.copy_url = true;
+		}
+		if let Some(seconds) = args.shutdown_grace {
+			config.shutdown_seconds = seconds;
+		}
+		if let Some(timeout) = args.timeout {
+			config.timeout = Some(timeout);
+		}
+		if let Some(idle) = args.exit_after_idle {
+			config.exit_after_idle = Some(idle);
+		}
+		if args.once {
+			config.max_downloads = Some(1);
+		}
+		if let Some(downloads) = args.max_downloads {
+			config.max_downloads = Some(downloads);
+		}
+		if let Some(workers) = args.workers {
+			config.workers = Some(workers);
+		}
+		if let Some(keep_alive) = args.keep_alive {
+			config.keep_alive = Some(keep_alive);
+		}
+		if let Some(timeout) = args.client_request_timeout {
+			config.client_request_timeout = Some(timeout);
+		}
+		if let Some(timeout) = args.client_disconnect_timeout {
+			config.client_disconnect_timeout = Some(timeout);
+		}
+		if let Some(cert) = &args.tls_cert {
+			config.tls_cert = Some(cert.clone());
+		}
+		if let Some(key) = &args.tls_key {
+			config.tls_key = Some(key.clone());
+		}
+		if args.tls_self_signed {
+			config.tls_self_signed = true;
+		}
+		if !args.acme.is_empty() {
+			config.acme.clone_from(&args.acme);
+		}
+		if !args.acme_email.is_empty() {
+			config.acme_email.clone_from(&args.acme_email);
+		}
+		if let Some(cache) = &args.acme_cache {
+			config.acme_cache = Some(cache.clone());
+		}
+		if args.acme_staging {
+			config.acme_staging = true;
+		}
+		if args.hsts {
+			config.hsts = true;
+		}
+		if let Some(seconds) = args.hsts_max_age {
+			config.hsts_max_age = seconds;
+		}
+		if args.hsts_include_subdomains {
+			config.hsts_include_subdomains = true;
+		}
+		if let Some(port) = args.redirect_http {
+			config.redirect_http_port = Some(port);
+		}
+		if !args.auth.is_empty() {
+			config.auth.clone_from(&args.auth);
+		}
+
+		if let Some(token) = &args.token {
+			config.token = Some(token.clone());
+		}
+
+		if args.allow_upload {
+			config.allow_upload = true;
+		}
+		if args.allow_delete {
+			config.allow_delete = true;
+		}
+		if args.webdav {
+			config.webdav = true;
+		}
+		if let Some(dir) = &args.cgi {
+			config.cgi_dir = Some(dir.clone());
+		}
+		if let Some(size) = args.max_upload_size {
+			config.max_upload_size = Some(size);
+		}
+		config.quotas.extend(args.quota.iter().cloned());
+		if args.index_content {
+			config.index_content = true;
+		}
+		if let Some(dir) = &args.index_dir {
+			config.index_dir = Some(dir.clone());
+		}
+		if let Some(format) = args.log_format {
+			config.log_format = format;
+		}
+		if let Some(level) = &args.log_level {
+			config.log_level = Some(level.clone());
+		}
+		if let Some(path) = &args.log_file {
+			config.log_file = Some(path.clone());
+		}
+		if let Some(rotation) = args.log_rotate {
+			config.log_rotate = rotation;
+		}
+		if let Some(keep) = args.log_keep {
+			config.log_keep = keep;
+		}
+		if args.metrics {
+			config.metrics = true;
+		}
+		if args.admin {
+			config.admin = true;
+		}
+		if args.server_timing {
+			config.server_timing = true;
+		}
+		if args.lenient_ranges {
+			config.lenient_ranges = true;
+		}
+		if let Some(size) = args.chunk_size {
+			config.chunk_size = size;
+		}
+		if let Some(chunks) = args.read_ahead {
+			config.read_ahead = chunks;
+		}
+		if let Some(size) = args.page_size {
+			config.page_size = Some(size);
+		}
+		if let Some(order) = args.sort {
+			config.sort = order;
+		}
+		if let Some(theme) = args.theme {
+			config.theme = theme;
+		}
+		if let Some(dir) = &args.template_dir {
+			config.template_dir = Some(dir.clone());
+		}
+		if let Some(file) = &args.custom_css {
+			config.custom_css = Some(file.clone());
+		}
+		if let Some(file) = &args.custom_js {
+			config.custom_js = Some(file.clone());
+		}
+		if let Some(parallelism) = args.stat_parallelism {
+			config.stat_parallelism = parallelism;
+		}
+		if args.no_compress {
+			config.compress = false;
+		}
+		if !args.compress_algorithms.is_empty() {
+			config.compress_algorithms.clone_from(&args.compress_algorithms);
+		}
+		if let Some(level) = args.compress_level {
+			config.compress_level = Some(level);
+		}
+		config.compress_exclude.extend(args.compress_exclude.iter().cloned());
+		if let Some(size) = args.mmap {
+			config.mmap_min_size = Some(size);
+		}
+		if let Some(rate) = args.limit_rate {
+			config.limit_rate = Some(rate);
+		}
+		if let Some(rate) = args.limit_rate_total {
+			config.limit_rate_total = Some(rate);
+		}
+		if let Some(rate) = args.rate_limit {
+			config.rate_limit = Some(rate);
+		}
+		if let Some(burst) = args.rate_limit_burst {
+			config.rate_limit_burst = burst;
+		}
+		if let Some(max) = args.max_connections_per_ip {
+			config.max_connections_per_ip = Some(max);
+		}
+		config.allow_ip.extend(args.allow_ip.iter().copied());
+		config.deny_ip.extend(args.deny_ip.iter().copied());
+		config.allowed_hosts.extend(args.allowed_hosts.iter().cloned());
+		if let Some(proxies) = args.trusted_proxies {
+			config.trusted_proxies = proxies;
+		}
+		if let Some(failures) = args.ban_after {
+			config.ban_after = Some(failures);
+		}
+		if let Some(seconds) = args.ban_time {
+			config.ban_seconds = seconds;
+		}
+		if args.follow_symlinks {
+			config.follow_symlinks = true;
+		}
+		if args.no_follow_symlinks {
+			config.follow_symlinks = false;
+		}
+		if args.hidden {
+			config.hidden = true;
+		}
+		config.ignore.extend(args.ignore.iter().cloned());
+		if args.use_gitignore {
+			config.use_gitignore = true;
+		}
+		if !args.index.is_empty() {
+			config.index_files.clone_from(&args.index);
+		}
+		if args.no_index {
+			config.index_files.clear();
+		}
+		if args.spa {
+			config.spa = true;
+		}
+		if args.no_dirlist {
+			config.dirlist = false;
+		}
+		config.error_pages.extend(args.error_page.iter().cloned());
+		config.mime_types.extend(args.mime_map.iter().cloned());
+		if let Some(file) = &args.mime_types {
+			config.mime_types_file = Some(file.clone());
+		}
+		if let Some(mime) = &args.default_mime {
+			config.default_mime_type = Some(mime.clone());
+		}
+		if let Some(charset) = &args.charset {
+			config.charset = Some(charset.clone());
+		}
+		config.force_download.extend(args.force_download_ext.iter().cloned());
+		if let Some(prefix) = &args.route_prefix {
+			config.route_prefix.clone_from(prefix);
+		}
+		config.mounts.extend(args.mount.iter().cloned());
+		config.proxies.extend(args.proxy.iter().cloned());
+		if args.security_headers {
+			config.security_headers = true;
+		}
+		if let Some(policy) = &args.csp {
+			config.content_security_policy.clone_from(policy);
+		}
+		config.headers.extend(args.header.iter().cloned());
+		if let Some(auth) = &args.metrics_auth {
+			config.metrics_auth = Some(auth.clone());
+		}
+		if let Some(secret) = &args.share_secret {
+			config.share_secret.clone_from(secret);
+		}
+
+		if let Some(entry) = config
+			.auth
+			.iter()
+			.chain(config.mounts.iter().flat_map(|mount| &mount.auth))
+			.chain(&config.metrics_auth)
+			.find(|entry| !entry.contains(':'))
+		{
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid auth entry {entry:?}, expected user:pass"),
+			));
+		}
+
+		if config.chunk_size.0 == 0 || usize::try_from(config.chunk_size.0).is_err() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid chunk size {}", config.chunk_size.0),
+			));
+		}
+
+		if let Some(rate) = [config.limit_rate, config.limit_rate_total]
+			.into_iter()
+			.flatten()
+			.find(|rate| rate.0 == 0)
+		{
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid rate limit {}", rate.0),
+			));
+		}
+
+		if let Some(rate) = config.rate_limit.filter(|rate| !rate.is_finite() || *rate <= 0.0) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid request rate limit {rate}"),
+			));
+		}
+
+		if config.spa && config.index_files.is_empty() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"--spa needs an index file to fall back to",
+			));
+		}
+
+		if actix_web::http::header::HeaderValue::from_str(&config.content_security_policy).is_err() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid Content-Security-Policy {:?}", config.content_security_policy),
+			));
+		}
+
+		Ok(config)
+	}
+}
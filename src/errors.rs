@@ -0,0 +1,119 @@
+use actix_web::body::{to_bytes, BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::structs::ErrorTemplate;
+use crate::templates;
+
+/// Error bodies larger than this are documents in their own right rather than a handler's
+/// message, and are passed through.
+const MAX_MESSAGE: u64 = 4096;
+
+/// The `--error-page` files by status, read once at startup.
+#[derive(Default)]
+pub struct ErrorPages(HashMap<StatusCode, (Bytes, HeaderValue)>);
+
+impl ErrorPages {
+	pub fn from_config(config: &Config) -> std::io::Result<Self> {
+		let mut pages = HashMap::new();
+		for (status, path) in &config.error_pages {
+			let page = std::fs::read(path).map_err(|e| {
+				std::io::Error::new(e.kind(), format!("Failed to read error page {}: {e}", path.display()))
+			})?;
+			// Builder callers can pass any number, which the CLI would have refused.
+			let Some(status) = StatusCode::from_u16(status.0)
+				.ok()
+				.filter(|status| status.is_client_error() || status.is_server_error())
+			else {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					format!("Invalid error status {}, expected 400 to 599", status.0),
+				));
+			};
+			let mime = mime_guess::from_path(path).first_or_octet_stream();
+			let content_type = HeaderValue::from_str(mime.as_ref())
+				.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+			pages.insert(status, (Bytes::from(page), content_type));
+		}
+		Ok(Self(pages))
+	}
+}
+
+fn accepts_html(req: &HttpRequest) -> bool {
+	req.headers()
+		.get(header::ACCEPT)
+		.and_then(|v| v.to_str().ok())
+		.is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Middleware through which every error response passes on its way out, so they all look the
+/// same to a browser: the `--error-page` for the status if there is one, or else the built-in
+/// page carrying the handler's plain text message. Other clients get the message as it is, as do
+/// responses with a body of another type, such as WebDAV's XML.
+pub async fn render_errors(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let res = next.call(req).await?;
+	let status = res.status();
+	let plain_text = res
+		.headers()
+		.get(header::CONTENT_TYPE)
+		.and_then(|v| v.to_str().ok())
+		.is_none_or(|content_type| content_type.starts_with("text/plain"));
+	let small = match res.response().body().size() {
+		BodySize::None => true,
+		BodySize::Sized(size) => size <= MAX_MESSAGE,
+		BodySize::Stream => false,
+	};
+	if !(status.is_client_error() || status.is_server_error())
+		|| !plain_text
+		|| !small
+		|| res.headers().contains_key(header::CONTENT_ENCODING)
+		|| !accepts_html(res.request())
+	{
+		return Ok(res.map_into_left_body());
+	}
+
+	let (req, res) = res.into_parts();
+	let (mut res, body) = res.into_parts();
+	let custom = req
+		.app_data::<web::Data<ErrorPages>>()
+		.and_then(|pages| pages.0.get(&status).cloned());
+	let (page, content_type) = if let Some(custom) = custom {
+		custom
+	} else {
+		let message = to_bytes(body).await.map_err(|e| ErrorInternalServerError(e.into()))?;
+		let message = String::from_utf8_lossy(&message).trim().to_string();
+		let reason = status.canonical_reason().unwrap_or("Error");
+		let config = req.app_data::<web::Data<Config>>();
+		let template = ErrorTemplate {
+			prefix: config.map(|config| config.route_prefix.clone()).unwrap_or_default(),
+			status: status.as_u16(),
+			reason,
+			// Many handlers have nothing to say beyond the reason phrase itself.
+			message: if message.eq_ignore_ascii_case(reason) {
+				String::new()
+			} else {
+				message
+			},
+		};
+		let html = templates::render(config.map(|config| &***config), &template)
+			.await
+			.map_err(ErrorInternalServerError)?;
+		(Bytes::from(html), HeaderValue::from_static("text/html; charset=utf-8"))
+	};
+
+	let headers = res.headers_mut();
+	headers.insert(header::CONTENT_TYPE, content_type);
+	headers.remove(header::CONTENT_LENGTH);
+	let res = res.set_body(EitherBody::right(BoxBody::new(page)));
+	Ok(ServiceResponse::new(req, res))
+}
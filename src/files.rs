@@ -0,0 +1,413 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{delete, http::header, route, web, HttpRequest, HttpResponse, Result};
+use percent_encoding::percent_decode_str;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::helpers::{encode_url_path, parent_within_root, resolve_path, sanitize_filename, unprefixed, Visibility};
+use crate::paths;
+use crate::upload;
+use crate::webdav::{locked_response, LockManager};
+
+#[allow(clippy::future_not_send)]
+#[delete("/{path:.*}")]
+async fn delete_path(
+	req: HttpRequest,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+) -> Result<HttpResponse> {
+	if !config.allow_delete {
+		return Ok(HttpResponse::MethodNotAllowed().body("Deleting is disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(target) = resolve_path(&config.root, &path).filter(|target| *target != config.root) else {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	};
+	if !parent_within_root(&config.root, &target) {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	}
+	// What is kept from listings cannot be removed by naming it either.
+	if !Visibility::of(&config, &req).allows(&path) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+
+	if !locks.may_modify(&req, &target, true) {
+		return Ok(locked_response());
+	}
+
+	// Symlinks are removed themselves rather than followed to whatever they point at.
+	let Ok(metadata) = tokio::fs::symlink_metadata(&target).await else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+
+	let result = if metadata.is_dir() {
+		tokio::fs::remove_dir(&target).await
+	} else {
+		tokio::fs::remove_file(&target).await
+	};
+
+	match result {
+		Ok(()) => {
+			locks.release_under(&target);
+			Ok(HttpResponse::NoContent().finish())
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HttpResponse::NotFound().body("Not found")),
+		Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {
+			Ok(HttpResponse::Conflict().body("Directory is not empty"))
+		},
+		Err(e) => Err(ErrorInternalServerError(e)),
+	}
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "MKCOL")]
+async fn make_directory(
+	req: HttpRequest,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+) -> Result<HttpResponse> {
+	if !config.allow_upload {
+		return Ok(HttpResponse::MethodNotAllowed().body("Write operations are disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(target) = resolve_path(&config.root, &path).filter(|target| *target != config.root) else {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	};
+
+	let name = target.file_name().unwrap_or_default().to_string_lossy();
+	if sanitize_filename(&name).as_deref() != Some(&*name) {
+		return Ok(HttpResponse::BadRequest().body("Invalid directory name"));
+	}
+	if !parent_within_root(&config.root, &target) {
+		return Ok(HttpResponse::Conflict().body("Parent directory does not exist"));
+	}
+	if !Visibility::of(&config, &req).allows_new(&path, true) {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	}
+	if !locks.may_modify(&req, &target, false) {
+		return Ok(locked_response());
+	}
+
+	match tokio::fs::create_dir(&target).await {
+		Ok(()) => Ok(HttpResponse::Created()
+			.insert_header((
+				header::LOCATION,
+				format!("{}/{}", config.route_prefix, encode_url_path(&path.to_string_lossy())),
+			))
+			.finish()),
+		Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+			Ok(HttpResponse::MethodNotAllowed().body("Already exists"))
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			Ok(HttpResponse::Conflict().body("Parent directory does not exist"))
+		},
+		Err(e) => Err(ErrorInternalServerError(e)),
+	}
+}
+
+/// Extracts the target of a `MOVE`/`COPY` from its `Destination` header, which may be either an
+/// absolute URL or an absolute path on this server, beneath the route prefix.
+fn destination_path(req: &HttpRequest, config: &Config) -> Option<PathBuf> {
+	let value = req.headers().get("destination")?.to_str().ok()?;
+	let path = match value.split_once("://") {
+		Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+		None => value,
+	};
+	let path = unprefixed(config, path.split(['?', '#']).next().unwrap_or_default())?;
+	let decoded = PathBuf::from(percent_decode_str(path).decode_utf8().ok()?.trim_start_matches('/'));
+	Some(paths::on_disk(&config.root, &decoded).unwrap_or(decoded))
+}
+
+/// Deletes what a `MOVE`/`COPY` replaces, with everything inside it (RFC 4918 §9.9.3).
+async fn remove_existing(target: &Path) -> std::io::Result<()> {
+	if tokio::fs::symlink_metadata(target).await?.is_dir() {
+		tokio::fs::remove_dir_all(target).await
+	} else {
+		tokio::fs::remove_file(target).await
+	}
+}
+
+/// Source and destination of a validated `MOVE`/`COPY` request.
+struct Transfer {
+	source: PathBuf,
+	dest: PathBuf,
+	dest_path: PathBuf,
+	/// Whether an existing destination was replaced.
+	replaced: bool,
+}
+
+impl Transfer {
+	fn response(&self, config: &Config) -> HttpResponse {
+		if self.replaced {
+			HttpResponse::NoContent().finish()
+		} else {
+			HttpResponse::Created()
+				.insert_header((
+					header::LOCATION,
+					format!(
+						"{}/{}",
+						config.route_prefix,
+						encode_url_path(&self.dest_path.to_string_lossy())
+					),
+				))
+				.finish()
+		}
+	}
+}
+
+/// Validates both ends of a `MOVE`/`COPY` and clears the destination unless `Overwrite: F` forbids
+/// it, returning the error response to send otherwise.
+async fn prepare_transfer(
+	req: &HttpRequest,
+	config: &Config,
+	locks: &LockManager,
+	moving: bool,
+) -> Result<std::result::Result<Transfer, HttpResponse>> {
+	if !config.allow_upload {
+		return Ok(Err(
+			HttpResponse::MethodNotAllowed().body("Write operations are disabled")
+		));
+	}
+
+	let path = paths::request_path(req, &config.root);
+	let Some(source) = resolve_path(&config.root, &path).filter(|source| !moving || *source != config.root) else {
+		return Ok(Err(HttpResponse::Forbidden().body("Access denied")));
+	};
+	let Some(dest_path) = destination_path(req, config) else {
+		return Ok(Err(
+			HttpResponse::BadRequest().body("Missing or invalid Destination header")
+		));
+	};
+	let Some(dest) = resolve_path(&config.root, &dest_path).filter(|dest| *dest != config.root) else {
+		return Ok(Err(HttpResponse::Forbidden().body("Access denied")));
+	};
+
+	let name = dest.file_name().unwrap_or_default().to_string_lossy();
+	if sanitize_filename(&name).as_deref() != Some(&*name) {
+		return Ok(Err(HttpResponse::BadRequest().body("Invalid destination name")));
+	}
+	if source != config.root && !parent_within_root(&config.root, &source) {
+		return Ok(Err(HttpResponse::Forbidden().body("Access denied")));
+	}
+	if !parent_within_root(&config.root, &dest) {
+		return Ok(Err(
+			HttpResponse::Conflict().body("Destination directory does not exist")
+		));
+	}
+	// Neither end may be something kept from listings, which could otherwise be taken out of
+	// hiding or replaced.
+	let visibility = Visibility::of(config, req);
+	let Ok(metadata) = tokio::fs::symlink_metadata(&source).await else {
+		return Ok(Err(HttpResponse::NotFound().body("Not found")));
+	};
+	if !visibility.allows(&path) {
+		return Ok(Err(HttpResponse::NotFound().body("Not found")));
+	}
+	if !visibility.allows_new(&dest_path, metadata.is_dir()) {
+		return Ok(Err(HttpResponse::Forbidden().body("Access denied")));
+	}
+	// Nor may a directory carry what is kept from listings to where it would be shown.
+	if moving
+		&& uncovers(&config.root, &visibility, &source, &dest)
+			.await
+			.map_err(ErrorInternalServerError)?
+	{
+		return Ok(Err(HttpResponse::Forbidden().body("Access denied")));
+	}
+	if dest.starts_with(&source) {
+		return Ok(Err(
+			HttpResponse::Conflict().body("Cannot move or copy a directory into itself")
+		));
+	}
+	if (moving && !locks.may_modify(req, &source, true)) || !locks.may_modify(req, &dest, true) {
+		return Ok(Err(locked_response()));
+	}
+
+	if let Some(response) = exceeds_limits(config, &source, &dest, moving, moving || recursive(req))
+		.await
+		.map_err(ErrorInternalServerError)?
+	{
+		return Ok(Err(response));
+	}
+
+	// Without the header, the destination is replaced (RFC 4918 §10.6).
+	let overwrite = !req
+		.headers()
+		.get("overwrite")
+		.is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"F"));
+	let replaced = tokio::fs::symlink_metadata(&dest).await.is_ok();
+	if replaced {
+		if !overwrite {
+			return Ok(Err(
+				HttpResponse::PreconditionFailed().body("Destination already exists")
+			));
+		}
+		if !config.allow_delete {
+			return Ok(Err(
+				HttpResponse::Forbidden().body("Overwriting requires --allow-delete")
+			));
+		}
+		remove_existing(&dest).await.map_err(ErrorInternalServerError)?;
+	}
+
+	Ok(Ok(Transfer {
+		source,
+		dest,
+		dest_path,
+		replaced,
+	}))
+}
+
+/// Whether a `COPY` takes what is inside a directory along, which it does unless `Depth: 0`.
+fn recursive(req: &HttpRequest) -> bool {
+	!matches!(req.headers().get("depth").and_then(|v| v.to_str().ok()), Some("0"))
+}
+
+/// The response refusing to copy `source` to `dest`, along with what is inside it when `recursive`,
+/// when that is larger than `--max-upload-size` or than the quotas there leave room for. A move
+/// writes nothing new, and so is only held to the quotas it would bring `source` under.
+async fn exceeds_limits(
+	config: &Config,
+	source: &Path,
+	dest: &Path,
+	moving: bool,
+	recursive: bool,
+) -> std::io::Result<Option<HttpResponse>> {
+	let dir = dest.parent().unwrap_or(&config.root);
+	let (quota, max) = if moving {
+		(upload::remaining_quota_from(config, source, dir).await?, None)
+	} else {
+		(
+			upload::remaining_quota(config, dir).await?,
+			config.max_upload_size.map(|size| size.0),
+		)
+	};
+	if quota.is_none() && max.is_none() {
+		return Ok(None);
+	}
+
+	let metadata = tokio::fs::metadata(source).await?;
+	let size = if !metadata.is_dir() {
+		metadata.len()
+	} else if recursive {
+		upload::dir_size(source).await?
+	} else {
+		0
+	};
+	if max.is_some_and(|max| size > max) {
+		return Ok(Some(
+			HttpResponse::PayloadTooLarge().body("Source exceeds the upload size limit"),
+		));
+	}
+	if quota.is_some_and(|left| size > left) {
+		return Ok(Some(
+			HttpResponse::InsufficientStorage().body("Source exceeds the quota of the destination"),
+		));
+	}
+	Ok(None)
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "MOVE")]
+async fn move_path(req: HttpRequest, config: web::Data<Config>, locks: web::Data<LockManager>) -> Result<HttpResponse> {
+	let transfer = match prepare_transfer(&req, &config, &locks, true).await? {
+		Ok(transfer) => transfer,
+		Err(response) => return Ok(response),
+	};
+
+	tokio::fs::rename(&transfer.source, &transfer.dest)
+		.await
+		.map_err(ErrorInternalServerError)?;
+	locks.release_under(&transfer.source);
+
+	Ok(transfer.response(&config))
+}
+
+/// Whether what is kept from clients at `from`, relative to `root`, would be shown at `to`.
+fn uncovered(root: &Path, visibility: &Visibility, from: &Path, to: &Path, is_dir: bool) -> bool {
+	let relative = |path: &Path| path.strip_prefix(root).map(Path::to_path_buf).unwrap_or_default();
+	!visibility.allows(&relative(from)) && visibility.allows_new(&relative(to), is_dir)
+}
+
+/// Whether moving `source` to `dest` would show anything beneath it that is kept from clients
+/// where it is now.
+async fn uncovers(root: &Path, visibility: &Visibility, source: &Path, dest: &Path) -> std::io::Result<bool> {
+	let mut pending = vec![source.to_path_buf()];
+	while let Some(dir) = pending.pop() {
+		let Ok(mut read_dir) = tokio::fs::read_dir(&dir).await else {
+			continue;
+		};
+		while let Some(entry) = read_dir.next_entry().await? {
+			let from = entry.path();
+			let to = dest.join(from.strip_prefix(source).unwrap_or(&from));
+			let is_dir = entry.file_type().await?.is_dir();
+			if uncovered(root, visibility, &from, &to, is_dir) {
+				return Ok(true);
+			}
+			if is_dir {
+				pending.push(from);
+			}
+		}
+	}
+	Ok(false)
+}
+
+/// Copies `source` to `dest`, descending into directories when `recursive` is set. Symlinks that
+/// resolve outside of `root` are skipped rather than copied, as is what is kept from clients
+/// where it is and would be shown in the copy.
+async fn copy_tree(
+	root: &Path,
+	visibility: &Visibility,
+	source: &Path,
+	dest: &Path,
+	recursive: bool,
+) -> std::io::Result<()> {
+	let mut pending = vec![(source.to_path_buf(), dest.to_path_buf())];
+
+	while let Some((from, to)) = pending.pop() {
+		if !tokio::fs::canonicalize(&from).await?.starts_with(root) {
+			continue;
+		}
+		if from != source && uncovered(root, visibility, &from, &to, tokio::fs::metadata(&from).await?.is_dir()) {
+			continue;
+		}
+
+		if tokio::fs::metadata(&from).await?.is_dir() {
+			tokio::fs::create_dir(&to).await?;
+			if from != source || recursive {
+				let mut read_dir = tokio::fs::read_dir(&from).await?;
+				while let Some(entry) = read_dir.next_entry().await? {
+					pending.push((entry.path(), to.join(entry.file_name())));
+				}
+			}
+		} else {
+			tokio::fs::copy(&from, &to).await?;
+		}
+	}
+
+	Ok(())
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "COPY")]
+async fn copy_path(req: HttpRequest, config: web::Data<Config>, locks: web::Data<LockManager>) -> Result<HttpResponse> {
+	let transfer = match prepare_transfer(&req, &config, &locks, false).await? {
+		Ok(transfer) => transfer,
+		Err(response) => return Ok(response),
+	};
+
+	let visibility = Visibility::of(&config, &req);
+	copy_tree(
+		&config.root,
+		&visibility,
+		&transfer.source,
+		&transfer.dest,
+		recursive(&req),
+	)
+	.await
+	.map_err(ErrorInternalServerError)?;
+
+	Ok(transfer.response(&config))
+}
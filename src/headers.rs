@@ -0,0 +1,82 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use globset::GlobMatcher;
+use percent_encoding::percent_decode_str;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::helpers::unprefixed;
+use crate::ignore::path_glob;
+
+/// The `--header`s of the configuration, parsed once at startup.
+pub struct CustomHeaders(Vec<(Option<GlobMatcher>, HeaderName, HeaderValue)>);
+
+impl CustomHeaders {
+	/// The headers of `config`, or `None` when it has none.
+	pub fn from_config(config: &Config) -> std::io::Result<Option<Self>> {
+		let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+		let headers = config
+			.headers
+			.iter()
+			.map(|custom| {
+				let glob = custom
+					.path
+					.as_deref()
+					.map(path_glob)
+					.transpose()
+					.map_err(|e| invalid(format!("Invalid header glob {:?}: {e}", custom.path)))?;
+				let name = HeaderName::try_from(custom.name.as_str())
+					.map_err(|e| invalid(format!("Invalid header name {:?}: {e}", custom.name)))?;
+				let value = HeaderValue::try_from(custom.value.as_str())
+					.map_err(|e| invalid(format!("Invalid value for header {}: {e}", custom.name)))?;
+				Ok((glob, name, value))
+			})
+			.collect::<std::io::Result<Vec<_>>>()?;
+		Ok((!headers.is_empty()).then_some(Self(headers)))
+	}
+}
+
+/// Middleware setting the `--header`s on each response, error responses included. Globs are
+/// matched against the path below the route prefix; requests outside it only get the headers
+/// without one.
+pub async fn add_headers(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+	let headers = req.app_data::<web::Data<CustomHeaders>>().cloned();
+	let path = req
+		.app_data::<web::Data<Config>>()
+		.and_then(|config| unprefixed(config, req.path()).map(str::to_string))
+		.map(|path| {
+			percent_decode_str(path.trim_start_matches('/'))
+				.decode_utf8_lossy()
+				.into_owned()
+		});
+	let mut res = next.call(req).await?;
+	let Some(headers) = headers else {
+		return Ok(res);
+	};
+
+	// Several headers of one name are all sent, replacing only what the response had.
+	let mut set = HashSet::new();
+	for (glob, name, value) in &headers.0 {
+		let applies = match (glob, &path) {
+			(None, _) => true,
+			(Some(glob), Some(path)) => glob.is_match(Path::new(path)),
+			(Some(_), None) => false,
+		};
+		if !applies {
+			continue;
+		}
+		if set.insert(name) {
+			res.headers_mut().insert(name.clone(), value.clone());
+		} else {
+			res.headers_mut().append(name.clone(), value.clone());
+		}
+	}
+	Ok(res)
+}
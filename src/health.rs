@@ -0,0 +1,27 @@
+use actix_web::{get, web, HttpResponse};
+
+use crate::config::Config;
+
+/// Probe endpoints, answered without authentication so load balancers and orchestrators can reach
+/// them.
+pub const PATHS: [&str; 2] = ["/_health", "/_ready"];
+
+/// Liveness: answering at all means the process is up.
+#[get("/_health")]
+async fn health() -> HttpResponse {
+	HttpResponse::Ok().content_type("text/plain").body("ok\n")
+}
+
+/// Readiness: the server can only do its job while the root directory can be listed.
+#[get("/_ready")]
+async fn ready(config: web::Data<Config>) -> HttpResponse {
+	match tokio::fs::read_dir(&config.root).await {
+		Ok(_) => HttpResponse::Ok().content_type("text/plain").body("ready\n"),
+		Err(e) => {
+			tracing::warn!(error = %e, "Root directory is not readable");
+			HttpResponse::ServiceUnavailable()
+				.content_type("text/plain")
+				.body("root directory is not readable\n")
+		},
+	}
+}
@@ -1,74 +1,568 @@
-use crate::structs::DirEntry;
-use std::path::Path;
+use crate::collation;
+use crate::config::Config;
+use crate::ignore::Ignore;
+use crate::share::{self, ShareCheck};
+use crate::structs::{DirEntry, ListingOrder, Pagination, Sort, SortKey};
+use actix_web::http::header::{self, ContentEncoding};
+use actix_web::http::Uri;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponseBuilder};
+use futures::{StreamExt, TryStreamExt};
+use mime_guess::Mime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::ffi::OsStr;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub async fn get_dir_entries(path: &Path) -> std::io::Result<Vec<DirEntry>> {
+/// Characters escaped when a filesystem path is turned into a URL path.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+	.add(b' ')
+	.add(b'"')
+	.add(b'#')
+	.add(b'%')
+	.add(b'<')
+	.add(b'>')
+	.add(b'?')
+	.add(b'`')
+	.add(b'{')
+	.add(b'}');
+
+pub fn encode_url_path(path: &str) -> String {
+	utf8_percent_encode(path, PATH_SEGMENT).to_string()
+}
+
+/// Per-user cache directory for state the server persists between runs.
+pub fn cache_dir() -> Option<PathBuf> {
+	let base = std::env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+	Some(base.join("http-server-rs"))
+}
+
+/// Returns the first value of the query parameter `key`, if present.
+pub fn query_param(req: &HttpRequest, key: &str) -> Option<String> {
+	web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+		.ok()?
+		.into_inner()
+		.into_iter()
+		.find_map(|(k, v)| (k == key).then_some(v))
+}
+
+/// The client's address. With `trusted_proxies` in front of the server, it is the one the
+/// outermost of them put in `X-Forwarded-For`, as anything further left could have been made up
+/// by the client. A header with fewer entries than there are proxies did not come through all of
+/// them, so the connection's own address is taken instead.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: usize) -> Option<IpAddr> {
+	// Connections over a Unix socket have no address, only that of the proxy forwarding them.
+	let peer = req.peer_addr().map(|peer| peer.ip().to_canonical());
+	if trusted_proxies == 0 {
+		return peer;
+	}
+	let forwarded: Vec<IpAddr> = req
+		.headers()
+		.get_all("X-Forwarded-For")
+		.filter_map(|v| v.to_str().ok())
+		.flat_map(|v| v.split(','))
+		.filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+		.collect();
+	let Some(index) = forwarded.len().checked_sub(trusted_proxies) else {
+		return peer;
+	};
+	forwarded.get(index).map(|ip| ip.to_canonical()).or(peer)
+}
+
+/// `path`, as requested, with the route prefix taken off, or `None` when it lies outside of it.
+pub fn unprefixed<'a>(config: &Config, path: &'a str) -> Option<&'a str> {
+	match path.strip_prefix(&config.route_prefix)? {
+		"" => Some("/"),
+		rest => rest.starts_with('/').then_some(rest),
+	}
+}
+
+/// Strips the port off a `Host`, minding the brackets around IPv6 addresses.
+pub fn hostname(authority: &str) -> &str {
+	match authority.find(']') {
+		Some(end) if authority.starts_with('[') => &authority[..=end],
+		_ => authority.split(':').next().unwrap_or(authority),
+	}
+}
+
+/// The host name a request was sent to, lowercased and without its port, from `Host` or, over
+/// HTTP/2, the URI's authority.
+pub fn request_host(headers: &header::HeaderMap, uri: &Uri) -> Option<String> {
+	let authority = headers
+		.get(header::HOST)
+		.and_then(|v| v.to_str().ok())
+		.or_else(|| uri.host())?;
+	Some(hostname(authority).trim_end_matches('.').to_ascii_lowercase())
+}
+
+/// Whether the client asked for a listing as JSON rather than HTML, via `?format=json` or an
+/// `Accept` header naming `application/json`.
+pub fn wants_json(req: &HttpRequest) -> bool {
+	if let Some(format) = query_param(req, "format") {
+		return format == "json";
+	}
+	req.headers()
+		.get(header::ACCEPT)
+		.and_then(|v| v.to_str().ok())
+		.is_some_and(|accept| accept.contains("application/json"))
+}
+
+/// Device names Windows opens in place of a file of that name in any directory, with or without
+/// an extension.
+const WINDOWS_DEVICES: [&str; 8] = ["CON", "PRN", "AUX", "NUL", "COM", "LPT", "CONIN$", "CONOUT$"];
+
+/// Whether Windows would take `name` for something other than the file it names: a device, an
+/// alternate data stream or drive (`file.txt::$DATA`, `C:file`), or, as it drops trailing dots
+/// and spaces, a different file.
+fn windows_unsafe(name: &OsStr) -> bool {
+	let Some(name) = name.to_str() else {
+		return true;
+	};
+	if name.contains(':') || name.ends_with(['.', ' ']) {
+		return true;
+	}
+	let stem = name.split('.').next().unwrap_or_default().trim_end();
+	WINDOWS_DEVICES.iter().any(|device| match stem.get(..device.len()) {
+		Some(start) if start.eq_ignore_ascii_case(device) => {
+			let rest = &stem[device.len()..];
+			match *device {
+				"COM" | "LPT" => matches!(
+					rest,
+					"0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³"
+				),
+				_ => rest.is_empty(),
+			}
+		},
+		_ => false,
+	})
+}
+
+/// Whether `name` is safe to use as a path component on this platform. Only Windows has names
+/// that do not mean what they say.
+pub fn safe_component(name: &OsStr) -> bool {
+	!cfg!(windows) || !windows_unsafe(name)
+}
+
+/// Maps a request path onto the filesystem beneath `root`, rejecting any component that could
+/// escape it. Drive and UNC prefixes are rejected along with `..`, and on Windows so are the
+/// names it would not take literally.
+pub fn resolve_path(root: &Path, path: &Path) -> Option<PathBuf> {
+	let mut final_path = root.to_path_buf();
+
+	for component in path.components() {
+		match component {
+			Component::Normal(c) if safe_component(c) => final_path.push(c),
+			Component::CurDir => (),
+			_ => return None,
+		}
+	}
+
+	Some(final_path)
+}
+
+/// Checks that the directory containing `path` still lies beneath `root` once symlinks in the
+/// intermediate components are resolved. `path` itself is not followed.
+pub fn parent_within_root(root: &Path, path: &Path) -> bool {
+	path.parent()
+		.and_then(|parent| parent.canonicalize().ok())
+		.is_some_and(|parent| parent.starts_with(root))
+}
+
+/// Whether the existing `path` may be read under the symlink policy: always when
+/// `follow_symlinks` is set, and otherwise only if it still lies beneath the root with every
+/// symlink on the way resolved.
+pub fn within_root(config: &Config, path: &Path) -> bool {
+	config.follow_symlinks || path.canonicalize().is_ok_and(|target| target.starts_with(&config.root))
+}
+
+/// Reduces a client-supplied file name to a single safe path component.
+pub fn sanitize_filename(name: &str) -> Option<String> {
+	let name = name.rsplit(['/', '\\']).next()?.trim();
+	if name.is_empty() || name == "." || name == ".." || name.contains('\0') || !safe_component(OsStr::new(name)) {
+		None
+	} else {
+		Some(name.to_string())
+	}
+}
+
+/// What of the tree a request gets to see, in listings as well as when asking for a path
+/// directly.
+#[derive(Clone)]
+pub struct Visibility {
+	/// Whether dotfiles are shown.
+	pub hidden: bool,
+	pub ignore: Option<web::Data<Ignore>>,
+}
+
+impl Visibility {
+	/// Dotfiles are shown with `--hidden`, or to an authenticated user asking with
+	/// `?hidden=true`. Share links do not count as authenticated, as anyone may hold one.
+	pub fn of(config: &Config, req: &HttpRequest) -> Self {
+		let authenticated =
+			(!config.auth.is_empty() || config.token.is_some()) && share::verify(config, req) != ShareCheck::Valid;
+		let asked = query_param(req, "hidden").is_some_and(|v| v == "true" || v == "1");
+		Self {
+			hidden: config.hidden || (authenticated && asked),
+			ignore: req.app_data::<web::Data<Ignore>>().cloned(),
+		}
+	}
+
+	/// Whether `path`, relative to the root, may be shown and served.
+	pub fn allows(&self, path: &Path) -> bool {
+		self.shows_dotfiles_in(path) && self.ignore.as_ref().is_none_or(|ignore| !ignore.ignores(path))
+	}
+
+	/// Whether `path`, relative to the root, may be shown once created as a directory or a file,
+	/// as `is_dir` says, for the writes that would otherwise slip past rules for directories.
+	pub fn allows_new(&self, path: &Path, is_dir: bool) -> bool {
+		self.shows_dotfiles_in(path)
+			&& self
+				.ignore
+				.as_ref()
+				.is_none_or(|ignore| !ignore.would_ignore(path, is_dir))
+	}
+
+	fn shows_dotfiles_in(&self, path: &Path) -> bool {
+		self.hidden
+			|| !path
+				.components()
+				.any(|c| matches!(c, Component::Normal(name) if name.as_encoded_bytes().starts_with(b".")))
+	}
+
+	/// Whether the entry at `path`, beneath the root, may be shown. Its directory is assumed to
+	/// be allowed already, so only its own name is checked for a dot.
+	fn allows_entry(&self, path: &Path) -> bool {
+		let hidden = path
+			.file_name()
+			.is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+		(self.hidden || !hidden)
+			&& self
+				.ignore
+				.as_ref()
+				.is_none_or(|ignore| !ignore.ignores(path.strip_prefix(ignore.root()).unwrap_or(path)))
+	}
+}
+
+/// The first of `config.index_files` that is a regular file in `dir`, at `path` relative to the
+/// root, and may be shown to the client.
+pub fn index_file(
+	config: &Config,
+	visibility: &Visibility,
+	path: &Path,
+	dir: &Path,
+) -> Option<(PathBuf, std::fs::Metadata)> {
+	config.index_files.iter().find_map(|name| {
+		let index = dir.join(name);
+		let metadata = std::fs::metadata(&index).ok()?;
+		(metadata.is_file() && visibility.allows(&path.join(name)) && within_root(config, &index))
+			.then_some((index, metadata))
+	})
+}
+
+/// Reads the entries of `dir` that are exposed to clients, paired with their metadata. Listings and
+/// archives both go through this so they always agree on what is visible. Up to `parallelism`
+/// entries are stat'ed at once, as on network filesystems each one costs a round trip.
+pub async fn visible_entries(
+	dir: &Path,
+	visibility: &Visibility,
+	parallelism: usize,
+) -> std::io::Result<Vec<(tokio::fs::DirEntry, std::fs::Metadata)>> {
 	let mut entries = Vec::new();
-	let mut read_dir = tokio::fs::read_dir(path).await?;
+	let mut read_dir = tokio::fs::read_dir(dir).await?;
 
 	while let Some(entry) = read_dir.next_entry().await? {
-		let metadata = entry.metadata().await?;
-		let modified = metadata.modified().unwrap_or_else(|_| std::time::SystemTime::now());
+		if visibility.allows_entry(&entry.path()) {
+			entries.push(entry);
+		}
+	}
+
+	futures::stream::iter(entries)
+		.map(|entry| async move {
+			let metadata = entry.metadata().await?;
+			Ok((entry, metadata))
+		})
+		.buffered(parallelism.max(1))
+		.try_collect()
+		.await
+}
+
+/// Describes one directory entry, with its path given relative to `root`.
+pub fn dir_entry(root: &Path, path: &Path, metadata: &std::fs::Metadata) -> DirEntry {
+	DirEntry {
+		name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+		path: path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned(),
+		is_dir: metadata.is_dir(),
+		size: (!metadata.is_dir()).then_some(metadata.len()),
+		modified: metadata
+			.modified()
+			.unwrap_or_else(|_| std::time::SystemTime::now())
+			.into(),
+	}
+}
 
-		let modified = chrono::DateTime::<chrono::Local>::from(modified)
-			.format("%Y-%m-%d %H:%M:%S")
-			.to_string();
+/// Listing order: directories first, then naturally by name.
+pub fn entry_order(a: &DirEntry, b: &DirEntry) -> std::cmp::Ordering {
+	sort_order(&Sort::default_for(ListingOrder::Natural), a, b)
+}
 
-		let size = if metadata.is_dir() {
-			"-".to_string()
+/// Orders `a` and `b` as `sort` says, falling back to their names where the column sorted by
+/// is the same.
+pub fn sort_order(sort: &Sort, a: &DirEntry, b: &DirEntry) -> std::cmp::Ordering {
+	let groups = if sort.dirs_first {
+		b.is_dir.cmp(&a.is_dir)
+	} else {
+		std::cmp::Ordering::Equal
+	};
+	let by_name = || {
+		if sort.natural {
+			collation::natural(&a.name, &b.name)
 		} else {
-			humansize::format_size(metadata.len(), humansize::BINARY).to_string()
-		};
-
-		let name = entry.file_name().to_string_lossy().into_owned();
-
-		let path = entry
-			.path()
-			.strip_prefix(".")
-			.unwrap_or(&entry.path())
-			.to_string_lossy()
-			.into_owned();
-
-		entries.push(DirEntry {
-			name,
-			path,
-			is_dir: metadata.is_dir(),
-			size,
-			modified,
-		});
-	}
-
-	entries.sort_by(|a, b| match (a.is_dir, b.is_dir) {
-		(true, false) => std::cmp::Ordering::Less,
-		(false, true) => std::cmp::Ordering::Greater,
-		_ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-	});
+			collation::lexicographic(&a.name, &b.name)
+		}
+	};
+	let order = match sort.key {
+		SortKey::Name => by_name(),
+		SortKey::Size => a.size.cmp(&b.size).then_with(by_name),
+		SortKey::Modified => a.modified.cmp(&b.modified).then_with(by_name),
+	};
+	groups.then(if sort.descending { order.reverse() } else { order })
+}
 
+/// The order asked for with `?sort=name|size|mtime`, `?order=asc|desc` and `?dirs_first=false`,
+/// or that of `order` for whatever is left out or not understood.
+pub fn listing_sort(req: &HttpRequest, order: ListingOrder) -> Sort {
+	let default = Sort::default_for(order);
+	let key = query_param(req, "sort").and_then(|key| SortKey::parse(&key));
+	Sort {
+		key: key.unwrap_or(default.key),
+		descending: match query_param(req, "order") {
+			Some(order) => order == "desc",
+			None => key.is_none() && default.descending,
+		},
+		dirs_first: query_param(req, "dirs_first").is_none_or(|v| v != "0" && v != "false"),
+		natural: default.natural,
+	}
+}
+
+pub async fn get_dir_entries(
+	root: &Path,
+	path: &Path,
+	visibility: &Visibility,
+	parallelism: usize,
+	sort: &Sort,
+) -> std::io::Result<Vec<DirEntry>> {
+	let mut entries: Vec<DirEntry> = visible_entries(path, visibility, parallelism)
+		.await?
+		.iter()
+		.map(|(entry, metadata)| dir_entry(root, &entry.path(), metadata))
+		.collect();
+	entries.sort_by(|a, b| sort_order(sort, a, b));
 	Ok(entries)
 }
 
-pub fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
-	let range = range_str.strip_prefix("bytes=")?;
-	let mut parts = range.split('-');
+/// Extensions of precompressed copies kept next to a file, in order of preference.
+const SIDECARS: [(ContentEncoding, &str); 2] = [(ContentEncoding::Brotli, "br"), (ContentEncoding::Gzip, "gz")];
 
-	let start_str = parts.next()?;
-	let end_str = parts.next()?;
+/// A precompressed copy of `path`, such as `app.js.br` next to `app.js`, in an encoding the
+/// client accepts and prefers over the plain file.
+pub fn precompressed(req: &HttpRequest, path: &Path) -> Option<(PathBuf, ContentEncoding)> {
+	let accept = req.get_header::<header::AcceptEncoding>()?;
+	let available: Vec<(PathBuf, ContentEncoding)> = SIDECARS
+		.iter()
+		.map(|&(encoding, ext)| {
+			let mut sidecar = path.as_os_str().to_owned();
+			sidecar.push(format!(".{ext}"));
+			(PathBuf::from(sidecar), encoding)
+		})
+		.filter(|(sidecar, _)| sidecar.is_file())
+		.filter(|(sidecar, _)| {
+			req.app_data::<web::Data<Config>>()
+				.is_none_or(|config| within_root(config, sidecar))
+		})
+		.collect();
+	if available.is_empty() {
+		return None;
+	}
 
-	let start = if start_str.is_empty() {
-		let last_n = end_str.parse::<u64>().ok()?;
-		file_size.checked_sub(last_n)?
-	} else {
-		start_str.parse::<u64>().ok()?
-	};
+	let supported: Vec<header::Encoding> = available
+		.iter()
+		.map(|&(_, encoding)| header::Encoding::Known(encoding))
+		.chain([header::Encoding::identity()])
+		.collect();
+	match accept.negotiate(supported.iter())? {
+		header::Encoding::Known(chosen) if chosen != ContentEncoding::Identity => {
+			available.into_iter().find(|&(_, encoding)| encoding == chosen)
+		},
+		_ => None,
+	}
+}
 
-	let end = if end_str.is_empty() {
-		file_size - 1
-	} else {
-		end_str.parse::<u64>().ok()?
+/// Number of entries in a whole directory, sent with every listing since it may only be a page.
+pub const TOTAL_COUNT: &str = "X-Total-Count";
+
+/// Page size for `?page=` without `?limit=` when `--page-size` is not set.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Cuts the page asked for with `?page=` (counting from 1) and `?limit=` out of a sorted listing,
+/// or its first `page_size` entries. Listings are left whole when neither applies.
+pub fn paginate(req: &HttpRequest, entries: &mut Vec<DirEntry>, page_size: Option<usize>) -> Option<Pagination> {
+	let page = query_param(req, "page").and_then(|v| v.parse::<usize>().ok());
+	let limit = query_param(req, "limit")
+		.and_then(|v| v.parse().ok())
+		.or(page_size)
+		.or(page.map(|_| DEFAULT_PAGE_SIZE))
+		.filter(|&limit| limit > 0)?;
+
+	let total = entries.len();
+	let pages = total.div_ceil(limit).max(1);
+	let page = page.unwrap_or(1).clamp(1, pages);
+	let start = (page - 1) * limit;
+	entries.truncate(start + limit);
+	entries.drain(..start);
+	Some(Pagination {
+		page,
+		pages,
+		limit,
+		total,
+	})
+}
+
+/// The content type `path` is served with: the one `config` maps its extension, or the lack of
+/// one, to, or else the built-in guess.
+pub fn mime_type(config: &Config, path: &Path) -> Mime {
+	let custom = match path.extension() {
+		Some(extension) => config.mime_types.get(&extension.to_string_lossy().to_ascii_lowercase()),
+		None => config.default_mime_type.as_ref(),
 	};
+	custom
+		.and_then(|mime| mime.parse().ok())
+		.unwrap_or_else(|| mime_guess::from_path(path).first_or_octet_stream())
+}
 
-	if start <= end && end < file_size {
-		Some((start, end))
-	} else {
-		None
+/// Whether the file at `path` goes out as an attachment: when asked for with `?download`, or
+/// when its extension is one of the `--force-download-ext` ones.
+pub fn is_download(config: &Config, req: &HttpRequest, path: &Path) -> bool {
+	let forced = path.extension().is_some_and(|extension| {
+		config.force_download.iter().any(|forced| {
+			forced
+				.trim_start_matches('.')
+				.eq_ignore_ascii_case(&extension.to_string_lossy())
+		})
+	});
+	forced || query_param(req, "download").is_some_and(|v| v != "0" && v != "false")
+}
+
+/// `Content-Disposition: attachment` for a file called `name`. Clients that only read `filename`
+/// get it with anything but printable ASCII replaced, next to the RFC 5987 `filename*` with the
+/// name as it is.
+pub fn attachment(name: &str) -> header::ContentDisposition {
+	let fallback = name
+		.chars()
+		.map(|c| if c.is_ascii_graphic() || c == ' ' { c } else { '_' })
+		.collect::<String>();
+	let mut parameters = vec![header::DispositionParam::Filename(fallback.clone())];
+	if fallback != name {
+		parameters.push(header::DispositionParam::FilenameExt(header::ExtendedValue {
+			charset: header::Charset::Ext("UTF-8".to_string()),
+			language_tag: None,
+			value: name.as_bytes().to_vec(),
+		}));
+	}
+	header::ContentDisposition {
+		disposition: header::DispositionType::Attachment,
+		parameters,
+	}
+}
+
+/// Strong validator for one version of a file, in the format `NamedFile` uses so that both ways of
+/// serving a file agree.
+pub fn file_etag(metadata: &std::fs::Metadata) -> header::EntityTag {
+	#[cfg(unix)]
+	let ino = std::os::unix::fs::MetadataExt::ino(metadata);
+	#[cfg(not(unix))]
+	let ino = 0;
+	let mtime = metadata
+		.modified()
+		.ok()
+		.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+		.unwrap_or_default();
+	header::EntityTag::new_strong(format!(
+		"{:x}:{:x}:{:x}:{:x}",
+		ino,
+		metadata.len(),
+		mtime.as_secs(),
+		mtime.subsec_nanos()
+	))
+}
+
+/// Whether the client's copy is current, so that a 304 will do. `If-Modified-Since` only counts
+/// when there is no `If-None-Match`, as the tag is the more precise of the two.
+pub fn not_modified(req: &HttpRequest, etag: &header::EntityTag, modified: Option<SystemTime>) -> bool {
+	match req.get_header::<header::IfNoneMatch>() {
+		Some(header::IfNoneMatch::Any) => true,
+		Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+		None => match (req.get_header::<header::IfModifiedSince>(), modified) {
+			(Some(header::IfModifiedSince(since)), Some(modified)) => unix_secs(modified) <= unix_secs(since.into()),
+			_ => false,
+		},
+	}
+}
+
+/// Adds the `ETag` and, when known, `Last-Modified` headers to a file response.
+pub fn insert_validators(response: &mut HttpResponseBuilder, etag: header::EntityTag, modified: Option<SystemTime>) {
+	response.insert_header(header::ETag(etag));
+	if let Some(modified) = modified {
+		response.insert_header(header::LastModified(modified.into()));
+	}
+}
+
+/// HTTP dates only have whole seconds, so times are compared at that precision.
+fn unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Whether a `Range` header may be honoured. With `If-Range` the client only wants a part while
+/// its copy is still current, and the whole file otherwise.
+pub fn range_allowed(req: &HttpRequest, etag: &header::EntityTag, modified: Option<SystemTime>) -> bool {
+	match req.get_header::<header::IfRange>() {
+		Some(header::IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+		Some(header::IfRange::Date(date)) => {
+			modified.is_some_and(|modified| unix_secs(modified) <= unix_secs(date.into()))
+		},
+		None => true,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use actix_web::test::TestRequest;
+
+	fn forwarded(header: &str) -> HttpRequest {
+		TestRequest::default()
+			.peer_addr("10.0.0.1:1234".parse().unwrap())
+			.insert_header(("X-Forwarded-For", header))
+			.to_http_request()
+	}
+
+	#[test]
+	fn client_ip_is_the_one_the_outermost_trusted_proxy_saw() {
+		let req = forwarded("1.1.1.1, 2.2.2.2, 3.3.3.3");
+		assert_eq!(client_ip(&req, 0), Some("10.0.0.1".parse().unwrap()));
+		assert_eq!(client_ip(&req, 1), Some("3.3.3.3".parse().unwrap()));
+		assert_eq!(client_ip(&req, 2), Some("2.2.2.2".parse().unwrap()));
+	}
+
+	#[test]
+	fn short_forwarded_for_falls_back_to_the_peer() {
+		// Only one entry for two proxies: it may well be the client's own.
+		let req = forwarded("6.6.6.6");
+		assert_eq!(client_ip(&req, 2), Some("10.0.0.1".parse().unwrap()));
+		let req = TestRequest::default()
+			.peer_addr("10.0.0.1:1234".parse().unwrap())
+			.to_http_request();
+		assert_eq!(client_ip(&req, 1), Some("10.0.0.1".parse().unwrap()));
 	}
 }
@@ -0,0 +1,65 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Extension points for embedding applications, registered through [`FileServer::hook`].
+///
+/// Every method has a no-op default, so implementors only override what they need. Hooks run on
+/// the server's worker threads; anything slow (such as scanning a large upload) should be handed
+/// off to a separate task rather than done inline.
+///
+/// [`FileServer::hook`]: crate::FileServer::hook
+pub trait Hook: Send + Sync + 'static {
+	/// Runs for every request that passed authentication, before it is routed. Returning a
+	/// response sends it instead of handling the request.
+	fn on_request(&self, _req: &HttpRequest) -> Option<HttpResponse> {
+		None
+	}
+
+	/// Runs before the contents of the file at `path` are sent. Returning a response sends it
+	/// instead of the file.
+	fn before_send_file(&self, _req: &HttpRequest, _path: &Path) -> Option<HttpResponse> {
+		None
+	}
+
+	/// Runs once a file uploaded through `POST` or `PUT` has been completely written to `path`.
+	fn after_upload(&self, _req: &HttpRequest, _path: &Path) {}
+}
+
+/// Registered hooks, called in the order they were added.
+#[derive(Default, Clone)]
+pub struct Hooks(Vec<Arc<dyn Hook>>);
+
+impl Hooks {
+	pub fn push(&mut self, hook: impl Hook) {
+		self.0.push(Arc::new(hook));
+	}
+
+	pub fn before_send_file(&self, req: &HttpRequest, path: &Path) -> Option<HttpResponse> {
+		self.0.iter().find_map(|hook| hook.before_send_file(req, path))
+	}
+
+	pub fn after_upload(&self, req: &HttpRequest, path: &Path) {
+		for hook in &self.0 {
+			hook.after_upload(req, path);
+		}
+	}
+}
+
+/// Middleware giving each hook's `on_request` the chance to answer a request first.
+pub async fn run_request_hooks(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let response = req
+		.app_data::<web::Data<Hooks>>()
+		.and_then(|hooks| hooks.0.iter().find_map(|hook| hook.on_request(req.request())));
+
+	match response {
+		Some(response) => Ok(req.into_response(response).map_into_right_body()),
+		None => Ok(next.call(req).await?.map_into_left_body()),
+	}
+}
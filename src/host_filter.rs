@@ -0,0 +1,90 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use std::net::IpAddr;
+
+use crate::config::Config;
+use crate::helpers::request_host;
+use crate::mdns;
+
+/// The host names requests are answered for, or `None` from `from_config` when any are.
+pub struct AllowedHosts {
+	names: Vec<String>,
+	/// Addresses a request may name itself, on top of loopback ones.
+	addresses: Vec<IpAddr>,
+	/// Whether any address is allowed, as when listening on all interfaces any of the machine's
+	/// may be the one asked for.
+	any_address: bool,
+}
+
+impl AllowedHosts {
+	/// The `allowed_hosts` of `config`, or by default `localhost`, the addresses in `listening`
+	/// and the names the configuration serves, has certificates for or advertises over mDNS.
+	pub fn from_config(config: &Config, listening: &[IpAddr]) -> Option<Self> {
+		if config.allowed_hosts.iter().any(|host| host == "*") {
+			return None;
+		}
+		if !config.allowed_hosts.is_empty() {
+			let (addresses, names) = config
+				.allowed_hosts
+				.iter()
+				.map(|host| host.trim_matches(['[', ']']).to_ascii_lowercase())
+				.partition::<Vec<_>, _>(|host| host.parse::<IpAddr>().is_ok());
+			return Some(Self {
+				names,
+				addresses: addresses.iter().filter_map(|address| address.parse().ok()).collect(),
+				any_address: false,
+			});
+		}
+		let names = ["localhost".to_string(), ".localhost".to_string()]
+			.into_iter()
+			.chain(config.hosts.keys().map(|host| host.to_ascii_lowercase()))
+			.chain(config.acme.iter().map(|domain| domain.to_ascii_lowercase()))
+			.chain(config.mdns.then(|| format!("{}.local", mdns::host_name())))
+			.collect();
+		Some(Self {
+			names,
+			addresses: listening.to_vec(),
+			any_address: listening.iter().any(IpAddr::is_unspecified),
+		})
+	}
+
+	fn allows(&self, host: &str) -> bool {
+		if let Ok(address) = host.trim_matches(['[', ']']).parse::<IpAddr>() {
+			let address = address.to_canonical();
+			return self.any_address || address.is_loopback() || self.addresses.contains(&address);
+		}
+		self.names.iter().any(|name| match name.strip_prefix('.') {
+			Some(domain) => host == domain || host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+			None => host == name,
+		})
+	}
+}
+
+/// Middleware answering `403 Forbidden` to requests whose `Host` is not one of the allowed ones.
+/// A page on another site can otherwise point its own name at the server's address and read
+/// what it serves. Requests without a `Host` come from no such page, and those over a Unix socket
+/// from a proxy choosing for itself which hosts reach the server, so both are let through.
+pub async fn filter_hosts(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let Some(allowed) = req
+		.app_data::<web::Data<AllowedHosts>>()
+		.filter(|_| req.peer_addr().is_some())
+		.cloned()
+	else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+
+	match request_host(req.headers(), req.uri()) {
+		Some(host) if !allowed.allows(&host) => {
+			tracing::debug!("Refusing request for {host}: not an allowed host");
+			Ok(req
+				.into_response(HttpResponse::Forbidden().body("Host not allowed"))
+				.map_into_right_body())
+		},
+		_ => Ok(next.call(req).await?.map_into_left_body()),
+	}
+}
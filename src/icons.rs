@@ -0,0 +1,50 @@
+use std::path::Path;
+
+/// Extensions shown as archives, ahead of the MIME type `mime_guess` has for them.
+const ARCHIVES: &[&str] = &[
+	"7z", "bz2", "cab", "deb", "gz", "iso", "lz", "lzma", "rar", "rpm", "tar", "tbz2", "tgz", "txz", "xz", "zip", "zst",
+];
+
+/// Extensions shown as source code and configuration. Several, such as `ts`, are media types to
+/// `mime_guess`.
+const CODE: &[&str] = &[
+	"bash", "bat", "c", "cc", "cfg", "clj", "cmake", "conf", "cpp", "cs", "css", "dart", "erl", "ex", "exs", "fish",
+	"go", "h", "hpp", "hs", "htm", "html", "ini", "java", "js", "json", "jsx", "kt", "lock", "lua", "mjs", "nim",
+	"php", "pl", "ps1", "py", "r", "rb", "rs", "sass", "scala", "scss", "sh", "sql", "svelte", "swift", "toml", "ts",
+	"tsx", "vue", "xml", "yaml", "yml", "zig", "zsh",
+];
+
+/// Extensions shown as documents.
+const DOCUMENTS: &[&str] = &[
+	"csv", "doc", "docx", "epub", "log", "markdown", "md", "mobi", "odp", "ods", "odt", "pdf", "ppt", "pptx", "rst",
+	"rtf", "tex", "tsv", "txt", "xls", "xlsx",
+];
+
+/// The symbol of `/_static/icons.svg` an entry called `name` is listed with: `folder`, `image`,
+/// `video`, `audio`, `archive`, `code`, `document` or, for anything else, `file`.
+pub fn icon(name: &str, is_dir: bool) -> &'static str {
+	if is_dir {
+		return "folder";
+	}
+	let Some(extension) = Path::new(name).extension() else {
+		return "file";
+	};
+	let extension = extension.to_string_lossy().to_ascii_lowercase();
+	let listed = |extensions: &[&str]| extensions.contains(&extension.as_str());
+	if listed(ARCHIVES) {
+		return "archive";
+	}
+	if listed(CODE) {
+		return "code";
+	}
+	if listed(DOCUMENTS) {
+		return "document";
+	}
+	match mime_guess::from_ext(&extension).first() {
+		Some(mime) if mime.type_() == mime_guess::mime::IMAGE => "image",
+		Some(mime) if mime.type_() == mime_guess::mime::VIDEO => "video",
+		Some(mime) if mime.type_() == mime_guess::mime::AUDIO => "audio",
+		Some(mime) if mime.type_() == mime_guess::mime::TEXT => "document",
+		_ => "file",
+	}
+}
@@ -0,0 +1,131 @@
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+struct Rule {
+	matcher: GlobMatcher,
+	/// A `!pattern`, bringing back what an earlier rule excluded.
+	negated: bool,
+	/// A `pattern/`, matching directories only.
+	dir_only: bool,
+}
+
+impl Rule {
+	/// Parses one pattern with `.gitignore` semantics.
+	fn parse(pattern: &str) -> Result<Self, globset::Error> {
+		let (negated, pattern) = match pattern.strip_prefix('!') {
+			Some(pattern) => (true, pattern),
+			None => (false, pattern),
+		};
+		let (dir_only, pattern) = match pattern.strip_suffix('/') {
+			Some(pattern) => (true, pattern),
+			None => (false, pattern),
+		};
+		Ok(Self {
+			matcher: path_glob(pattern)?,
+			negated,
+			dir_only,
+		})
+	}
+}
+
+/// Compiles a glob to match paths relative to the root with: one containing a slash is anchored
+/// to the root, any other matches file names at any depth.
+pub fn path_glob(pattern: &str) -> Result<GlobMatcher, globset::Error> {
+	let glob = match pattern.strip_prefix('/') {
+		Some(anchored) => anchored.to_string(),
+		None if pattern.contains('/') => pattern.to_string(),
+		None => format!("**/{pattern}"),
+	};
+	Ok(GlobBuilder::new(&glob)
+		.literal_separator(true)
+		.build()?
+		.compile_matcher())
+}
+
+/// Paths below the root kept from clients by `--ignore` globs and, with `--use-gitignore`, the
+/// root's `.gitignore`. The file is read once at startup.
+pub struct Ignore {
+	root: PathBuf,
+	rules: Vec<Rule>,
+}
+
+impl Ignore {
+	/// The rules of `config`, or `None` when nothing is to be ignored.
+	pub fn from_config(config: &Config) -> std::io::Result<Option<Self>> {
+		Self::new(config).map(|ignore| (!ignore.rules.is_empty()).then_some(ignore))
+	}
+
+	/// The rules of `config`, which may be none at all.
+	pub fn new(config: &Config) -> std::io::Result<Self> {
+		let mut patterns = Vec::new();
+		if config.use_gitignore {
+			match std::fs::read_to_string(config.root.join(".gitignore")) {
+				Ok(gitignore) => patterns.extend(gitignore.lines().map(str::to_string)),
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+				Err(e) => return Err(e),
+			}
+		}
+		// Given last so they win over the .gitignore.
+		patterns.extend(config.ignore.iter().cloned());
+
+		let rules = patterns
+			.iter()
+			.map(|line| line.trim_end())
+			.filter(|line| !line.is_empty() && !line.starts_with('#'))
+			.map(|pattern| {
+				Rule::parse(pattern).map_err(|e| {
+					std::io::Error::new(
+						std::io::ErrorKind::InvalidInput,
+						format!("Invalid ignore pattern {pattern:?}: {e}"),
+					)
+				})
+			})
+			.collect::<std::io::Result<Vec<_>>>()?;
+		Ok(Self {
+			root: config.root.clone(),
+			rules,
+		})
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	/// Whether `path`, relative to the root, is ignored. As with git, nothing below an ignored
+	/// directory can be brought back by a negated rule.
+	pub fn ignores(&self, path: &Path) -> bool {
+		self.matches(path, || self.root.join(path).is_dir())
+	}
+
+	/// Whether `path`, relative to the root, would be ignored once created as a directory or a
+	/// file, as `is_dir` says.
+	pub fn would_ignore(&self, path: &Path, is_dir: bool) -> bool {
+		self.matches(path, || is_dir)
+	}
+
+	fn matches(&self, path: &Path, path_is_dir: impl Fn() -> bool) -> bool {
+		let mut prefix = PathBuf::new();
+		let mut components = path.components().peekable();
+		while let Some(component) = components.next() {
+			prefix.push(component);
+			let last = components.peek().is_none();
+			let is_dir = || !last || path_is_dir();
+
+			let mut excluded = false;
+			for rule in &self.rules {
+				if excluded != rule.negated {
+					continue;
+				}
+				if rule.matcher.is_match(&prefix) && (!rule.dir_only || is_dir()) {
+					excluded = !rule.negated;
+				}
+			}
+			if excluded {
+				return true;
+			}
+		}
+		false
+	}
+}
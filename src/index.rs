@@ -0,0 +1,211 @@
+use notify::{RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{QueryParser, RegexQuery};
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+use crate::config::Config;
+use crate::helpers::cache_dir;
+
+/// Only the start of larger files is indexed.
+const MAX_INDEXED_BYTES: u64 = 4 * 1024 * 1024;
+/// Files with a NUL byte this close to the start are treated as binary and skipped.
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+const WRITER_MEMORY: usize = 50 * 1024 * 1024;
+/// How long the watcher waits for a burst of changes to settle before committing them.
+const COMMIT_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy)]
+struct Fields {
+	/// Path relative to the root, with `/` separators.
+	path: Field,
+	body: Field,
+}
+
+/// Full-text index over the text files below the root, kept on disk and updated by a background
+/// thread as files change.
+pub struct ContentIndex {
+	index: Index,
+	reader: IndexReader,
+	fields: Fields,
+}
+
+/// Default index location, keyed by the root so serving different trees never mixes results.
+fn default_index_dir(root: &Path) -> Option<PathBuf> {
+	let digest = Sha256::digest(root.as_os_str().as_encoded_bytes());
+	let key: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+	Some(cache_dir()?.join("index").join(key))
+}
+
+fn relative(root: &Path, path: &Path) -> Option<String> {
+	let relative = path.strip_prefix(root).ok()?;
+	Some(
+		relative
+			.components()
+			.map(|c| c.as_os_str().to_string_lossy())
+			.collect::<Vec<_>>()
+			.join("/"),
+	)
+}
+
+/// Reads the indexable text of `path`, or `None` if it is not a regular text file.
+fn read_text(path: &Path) -> Option<String> {
+	if !std::fs::symlink_metadata(path).ok()?.is_file() {
+		return None;
+	}
+	let mut bytes = Vec::new();
+	std::fs::File::open(path)
+		.ok()?
+		.take(MAX_INDEXED_BYTES)
+		.read_to_end(&mut bytes)
+		.ok()?;
+	if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+		return None;
+	}
+	Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Escapes `text` for use as a literal inside a tantivy regex.
+fn escape_regex(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		if "\\.+*?()|[]{}^$#&-~\"@<>".contains(c) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+impl ContentIndex {
+	/// Opens (or creates) the index, then rebuilds it and keeps it up to date on a background
+	/// thread. Searches work right away but only see files once the rebuild reaches them.
+	pub fn start(config: &Config) -> std::io::Result<Self> {
+		let dir = config
+			.index_dir
+			.clone()
+			.or_else(|| default_index_dir(&config.root))
+			.ok_or_else(|| {
+				std::io::Error::new(
+					std::io::ErrorKind::NotFound,
+					"No cache directory found for the content index; pass --index-dir",
+				)
+			})?;
+		std::fs::create_dir_all(&dir)?;
+
+		let mut schema = Schema::builder();
+		let fields = Fields {
+			path: schema.add_text_field("path", STRING | STORED),
+			body: schema.add_text_field("body", TEXT),
+		};
+		let directory = MmapDirectory::open(&dir).map_err(std::io::Error::other)?;
+		let index = Index::open_or_create(directory, schema.build()).map_err(std::io::Error::other)?;
+		let reader = index
+			.reader_builder()
+			.reload_policy(ReloadPolicy::OnCommitWithDelay)
+			.try_into()
+			.map_err(std::io::Error::other)?;
+		let writer = index.writer(WRITER_MEMORY).map_err(std::io::Error::other)?;
+
+		let root = config.root.clone();
+		let skip = std::fs::canonicalize(&dir)?;
+		std::thread::spawn(move || {
+			if let Err(e) = maintain(&root, &skip, writer, fields) {
+				tracing::error!(error = %e, "Content index stopped updating");
+			}
+		});
+
+		Ok(Self { index, reader, fields })
+	}
+
+	/// Returns the relative paths of up to `limit` files matching `query`, best match first.
+	pub fn search(&self, query: &str, limit: usize) -> tantivy::Result<Vec<String>> {
+		let parser = QueryParser::for_index(&self.index, vec![self.fields.body]);
+		let (query, _) = parser.parse_query_lenient(query);
+		let searcher = self.reader.searcher();
+
+		let mut paths = Vec::new();
+		for (_, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+			let doc: TantivyDocument = searcher.doc(address)?;
+			if let Some(path) = doc.get_first(self.fields.path).and_then(|v| v.as_str()) {
+				paths.push(path.to_string());
+			}
+		}
+		Ok(paths)
+	}
+}
+
+/// Replaces whatever the index holds for `path` with its current contents, descending into it if
+/// it is now a directory.
+fn reindex(root: &Path, skip: &Path, writer: &IndexWriter, fields: Fields, path: &Path) -> tantivy::Result<()> {
+	let Some(key) = relative(root, path) else {
+		return Ok(());
+	};
+	if key.is_empty() {
+		writer.delete_all_documents()?;
+	} else {
+		writer.delete_term(Term::from_field_text(fields.path, &key));
+		let under = format!("{}/.*", escape_regex(&key));
+		writer.delete_query(Box::new(RegexQuery::from_pattern(&under, fields.path)?))?;
+	}
+
+	let mut pending = vec![path.to_path_buf()];
+	while let Some(path) = pending.pop() {
+		if path.starts_with(skip) {
+			continue;
+		}
+		let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+			continue;
+		};
+		if metadata.is_dir() {
+			if let Ok(read_dir) = std::fs::read_dir(&path) {
+				pending.extend(read_dir.flatten().map(|entry| entry.path()));
+			}
+		} else if let (Some(key), Some(body)) = (relative(root, &path), read_text(&path)) {
+			writer.add_document(doc!(fields.path => key, fields.body => body))?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Rebuilds the index from scratch, then applies filesystem changes as they are reported.
+fn maintain(root: &Path, skip: &Path, mut writer: IndexWriter, fields: Fields) -> tantivy::Result<()> {
+	let (tx, rx) = mpsc::channel();
+	let mut watcher = notify::recommended_watcher(tx).map_err(|e| tantivy::TantivyError::SystemError(e.to_string()))?;
+	watcher
+		.watch(root, RecursiveMode::Recursive)
+		.map_err(|e| tantivy::TantivyError::SystemError(e.to_string()))?;
+
+	reindex(root, skip, &writer, fields, root)?;
+	writer.commit()?;
+
+	while let Ok(event) = rx.recv() {
+		let mut changed = HashSet::new();
+		let mut next = Some(event);
+		while let Some(event) = next {
+			// Committing writes into the index itself, which must not set off another commit.
+			if let Ok(event) = event {
+				changed.extend(event.paths.into_iter().filter(|path| !path.starts_with(skip)));
+			}
+			next = rx.recv_timeout(COMMIT_DELAY).ok();
+		}
+		if changed.is_empty() {
+			continue;
+		}
+
+		for path in &changed {
+			reindex(root, skip, &writer, fields, path)?;
+		}
+		writer.commit()?;
+	}
+
+	Ok(())
+}
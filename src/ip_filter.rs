@@ -0,0 +1,36 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::ConnectionType;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use std::net::IpAddr;
+
+use crate::config::Config;
+use crate::helpers::client_ip;
+
+/// Whether `ip` gets in: it must match `allow_ip` if there is any, and must not match `deny_ip`.
+fn permitted(config: &Config, ip: &IpAddr) -> bool {
+	(config.allow_ip.is_empty() || config.allow_ip.iter().any(|range| range.contains(ip)))
+		&& !config.deny_ip.iter().any(|range| range.contains(ip))
+}
+
+/// Middleware answering `403 Forbidden`, and closing the connection, to clients outside the
+/// configured address ranges. Clients whose address cannot be told are turned away too.
+pub async fn filter_ips(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let Some(config) = req.app_data::<web::Data<Config>>().cloned() else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+
+	match client_ip(req.request(), config.trusted_proxies) {
+		Some(ip) if permitted(&config, &ip) => Ok(next.call(req).await?.map_into_left_body()),
+		ip => {
+			tracing::debug!("Refusing request from {ip:?}: not an allowed address");
+			let mut response = HttpResponse::Forbidden().body("Forbidden");
+			response.head_mut().set_connection_type(ConnectionType::Close);
+			Ok(req.into_response(response).map_into_right_body())
+		},
+	}
+}
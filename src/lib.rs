@@ -0,0 +1,60 @@
+//! A file server for quickly sharing directories over HTTP(S), usable both as the `http-server`
+//! binary and as a library through [`FileServer`].
+
+mod access_log;
+mod admin;
+mod api;
+mod archive;
+mod auth;
+mod bans;
+mod buffers;
+mod cgi;
+mod charset;
+mod checksum;
+mod clipboard;
+mod collation;
+pub mod completions;
+mod compression;
+pub mod config;
+mod errors;
+mod files;
+mod headers;
+mod health;
+mod helpers;
+mod hooks;
+mod host_filter;
+mod icons;
+mod ignore;
+mod index;
+mod ip_filter;
+mod limits;
+mod listeners;
+pub mod logging;
+mod mdns;
+mod metrics;
+mod mounts;
+mod paths;
+mod proxy;
+mod qr;
+mod ranges;
+mod reload;
+mod rules;
+mod server;
+mod share;
+mod shutdown;
+mod stats;
+pub mod structs;
+mod templates;
+mod throttle;
+mod timing;
+mod tls;
+mod upload;
+mod webdav;
+mod zipfs;
+
+pub use access_log::{LogFormat, RequestId};
+pub use config::Config;
+pub use hooks::Hook;
+pub use server::FileServer;
+
+const CHUNK_SIZE: usize = 64 * 1024;
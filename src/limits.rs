@@ -0,0 +1,145 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::helpers::client_ip;
+
+/// Clients tracked before those whose buckets have refilled are forgotten, so a scan from many
+/// addresses cannot grow the table without bound.
+const MAX_TRACKED: usize = 10_000;
+
+/// Request budgets and open connections of each client IP, shared by all workers. The request
+/// rate is that of the configuration each request is handled under, which may change at runtime.
+pub struct Limiter {
+	buckets: Mutex<HashMap<IpAddr, (f64, Instant)>>,
+	max_connections: Option<usize>,
+	connections: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl Limiter {
+	pub fn new(config: &Config) -> Self {
+		Self {
+			buckets: Mutex::new(HashMap::new()),
+			max_connections: config.max_connections_per_ip,
+			connections: Mutex::new(HashMap::new()),
+		}
+	}
+
+	pub const fn limits_connections(&self) -> bool {
+		self.max_connections.is_some()
+	}
+
+	/// Spends one of `ip`'s tokens on a request, or says how long until it has one again.
+	fn acquire(&self, ip: IpAddr, config: &Config) -> Result<(), Duration> {
+		let Some(rate) = config.rate_limit else {
+			return Ok(());
+		};
+		let burst = f64::from(config.rate_limit_burst.max(1));
+		let now = Instant::now();
+		let refilled =
+			|(tokens, last): (f64, Instant)| (tokens + now.duration_since(last).as_secs_f64() * rate).min(burst);
+
+		let mut buckets = self.buckets.lock().unwrap();
+		if buckets.len() >= MAX_TRACKED && !buckets.contains_key(&ip) {
+			buckets.retain(|_, bucket| refilled(*bucket) < burst);
+		}
+		let bucket = buckets.entry(ip).or_insert((burst, now));
+		let tokens = refilled(*bucket);
+		if tokens < 1.0 {
+			return Err(Duration::from_secs_f64((1.0 - tokens) / rate));
+		}
+		*bucket = (tokens - 1.0, now);
+		Ok(())
+	}
+
+	/// Claims one of `ip`'s connection slots, or `None` when it already has all it may.
+	fn open(limiter: &web::Data<Self>, ip: IpAddr) -> Option<Slot> {
+		let mut connections = limiter.connections.lock().unwrap();
+		let open = connections.entry(ip).or_default();
+		if limiter.max_connections.is_some_and(|max| *open >= max) {
+			return None;
+		}
+		*open += 1;
+		Some(Slot {
+			limiter: limiter.clone(),
+			ip,
+		})
+	}
+}
+
+/// Counts a connection against its client for as long as it is open.
+struct Slot {
+	limiter: web::Data<Limiter>,
+	ip: IpAddr,
+}
+
+impl Drop for Slot {
+	fn drop(&mut self) {
+		let mut connections = self.limiter.connections.lock().unwrap();
+		if let Some(open) = connections.get_mut(&self.ip) {
+			*open -= 1;
+			if *open == 0 {
+				connections.remove(&self.ip);
+			}
+		}
+	}
+}
+
+/// Lives in each connection's extensions. The client is only known once the first request
+/// arrives, which is when the connection claims its slot; it is released when the connection
+/// closes.
+#[derive(Default)]
+pub struct Connection(OnceCell<Option<Slot>>);
+
+fn too_many_requests(retry_after: Duration) -> HttpResponse {
+	let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+	HttpResponse::TooManyRequests()
+		.insert_header((RETRY_AFTER, seconds.max(1).to_string()))
+		.body("Too many requests")
+}
+
+/// Middleware answering `429 Too Many Requests` to clients past their request rate or holding
+/// too many connections open. The latter have their connection closed with the response.
+pub async fn limit_requests(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let Some(limiter) = req.app_data::<web::Data<Limiter>>().cloned() else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+
+	// Connections are counted against whoever opened them, which behind a proxy is the proxy.
+	let peer = req.peer_addr().map(|addr| addr.ip().to_canonical());
+	let over_connections = req
+		.conn_data::<Connection>()
+		.zip(peer)
+		.is_some_and(|(connection, peer)| connection.0.get_or_init(|| Limiter::open(&limiter, peer)).is_none());
+	if over_connections {
+		tracing::debug!("Refusing request from {peer:?}: too many connections");
+		let mut response = too_many_requests(Duration::from_secs(1));
+		response
+			.head_mut()
+			.set_connection_type(actix_web::http::ConnectionType::Close);
+		return Ok(req.into_response(response).map_into_right_body());
+	}
+
+	let Some(config) = req.app_data::<web::Data<Config>>().cloned() else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+	let Some(ip) = client_ip(req.request(), config.trusted_proxies) else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+	if let Err(retry_after) = limiter.acquire(ip, &config) {
+		tracing::debug!("Refusing request from {ip}: rate limited");
+		return Ok(req.into_response(too_many_requests(retry_after)).map_into_right_body());
+	}
+	Ok(next.call(req).await?.map_into_left_body())
+}
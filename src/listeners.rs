@@ -0,0 +1,279 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, Listener};
+
+/// Connections waiting to be accepted on each socket, as with actix's own `bind`.
+const BACKLOG: i32 = 1024;
+/// Addresses reserved for documentation (RFC 5737 and RFC 3849), which are on no network, so only
+/// the default route leads to them.
+const OFF_NETWORK_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+const OFF_NETWORK_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+/// Where `config` listens over TCP: its `listen`, or else `port` at each address it binds, or
+/// localhost unless it listens on a Unix socket instead.
+pub fn from_config(config: &Config) -> Vec<Listener> {
+	if !config.listen.is_empty() {
+		return config.listen.clone();
+	}
+	let addresses = if config.bind.is_empty() && config.unix_socket.is_some() {
+		Vec::new()
+	} else if config.bind.is_empty() {
+		vec![Ipv4Addr::LOCALHOST.into()]
+	} else {
+		config.bind.clone()
+	};
+	addresses
+		.into_iter()
+		.map(|address| Listener {
+			address: SocketAddr::new(address, config.port),
+			tls: None,
+		})
+		.collect()
+}
+
+fn listen(address: SocketAddr, dual_stack: bool) -> std::io::Result<TcpListener> {
+	let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+	if address.is_ipv6() {
+		socket.set_only_v6(!dual_stack)?;
+	}
+	#[cfg(not(windows))]
+	socket.set_reuse_address(true)?;
+	socket.bind(&address.into())?;
+	socket.listen(BACKLOG)?;
+	Ok(socket.into())
+}
+
+/// Whether a socket at `address`, one of `all`, takes IPv4 connections as well as IPv6 ones.
+fn dual_stack(address: SocketAddr, all: &[SocketAddr]) -> bool {
+	address.is_ipv6()
+		&& address.ip().is_unspecified()
+		&& !all.contains(&SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), address.port()))
+}
+
+/// A socket listening at `address`, one of `all` being listened on. `[::]` takes IPv4
+/// connections too, whatever the system's default, unless `0.0.0.0` is listened on separately at
+/// the same port.
+pub fn tcp(address: SocketAddr, all: &[SocketAddr]) -> std::io::Result<TcpListener> {
+	listen(address, dual_stack(address, all)).map_err(|e| match e.kind() {
+		std::io::ErrorKind::AddrInUse => std::io::Error::new(
+			e.kind(),
+			format!(
+				"Cannot listen on {address}: port {} is in use by another program; choose another with --port, 0 for any that is free, or try those above it with --port-retry",
+				address.port()
+			),
+		),
+		_ => std::io::Error::new(e.kind(), format!("Cannot listen on {address}: {e}")),
+	})
+}
+
+/// Sockets listening at each of `addresses` on `port`, or on one free port they share for 0.
+fn tcp_group(addresses: &[SocketAddr], mut port: u16) -> std::io::Result<Vec<TcpListener>> {
+	let mut sockets = Vec::with_capacity(addresses.len());
+	for address in addresses {
+		let all: Vec<_> = addresses
+			.iter()
+			.map(|address| SocketAddr::new(address.ip(), port))
+			.collect();
+		let socket = tcp(SocketAddr::new(address.ip(), port), &all)?;
+		port = socket.local_addr()?.port();
+		sockets.push(socket);
+	}
+	Ok(sockets)
+}
+
+/// Sockets for each of `listening`, whose addresses are updated to the ports they got. Listeners
+/// on the same port stay on one: port 0 picks one that is free for all of them, and a port in use
+/// is moved up from by up to `retries` ports.
+pub fn bind(listening: &mut [Listener], retries: u16) -> std::io::Result<Vec<TcpListener>> {
+	let mut sockets: Vec<Option<TcpListener>> = listening.iter().map(|_| None).collect();
+	let mut ports: Vec<u16> = Vec::new();
+	for listener in listening.iter() {
+		if !ports.contains(&listener.address.port()) {
+			ports.push(listener.address.port());
+		}
+	}
+	for requested in ports {
+		let group: Vec<usize> = (0..listening.len())
+			.filter(|&i| listening[i].address.port() == requested)
+			.collect();
+		let addresses: Vec<_> = group.iter().map(|&i| listening[i].address).collect();
+		// A free port picked for one address may be taken at another, so that is retried too.
+		let attempts = if requested == 0 { 8 } else { retries };
+		let mut attempt = 0;
+		let bound = loop {
+			let port = if requested == 0 {
+				0
+			} else {
+				requested.saturating_add(attempt)
+			};
+			match tcp_group(&addresses, port) {
+				Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempt < attempts && port != u16::MAX => {
+					attempt += 1;
+				},
+				result => break result?,
+			}
+		};
+		let port = bound[0].local_addr()?.port();
+		if requested != 0 && port != requested {
+			tracing::warn!("Port {requested} is in use; listening on port {port} instead");
+		}
+		for (i, socket) in group.into_iter().zip(bound) {
+			listening[i].address.set_port(port);
+			sockets[i] = Some(socket);
+		}
+	}
+	Ok(sockets.into_iter().flatten().collect())
+}
+
+/// The addresses of the machine's network interfaces that are up, loopback and IPv6 link-local
+/// ones aside, as the latter need the interface named in URLs.
+#[cfg(unix)]
+pub fn interface_addresses() -> Vec<IpAddr> {
+	let mut addresses = Vec::new();
+	let mut list = std::ptr::null_mut();
+	// SAFETY: on success the list is freed below, once it is no longer read.
+	if unsafe { libc::getifaddrs(&mut list) } != 0 {
+		return addresses;
+	}
+	let mut entry = list;
+	while !entry.is_null() {
+		// SAFETY: entries, and the addresses they point to, are valid until the list is freed.
+		let interface = unsafe { &*entry };
+		entry = interface.ifa_next;
+		if interface.ifa_addr.is_null() || interface.ifa_flags & libc::IFF_UP as libc::c_uint == 0 {
+			continue;
+		}
+		// SAFETY: the family says which kind of address it is.
+		let address: IpAddr = unsafe {
+			match i32::from((*interface.ifa_addr).sa_family) {
+				libc::AF_INET => {
+					let address = &*interface.ifa_addr.cast::<libc::sockaddr_in>();
+					Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)).into()
+				},
+				libc::AF_INET6 => {
+					let address = &*interface.ifa_addr.cast::<libc::sockaddr_in6>();
+					Ipv6Addr::from(address.sin6_addr.s6_addr).into()
+				},
+				_ => continue,
+			}
+		};
+		let link_local = matches!(address, IpAddr::V6(address) if address.is_unicast_link_local());
+		if !address.is_loopback() && !link_local && !addresses.contains(&address) {
+			addresses.push(address);
+		}
+	}
+	// SAFETY: the list came from `getifaddrs` and nothing borrowed from it is left.
+	unsafe { libc::freeifaddrs(list) };
+	addresses
+}
+
+/// The address the default route leaves from, where interfaces cannot be listed.
+#[cfg(not(unix))]
+pub fn interface_addresses() -> Vec<IpAddr> {
+	lan_address(Ipv6Addr::UNSPECIFIED.into()).into_iter().collect()
+}
+
+/// The addresses clients can open a socket at `address`, one of `all`, by: that one, or for all
+/// interfaces localhost and each interface's of the families it takes, as `0.0.0.0` and `[::]`
+/// are no address a browser can go to.
+pub fn reachable(address: SocketAddr, all: &[SocketAddr]) -> Vec<SocketAddr> {
+	if !address.ip().is_unspecified() {
+		return vec![address];
+	}
+	let v4 = address.is_ipv4() || dual_stack(address, all);
+	let localhost: IpAddr = if v4 {
+		Ipv4Addr::LOCALHOST.into()
+	} else {
+		Ipv6Addr::LOCALHOST.into()
+	};
+	std::iter::once(localhost)
+		.chain(
+			interface_addresses()
+				.into_iter()
+				.filter(|interface| if interface.is_ipv4() { v4 } else { address.is_ipv6() }),
+		)
+		.map(|ip| SocketAddr::new(ip, address.port()))
+		.collect()
+}
+
+/// The distinct addresses of `listeners`, such as for the certificates and hosts they are
+/// reached by.
+pub fn addresses(listeners: &[Listener]) -> Vec<IpAddr> {
+	let mut addresses: Vec<IpAddr> = Vec::new();
+	for listener in listeners {
+		if !addresses.contains(&listener.address.ip()) {
+			addresses.push(listener.address.ip());
+		}
+	}
+	addresses
+}
+
+/// The local address traffic to `peer` leaves from, which is the one it can reach the server at.
+/// Nothing is sent: connecting a UDP socket only picks the route.
+pub fn facing(peer: IpAddr) -> Option<IpAddr> {
+	let unspecified: IpAddr = match peer {
+		IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+		IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+	};
+	let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+	socket.connect((peer, 9)).ok()?;
+	Some(socket.local_addr().ok()?.ip()).filter(|address| !address.is_unspecified())
+}
+
+/// The address others on the network reach a listener at `address` by: that one unless it is
+/// loopback, or for all interfaces the one the default route leaves from, over IPv4 first.
+pub fn lan_address(address: IpAddr) -> Option<IpAddr> {
+	match address {
+		address if address.is_loopback() => None,
+		IpAddr::V4(address) if address.is_unspecified() => facing(OFF_NETWORK_V4.into()),
+		IpAddr::V6(address) if address.is_unspecified() => {
+			facing(OFF_NETWORK_V4.into()).or_else(|| facing(OFF_NETWORK_V6.into()))
+		},
+		address => Some(address),
+	}
+}
+
+/// The socket file of a `unix_socket`, removed again once the server stops with it.
+#[cfg(unix)]
+pub struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+/// A Unix socket listening at `path`, created with permissions `mode`. A socket file left behind
+/// by a server no longer running is replaced; one still answering, or any other file, is not.
+#[cfg(unix)]
+pub fn unix(path: &Path, mode: Option<u32>) -> std::io::Result<(UnixListener, SocketFile)> {
+	use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+	let error =
+		|kind, message: String| std::io::Error::new(kind, format!("Cannot listen on {}: {message}", path.display()));
+	if let Ok(metadata) = std::fs::symlink_metadata(path) {
+		if !metadata.file_type().is_socket() {
+			return Err(error(std::io::ErrorKind::AlreadyExists, "not a socket".to_string()));
+		}
+		if UnixStream::connect(path).is_ok() {
+			return Err(error(
+				std::io::ErrorKind::AddrInUse,
+				"in use by another server".to_string(),
+			));
+		}
+		std::fs::remove_file(path).map_err(|e| error(e.kind(), e.to_string()))?;
+	}
+	let listener = UnixListener::bind(path).map_err(|e| error(e.kind(), e.to_string()))?;
+	let file = SocketFile(path.to_path_buf());
+	if let Some(mode) = mode {
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+			.map_err(|e| error(e.kind(), e.to_string()))?;
+	}
+	Ok((listener, file))
+}
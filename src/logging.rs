@@ -0,0 +1,218 @@
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::access_log::{LogFormat, TARGET};
+use crate::config::{ByteSize, Config};
+
+/// When `--log-file` is moved aside and started afresh.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String")]
+pub enum LogRotation {
+	Never,
+	Hourly,
+	#[default]
+	Daily,
+	/// Once writing the next event would grow the file past this size.
+	Size(ByteSize),
+}
+
+impl FromStr for LogRotation {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"never" => Ok(Self::Never),
+			"hourly" => Ok(Self::Hourly),
+			"daily" => Ok(Self::Daily),
+			_ => s
+				.parse()
+				.map(Self::Size)
+				.map_err(|_| format!("Invalid rotation {s:?}, expected daily, hourly, never or a size")),
+		}
+	}
+}
+
+impl TryFrom<String> for LogRotation {
+	type Error = String;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+/// The time-based period a moment falls into, which also names the file rotated out at its end.
+fn period(rotation: LogRotation, time: chrono::DateTime<chrono::Local>) -> Option<String> {
+	match rotation {
+		LogRotation::Hourly => Some(time.format("%Y-%m-%d-%H").to_string()),
+		LogRotation::Daily => Some(time.format("%Y-%m-%d").to_string()),
+		LogRotation::Never | LogRotation::Size(_) => None,
+	}
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+	OpenOptions::new().create(true).append(true).open(path)
+}
+
+struct State {
+	file: File,
+	size: u64,
+	period: Option<String>,
+}
+
+/// A log file that renames itself to `<name>.<period or timestamp>` when due and keeps only the
+/// newest `keep` of those.
+pub struct RotatingFile {
+	path: PathBuf,
+	rotation: LogRotation,
+	keep: usize,
+	state: Mutex<State>,
+}
+
+impl RotatingFile {
+	pub fn open(path: &Path, rotation: LogRotation, keep: usize) -> std::io::Result<Self> {
+		let file = open_append(path)?;
+		let metadata = file.metadata()?;
+		// A file left by an earlier run belongs to the period it was last written in.
+		let written = metadata.modified().map_or_else(|_| chrono::Local::now(), Into::into);
+		Ok(Self {
+			path: path.to_path_buf(),
+			rotation,
+			keep,
+			state: Mutex::new(State {
+				file,
+				size: metadata.len(),
+				period: period(rotation, written),
+			}),
+		})
+	}
+
+	fn rotate_if_due(&self, state: &mut State, incoming: usize) -> std::io::Result<()> {
+		let now = chrono::Local::now();
+		let suffix = match self.rotation {
+			LogRotation::Size(ByteSize(limit)) if state.size > 0 && state.size + incoming as u64 > limit => {
+				now.format("%Y-%m-%d-%H%M%S-%3f").to_string()
+			},
+			LogRotation::Hourly | LogRotation::Daily => {
+				let current = period(self.rotation, now);
+				if state.period == current {
+					return Ok(());
+				}
+				let previous = std::mem::replace(&mut state.period, current);
+				previous.unwrap_or_default()
+			},
+			_ => return Ok(()),
+		};
+
+		let name = self.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+		let mut rotated = self.path.with_file_name(format!("{name}.{suffix}"));
+		let mut n = 1;
+		while rotated.exists() {
+			rotated = self.path.with_file_name(format!("{name}.{suffix}.{n}"));
+			n += 1;
+		}
+		std::fs::rename(&self.path, &rotated)?;
+		state.file = open_append(&self.path)?;
+		state.size = 0;
+		self.prune(&name)
+	}
+
+	/// Removes all but the newest `keep` rotated files. Their suffixes sort chronologically, with
+	/// the counter added on collisions breaking ties.
+	fn prune(&self, name: &str) -> std::io::Result<()> {
+		let dir = match self.path.parent() {
+			Some(dir) if !dir.as_os_str().is_empty() => dir,
+			_ => Path::new("."),
+		};
+		let prefix = format!("{name}.");
+		let mut rotated: Vec<((String, u32), PathBuf)> = std::fs::read_dir(dir)?
+			.flatten()
+			.filter_map(|entry| {
+				let file_name = entry.file_name().to_string_lossy().into_owned();
+				let suffix = file_name.strip_prefix(&prefix)?;
+				let key = match suffix.split_once('.') {
+					Some((stamp, n)) => (stamp.to_string(), n.parse().ok()?),
+					None => (suffix.to_string(), 0),
+				};
+				Some((key, entry.path()))
+			})
+			.collect();
+		rotated.sort();
+		for (_, old) in &rotated[..rotated.len().saturating_sub(self.keep)] {
+			std::fs::remove_file(old)?;
+		}
+		Ok(())
+	}
+}
+
+/// Handle given to the subscriber for writing one event.
+pub struct RotatingWriter<'a>(&'a RotatingFile);
+
+impl Write for RotatingWriter<'_> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let mut state = self.0.state.lock().unwrap();
+		// A failed rotation must not lose the event, so it goes to the current file instead.
+		let _ = self.0.rotate_if_due(&mut state, buf.len());
+		let written = state.file.write(buf)?;
+		state.size += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.0.state.lock().unwrap().file.flush()
+	}
+}
+
+impl<'a> MakeWriter<'a> for RotatingFile {
+	type Writer = RotatingWriter<'a>;
+
+	fn make_writer(&'a self) -> Self::Writer {
+		RotatingWriter(self)
+	}
+}
+
+/// Installs the global subscriber. Stdout shows events at `--log-level` (or `RUST_LOG`), as JSON
+/// for the `json` format; `--log-file` gets every access entry plus warnings and errors as JSON,
+/// whatever stdout is set to.
+pub fn init(config: &Config) -> std::io::Result<()> {
+	let filter = match &config.log_level {
+		Some(level) => EnvFilter::try_new(level)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid log level: {e}")))?,
+		None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+	};
+	let stdout = if config.log_format == LogFormat::Json {
+		tracing_subscriber::fmt::layer().json().with_filter(filter).boxed()
+	} else {
+		// The text formats write access lines to stdout themselves.
+		let filter = filter.add_directive(format!("{TARGET}=off").parse().map_err(std::io::Error::other)?);
+		tracing_subscriber::fmt::layer()
+			.with_ansi(std::io::stdout().is_terminal())
+			.with_filter(filter)
+			.boxed()
+	};
+
+	let file = match &config.log_file {
+		Some(path) => {
+			let writer = RotatingFile::open(path, config.log_rotate, config.log_keep)?;
+			let filter = EnvFilter::new(format!("warn,{TARGET}=info"));
+			Some(
+				tracing_subscriber::fmt::layer()
+					.json()
+					.with_ansi(false)
+					.with_writer(writer)
+					.with_filter(filter),
+			)
+		},
+		None => None,
+	};
+
+	tracing_subscriber::registry().with(stdout).with(file).init();
+	Ok(())
+}
@@ -0,0 +1,492 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+
+use crate::config::Listener;
+use crate::listeners;
+
+/// Where mDNS queries and announcements are sent (RFC 6762, section 3).
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+/// How long, in seconds, others may cache the records.
+const TTL: u32 = 120;
+
+const A: u16 = 1;
+const PTR: u16 = 12;
+const TXT: u16 = 16;
+const SRV: u16 = 33;
+const ANY: u16 = 255;
+
+/// The labels of a domain name, such as `["_http", "_tcp", "local"]`.
+type Name = Vec<String>;
+
+fn name(labels: &[&str]) -> Name {
+	labels.iter().map(|label| label.to_string()).collect()
+}
+
+/// The machine's host name, reduced to a single label of the letters, digits and hyphens a
+/// `.local` name may hold.
+pub fn host_name() -> String {
+	#[cfg(unix)]
+	let name = {
+		let mut buffer = [0u8; 256];
+		// SAFETY: the buffer is valid for its whole length, which is what is passed.
+		let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+		let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+		if result == 0 {
+			String::from_utf8_lossy(&buffer[..end]).into_owned()
+		} else {
+			String::new()
+		}
+	};
+	#[cfg(not(unix))]
+	let name = std::env::var("COMPUTERNAME").unwrap_or_default();
+
+	let label: String = name
+		.split('.')
+		.next()
+		.unwrap_or_default()
+		.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() {
+				c.to_ascii_lowercase()
+			} else {
+				'-'
+			}
+		})
+		.collect();
+	match label.trim_matches('-') {
+		"" => "http-server".to_string(),
+		label => label.to_string(),
+	}
+}
+
+/// The IPv4 address `peer` can reach the server at.
+fn facing(peer: Ipv4Addr) -> Option<Ipv4Addr> {
+	match listeners::facing(peer.into())? {
+		IpAddr::V4(address) => Some(address),
+		IpAddr::V6(_) => None,
+	}
+}
+
+/// Reads the name at `pos` in `packet`, following compression pointers, returning it lowercased
+/// along with where the data after it starts.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(Name, usize)> {
+	let mut labels = Vec::new();
+	let mut end = None;
+	// Bounded so that pointers leading in a circle end too.
+	for _ in 0..128 {
+		let len = usize::from(*packet.get(pos)?);
+		if len & 0xC0 == 0xC0 {
+			end.get_or_insert(pos + 2);
+			pos = (len & 0x3F) << 8 | usize::from(*packet.get(pos + 1)?);
+			continue;
+		}
+		if len == 0 {
+			return Some((labels, end.unwrap_or(pos + 1)));
+		}
+		let label = packet.get(pos + 1..pos + 1 + len)?;
+		labels.push(String::from_utf8_lossy(label).to_lowercase());
+		pos += 1 + len;
+	}
+	None
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[String]) {
+	for label in name {
+		let bytes = label.as_bytes();
+		let bytes = &bytes[..bytes.len().min(63)];
+		out.push(bytes.len() as u8);
+		out.extend_from_slice(bytes);
+	}
+	out.push(0);
+}
+
+fn same(a: &[String], b: &[String]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+}
+
+/// The `_http._tcp` (or `_https._tcp`) service the server is advertised as.
+pub struct Service {
+	/// Such as `["My files", "_http", "_tcp", "local"]`.
+	instance: Name,
+	/// Such as `["_http", "_tcp", "local"]`.
+	kind: Name,
+	/// Such as `["laptop", "local"]`.
+	host: Name,
+	port: u16,
+	/// The route prefix, for clients to open below the host.
+	path: String,
+	/// Set when listening on one address of the network only, which is then the only one given
+	/// out.
+	address: Option<Ipv4Addr>,
+}
+
+impl Service {
+	/// The service for `listener`, named `instance` or else after the machine.
+	pub fn new(instance: Option<&str>, listener: &Listener, secure: bool, prefix: &str) -> Self {
+		let host = host_name();
+		let mut instance = instance.map_or_else(|| host.clone(), str::to_string);
+		while instance.len() > 63 {
+			instance.pop();
+		}
+		let kind = name(&[if secure { "_https" } else { "_http" }, "_tcp", "local"]);
+		Self {
+			instance: [vec![instance], kind.clone()].concat(),
+			kind,
+			host: vec![host, "local".to_string()],
+			port: listener.address.port(),
+			path: if prefix.is_empty() {
+				"/".to_string()
+			} else {
+				format!("{prefix}/")
+			},
+			address: match listener.address.ip() {
+				IpAddr::V4(address) if !address.is_unspecified() && !address.is_loopback() => Some(address),
+				_ => None,
+			},
+		}
+	}
+
+	/// The host name the server is advertised at, such as `laptop.local`.
+	pub fn host(&self) -> String {
+		self.host.join(".")
+	}
+
+	fn record(out: &mut Vec<u8>, name: &[String], kind: u16, unique: bool, ttl: u32, data: &[u8]) {
+		write_name(out, name);
+		out.extend_from_slice(&kind.to_be_bytes());
+		// The top bit of the class tells caches to drop what they have for a name only this
+		// server answers for.
+		out.extend_from_slice(&(if unique { 0x8001u16 } else { 1 }).to_be_bytes());
+		out.extend_from_slice(&ttl.to_be_bytes());
+		out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+		out.extend_from_slice(data);
+	}
+
+	/// The records answering a question for `name` of type `kind`, counted.
+	fn answer(&self, out: &mut Vec<u8>, name: &[String], kind: u16, address: Option<Ipv4Addr>, ttl: u32) -> u16 {
+		let mut count = 0;
+		let wants = |record: u16| kind == record || kind == ANY;
+		let mut data = Vec::new();
+		if same(name, &self.kind) && wants(PTR) {
+			write_name(&mut data, &self.instance);
+			Self::record(out, &self.kind, PTR, false, ttl, &data);
+			count += 1;
+		}
+		if same(name, &["_services", "_dns-sd", "_udp", "local"].map(String::from)) && wants(PTR) {
+			data.clear();
+			write_name(&mut data, &self.kind);
+			Self::record(out, name, PTR, false, ttl, &data);
+			count += 1;
+		}
+		if same(name, &self.instance) && wants(SRV) {
+			data.clear();
+			data.extend_from_slice(&[0, 0, 0, 0]);
+			data.extend_from_slice(&self.port.to_be_bytes());
+			write_name(&mut data, &self.host);
+			Self::record(out, &self.instance, SRV, true, ttl, &data);
+			count += 1;
+		}
+		if same(name, &self.instance) && wants(TXT) {
+			data.clear();
+			let entry = format!("path={}", self.path);
+			data.push(entry.len().min(255) as u8);
+			data.extend_from_slice(&entry.as_bytes()[..entry.len().min(255)]);
+			Self::record(out, &self.instance, TXT, true, ttl, &data);
+			count += 1;
+		}
+		if let Some(address) = address.filter(|_| same(name, &self.host) && wants(A)) {
+			Self::record(out, &self.host, A, true, ttl, &address.octets());
+			count += 1;
+		}
+		count
+	}
+
+	/// A response carrying every record, as sent when announcing the service or, with a TTL of
+	/// zero, withdrawing it.
+	fn announcement(&self, ttl: u32) -> Vec<u8> {
+		let address = self.address.or_else(|| facing(GROUP));
+		let mut answers = Vec::new();
+		let count = self.answer(&mut answers, &self.kind, PTR, None, ttl)
+			+ self.answer(&mut answers, &self.instance, ANY, None, ttl)
+			+ self.answer(&mut answers, &self.host, A, address, ttl);
+		let mut packet = vec![0, 0, 0x84, 0, 0, 0];
+		packet.extend_from_slice(&count.to_be_bytes());
+		packet.extend_from_slice(&[0, 0, 0, 0]);
+		packet.extend_from_slice(&answers);
+		packet
+	}
+
+	/// The response to the query `packet` from `peer`, if it asks about the service.
+	fn respond(&self, packet: &[u8], peer: SocketAddrV4) -> Option<Vec<u8>> {
+		let header = packet.get(..12)?;
+		// Responses, and queries of other kinds than standard ones, are not answered.
+		if header[2] & 0xF8 != 0 {
+			return None;
+		}
+		let questions = u16::from_be_bytes([header[4], header[5]]);
+		// Queries from other ports are one-shot ones expecting an ordinary DNS reply, which
+		// repeats their ID and questions and is not cached for long (section 6.7).
+		let legacy = peer.port() != PORT;
+		let ttl = if legacy { TTL.min(10) } else { TTL };
+		let address = self.address.or_else(|| facing(*peer.ip()));
+		let mut answers = Vec::new();
+		let mut count = 0;
+		let mut pos = 12;
+		for _ in 0..questions {
+			let (name, end) = read_name(packet, pos)?;
+			let kind = u16::from_be_bytes([*packet.get(end)?, *packet.get(end + 1)?]);
+			pos = end + 4;
+			count += self.answer(&mut answers, &name, kind, address, ttl);
+		}
+		if count == 0 {
+			return None;
+		}
+		let mut response = if legacy {
+			vec![header[0], header[1], 0x84, 0, header[4], header[5]]
+		} else {
+			vec![0, 0, 0x84, 0, 0, 0]
+		};
+		response.extend_from_slice(&count.to_be_bytes());
+		response.extend_from_slice(&[0, 0, 0, 0]);
+		if legacy {
+			response.extend_from_slice(packet.get(12..pos)?);
+		}
+		response.extend_from_slice(&answers);
+		Some(response)
+	}
+}
+
+/// The mDNS socket answering for a [`Service`], which withdraws it when dropped.
+pub struct Responder {
+	service: Arc<Service>,
+	socket: UdpSocket,
+}
+
+impl Responder {
+	/// Joins the mDNS group, announces `service` and answers queries for it from a thread of its
+	/// own. The port is shared with any other responder on the machine.
+	pub fn start(service: Service) -> std::io::Result<Self> {
+		let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+		socket.set_reuse_address(true)?;
+		#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+		socket.set_reuse_port(true)?;
+		socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT).into())?;
+		socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+		socket.set_multicast_ttl_v4(255)?;
+		let socket: UdpSocket = socket.into();
+
+		let service = Arc::new(service);
+		let group = SocketAddrV4::new(GROUP, PORT);
+		socket.send_to(&service.announcement(TTL), group)?;
+		let (responder, answering) = (socket.try_clone()?, service.clone());
+		std::thread::Builder::new().name("mdns".to_string()).spawn(move || {
+			let mut buffer = [0u8; 9000];
+			while let Ok((len, peer)) = responder.recv_from(&mut buffer) {
+				let SocketAddr::V4(peer) = peer else {
+					continue;
+				};
+				if let Some(response) = answering.respond(&buffer[..len], peer) {
+					let to = if peer.port() == PORT { group } else { peer };
+					if let Err(e) = responder.send_to(&response, to) {
+						tracing::debug!("Failed to answer mDNS query from {peer}: {e}");
+					}
+				}
+			}
+		})?;
+		Ok(Self { service, socket })
+	}
+}
+
+impl Drop for Responder {
+	fn drop(&mut self) {
+		let _ = self
+			.socket
+			.send_to(&self.service.announcement(0), SocketAddrV4::new(GROUP, PORT));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn service() -> Service {
+		let listener = "192.168.1.5:8080".parse().unwrap();
+		Service::new(Some("My files"), &listener, false, "/share")
+	}
+
+	fn query(id: u16, questions: &[(&[&str], u16)]) -> Vec<u8> {
+		let mut packet = id.to_be_bytes().to_vec();
+		packet.extend_from_slice(&[0, 0]);
+		packet.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+		packet.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+		for (labels, kind) in questions {
+			write_name(&mut packet, &name(labels));
+			packet.extend_from_slice(&kind.to_be_bytes());
+			packet.extend_from_slice(&[0, 1]);
+		}
+		packet
+	}
+
+	/// The records of `packet` as (name, type, TTL, data), once past its questions.
+	fn records(packet: &[u8]) -> Vec<(Name, u16, u32, Vec<u8>)> {
+		let field = |pos: usize| u16::from_be_bytes([packet[pos], packet[pos + 1]]);
+		let mut pos = 12;
+		for _ in 0..field(4) {
+			pos = read_name(packet, pos).unwrap().1 + 4;
+		}
+		(0..field(6))
+			.map(|_| {
+				let (name, end) = read_name(packet, pos).unwrap();
+				let ttl = u32::from_be_bytes(packet[end + 4..end + 8].try_into().unwrap());
+				let len = usize::from(field(end + 8));
+				pos = end + 10 + len;
+				(name, field(end), ttl, packet[end + 10..pos].to_vec())
+			})
+			.collect()
+	}
+
+	const MULTICAST: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 9), PORT);
+
+	#[test]
+	fn browsing_finds_the_service() {
+		let service = service();
+		let response = service
+			.respond(&query(7, &[(&["_http", "_tcp", "local"], PTR)]), MULTICAST)
+			.unwrap();
+		// Multicast responses carry no ID or questions.
+		assert_eq!(response[..6], [0, 0, 0x84, 0, 0, 0]);
+		let records = records(&response);
+		assert_eq!(records.len(), 1);
+		let (name, kind, ttl, data) = &records[0];
+		assert_eq!(
+			(name.join("."), *kind, *ttl),
+			("_http._tcp.local".to_string(), PTR, TTL)
+		);
+		assert_eq!(read_name(data, 0).unwrap().0, ["my files", "_http", "_tcp", "local"]);
+	}
+
+	#[test]
+	fn instance_and_host_questions_are_answered() {
+		let service = service();
+		let instance = ["My files", "_http", "_tcp", "local"];
+		let host = [service.host[0].as_str(), "local"];
+		let packet = query(0, &[(&instance, ANY), (&host, A)]);
+		let records = records(&service.respond(&packet, MULTICAST).unwrap());
+		let kinds: Vec<u16> = records.iter().map(|record| record.1).collect();
+		assert_eq!(kinds, [SRV, TXT, A]);
+		let srv = &records[0].3;
+		assert_eq!(u16::from_be_bytes([srv[4], srv[5]]), 8080);
+		assert_eq!(read_name(srv, 6).unwrap().0, service.host);
+		assert_eq!(records[1].3, b"\x0cpath=/share/");
+		assert_eq!(records[2].3, [192, 168, 1, 5]);
+	}
+
+	#[test]
+	fn legacy_queries_get_an_ordinary_reply() {
+		let service = service();
+		let packet = query(0xBEEF, &[(&["_HTTP", "_tcp", "local"], PTR)]);
+		let peer = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 9), 40000);
+		let response = service.respond(&packet, peer).unwrap();
+		assert_eq!(response[..6], [0xBE, 0xEF, 0x84, 0, 0, 1]);
+		assert_eq!(response[12..packet.len()], packet[12..]);
+		assert_eq!(records(&response)[0].2, 10);
+	}
+
+	#[test]
+	fn other_names_and_responses_are_ignored() {
+		let service = service();
+		assert!(service
+			.respond(&query(0, &[(&["_ipp", "_tcp", "local"], PTR)]), MULTICAST)
+			.is_none());
+		assert!(service
+			.respond(&query(0, &[(&["_http", "_tcp", "local"], TXT)]), MULTICAST)
+			.is_none());
+		let mut response = query(0, &[(&["_http", "_tcp", "local"], PTR)]);
+		response[2] = 0x84;
+		assert!(service.respond(&response, MULTICAST).is_none());
+	}
+
+	#[test]
+	fn announcements_carry_every_record() {
+		let service = service();
+		for ttl in [TTL, 0] {
+			let records = records(&service.announcement(ttl));
+			let kinds: Vec<u16> = records.iter().map(|record| record.1).collect();
+			assert_eq!(kinds, [PTR, SRV, TXT, A]);
+			assert!(records.iter().all(|record| record.2 == ttl));
+		}
+	}
+
+	#[test]
+	fn compressed_names_are_followed() {
+		// "local" at 12, then "_tcp" pointing back at it.
+		let packet = b"\0\0\0\0\0\0\0\0\0\0\0\0\x05local\0\x04_tcp\xc0\x0c";
+		assert_eq!(read_name(packet, 19), Some((name(&["_tcp", "local"]), 26)));
+	}
+
+	#[test]
+	fn malformed_packets_are_dropped() {
+		let service = service();
+		let header = |questions: u16| {
+			let mut packet = vec![0; 12];
+			packet[4..6].copy_from_slice(&questions.to_be_bytes());
+			packet
+		};
+		let with = |questions: u16, rest: &[u8]| [header(questions), rest.to_vec()].concat();
+		let packets = [
+			Vec::new(),
+			vec![0; 11],
+			header(1),
+			header(u16::MAX),
+			// A label running past the end.
+			with(1, b"\x3fabc"),
+			// A name without its type and class.
+			with(1, b"\x05_http\0"),
+			// A pointer to itself, two pointing at each other, and one past the end.
+			with(1, b"\xc0\x0c\0\x0c\0\x01"),
+			with(1, b"\xc0\x0e\xc0\x0c\0\x0c\0\x01"),
+			with(1, b"\xc0\xff\0\x0c\0\x01"),
+			with(1, b"\xc0"),
+		];
+		for packet in &packets {
+			assert!(service.respond(packet, MULTICAST).is_none(), "{packet:?}");
+		}
+		// A truncated copy of a good query never panics either.
+		let good = query(
+			0,
+			&[
+				(&["_http", "_tcp", "local"], PTR),
+				(&["My files", "_http", "_tcp", "local"], SRV),
+			],
+		);
+		for len in 0..good.len() {
+			let _ = service.respond(&good[..len], MULTICAST);
+			let _ = service.respond(&good[..len], SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1));
+		}
+	}
+
+	#[test]
+	fn random_packets_do_not_panic() {
+		let service = service();
+		let mut state = 0x2545_F491_4F6C_DD1Du64;
+		let mut next = move || {
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			state
+		};
+		for _ in 0..10_000 {
+			let len = (next() % 64) as usize;
+			let mut packet: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+			// Mostly queries with a question or two, so parsing gets past the header.
+			if len > 5 {
+				packet[2] = 0;
+				packet[4] = 0;
+				packet[5] %= 3;
+			}
+			let _ = service.respond(&packet, MULTICAST);
+		}
+	}
+}
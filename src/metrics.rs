@@ -0,0 +1,120 @@
+use actix_web::{get, web, HttpResponse};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Served outside the file tree and, with `--metrics-auth`, behind its own credentials.
+pub const PATH: &str = "/_metrics";
+
+/// Counters shared by all workers, exposed in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+	responses: Mutex<BTreeMap<u16, u64>>,
+	sent_bytes: AtomicU64,
+	connections: AtomicU64,
+	streams: AtomicU64,
+	uploads: AtomicU64,
+	uploaded_bytes: AtomicU64,
+}
+
+impl Metrics {
+	pub fn record_response(&self, status: u16) {
+		*self.responses.lock().unwrap().entry(status).or_default() += 1;
+		self.streams.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Called once per response body, when it has been sent or abandoned.
+	pub fn record_sent(&self, bytes: u64) {
+		self.sent_bytes.fetch_add(bytes, Ordering::Relaxed);
+		self.streams.fetch_sub(1, Ordering::Relaxed);
+	}
+
+	pub fn record_upload(&self, bytes: u64) {
+		self.uploads.fetch_add(1, Ordering::Relaxed);
+		self.uploaded_bytes.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	fn render(&self) -> String {
+		let mut out = String::new();
+		let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+			let _ = writeln!(out, "# HELP {name} {help}");
+			let _ = writeln!(out, "# TYPE {name} {kind}");
+			for (labels, value) in samples {
+				let _ = writeln!(out, "{name}{labels} {value}");
+			}
+		};
+		let value = |counter: &AtomicU64| vec![(String::new(), counter.load(Ordering::Relaxed))];
+
+		let responses: Vec<(String, u64)> = self
+			.responses
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|(status, count)| (format!("{{status=\"{status}\"}}"), *count))
+			.collect();
+		metric(
+			"http_server_requests_total",
+			"counter",
+			"Requests answered, by response status.",
+			&responses,
+		);
+		metric(
+			"http_server_sent_bytes_total",
+			"counter",
+			"Response body bytes sent.",
+			&value(&self.sent_bytes),
+		);
+		metric(
+			"http_server_connections",
+			"gauge",
+			"Open client connections.",
+			&value(&self.connections),
+		);
+		metric(
+			"http_server_active_streams",
+			"gauge",
+			"Response bodies currently being sent.",
+			&value(&self.streams),
+		);
+		metric(
+			"http_server_uploads_total",
+			"counter",
+			"Files written by uploads.",
+			&value(&self.uploads),
+		);
+		metric(
+			"http_server_uploaded_bytes_total",
+			"counter",
+			"Bytes written by uploads.",
+			&value(&self.uploaded_bytes),
+		);
+		out
+	}
+}
+
+/// Counts a connection as open for as long as it lives in the connection's extensions.
+pub struct ConnectionGuard(web::Data<Metrics>);
+
+impl ConnectionGuard {
+	pub fn new(metrics: web::Data<Metrics>) -> Self {
+		metrics.connections.fetch_add(1, Ordering::Relaxed);
+		Self(metrics)
+	}
+}
+
+impl Drop for ConnectionGuard {
+	fn drop(&mut self) {
+		self.0.connections.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+#[get("/_metrics")]
+async fn serve_metrics(metrics: Option<web::Data<Metrics>>) -> HttpResponse {
+	let Some(metrics) = metrics else {
+		return HttpResponse::NotFound().body("Metrics are disabled");
+	};
+	HttpResponse::Ok()
+		.content_type("text/plain; version=0.0.4")
+		.body(metrics.render())
+}
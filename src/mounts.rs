@@ -0,0 +1,220 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::config::{normalize_prefix, Config};
+use crate::helpers::{request_host, unprefixed};
+use crate::ignore::Ignore;
+use crate::index::ContentIndex;
+
+/// A `--mount`, or a virtual host, with what its requests are handled with in place of the
+/// root's.
+pub struct Mounted {
+	/// The virtual host name, or `None` for a mount of the default host.
+	pub host: Option<String>,
+	/// The server's configuration with the mount's root, prefix and settings.
+	pub config: web::Data<Config>,
+	/// Registered even without any rules, so the root's never apply here.
+	pub ignore: web::Data<Ignore>,
+	pub content_index: Option<web::Data<ContentIndex>>,
+}
+
+/// Every virtual host and then every mount, longest path first, as the first one matching a
+/// request is the one it is for.
+#[derive(Default)]
+pub struct Mounts(pub Vec<Mounted>);
+
+impl Mounts {
+	pub fn from_config(config: &Config) -> std::io::Result<Self> {
+		Self::reusing(config, &Self::default())
+	}
+
+	/// The mounts of `config`, which must have the same virtual hosts as these. Those whose root
+	/// is unchanged keep their content index, which may only be open once.
+	pub fn reload(&self, config: &Config) -> std::io::Result<Self> {
+		let reloaded = Self::reusing(config, self)?;
+		if reloaded.hosts() != self.hosts() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"Virtual hosts cannot be added or removed without a restart",
+			));
+		}
+		Ok(reloaded)
+	}
+
+	fn reusing(config: &Config, previous: &Self) -> std::io::Result<Self> {
+		let mut hosts = Vec::new();
+		for (host, root) in &config.hosts {
+			let mut scoped = config.clone();
+			scoped.root = canonical_root(root)?;
+			scoped.mounts.clear();
+			scoped.hosts.clear();
+			scoped.index_dir = None;
+			hosts.push(Mounted::new(Some(host.to_ascii_lowercase()), scoped, previous)?);
+		}
+
+		let mut mounts = Vec::new();
+		for mount in &config.mounts {
+			let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+			let path = normalize_prefix(&mount.path)
+				.filter(|path| !path.is_empty())
+				.ok_or_else(|| invalid(format!("Invalid mount path {:?}", mount.path)))?;
+
+			let mut scoped = config.clone();
+			scoped.root = canonical_root(&mount.root)?;
+			scoped.route_prefix = format!("{}{path}", config.route_prefix);
+			if mount.read_only {
+				scoped.allow_upload = false;
+				scoped.allow_delete = false;
+			}
+			if !mount.auth.is_empty() {
+				scoped.auth.clone_from(&mount.auth);
+			}
+			scoped.mounts.clear();
+			scoped.hosts.clear();
+			scoped.index_dir = None;
+			mounts.push(Mounted::new(None, scoped, previous)?);
+		}
+		mounts.sort_by_key(|mount| Reverse(mount.config.route_prefix.len()));
+		hosts.extend(mounts);
+		Ok(Self(hosts))
+	}
+
+	/// The name of every virtual host, in no particular order.
+	fn hosts(&self) -> Vec<&str> {
+		let mut hosts: Vec<_> = self.0.iter().filter_map(|mount| mount.host.as_deref()).collect();
+		hosts.sort_unstable();
+		hosts
+	}
+
+	/// The virtual host or mount a request for `path` on `host` is for, or `None` when it belongs
+	/// to the root.
+	pub fn mounted_for(&self, host: Option<&str>, path: &str) -> Option<&Mounted> {
+		self.0.iter().find(|mount| match &mount.host {
+			Some(name) => host == Some(name.as_str()),
+			None => unprefixed(&mount.config, path).is_some(),
+		})
+	}
+
+	/// The configuration of the virtual host or mount a request for `path` on `host` is for, or
+	/// `None` when it belongs to the root.
+	pub fn config_for(&self, host: Option<&str>, path: &str) -> Option<&web::Data<Config>> {
+		self.mounted_for(host, path).map(|mount| &mount.config)
+	}
+}
+
+impl Mounted {
+	/// Sets up the app data for `config`, the server's with the root replaced. It should have no
+	/// `index_dir`, which belongs to the server's own root. The content index of the same mount in
+	/// `previous` is used if it has the same root.
+	fn new(host: Option<String>, config: Config, previous: &Mounts) -> std::io::Result<Self> {
+		let content_index = if config.index_content {
+			let kept = previous
+				.0
+				.iter()
+				.find(|mount| {
+					mount.host == host
+						&& mount.config.route_prefix == config.route_prefix
+						&& mount.config.root == config.root
+				})
+				.and_then(|mount| mount.content_index.clone());
+			match kept {
+				Some(content_index) => Some(content_index),
+				None => Some(web::Data::new(ContentIndex::start(&config)?)),
+			}
+		} else {
+			None
+		};
+		Ok(Self {
+			host,
+			ignore: web::Data::new(Ignore::new(&config)?),
+			content_index,
+			config: web::Data::new(config),
+		})
+	}
+}
+
+/// Canonicalizes a mount or virtual host's directory, expanding a leading `~`, which the shell
+/// leaves alone after `--mount /docs=`.
+fn canonical_root(root: &Path) -> std::io::Result<PathBuf> {
+	let expanded = match (root.strip_prefix("~"), std::env::var_os("HOME")) {
+		(Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+		_ => root.to_path_buf(),
+	};
+	let canonical = std::fs::canonicalize(expanded)
+		.map_err(|e| std::io::Error::new(e.kind(), format!("Cannot serve {}: {e}", root.display())))?;
+	if !canonical.is_dir() {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidInput,
+			format!("{} is not a directory", canonical.display()),
+		));
+	}
+	Ok(canonical)
+}
+
+/// Where requests for a virtual host or mount are routed, whatever path they are for, so that
+/// mounts can come and go without the routes changing.
+pub const SCOPE: &str = "/_mounted";
+
+/// Marks a request routed to [`SCOPE`], which no other request is.
+pub struct Routed;
+
+/// Middleware routing requests for a virtual host or mount to [`SCOPE`], with the mount's app
+/// data in place of the root's. The mounts are looked up for each request, as they may have
+/// changed since the routes were set up. Paths handed to other servers by `proxies` are left to
+/// them, as are those outside the route prefix.
+pub async fn use_mount(
+	mut req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+	let root = req.app_data::<web::Data<Config>>().cloned();
+	let routed = req.app_data::<web::Data<Mounts>>().cloned().and_then(|mounts| {
+		let host = request_host(req.headers(), req.uri());
+		let mount = mounts.mounted_for(host.as_deref(), req.path())?;
+		let rest = req.path().strip_prefix(&mount.config.route_prefix)?;
+		if !(rest.is_empty() || rest.starts_with('/')) || root.as_deref().is_some_and(|root| proxied(root, req.path()))
+		{
+			return None;
+		}
+		let uri = format!("{SCOPE}{rest}").parse().ok()?;
+		let mut data = Extensions::new();
+		data.insert(mount.config.clone());
+		data.insert(mount.ignore.clone());
+		if let Some(content_index) = &mount.content_index {
+			data.insert(content_index.clone());
+		}
+		Some((uri, data))
+	});
+	if let Some((uri, data)) = routed {
+		// Only the path routed on changes; handlers still see the one requested.
+		req.match_info_mut().get_mut().update(&uri);
+		req.extensions_mut().insert(Routed);
+		req.add_data_container(Rc::new(data));
+	}
+	next.call(req).await
+}
+
+/// Whether `path` is beneath one of the `proxies` of `config`.
+fn proxied(config: &Config, path: &str) -> bool {
+	config.proxies.iter().any(|proxy| {
+		normalize_prefix(&proxy.path).is_some_and(|prefix| {
+			path.strip_prefix(&format!("{}{prefix}", config.route_prefix))
+				.is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+		})
+	})
+}
+
+/// The configuration `req` is handled under. Middleware runs before a request is routed into its
+/// host or mount's scope, where that configuration is app data, so it looks it up here.
+pub fn request_config(req: &ServiceRequest) -> Option<web::Data<Config>> {
+	req.app_data::<web::Data<Mounts>>()
+		.and_then(|mounts| {
+			let host = request_host(req.headers(), req.uri());
+			mounts.config_for(host.as_deref(), req.path()).cloned()
+		})
+		.or_else(|| req.app_data::<web::Data<Config>>().cloned())
+}
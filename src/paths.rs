@@ -0,0 +1,162 @@
+use actix_web::HttpRequest;
+use icu_normalizer::ComposingNormalizer;
+use percent_encoding::percent_decode_str;
+use std::path::{Component, Path, PathBuf};
+
+/// Turns the `{path}` a route matched into the path, relative to the root, it names. The router
+/// has decoded all of it but `%25`, `%2B` and `%2F`, which it leaves for handlers to tell apart
+/// from `%`, `+` and `/`; they are decoded here. A `+` stays a plus, as it is only a space in
+/// query strings.
+pub fn decode(matched: &str) -> PathBuf {
+	PathBuf::from(percent_decode_str(matched).decode_utf8_lossy().trim_start_matches('/'))
+}
+
+fn nfc(name: &str) -> String {
+	const NFC: ComposingNormalizer = ComposingNormalizer::new_nfc();
+	NFC.normalize(name)
+}
+
+/// `path` as it is named on disk beneath `root`. Names missing as given are looked up among their
+/// directory's entries by their NFC form, so a request for `café` finds a `café` stored
+/// decomposed, as macOS keeps names and browsers do not send them, and the other way round. What
+/// is not found at all is kept as given, for paths about to be created; `None` is returned only
+/// for paths that are not plainly relative.
+pub fn on_disk(root: &Path, path: &Path) -> Option<PathBuf> {
+	if path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_)))
+		&& root.join(path).symlink_metadata().is_ok()
+	{
+		return Some(path.to_path_buf());
+	}
+	let mut found = PathBuf::new();
+	let mut exists = true;
+	for component in path.components() {
+		let name = match component {
+			Component::Normal(name) => name,
+			Component::CurDir => continue,
+			// Left for `resolve_path` to refuse, rather than followed out of the root.
+			_ => return None,
+		};
+		let dir = root.join(&found);
+		if !exists || dir.join(name).symlink_metadata().is_ok() {
+			found.push(name);
+			continue;
+		}
+		let wanted = name.to_str().map(nfc);
+		let entry = std::fs::read_dir(&dir).ok().and_then(|entries| {
+			entries
+				.filter_map(Result::ok)
+				.find(|entry| wanted.is_some() && entry.file_name().to_str().map(nfc) == wanted)
+		});
+		match entry {
+			Some(entry) => found.push(entry.file_name()),
+			None => {
+				exists = false;
+				found.push(name);
+			},
+		}
+	}
+	Some(found)
+}
+
+/// The path, relative to `root`, a request's `{path}` names, decoded and as it is spelled on disk
+/// if it exists there in another normalization form.
+pub fn request_path(req: &HttpRequest, root: &Path) -> PathBuf {
+	let path = decode(req.match_info().query("path"));
+	on_disk(root, &path).unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::helpers::encode_url_path;
+
+	/// A fresh directory for one test, removed again when dropped.
+	struct TempDir(PathBuf);
+
+	impl TempDir {
+		fn new(name: &str) -> Self {
+			let dir = std::env::temp_dir().join(format!("http-server-rs-paths-{name}-{}", std::process::id()));
+			let _ = std::fs::remove_dir_all(&dir);
+			std::fs::create_dir_all(&dir).unwrap();
+			Self(dir)
+		}
+	}
+
+	impl Drop for TempDir {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_dir_all(&self.0);
+		}
+	}
+
+	/// What a listing link for `name` comes back as, once the router has decoded it as it does.
+	fn round_trip(name: &str) -> PathBuf {
+		let link = encode_url_path(name);
+		let routed = percent_decode_str(&link)
+			.decode_utf8_lossy()
+			.replace('%', "%25")
+			.replace('+', "%2B");
+		decode(&routed)
+	}
+
+	#[test]
+	fn decodes_what_the_router_leaves() {
+		assert_eq!(decode("100%25.txt"), PathBuf::from("100%.txt"));
+		assert_eq!(decode("a%2Bb.txt"), PathBuf::from("a+b.txt"));
+		assert_eq!(decode("a%2Fb.txt"), PathBuf::from("a/b.txt"));
+		assert_eq!(decode("x%2520y.txt"), PathBuf::from("x%20y.txt"));
+	}
+
+	#[test]
+	fn keeps_plus_and_spaces() {
+		assert_eq!(decode("a+b.txt"), PathBuf::from("a+b.txt"));
+		assert_eq!(decode("a b.txt"), PathBuf::from("a b.txt"));
+		assert_eq!(decode("/dir/file"), PathBuf::from("dir/file"));
+		assert_eq!(decode(""), PathBuf::new());
+	}
+
+	#[test]
+	fn listing_links_round_trip() {
+		for name in [
+			"a b.txt",
+			"a+b.txt",
+			"100%.txt",
+			"x%20y.txt",
+			"q?.txt",
+			"h#.txt",
+			"naïve.txt",
+			"日本語/ファイル.txt",
+			"emoji 🎉.txt",
+		] {
+			assert_eq!(round_trip(name), PathBuf::from(name), "{name}");
+		}
+	}
+
+	#[test]
+	fn finds_names_in_another_normalization_form() {
+		let dir = TempDir::new("normalization");
+		let decomposed = "cafe\u{301}";
+		std::fs::create_dir(dir.0.join(decomposed)).unwrap();
+		std::fs::write(dir.0.join(decomposed).join("r\u{e9}sum\u{e9}.txt"), "").unwrap();
+
+		assert_eq!(on_disk(&dir.0, Path::new("caf\u{e9}")), Some(PathBuf::from(decomposed)));
+		assert_eq!(
+			on_disk(&dir.0, Path::new("caf\u{e9}/re\u{301}sume\u{301}.txt")),
+			Some(Path::new(decomposed).join("r\u{e9}sum\u{e9}.txt"))
+		);
+		assert_eq!(on_disk(&dir.0, Path::new(decomposed)), Some(PathBuf::from(decomposed)));
+		assert_eq!(
+			on_disk(&dir.0, Path::new("caf\u{e9}/new/file.txt")),
+			Some(Path::new(decomposed).join("new/file.txt"))
+		);
+	}
+
+	#[test]
+	fn does_not_leave_the_root() {
+		let dir = TempDir::new("traversal");
+		std::fs::create_dir(dir.0.join("inner")).unwrap();
+		assert_eq!(on_disk(&dir.0.join("inner"), Path::new("../inner")), None);
+		assert_eq!(on_disk(&dir.0, Path::new("/etc/missing")), None);
+	}
+}
@@ -0,0 +1,198 @@
+use actix_web::body::SizedStream;
+use actix_web::error::{ErrorBadGateway, ErrorBadRequest, ErrorPayloadTooLarge};
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Url;
+
+use crate::auth;
+use crate::config::{normalize_prefix, Config, MAX_BUFFERED_BODY};
+
+/// Headers describing one connection rather than the message (RFC 9110, section 7.6.1), which
+/// are never passed on.
+const HOP_BY_HOP: [&str; 8] = [
+	"connection",
+	"keep-alive",
+	"proxy-authenticate",
+	"proxy-authorization",
+	"te",
+	"trailer",
+	"transfer-encoding",
+	"upgrade",
+];
+
+/// A `--proxy`, with the client its requests are forwarded through.
+pub struct Upstream {
+	/// The path requests are forwarded from, with the route prefix.
+	pub path: String,
+	pub url: Url,
+	client: reqwest::Client,
+}
+
+impl Upstream {
+	pub fn from_config(config: &Config) -> std::io::Result<Vec<Self>> {
+		let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+		// Responses are handed back as they are, redirects and all.
+		let client = reqwest::Client::builder()
+			.redirect(reqwest::redirect::Policy::none())
+			.build()
+			.map_err(|e| invalid(format!("Failed to set up the proxy client: {e}")))?;
+		config
+			.proxies
+			.iter()
+			.map(|proxy| {
+				let path = normalize_prefix(&proxy.path)
+					.filter(|path| !path.is_empty())
+					.ok_or_else(|| invalid(format!("Invalid proxy path {:?}", proxy.path)))?;
+				let url = Url::parse(&proxy.upstream)
+					.ok()
+					.filter(|url| matches!(url.scheme(), "http" | "https") && url.query().is_none())
+					.ok_or_else(|| invalid(format!("Invalid proxy upstream {:?}", proxy.upstream)))?;
+				// Without a path of its own the upstream gets the request's, less any route prefix.
+				let mut url = url;
+				if url.path() == "/" {
+					url.set_path(&path);
+				}
+				Ok(Self {
+					path: format!("{}{path}", config.route_prefix),
+					url,
+					client: client.clone(),
+				})
+			})
+			.collect()
+	}
+
+	/// Where a request for `path`, which is beneath `self.path`, goes.
+	fn target(&self, path: &str, query: &str) -> Url {
+		let rest = path.strip_prefix(&self.path).unwrap_or_default();
+		let mut url = self.url.clone();
+		url.set_path(&format!("{}{rest}", self.url.path().trim_end_matches('/')));
+		url.set_query((!query.is_empty()).then_some(query));
+		url
+	}
+}
+
+/// The names a `Connection` header lists as hop-by-hop on top of the standard ones.
+fn connection_options<'a>(values: impl Iterator<Item = &'a [u8]>) -> Vec<String> {
+	values
+		.filter_map(|v| std::str::from_utf8(v).ok())
+		.flat_map(|v| v.split(','))
+		.map(|name| name.trim().to_ascii_lowercase())
+		.collect()
+}
+
+/// The request's end-to-end headers, with the `X-Forwarded-*` ones telling the upstream who
+/// the client was and what it asked for. The credentials of this server, the `Authorization`
+/// header it checks and its token cookie, are kept from the upstream.
+fn forwarded_headers(req: &HttpRequest, config: &Config) -> Result<HeaderMap, Error> {
+	let options = connection_options(req.headers().get_all(header::CONNECTION).map(|v| v.as_bytes()));
+	let own_credentials = !config.auth.is_empty() || config.token.is_some();
+	let mut headers = HeaderMap::new();
+	for (name, value) in req.headers() {
+		if HOP_BY_HOP.contains(&name.as_str())
+			|| options.iter().any(|option| option == name.as_str())
+			|| name == header::HOST
+			|| name == header::CONTENT_LENGTH
+			|| name == header::X_FORWARDED_FOR
+			|| (name == header::AUTHORIZATION && own_credentials)
+		{
+			continue;
+		}
+		let value = if name == header::COOKIE {
+			let Some(cookies) = value.to_str().ok().and_then(auth::without_token_cookie) else {
+				continue;
+			};
+			HeaderValue::from_str(&cookies).map_err(ErrorBadRequest)?
+		} else {
+			HeaderValue::from_bytes(value.as_bytes()).map_err(ErrorBadRequest)?
+		};
+		headers.append(
+			HeaderName::from_bytes(name.as_str().as_bytes()).map_err(ErrorBadRequest)?,
+			value,
+		);
+	}
+
+	let mut forwarded_for: Vec<String> = req
+		.headers()
+		.get_all(header::X_FORWARDED_FOR)
+		.filter_map(|v| v.to_str().ok())
+		.map(str::to_string)
+		.collect();
+	if let Some(peer) = req.peer_addr() {
+		forwarded_for.push(peer.ip().to_canonical().to_string());
+	}
+	let info = req.connection_info();
+	for (name, value) in [
+		("x-forwarded-for", forwarded_for.join(", ")),
+		("x-forwarded-host", info.host().to_string()),
+		("x-forwarded-proto", info.scheme().to_string()),
+	] {
+		if !value.is_empty() {
+			headers.insert(name, HeaderValue::from_str(&value).map_err(ErrorBadRequest)?);
+		}
+	}
+	Ok(headers)
+}
+
+/// Forwards the request to its `--proxy` upstream and streams the response back. The request
+/// body is read in full first, up to `--max-upload-size` or else [`MAX_BUFFERED_BODY`]; connection
+/// upgrades such as WebSockets are not passed through.
+pub async fn forward(
+	req: HttpRequest,
+	payload: web::Payload,
+	upstream: web::Data<Upstream>,
+	config: web::Data<Config>,
+) -> Result<HttpResponse, Error> {
+	let target = upstream.target(req.path(), req.query_string());
+	let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes()).map_err(ErrorBadRequest)?;
+	let headers = forwarded_headers(&req, &config)?;
+	let limit = config.max_upload_size.map_or(MAX_BUFFERED_BODY, |max| max.0);
+	let body = payload
+		.to_bytes_limited(usize::try_from(limit).unwrap_or(usize::MAX))
+		.await
+		.map_err(ErrorPayloadTooLarge)??;
+
+	let response = upstream
+		.client
+		.request(method, target.clone())
+		.headers(headers)
+		.body(body)
+		.send()
+		.await
+		.map_err(|e| {
+			tracing::warn!("Proxying {} to {target} failed: {e}", req.path());
+			ErrorBadGateway("Upstream unavailable")
+		})?;
+
+	let status = StatusCode::from_u16(response.status().as_u16()).map_err(ErrorBadGateway)?;
+	let mut builder = HttpResponse::build(status);
+	let options = connection_options(
+		response
+			.headers()
+			.get_all(reqwest::header::CONNECTION)
+			.iter()
+			.map(HeaderValue::as_bytes),
+	);
+	for (name, value) in response.headers() {
+		if HOP_BY_HOP.contains(&name.as_str())
+			|| options.iter().any(|option| option == name.as_str())
+			|| name == reqwest::header::CONTENT_LENGTH
+		{
+			continue;
+		}
+		builder.append_header((name.as_str(), value.as_bytes()));
+	}
+
+	// Read from the header, as the body of a response to HEAD is empty whatever its length.
+	let length = response
+		.headers()
+		.get(reqwest::header::CONTENT_LENGTH)
+		.and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+	let body = Box::pin(futures::stream::unfold(response, |mut response| async move {
+		response.chunk().await.transpose().map(|chunk| (chunk, response))
+	}));
+	Ok(match length {
+		Some(length) => builder.body(SizedStream::new(length, body)),
+		None => builder.streaming(body),
+	})
+}
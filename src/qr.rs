@@ -0,0 +1,683 @@
+//! A small QR code encoder, enough to show a URL in the terminal: byte mode at error correction
+//! level M, in versions 1 to 10 (up to 213 bytes).
+
+/// Error correction codewords per block at level M, by version.
+const ECC_PER_BLOCK: [usize; 10] = [10, 16, 26, 18, 24, 16, 18, 22, 22, 26];
+/// Error correction blocks at level M, by version.
+const BLOCKS: [usize; 10] = [1, 1, 1, 2, 2, 4, 4, 4, 5, 5];
+/// The format information bits of level M.
+const LEVEL_M: u32 = 0b00;
+
+/// A QR code, as rows of modules, `true` being dark.
+pub struct QrCode {
+	size: usize,
+	modules: Vec<Vec<bool>>,
+	/// Finder, timing and other modules that are not data, and so left alone by masks.
+	function: Vec<Vec<bool>>,
+}
+
+/// Data and error correction modules in a symbol of `version`, left over by the function
+/// patterns.
+fn raw_modules(version: usize) -> usize {
+	let mut modules = (16 * version + 128) * version + 64;
+	if version >= 2 {
+		let alignments = version / 7 + 2;
+		modules -= (25 * alignments - 10) * alignments - 55;
+		if version >= 7 {
+			modules -= 36;
+		}
+	}
+	modules
+}
+
+fn data_codewords(version: usize) -> usize {
+	raw_modules(version) / 8 - ECC_PER_BLOCK[version - 1] * BLOCKS[version - 1]
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn multiply(x: u8, y: u8) -> u8 {
+	let mut product = 0;
+	for i in (0..8).rev() {
+		product = (product << 1) ^ ((product >> 7) * 0x1D);
+		product ^= ((y >> i) & 1) * x;
+	}
+	product
+}
+
+/// The Reed-Solomon generator polynomial of `degree`, highest coefficient first and the leading 1
+/// left out.
+fn divisor(degree: usize) -> Vec<u8> {
+	let mut result = vec![0; degree];
+	result[degree - 1] = 1;
+	let mut root = 1;
+	for _ in 0..degree {
+		for j in 0..degree {
+			result[j] = multiply(result[j], root);
+			if j + 1 < degree {
+				result[j] ^= result[j + 1];
+			}
+		}
+		root = multiply(root, 0x02);
+	}
+	result
+}
+
+fn remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+	let mut result = vec![0; divisor.len()];
+	for &byte in data {
+		let factor = byte ^ result.remove(0);
+		result.push(0);
+		for (r, &d) in result.iter_mut().zip(divisor) {
+			*r ^= multiply(d, factor);
+		}
+	}
+	result
+}
+
+/// Rows and columns alignment patterns are centred on.
+fn alignment_positions(version: usize) -> Vec<usize> {
+	if version == 1 {
+		return Vec::new();
+	}
+	let count = version / 7 + 2;
+	let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+	let mut positions = vec![6];
+	let mut position = version * 4 + 17 - 7;
+	for _ in 0..count - 1 {
+		positions.insert(1, position);
+		position -= step;
+	}
+	positions
+}
+
+/// Whether mask `mask` flips the module at column `x` of row `y`.
+fn masked(mask: u8, x: usize, y: usize) -> bool {
+	match mask {
+		0 => (x + y).is_multiple_of(2),
+		1 => y.is_multiple_of(2),
+		2 => x.is_multiple_of(3),
+		3 => (x + y).is_multiple_of(3),
+		4 => (x / 3 + y / 2).is_multiple_of(2),
+		5 => x * y % 2 + x * y % 3 == 0,
+		6 => (x * y % 2 + x * y % 3).is_multiple_of(2),
+		_ => ((x + y) % 2 + x * y % 3).is_multiple_of(2),
+	}
+}
+
+impl QrCode {
+	/// The smallest code holding `data`, or `None` when it is too long.
+	pub fn new(data: &[u8]) -> Option<Self> {
+		let version = (1..=10).find(|&version| {
+			let count_bits = if version < 10 { 8 } else { 16 };
+			4 + count_bits + data.len() * 8 <= data_codewords(version) * 8
+		})?;
+
+		// Mode, length and the bytes themselves, then a terminator and padding up to capacity.
+		let capacity = data_codewords(version);
+		let mut bits = Vec::with_capacity(capacity * 8);
+		let mut push = |value: usize, len: usize| bits.extend((0..len).rev().map(|i| (value >> i) & 1 == 1));
+		push(0b0100, 4);
+		push(data.len(), if version < 10 { 8 } else { 16 });
+		for &byte in data {
+			push(usize::from(byte), 8);
+		}
+		let terminator = (capacity * 8 - bits.len()).min(4);
+		bits.extend(std::iter::repeat_n(false, terminator));
+		bits.extend(std::iter::repeat_n(false, (8 - bits.len() % 8) % 8));
+		let mut codewords: Vec<u8> = bits
+			.chunks(8)
+			.map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+			.collect();
+		for pad in [0xEC, 0x11].into_iter().cycle() {
+			if codewords.len() == capacity {
+				break;
+			}
+			codewords.push(pad);
+		}
+
+		let mut code = Self::blank(version);
+		code.draw_codewords(&interleave(version, &codewords));
+		let mask = (0..8)
+			.min_by_key(|&mask| {
+				code.apply_mask(mask);
+				code.draw_format(mask);
+				let penalty = code.penalty();
+				code.apply_mask(mask);
+				penalty
+			})
+			.unwrap_or_default();
+		code.apply_mask(mask);
+		code.draw_format(mask);
+		Some(code)
+	}
+
+	/// A symbol of `version` with its function patterns drawn.
+	fn blank(version: usize) -> Self {
+		let size = version * 4 + 17;
+		let mut code = Self {
+			size,
+			modules: vec![vec![false; size]; size],
+			function: vec![vec![false; size]; size],
+		};
+		for i in 0..size {
+			code.set(6, i, i % 2 == 0);
+			code.set(i, 6, i % 2 == 0);
+		}
+		for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+			code.finder(x, y);
+		}
+		let positions = alignment_positions(version);
+		let last = positions.len().saturating_sub(1);
+		for (i, &x) in positions.iter().enumerate() {
+			for (j, &y) in positions.iter().enumerate() {
+				// The corners with finder patterns have none.
+				let corner = (i == 0 || i == last) && (j == 0 || j == last) && (i, j) != (last, last);
+				if !corner {
+					code.alignment(x, y);
+				}
+			}
+		}
+		// Reserved until the mask is chosen.
+		code.draw_format(0);
+		if version >= 7 {
+			let mut remainder = version as u32;
+			for _ in 0..12 {
+				remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+			}
+			let bits = (version as u32) << 12 | remainder;
+			for i in 0..18 {
+				let dark = (bits >> i) & 1 == 1;
+				let (a, b) = (size - 11 + i % 3, i / 3);
+				code.set(a, b, dark);
+				code.set(b, a, dark);
+			}
+		}
+		code
+	}
+
+	fn set(&mut self, x: usize, y: usize, dark: bool) {
+		self.modules[y][x] = dark;
+		self.function[y][x] = true;
+	}
+
+	/// A finder pattern centred on `(x, y)`, with the light separator around it.
+	fn finder(&mut self, x: usize, y: usize) {
+		for dy in -4i32..=4 {
+			for dx in -4i32..=4 {
+				let (Some(xx), Some(yy)) = (x.checked_add_signed(dx as isize), y.checked_add_signed(dy as isize))
+				else {
+					continue;
+				};
+				if xx < self.size && yy < self.size {
+					let distance = dx.abs().max(dy.abs());
+					self.set(xx, yy, distance != 2 && distance != 4);
+				}
+			}
+		}
+	}
+
+	fn alignment(&mut self, x: usize, y: usize) {
+		for dy in -2i32..=2 {
+			for dx in -2i32..=2 {
+				let (xx, yy) = ((x as i32 + dx) as usize, (y as i32 + dy) as usize);
+				self.set(xx, yy, dx.abs().max(dy.abs()) != 1);
+			}
+		}
+	}
+
+	fn draw_format(&mut self, mask: u8) {
+		let data = LEVEL_M << 3 | u32::from(mask);
+		let mut remainder = data;
+		for _ in 0..10 {
+			remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+		}
+		let bits = (data << 10 | remainder) ^ 0x5412;
+		let bit = |i: usize| (bits >> i) & 1 == 1;
+		let size = self.size;
+		for i in 0..=5 {
+			self.set(8, i, bit(i));
+		}
+		self.set(8, 7, bit(6));
+		self.set(8, 8, bit(7));
+		self.set(7, 8, bit(8));
+		for i in 9..15 {
+			self.set(14 - i, 8, bit(i));
+		}
+		for i in 0..8 {
+			self.set(size - 1 - i, 8, bit(i));
+		}
+		for i in 8..15 {
+			self.set(8, size - 15 + i, bit(i));
+		}
+		self.set(8, size - 8, true);
+	}
+
+	/// Lays `data` out in the zigzag of two-module columns, upwards and downwards in turn from the
+	/// bottom right.
+	fn draw_codewords(&mut self, data: &[u8]) {
+		let mut i = 0;
+		let mut right = self.size - 1;
+		loop {
+			if right == 6 {
+				right = 5;
+			}
+			let upward = (right + 1) & 2 == 0;
+			for vertical in 0..self.size {
+				let y = if upward { self.size - 1 - vertical } else { vertical };
+				for x in [right, right - 1] {
+					if !self.function[y][x] && i < data.len() * 8 {
+						self.modules[y][x] = (data[i / 8] >> (7 - i % 8)) & 1 == 1;
+						i += 1;
+					}
+				}
+			}
+			if right < 2 {
+				break;
+			}
+			right -= 2;
+		}
+	}
+
+	/// Flips the data modules `mask` selects; applying it twice undoes it.
+	fn apply_mask(&mut self, mask: u8) {
+		for y in 0..self.size {
+			for x in 0..self.size {
+				if !self.function[y][x] && masked(mask, x, y) {
+					self.modules[y][x] ^= true;
+				}
+			}
+		}
+	}
+
+	/// How hard the code is to scan, by the four rules of the standard, for picking a mask.
+	fn penalty(&self) -> usize {
+		let size = self.size;
+		let mut penalty = 0;
+		let lines = (0..size).flat_map(|i| {
+			[
+				(0..size).map(|j| self.modules[i][j]).collect::<Vec<_>>(),
+				(0..size).map(|j| self.modules[j][i]).collect::<Vec<_>>(),
+			]
+		});
+		let finder = [true, false, true, true, true, false, true];
+		for line in lines {
+			let mut run = 1;
+			for j in 1..=size {
+				if j < size && line[j] == line[j - 1] {
+					run += 1;
+					continue;
+				}
+				if run >= 5 {
+					penalty += run - 2;
+				}
+				run = 1;
+			}
+			for start in 0..=size - finder.len() {
+				if line[start..start + finder.len()] == finder {
+					// Beyond the edge is the quiet zone, which is light.
+					let light = |range: std::ops::Range<usize>| range.into_iter().all(|k| line.get(k) != Some(&true));
+					if light(start.saturating_sub(4)..start) || light(start + 7..start + 11) {
+						penalty += 40;
+					}
+				}
+			}
+		}
+		for y in 0..size - 1 {
+			for x in 0..size - 1 {
+				let dark = self.modules[y][x];
+				if self.modules[y][x + 1] == dark
+					&& self.modules[y + 1][x] == dark
+					&& self.modules[y + 1][x + 1] == dark
+				{
+					penalty += 3;
+				}
+			}
+		}
+		let dark = self.modules.iter().flatten().filter(|&&dark| dark).count();
+		let total = size * size;
+		let deviation = (dark * 20).abs_diff(total * 10);
+		penalty + deviation.div_ceil(total).saturating_sub(1) * 10
+	}
+
+	/// The code as lines of text, two rows of modules to each line using half blocks, inside a
+	/// quiet zone. Light modules are the ones drawn, as terminal text is light on dark.
+	pub fn to_lines(&self) -> Vec<String> {
+		const QUIET: usize = 2;
+		let light = |x: usize, y: usize| {
+			let (Some(x), Some(y)) = (x.checked_sub(QUIET), y.checked_sub(QUIET)) else {
+				return true;
+			};
+			x >= self.size || y >= self.size || !self.modules[y][x]
+		};
+		let width = self.size + 2 * QUIET;
+		(0..width)
+			.step_by(2)
+			.map(|y| {
+				(0..width)
+					.map(|x| match (light(x, y), y + 1 < width && light(x, y + 1)) {
+						(true, true) => '█',
+						(true, false) => '▀',
+						(false, true) => '▄',
+						(false, false) => ' ',
+					})
+					.collect()
+			})
+			.collect()
+	}
+}
+
+/// The codewords of `version` split into blocks, each followed by its error correction, and read
+/// out a column at a time across the blocks.
+fn interleave(version: usize, data: &[u8]) -> Vec<u8> {
+	let blocks = BLOCKS[version - 1];
+	let ecc = ECC_PER_BLOCK[version - 1];
+	let raw = raw_modules(version) / 8;
+	let short_blocks = blocks - raw % blocks;
+	let short_len = raw / blocks;
+	let divisor = divisor(ecc);
+	let mut split = Vec::with_capacity(blocks);
+	let mut start = 0;
+	for i in 0..blocks {
+		let len = short_len - ecc + usize::from(i >= short_blocks);
+		let block = &data[start..start + len];
+		start += len;
+		split.push((block.to_vec(), remainder(block, &divisor)));
+	}
+	let mut result = Vec::with_capacity(raw);
+	for i in 0..=short_len - ecc {
+		for (block, _) in &split {
+			if let Some(&byte) = block.get(i) {
+				result.push(byte);
+			}
+		}
+	}
+	for i in 0..ecc {
+		for (_, correction) in &split {
+			result.push(correction[i]);
+		}
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Bytes in a version at level M, from the capacity table of ISO/IEC 18004.
+	const CAPACITY: [usize; 10] = [14, 26, 42, 62, 84, 106, 122, 152, 180, 213];
+	/// Blocks at level M as (count, data codewords) groups, and the error correction codewords of
+	/// each, from the same standard.
+	const STRUCTURE: [(&[(usize, usize)], usize); 10] = [
+		(&[(1, 16)], 10),
+		(&[(1, 28)], 16),
+		(&[(1, 44)], 26),
+		(&[(2, 32)], 18),
+		(&[(2, 43)], 24),
+		(&[(4, 27)], 16),
+		(&[(4, 31)], 18),
+		(&[(2, 38), (2, 39)], 22),
+		(&[(3, 36), (2, 37)], 22),
+		(&[(4, 43), (1, 44)], 26),
+	];
+	/// Alignment pattern centres of versions 2 to 10.
+	const ALIGNMENT: [&[usize]; 9] = [
+		&[6, 18],
+		&[6, 22],
+		&[6, 26],
+		&[6, 30],
+		&[6, 34],
+		&[6, 22, 38],
+		&[6, 24, 42],
+		&[6, 26, 46],
+		&[6, 28, 50],
+	];
+	/// Format information of level M by mask, and version information of versions 7 to 10.
+	const FORMAT: [u32; 8] = [
+		0b101010000010010,
+		0b101000100100101,
+		0b101111001111100,
+		0b101101101001011,
+		0b100010111111001,
+		0b100000011001110,
+		0b100111110010111,
+		0b100101010100000,
+	];
+	const VERSION: [u32; 4] = [
+		0b000111110010010100,
+		0b001000010110111100,
+		0b001001101010011001,
+		0b001010010011010011,
+	];
+
+	fn sample(len: usize) -> Vec<u8> {
+		(0..len).map(|i| (i * 37 + 11) as u8).collect()
+	}
+
+	/// The modules that are not data, worked out from the standard rather than the encoder.
+	fn function_modules(version: usize) -> Vec<Vec<bool>> {
+		let size = version * 4 + 17;
+		let mut function = vec![vec![false; size]; size];
+		let mut mark = |x0: usize, y0: usize, w: usize, h: usize| {
+			for row in &mut function[y0..y0 + h] {
+				row[x0..x0 + w].fill(true);
+			}
+		};
+		// Finders with their separators and format information.
+		mark(0, 0, 9, 9);
+		mark(size - 8, 0, 8, 9);
+		mark(0, size - 8, 9, 8);
+		mark(6, 0, 1, size);
+		mark(0, 6, size, 1);
+		if version >= 2 {
+			let centres = ALIGNMENT[version - 2];
+			for &x in centres {
+				for &y in centres {
+					let end = |c: usize| {
+						if c < 9 {
+							Some(false)
+						} else {
+							(c > size - 9).then_some(true)
+						}
+					};
+					// Those that would overlap a finder are left out.
+					if !matches!((end(x), end(y)), (Some(_), Some(false)) | (Some(false), Some(true))) {
+						mark(x - 2, y - 2, 5, 5);
+					}
+				}
+			}
+		}
+		if version >= 7 {
+			mark(size - 11, 0, 3, 6);
+			mark(0, size - 11, 6, 3);
+		}
+		function
+	}
+
+	/// Reads `code` back as a decoder would, checking every codeword against its error correction,
+	/// and returns the bytes it holds.
+	fn decode(code: &QrCode, version: usize) -> Vec<u8> {
+		let size = code.size;
+		assert_eq!(size, version * 4 + 17);
+		let function = function_modules(version);
+		assert!(function == code.function, "function patterns of version {version}");
+		let dark = |x: usize, y: usize| code.modules[y][x];
+
+		for (x, y) in [(0, 0), (size - 7, 0), (0, size - 7)] {
+			for dy in 0..7 {
+				for dx in 0..7 {
+					let ring = dx.min(dy).min(6 - dx).min(6 - dy);
+					assert_eq!(dark(x + dx, y + dy), ring != 1, "finder at ({x}, {y})");
+				}
+			}
+		}
+		for i in 8..size - 8 {
+			assert_eq!(dark(i, 6), i % 2 == 0);
+			assert_eq!(dark(6, i), i % 2 == 0);
+		}
+		assert!(dark(8, size - 8));
+
+		let read = |positions: &[(usize, usize)]| {
+			positions
+				.iter()
+				.fold(0, |acc, &(x, y)| acc << 1 | u32::from(dark(x, y)))
+		};
+		let around: Vec<_> = (0..=5)
+			.map(|y| (8, y))
+			.chain([(8, 7), (8, 8), (7, 8)])
+			.chain((0..=5).rev().map(|x| (x, 8)))
+			.rev()
+			.collect();
+		let beside: Vec<_> = (size - 7..size)
+			.rev()
+			.map(|y| (8, y))
+			.chain((size - 8..size).map(|x| (x, 8)))
+			.collect();
+		let format = read(&around);
+		assert_eq!(read(&beside), format);
+		let mask = FORMAT
+			.iter()
+			.position(|&bits| bits == format)
+			.expect("format information of level M");
+		if version >= 7 {
+			let bits: Vec<_> = (0..18).rev().map(|i| (size - 11 + i % 3, i / 3)).collect();
+			let transposed: Vec<_> = bits.iter().map(|&(x, y)| (y, x)).collect();
+			assert_eq!(read(&bits), VERSION[version - 7]);
+			assert_eq!(read(&transposed), VERSION[version - 7]);
+		}
+
+		// The data modules in the zigzag, unmasked.
+		let mut bits = Vec::new();
+		let mut columns: Vec<usize> = (7..size).rev().step_by(2).collect();
+		columns.extend((1..6).rev().step_by(2));
+		for (pair, &right) in columns.iter().enumerate() {
+			for step in 0..size {
+				let y = if pair % 2 == 0 { size - 1 - step } else { step };
+				for x in [right, right - 1] {
+					if !function[y][x] {
+						let flip = match mask {
+							0 => (y + x) % 2 == 0,
+							1 => y % 2 == 0,
+							2 => x % 3 == 0,
+							3 => (y + x) % 3 == 0,
+							4 => (y / 2 + x / 3) % 2 == 0,
+							5 => (y * x) % 2 + (y * x) % 3 == 0,
+							6 => ((y * x) % 2 + (y * x) % 3) % 2 == 0,
+							_ => ((y + x) % 2 + (y * x) % 3) % 2 == 0,
+						};
+						bits.push(dark(x, y) != flip);
+					}
+				}
+			}
+		}
+		let codewords: Vec<u8> = bits
+			.chunks_exact(8)
+			.map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+			.collect();
+
+		// Undo the interleaving, then check each block has no errors: a Reed-Solomon codeword is
+		// zero at the first `ecc` powers of the generator.
+		let (groups, ecc) = STRUCTURE[version - 1];
+		let lengths: Vec<usize> = groups
+			.iter()
+			.flat_map(|&(count, len)| std::iter::repeat_n(len, count))
+			.collect();
+		let longest = lengths.iter().max().copied().unwrap();
+		let mut blocks: Vec<Vec<u8>> = vec![Vec::new(); lengths.len()];
+		let mut next = codewords.iter().copied();
+		for i in 0..longest + ecc {
+			for (block, &len) in blocks.iter_mut().zip(&lengths) {
+				if i < len || i >= longest {
+					block.push(next.next().expect("enough codewords"));
+				}
+			}
+		}
+		let mut exp = [0u8; 255];
+		let mut x = 1u16;
+		for power in &mut exp {
+			*power = x as u8;
+			x <<= 1;
+			if x > 0xFF {
+				x ^= 0x11D;
+			}
+		}
+		let log = |value: u8| exp.iter().position(|&power| power == value).unwrap();
+		for block in &blocks {
+			for i in 0..ecc {
+				let syndrome = block.iter().fold(0, |acc: u8, &c| {
+					let scaled = if acc == 0 { 0 } else { exp[(log(acc) + i) % 255] };
+					scaled ^ c
+				});
+				assert_eq!(syndrome, 0, "error correction of version {version}");
+			}
+		}
+
+		let data: Vec<bool> = blocks
+			.iter()
+			.zip(&lengths)
+			.flat_map(|(block, &len)| block[..len].to_vec())
+			.flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+			.collect();
+		let mut data = data.into_iter();
+		let mut take = |len: usize| (0..len).fold(0, |acc, _| acc << 1 | usize::from(data.next().unwrap()));
+		assert_eq!(take(4), 0b0100, "byte mode");
+		let len = take(if version < 10 { 8 } else { 16 });
+		let bytes: Vec<u8> = (0..len).map(|_| take(8) as u8).collect();
+		// A terminator up to the byte boundary, then alternating pad codewords.
+		let rest: Vec<bool> = data.collect();
+		let (terminator, pads) = rest.split_at(rest.len() % 8);
+		assert!(terminator.iter().all(|&bit| !bit));
+		for (pad, expected) in pads.chunks(8).zip([0xEC, 0x11].into_iter().cycle()) {
+			assert_eq!(
+				pad.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)),
+				expected,
+				"padding"
+			);
+		}
+		bytes
+	}
+
+	#[test]
+	fn error_correction_matches_the_worked_example() {
+		// "HELLO WORLD" at 1-M, as the standard's annex and most tutorials work it through.
+		let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+		let ecc = [196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+		assert_eq!(remainder(&data, &divisor(10)), ecc);
+	}
+
+	#[test]
+	fn capacity_is_that_of_the_standard() {
+		for version in 1..=10 {
+			let (groups, ecc) = STRUCTURE[version - 1];
+			let data: usize = groups.iter().map(|(count, len)| count * len).sum();
+			assert_eq!(data_codewords(version), data);
+			assert_eq!(
+				raw_modules(version) / 8,
+				data + ecc * groups.iter().map(|(count, _)| count).sum::<usize>()
+			);
+			let mut centres = alignment_positions(version);
+			centres.sort_unstable();
+			assert_eq!(
+				centres,
+				ALIGNMENT.get(version.wrapping_sub(2)).copied().unwrap_or_default()
+			);
+		}
+	}
+
+	#[test]
+	fn codes_decode_back_at_every_version_boundary() {
+		let mut lengths = vec![0, 1];
+		lengths.extend(CAPACITY.iter().flat_map(|&capacity| [capacity, capacity + 1]));
+		for len in lengths {
+			let data = sample(len);
+			let version = CAPACITY.iter().position(|&capacity| len <= capacity).map(|i| i + 1);
+			match (QrCode::new(&data), version) {
+				(Some(code), Some(version)) => assert_eq!(decode(&code, version), data, "{len} bytes"),
+				(None, None) => {},
+				(code, _) => panic!(
+					"{len} bytes made a code: {}, expected version {version:?}",
+					code.is_some()
+				),
+			}
+		}
+	}
+}
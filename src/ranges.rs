@@ -0,0 +1,290 @@
+use actix_web::body::SizedStream;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{EntityTag, RANGE};
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder, Result};
+use bytes::Bytes;
+use futures::stream::{self, LocalBoxStream, Stream, StreamExt};
+use std::fs::File;
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::helpers::{insert_validators, range_allowed};
+use crate::structs::{self, VideoStream};
+
+/// Requests for more ranges than this are served whole, as many small overlapping ranges could
+/// make the response far larger than the file itself.
+const MAX_RANGES: usize = 32;
+
+/// One `first-last`, `first-` or `-suffix` spec. `None` if it is malformed, `Some(None)` if it
+/// lies past the end of the file.
+fn parse_spec(spec: &str, size: u64) -> Option<Option<(u64, u64)>> {
+	let (first, last) = spec.split_once('-')?;
+	if first.is_empty() {
+		let suffix = last.parse::<u64>().ok()?;
+		return Some((suffix > 0 && size > 0).then(|| (size.saturating_sub(suffix), size - 1)));
+	}
+
+	let first = first.parse::<u64>().ok()?;
+	let last = if last.is_empty() {
+		u64::MAX
+	} else {
+		last.parse::<u64>().ok()?
+	};
+	if last < first {
+		return None;
+	}
+	Some((first < size).then(|| (first, last.min(size - 1))))
+}
+
+/// Parses a `Range` header against `size` bytes into inclusive `(first, last)` pairs, with ends
+/// past the file clamped to it. `None` means the header is to be ignored, being in another unit
+/// or asking for too many ranges; an empty list means none of it can be satisfied.
+pub fn parse_ranges(header: &str, size: u64) -> Option<Vec<(u64, u64)>> {
+	let specs: Vec<&str> = header
+		.trim()
+		.strip_prefix("bytes=")?
+		.split(',')
+		.map(str::trim)
+		.filter(|spec| !spec.is_empty())
+		.collect();
+	if specs.len() > MAX_RANGES {
+		return None;
+	}
+
+	let ranges = specs
+		.iter()
+		.map(|spec| parse_spec(spec, size))
+		.collect::<Option<Vec<_>>>();
+	Some(ranges.unwrap_or_default().into_iter().flatten().collect())
+}
+
+fn lenient(req: &HttpRequest) -> bool {
+	req.app_data::<web::Data<Config>>()
+		.is_some_and(|config| config.lenient_ranges)
+}
+
+/// Streams a span of the file with the configured chunk size and read-ahead, or from a memory map
+/// when it is large enough for `mmap_min_size` and on a read-only filesystem.
+fn chunks(req: &HttpRequest, file: File, start: u64, end: u64) -> std::io::Result<VideoStream> {
+	let Some(config) = req.app_data::<web::Data<Config>>() else {
+		return VideoStream::new(file, start, end);
+	};
+	let chunk_size = usize::try_from(config.chunk_size.0).unwrap_or(usize::MAX);
+	if config.mmap_min_size.is_some_and(|min| end - start + 1 >= min.0) && structs::unchanging(&file) {
+		return VideoStream::mapped(file, start, end, chunk_size);
+	}
+	VideoStream::with_buffering(file, start, end, chunk_size, config.read_ahead)
+}
+
+/// A body of `len` bytes. Answers to `HEAD` only announce its length, so nothing is read just
+/// to be thrown away.
+pub fn body<S>(req: &HttpRequest, len: u64, stream: S) -> SizedStream<LocalBoxStream<'static, std::io::Result<Bytes>>>
+where
+	S: Stream<Item = std::io::Result<Bytes>> + 'static,
+{
+	let stream = if req.method() == Method::HEAD {
+		stream::empty().boxed_local()
+	} else {
+		stream.boxed_local()
+	};
+	SizedStream::new(len, stream)
+}
+
+fn boundary() -> String {
+	let mut bytes = [0u8; 12];
+	rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut bytes);
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Sends the `size` bytes of `file` starting at `offset`: as the ranges asked for when the
+/// `Range` header may be honoured, as a `multipart/byteranges` body when there are several of
+/// them, and whole otherwise. Ranges that all miss the file get a `416`, unless
+/// `lenient_ranges` asks for the whole file instead.
+pub fn respond(
+	req: &HttpRequest,
+	file: File,
+	offset: u64,
+	size: u64,
+	mime_type: String,
+	etag: EntityTag,
+	modified: Option<SystemTime>,
+) -> Result<HttpResponse> {
+	let ranges = req
+		.headers()
+		.get(RANGE)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| parse_ranges(v, size))
+		// A stale `If-Range` means the client's partial copy is outdated, so it gets the whole
+		// file instead.
+		.filter(|_| range_allowed(req, &etag, modified))
+		.filter(|ranges| !ranges.is_empty() || !lenient(req));
+
+	let mut response = match ranges.as_deref() {
+		Some([]) => HttpResponse::RangeNotSatisfiable(),
+		Some(_) => HttpResponse::PartialContent(),
+		None => HttpResponse::Ok(),
+	};
+	insert_validators(&mut response, etag, modified);
+	response.append_header(("Accept-Ranges", "bytes"));
+
+	match ranges.as_deref() {
+		Some([]) => Ok(response
+			.append_header(("Content-Range", format!("bytes */{size}")))
+			.finish()),
+		Some(&[(first, last)]) => {
+			let stream = chunks(req, file, offset + first, offset + last).map_err(ErrorInternalServerError)?;
+			Ok(response
+				.append_header(("Content-Range", format!("bytes {first}-{last}/{size}")))
+				.content_type(mime_type)
+				.body(body(req, last - first + 1, stream)))
+		},
+		Some(ranges) => Ok(multipart(req, response, file, offset, size, &mime_type, ranges)),
+		None if size == 0 => Ok(response.content_type(mime_type).finish()),
+		None => {
+			let stream = chunks(req, file, offset, offset + size - 1).map_err(ErrorInternalServerError)?;
+			Ok(response.content_type(mime_type).body(body(req, size, stream)))
+		},
+	}
+}
+
+/// Each part is read only once the previous one is done, so the parts can share the file's
+/// position between clones of its handle.
+fn multipart(
+	req: &HttpRequest,
+	mut response: HttpResponseBuilder,
+	file: File,
+	offset: u64,
+	size: u64,
+	mime_type: &str,
+	ranges: &[(u64, u64)],
+) -> HttpResponse {
+	let boundary = boundary();
+	let parts: Vec<(Bytes, u64, u64)> = ranges
+		.iter()
+		.enumerate()
+		.map(|(i, &(first, last))| {
+			let separator = if i == 0 { "" } else { "\r\n" };
+			let head = format!(
+				"{separator}--{boundary}\r\nContent-Type: {mime_type}\r\nContent-Range: bytes {first}-{last}/{size}\r\n\r\n"
+			);
+			(Bytes::from(head), first, last)
+		})
+		.collect();
+	let tail = Bytes::from(format!("\r\n--{boundary}--\r\n"));
+	let length = parts
+		.iter()
+		.map(|(head, first, last)| head.len() as u64 + last - first + 1)
+		.sum::<u64>()
+		+ tail.len() as u64;
+
+	let request = req.clone();
+	let parts = stream::iter(parts)
+		.flat_map(move |(head, first, last)| {
+			let part = file
+				.try_clone()
+				.and_then(|file| chunks(&request, file, offset + first, offset + last));
+			match part {
+				Ok(part) => stream::once(async { Ok(head) }).chain(part).left_stream(),
+				Err(e) => stream::once(async { Err(e) }).right_stream(),
+			}
+		})
+		.chain(stream::once(async { Ok(tail) }));
+
+	response
+		.content_type(format!("multipart/byteranges; boundary={boundary}"))
+		.body(body(req, length, parts))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use actix_web::http::StatusCode;
+	use actix_web::test::TestRequest;
+
+	#[test]
+	fn suffix_ranges_count_back_from_the_end() {
+		assert_eq!(parse_ranges("bytes=-500", 10_000), Some(vec![(9_500, 9_999)]));
+		// Longer than the file, so all of it.
+		assert_eq!(parse_ranges("bytes=-20000", 100), Some(vec![(0, 99)]));
+		assert_eq!(parse_ranges("bytes=-0", 100), Some(vec![]));
+	}
+
+	#[test]
+	fn ends_past_the_file_are_clamped() {
+		assert_eq!(parse_ranges("bytes=0-5000", 1_000), Some(vec![(0, 999)]));
+		assert_eq!(parse_ranges("bytes=990-", 1_000), Some(vec![(990, 999)]));
+		assert_eq!(parse_ranges(" bytes=0-0 ", 1_000), Some(vec![(0, 0)]));
+	}
+
+	#[test]
+	fn overlapping_ranges_are_kept_as_asked() {
+		assert_eq!(
+			parse_ranges("bytes=0-499, 200-699,0-", 1_000),
+			Some(vec![(0, 499), (200, 699), (0, 999)])
+		);
+	}
+
+	#[test]
+	fn too_many_ranges_are_ignored() {
+		let specs = |n: u64| (0..n).map(|i| format!("{i}-{i}")).collect::<Vec<_>>().join(",");
+		let ranges = parse_ranges(&format!("bytes={}", specs(MAX_RANGES as u64)), 1_000);
+		assert_eq!(ranges.map(|ranges| ranges.len()), Some(MAX_RANGES));
+		assert_eq!(
+			parse_ranges(&format!("bytes={}", specs(MAX_RANGES as u64 + 1)), 1_000),
+			None
+		);
+	}
+
+	#[test]
+	fn malformed_specs_satisfy_nothing() {
+		assert_eq!(parse_ranges("bytes=500-100", 1_000), Some(vec![]));
+		assert_eq!(parse_ranges("bytes=0-9,500-100", 1_000), Some(vec![]));
+		assert_eq!(parse_ranges("bytes=a-b", 1_000), Some(vec![]));
+		assert_eq!(parse_ranges("bytes=100", 1_000), Some(vec![]));
+	}
+
+	#[test]
+	fn other_units_are_ignored() {
+		assert_eq!(parse_ranges("items=0-9", 1_000), None);
+		assert_eq!(parse_ranges("0-9", 1_000), None);
+	}
+
+	#[test]
+	fn ranges_past_the_end_are_unsatisfiable() {
+		assert_eq!(parse_ranges("bytes=999-", 1_000), Some(vec![(999, 999)]));
+		assert_eq!(parse_ranges("bytes=1000-", 1_000), Some(vec![]));
+		// Only the ranges that miss are dropped.
+		assert_eq!(parse_ranges("bytes=0-9,5000-", 1_000), Some(vec![(0, 9)]));
+	}
+
+	#[test]
+	fn nothing_in_an_empty_file_is_satisfiable() {
+		assert_eq!(parse_ranges("bytes=0-", 0), Some(vec![]));
+		assert_eq!(parse_ranges("bytes=0-0", 0), Some(vec![]));
+		assert_eq!(parse_ranges("bytes=-5", 0), Some(vec![]));
+	}
+
+	#[test]
+	fn unsatisfiable_ranges_get_a_416() {
+		let path = std::env::temp_dir().join(format!("http-server-rs-ranges-{}", std::process::id()));
+		std::fs::write(&path, b"0123456789").unwrap();
+		let file = File::open(&path).unwrap();
+		let req = TestRequest::default()
+			.insert_header((RANGE, "bytes=10-"))
+			.to_http_request();
+		let response = respond(
+			&req,
+			file,
+			0,
+			10,
+			"text/plain".into(),
+			EntityTag::new_strong("x".into()),
+			None,
+		)
+		.unwrap();
+		let _ = std::fs::remove_file(&path);
+		assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+		assert_eq!(response.headers().get("content-range").unwrap(), "bytes */10");
+	}
+}
@@ -0,0 +1,239 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Extensions, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{post, web, Error, HttpResponse};
+use arc_swap::ArcSwap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::auth::generate_token;
+use crate::config::Config;
+use crate::errors::ErrorPages;
+use crate::headers::CustomHeaders;
+use crate::ignore::Ignore;
+use crate::mounts::Mounts;
+use crate::rules::Rules;
+use crate::stats::Stats;
+use crate::throttle::Bucket;
+
+/// Where a fresh configuration comes from when reloading.
+pub type Loader = Box<dyn Fn() -> std::io::Result<Config> + Send + Sync>;
+
+/// The app data a reload replaces, given to each request as it was when the request came in.
+pub struct Settings {
+	/// The configuration as loaded or changed through the admin API, before `read_only`.
+	configured: Config,
+	/// Refuses uploads, deletions and other changes everywhere, whatever the configuration says.
+	pub read_only: bool,
+	/// The configuration requests are handled under.
+	pub config: web::Data<Config>,
+	pub mounts: web::Data<Mounts>,
+	pub ignore: Option<web::Data<Ignore>>,
+	pub rules: Option<web::Data<Rules>>,
+	pub custom_headers: Option<web::Data<CustomHeaders>>,
+	pub total_rate: Option<web::Data<Bucket>>,
+	pub error_pages: web::Data<ErrorPages>,
+}
+
+impl Settings {
+	/// The settings for `configured`, keeping the content indexes of mounts and the shared rate
+	/// limit of `previous` where they are unchanged. The `.gitignore` and error pages are read
+	/// again.
+	pub fn new(configured: Config, read_only: bool, previous: Option<&Self>) -> std::io::Result<Self> {
+		let mut config = configured.clone();
+		if read_only {
+			config.allow_upload = false;
+			config.allow_delete = false;
+		}
+		let mounts = match previous {
+			Some(previous) => previous.mounts.reload(&config)?,
+			None => Mounts::from_config(&config)?,
+		};
+		let total_rate = match previous {
+			Some(previous) if previous.configured.limit_rate_total == configured.limit_rate_total => {
+				previous.total_rate.clone()
+			},
+			_ => configured
+				.limit_rate_total
+				.map(|rate| web::Data::new(Bucket::new(rate.0))),
+		};
+		Ok(Self {
+			mounts: web::Data::new(mounts),
+			ignore: Ignore::from_config(&config)?.map(web::Data::new),
+			rules: Rules::from_config(&config)?.map(web::Data::new),
+			custom_headers: CustomHeaders::from_config(&config)?.map(web::Data::new),
+			total_rate,
+			error_pages: web::Data::new(ErrorPages::from_config(&config)?),
+			config: web::Data::new(config),
+			configured,
+			read_only,
+		})
+	}
+
+	/// These settings with what `change` makes of the configuration.
+	pub fn changed(&self, change: impl FnOnce(&mut Config) -> std::io::Result<()>) -> std::io::Result<Self> {
+		let mut configured = self.configured.clone();
+		change(&mut configured)?;
+		Self::new(configured, self.read_only, Some(self))
+	}
+
+	/// These settings made read-only, or no longer.
+	pub fn with_read_only(&self, read_only: bool) -> std::io::Result<Self> {
+		Self::new(self.configured.clone(), read_only, Some(self))
+	}
+
+	/// These settings with the credentials, mounts, headers, rules and ignore patterns of
+	/// `fresh`. The rest of the configuration only takes effect on restart.
+	fn reloaded(&self, fresh: Config) -> std::io::Result<Self> {
+		self.changed(|config| {
+			config.auth = fresh.auth;
+			config.token = match fresh.token.as_deref() {
+				// Generated once at startup rather than anew on every reload.
+				Some("") => config.token.take().or_else(|| Some(generate_token())),
+				_ => fresh.token,
+			};
+			config.metrics_auth = fresh.metrics_auth;
+			config.ignore = fresh.ignore;
+			config.use_gitignore = fresh.use_gitignore;
+			config.mounts = fresh.mounts;
+			config.hosts = fresh.hosts;
+			config.headers = fresh.headers;
+			config.redirects = fresh.redirects;
+			config.rewrites = fresh.rewrites;
+			if config.admin && config.auth.is_empty() && config.token.is_none() {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					"--admin requires --auth or --token",
+				));
+			}
+			Ok(())
+		})
+	}
+}
+
+/// The current [`Settings`], which requests already being handled keep the old ones of when
+/// replaced.
+pub struct Reloader {
+	current: ArcSwap<Settings>,
+	load: Option<Loader>,
+	/// Held while updating, so that two updates at once cannot undo one another.
+	updating: Mutex<()>,
+}
+
+impl Reloader {
+	/// Starts out with `settings`, reloading them from what `load` returns, if anything.
+	pub fn new(settings: Settings, load: Option<Loader>) -> Self {
+		Self {
+			current: ArcSwap::from_pointee(settings),
+			load,
+			updating: Mutex::new(()),
+		}
+	}
+
+	pub const fn reloads(&self) -> bool {
+		self.load.is_some()
+	}
+
+	/// The settings requests are being handled under.
+	pub fn current(&self) -> Arc<Settings> {
+		self.current.load_full()
+	}
+
+	/// Switches to the settings `change` makes of the current ones, keeping those if it fails.
+	/// Waits for other updates and reads files, so is best kept off the workers.
+	pub fn update(
+		&self,
+		change: impl FnOnce(&Settings) -> std::io::Result<Settings>,
+	) -> std::io::Result<Arc<Settings>> {
+		let _updating = self.updating.lock().unwrap();
+		let settings = Arc::new(change(&self.current.load())?);
+		self.current.store(settings.clone());
+		Ok(settings)
+	}
+
+	/// Loads the configuration again and switches to it, keeping the current one if it is
+	/// invalid.
+	pub fn reload(&self) -> std::io::Result<()> {
+		let Some(load) = &self.load else {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::Unsupported,
+				"The configuration cannot be reloaded",
+			));
+		};
+		self.update(|settings| settings.reloaded(load()?)).map(drop)
+	}
+
+	fn reload_logged(&self) -> std::io::Result<()> {
+		match self.reload() {
+			Ok(()) => {
+				tracing::info!("Reloaded the configuration");
+				Ok(())
+			},
+			Err(e) => {
+				tracing::error!("Cannot reload the configuration, keeping the current one: {e}");
+				Err(e)
+			},
+		}
+	}
+}
+
+/// Reloads the configuration on every SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_hangup(reloader: web::Data<Reloader>) {
+	use actix_web::rt::signal::unix::{signal, SignalKind};
+
+	let mut hangup = match signal(SignalKind::hangup()) {
+		Ok(hangup) => hangup,
+		Err(e) => {
+			tracing::warn!("Cannot reload the configuration on SIGHUP: {e}");
+			return;
+		},
+	};
+	while hangup.recv().await.is_some() {
+		let reloader = reloader.clone();
+		let _ = web::block(move || reloader.reload_logged()).await;
+	}
+}
+
+/// Middleware giving each request the current settings as app data, over any registered with the
+/// app.
+pub async fn use_settings(
+	mut req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+	if let Some(reloader) = req.app_data::<web::Data<Reloader>>().cloned() {
+		let settings = reloader.current.load();
+		let mut data = Extensions::new();
+		data.insert(settings.config.clone());
+		data.insert(settings.mounts.clone());
+		if let Some(ignore) = &settings.ignore {
+			data.insert(ignore.clone());
+		}
+		if let Some(rules) = &settings.rules {
+			data.insert(rules.clone());
+		}
+		if let Some(custom_headers) = &settings.custom_headers {
+			data.insert(custom_headers.clone());
+		}
+		if let Some(total_rate) = &settings.total_rate {
+			data.insert(total_rate.clone());
+		}
+		data.insert(settings.error_pages.clone());
+		req.add_data_container(Rc::new(data));
+	}
+	next.call(req).await
+}
+
+/// Reloads the configuration, as SIGHUP does, for admins.
+#[post("/_admin/reload")]
+async fn reload_config(stats: Option<web::Data<Stats>>, reloader: web::Data<Reloader>) -> HttpResponse {
+	if stats.is_none() {
+		return HttpResponse::NotFound().body("The admin dashboard is disabled");
+	}
+	match web::block(move || reloader.reload_logged()).await {
+		Ok(Ok(())) => HttpResponse::Ok().body("Reloaded the configuration"),
+		Ok(Err(e)) if e.kind() == std::io::ErrorKind::Unsupported => HttpResponse::NotFound().body(e.to_string()),
+		Ok(Err(e)) => HttpResponse::UnprocessableEntity().body(e.to_string()),
+		Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+	}
+}
@@ -0,0 +1,126 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::LOCATION;
+use actix_web::http::{StatusCode, Uri};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use regex::Regex;
+
+use crate::config::Config;
+
+struct Redirect {
+	/// What the path must equal, or with `wildcard` start with.
+	from: String,
+	wildcard: bool,
+	to: String,
+	status: StatusCode,
+}
+
+impl Redirect {
+	fn target(&self, path: &str) -> Option<String> {
+		if !self.wildcard {
+			return (path == self.from).then(|| self.to.clone());
+		}
+		let rest = path.strip_prefix(&self.from)?;
+		Some(match self.to.strip_suffix('*') {
+			Some(to) => format!("{to}{rest}"),
+			None => self.to.clone(),
+		})
+	}
+}
+
+/// The `[[redirects]]` and `[[rewrites]]` of the configuration, compiled once at startup.
+pub struct Rules {
+	redirects: Vec<Redirect>,
+	rewrites: Vec<(Regex, String)>,
+}
+
+impl Rules {
+	/// The rules of `config`, or `None` when it has none.
+	pub fn from_config(config: &Config) -> std::io::Result<Option<Self>> {
+		let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+		let redirects = config
+			.redirects
+			.iter()
+			.map(|redirect| {
+				let status = StatusCode::from_u16(redirect.status)
+					.ok()
+					.filter(|status| [301, 302, 303, 307, 308].contains(&status.as_u16()))
+					.ok_or_else(|| invalid(format!("Invalid redirect status {}", redirect.status)))?;
+				let (from, wildcard) = match redirect.from.strip_suffix('*') {
+					Some(from) => (from.to_string(), true),
+					None => (redirect.from.clone(), false),
+				};
+				Ok(Redirect {
+					from,
+					wildcard,
+					to: redirect.to.clone(),
+					status,
+				})
+			})
+			.collect::<std::io::Result<Vec<_>>>()?;
+		let rewrites = config
+			.rewrites
+			.iter()
+			.map(|rewrite| {
+				let pattern = Regex::new(&rewrite.pattern)
+					.map_err(|e| invalid(format!("Invalid rewrite pattern {:?}: {e}", rewrite.pattern)))?;
+				Ok((pattern, rewrite.to.clone()))
+			})
+			.collect::<std::io::Result<Vec<_>>>()?;
+
+		Ok((!redirects.is_empty() || !rewrites.is_empty()).then_some(Self { redirects, rewrites }))
+	}
+
+	fn redirect(&self, path: &str) -> Option<(String, StatusCode)> {
+		self.redirects
+			.iter()
+			.find_map(|redirect| Some((redirect.target(path)?, redirect.status)))
+	}
+
+	fn rewrite(&self, path: &str) -> Option<String> {
+		let (pattern, to) = self.rewrites.iter().find(|(pattern, _)| pattern.is_match(path))?;
+		Some(pattern.replace(path, to.as_str()).into_owned())
+	}
+}
+
+/// Keeps the request's query, after any the rule's target has of its own.
+fn with_query(target: String, query: &str) -> String {
+	match (query.is_empty(), target.contains('?')) {
+		(true, _) => target,
+		(false, true) => format!("{target}&{query}"),
+		(false, false) => format!("{target}?{query}"),
+	}
+}
+
+/// Middleware answering requests matching a redirect with it, and routing those matching a
+/// rewrite as if they were for its target. Paths are matched as requested, still
+/// percent-encoded and with any route prefix.
+pub async fn apply_rules(
+	mut req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+	let Some(rules) = req.app_data::<web::Data<Rules>>().cloned() else {
+		return Ok(next.call(req).await?.map_into_left_body());
+	};
+
+	if let Some((target, status)) = rules.redirect(req.path()) {
+		let response = HttpResponse::build(status)
+			.insert_header((LOCATION, with_query(target, req.query_string())))
+			.finish();
+		return Ok(req.into_response(response).map_into_right_body());
+	}
+
+	if let Some(target) = rules.rewrite(req.path()) {
+		let target = with_query(target, req.query_string());
+		match target.parse::<Uri>() {
+			Ok(uri) => {
+				tracing::debug!("Rewriting {} to {target}", req.path());
+				req.match_info_mut().get_mut().update(&uri);
+				req.head_mut().uri = uri;
+			},
+			Err(e) => tracing::warn!("Not rewriting {} to {target:?}: {e}", req.path()),
+		}
+	}
+	Ok(next.call(req).await?.map_into_left_body())
+}
@@ -0,0 +1,1349 @@
+use actix_files::NamedFile;
+use actix_web::dev::Extensions;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{self, HeaderValue, TryIntoHeaderValue};
+use actix_web::http::{KeepAlive, Method};
+use actix_web::{get, guard, middleware, route, web, App, HttpRequest, HttpResponse, HttpServer, Result};
+use mime_guess::mime::{self, Mime};
+use std::fs::File;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::access_log::LogFormat;
+use crate::config::{
+	normalize_prefix, ByteSize, Config, CustomHeader, ErrorStatus, IpRange, Listener, Mount, Proxy, Redirect, Rewrite,
+	Seconds,
+};
+use crate::helpers::{
+	attachment, file_etag, get_dir_entries, index_file, insert_validators, is_download, listing_sort, mime_type,
+	not_modified, paginate, precompressed, query_param, resolve_path, wants_json, within_root, Visibility, TOTAL_COUNT,
+};
+use crate::hooks::{self, Hook, Hooks};
+use crate::share::ShareCheck;
+use crate::structs::DirectoryTemplate;
+use crate::{
+	access_log, admin, api, archive, auth, bans, cgi, charset, checksum, clipboard, compression, errors, files,
+	headers, health, host_filter, index, ip_filter, limits, listeners, mdns, metrics, mounts, paths, proxy, qr, ranges,
+	reload, rules, share, shutdown, stats, structs, templates, throttle, timing, tls, upload, webdav, zipfs,
+};
+
+const VIDEO_CSS: &str = include_str!(concat!(env!("OUT_DIR"), "/video-js.min.css"));
+const VIDEO_JS: &str = include_str!(concat!(env!("OUT_DIR"), "/video.min.js"));
+const LISTING_JS: &str = include_str!("../static/listing.js");
+const ADMIN_JS: &str = include_str!("../static/admin.js");
+const FILTER_JS: &str = include_str!("../static/filter.js");
+const GALLERY_JS: &str = include_str!("../static/gallery.js");
+const ICONS_SVG: &str = include_str!("../static/icons.svg");
+const THEME_JS: &str = include_str!("../static/theme.js");
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "GET", method = "HEAD")]
+async fn serve_path(
+	req: HttpRequest,
+	config: web::Data<Config>,
+	checksums: web::Data<checksum::ChecksumCache>,
+	hooks: web::Data<Hooks>,
+) -> Result<HttpResponse> {
+	match share::verify(&config, &req) {
+		ShareCheck::Expired => return Ok(HttpResponse::Gone().body("Link expired")),
+		ShareCheck::Invalid => return Ok(HttpResponse::Forbidden().body("Invalid link signature")),
+		ShareCheck::Unsigned | ShareCheck::Valid => (),
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(final_path) = timing::measure(&req, "resolve", || resolve_path(&config.root, &path)) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	if !visibility.allows(&path) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+
+	if let Some((archive, inner)) = zipfs::locate(&config.root, &final_path, req.path().ends_with('/')) {
+		if !within_root(&config, &archive) {
+			return Ok(HttpResponse::NotFound().body("Access denied"));
+		}
+		return zipfs::serve(&req, &hooks, &visibility, &path, &archive, &inner, &config).await;
+	}
+
+	let (final_path, metadata) = match timing::measure(&req, "metadata", || std::fs::metadata(&final_path)) {
+		Ok(metadata) => (final_path, metadata),
+		// Paths the app routes itself look like directories, while a missing asset stays missing.
+		Err(_) if config.spa && path.extension().is_none() => {
+			match index_file(&config, &visibility, Path::new(""), &config.root) {
+				Some(index) => index,
+				None => return Ok(HttpResponse::NotFound().body("Not found")),
+			}
+		},
+		Err(_) => return Ok(HttpResponse::NotFound().body("Not found")),
+	};
+	if !within_root(&config, &final_path) {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	}
+
+	// Listings asked for as JSON or as an archive are never replaced by the index file.
+	let index = if metadata.is_dir() && !wants_json(&req) && query_param(&req, "archive").is_none() {
+		index_file(&config, &visibility, &path, &final_path)
+	} else {
+		None
+	};
+	let (final_path, metadata) = match index {
+		// The index file's relative links only resolve against the directory with a trailing slash.
+		Some(_) if !req.path().ends_with('/') => {
+			let query = req.query_string();
+			let location = if query.is_empty() {
+				format!("{}/", req.path())
+			} else {
+				format!("{}/?{query}", req.path())
+			};
+			return Ok(HttpResponse::PermanentRedirect()
+				.insert_header((header::LOCATION, location))
+				.finish());
+		},
+		Some(index) => index,
+		None => (final_path, metadata),
+	};
+
+	if metadata.is_dir() {
+		if !config.dirlist {
+			return Ok(HttpResponse::NotFound().body("Not found"));
+		}
+
+		match query_param(&req, "archive").as_deref() {
+			Some("zip") => return Ok(archive::zip_response(&config, &final_path, visibility)),
+			Some("tar.gz") => return Ok(archive::tar_gz_response(&config, &final_path, visibility)),
+			Some(_) => return Ok(HttpResponse::BadRequest().body("Unsupported archive format")),
+			None => (),
+		}
+
+		let sort = listing_sort(&req, config.sort);
+		let Ok(mut entries) =
+			get_dir_entries(&config.root, &final_path, &visibility, config.stat_parallelism, &sort).await
+		else {
+			return Ok(HttpResponse::InternalServerError().body("Failed to read directory"));
+		};
+		let mut response = HttpResponse::Ok();
+		response.insert_header((TOTAL_COUNT, entries.len()));
+		let pagination = paginate(&req, &mut entries, config.page_size);
+		if wants_json(&req) {
+			return Ok(response.json(entries));
+		}
+
+		let current_path = path.to_string_lossy().to_string();
+		let quota_remaining = upload::remaining_quota(&config, &final_path)
+			.await
+			.map_err(ErrorInternalServerError)?
+			.map(|left| humansize::format_size(left, humansize::BINARY));
+		let template = DirectoryTemplate {
+			prefix: config.route_prefix.clone(),
+			current_path,
+			allow_upload: config.allow_upload,
+			allow_delete: config.allow_delete,
+			allow_rename: config.allow_upload,
+			in_archive: false,
+			content_search: config.index_content,
+			quota_remaining,
+			entries,
+			pagination,
+			sort,
+			theme: config.theme,
+			custom_css: config.custom_css.is_some(),
+			custom_js: config.custom_js.is_some(),
+		};
+		let html = templates::render(Some(&config), &template)
+			.await
+			.map_err(ErrorInternalServerError)?;
+		Ok(response.content_type("text/html").body(html))
+	} else {
+		if let Some(algorithm) = query_param(&req, "hash") {
+			return checksum::response(&checksums, &final_path, &algorithm).await;
+		}
+
+		if let Some(response) = hooks.before_send_file(&req, &final_path) {
+			return Ok(response);
+		}
+
+		// A precompressed copy is sent as it is, instead of compressing the file on the fly.
+		let sidecar = precompressed(&req, &final_path);
+		let served_path = sidecar.as_ref().map_or(&final_path, |(path, _)| path);
+		let Ok(file) = timing::measure(&req, "open", || File::open(served_path)) else {
+			return Ok(HttpResponse::NotFound().body("File not found"));
+		};
+
+		let mime = charset::with_charset(&config, &final_path, mime_type(&config, &final_path));
+		let disposition = is_download(&config, &req, &final_path)
+			.then(|| attachment(&final_path.file_name().unwrap_or_default().to_string_lossy()));
+		let file_metadata = file.metadata()?;
+		let file_size = file_metadata.len();
+
+		// Ranges are served here rather than by `NamedFile`, so the validators are handled here too.
+		// So is `HEAD`, which `NamedFile` would answer by reading the whole file, and anything
+		// large enough to be memory-mapped, or precompressed.
+		let mapped = config.mmap_min_size.is_some_and(|min| file_size >= min.0) && structs::unchanging(&file);
+		if req.headers().contains_key("range") || req.method() == Method::HEAD || mapped || sidecar.is_some() {
+			let etag = file_etag(&file_metadata);
+			let modified = file_metadata.modified().ok();
+			let mut response = if not_modified(&req, &etag, modified) {
+				let mut response = HttpResponse::NotModified();
+				insert_validators(&mut response, etag, modified);
+				response.finish()
+			} else {
+				ranges::respond(&req, file, 0, file_size, mime.to_string(), etag, modified)?
+			};
+			if let Some(disposition) = disposition {
+				response
+					.headers_mut()
+					.insert(header::CONTENT_DISPOSITION, disposition.try_into_value()?);
+			}
+			if let Some((_, encoding)) = sidecar {
+				let headers = response.headers_mut();
+				headers.insert(header::CONTENT_ENCODING, encoding.to_header_value());
+				headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+			}
+			return Ok(response);
+		}
+
+		let mut file = NamedFile::open(&final_path)?.set_content_type(mime);
+		if let Some(disposition) = disposition {
+			file = file.set_content_disposition(disposition);
+		}
+		Ok(file.into_response(&req))
+	}
+}
+
+#[get("/_static/video-js.min.css")]
+async fn serve_css() -> HttpResponse {
+	HttpResponse::Ok().content_type("text/css").body(VIDEO_CSS)
+}
+
+#[get("/_static/video.min.js")]
+async fn serve_js() -> HttpResponse {
+	HttpResponse::Ok().content_type("application/javascript").body(VIDEO_JS)
+}
+
+#[get("/_static/listing.js")]
+async fn serve_listing_js() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("application/javascript")
+		.body(LISTING_JS)
+}
+
+#[get("/_static/filter.js")]
+async fn serve_filter_js() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("application/javascript")
+		.body(FILTER_JS)
+}
+
+#[get("/_static/theme.js")]
+async fn serve_theme_js() -> HttpResponse {
+	HttpResponse::Ok().content_type("application/javascript").body(THEME_JS)
+}
+
+#[get("/_static/gallery.js")]
+async fn serve_gallery_js() -> HttpResponse {
+	HttpResponse::Ok()
+		.content_type("application/javascript")
+		.body(GALLERY_JS)
+}
+
+#[get("/_static/icons.svg")]
+async fn serve_icons() -> HttpResponse {
+	HttpResponse::Ok().content_type("image/svg+xml").body(ICONS_SVG)
+}
+
+/// The file given as `--custom-css` or `--custom-js`, read on each request so it can be edited
+/// while the server runs.
+async fn serve_custom(req: &HttpRequest, path: Option<&Path>, mime: Mime) -> Result<HttpResponse> {
+	let Some(path) = path else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+	Ok(NamedFile::open_async(path)
+		.await?
+		.set_content_type(mime)
+		.disable_content_disposition()
+		.into_response(req))
+}
+
+#[get("/_static/custom.css")]
+async fn serve_custom_css(req: HttpRequest, config: web::Data<Config>) -> Result<HttpResponse> {
+	serve_custom(&req, config.custom_css.as_deref(), mime::TEXT_CSS).await
+}
+
+#[get("/_static/custom.js")]
+async fn serve_custom_js(req: HttpRequest, config: web::Data<Config>) -> Result<HttpResponse> {
+	serve_custom(&req, config.custom_js.as_deref(), mime::APPLICATION_JAVASCRIPT).await
+}
+
+#[get("/_static/admin.js")]
+async fn serve_admin_js() -> HttpResponse {
+	HttpResponse::Ok().content_type("application/javascript").body(ADMIN_JS)
+}
+
+/// Every route of the server, beneath `prefix`.
+fn routes(prefix: &str) -> actix_web::Scope {
+	web::scope(prefix)
+		// The prefix itself, which the routes below only match with a trailing slash. Mounts are
+		// all routed beneath the same prefix, so the path requested is what tells them apart.
+		.route(
+			"",
+			web::get().to(|req: HttpRequest| async move {
+				HttpResponse::PermanentRedirect()
+					.insert_header((header::LOCATION, format!("{}/", req.path())))
+					.finish()
+			}),
+		)
+		.service(serve_css)
+		.service(serve_js)
+		.service(serve_listing_js)
+		.service(serve_filter_js)
+		.service(serve_icons)
+		.service(serve_theme_js)
+		.service(serve_gallery_js)
+		.service(serve_custom_css)
+		.service(serve_custom_js)
+		.service(serve_admin_js)
+		.service(health::health)
+		.service(health::ready)
+		.service(share::create_share_link)
+		.service(api::tree)
+		.service(api::search)
+		.service(api::stat)
+		.service(metrics::serve_metrics)
+		.service(stats::admin_page)
+		.service(stats::admin_events)
+		.service(reload::reload_config)
+		.service(admin::show_settings)
+		.service(admin::change_settings)
+		.service(admin::add_mount)
+		.service(admin::remove_mount)
+		.service(admin::invalidate)
+		.service(archive::download_selection)
+		.service(serve_path)
+		.service(upload::upload_files)
+		.service(upload::put_file)
+		.service(files::delete_path)
+		.service(files::make_directory)
+		.service(files::move_path)
+		.service(files::copy_path)
+		.service(webdav::options)
+		.service(webdav::propfind)
+		.service(webdav::proppatch)
+		.service(webdav::lock_resource)
+		.service(webdav::unlock_resource)
+}
+
+/// Embeddable file server, configured through builder methods and started with [`FileServer::run`].
+///
+/// ```no_run
+/// # #[actix_web::main]
+/// # async fn main() -> std::io::Result<()> {
+/// http_server_rs::FileServer::new("./public")
+///     .port(8000)
+///     .auth("admin", "secret")
+///     .run()
+///     .await
+/// # }
+/// ```
+pub struct FileServer {
+	config: Config,
+	hooks: Hooks,
+	reload: Option<reload::Loader>,
+}
+
+impl FileServer {
+	/// Serves `root` with the same defaults as the command line.
+	pub fn new(root: impl Into<PathBuf>) -> Self {
+		Self::from_config(Config {
+			root: root.into(),
+			..Config::default()
+		})
+	}
+
+	pub fn from_config(config: Config) -> Self {
+		Self {
+			config,
+			hooks: Hooks::default(),
+			reload: None,
+		}
+	}
+
+	pub const fn port(mut self, port: u16) -> Self {
+		self.config.port = port;
+		self
+	}
+
+	/// Tries up to `retries` ports above the port in turn while it is in use.
+	pub const fn port_retry(mut self, retries: u16) -> Self {
+		self.config.port_retry = retries;
+		self
+	}
+
+	/// Listens on `address`, which may be `0.0.0.0` or `::` for all interfaces; may be called
+	/// repeatedly. Only localhost is listened on until one is given.
+	pub fn bind(mut self, address: IpAddr) -> Self {
+		self.config.bind.push(address);
+		self
+	}
+
+	/// Takes HTTP/2 on plain HTTP listeners too, from clients that start with it.
+	pub const fn h2c(mut self, h2c: bool) -> Self {
+		self.config.h2c = h2c;
+		self
+	}
+
+	/// Offers HTTP/2 over HTTPS, as is the default; when unset, only HTTP/1.1 is spoken.
+	pub const fn http2(mut self, http2: bool) -> Self {
+		self.config.http2 = http2;
+		self
+	}
+
+	/// Listens on a Unix socket at `path`, in place of the TCP port unless [`Self::bind`] or
+	/// [`Self::listen`] are also used.
+	pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+		self.config.unix_socket = Some(path.into());
+		self
+	}
+
+	/// Creates the Unix socket with permissions `mode`, such as `0o660`.
+	pub const fn unix_socket_mode(mut self, mode: u32) -> Self {
+		self.config.unix_socket_mode = Some(mode);
+		self
+	}
+
+	/// Advertises the server on the local network over mDNS, under `name` or else the host name.
+	pub fn mdns(mut self, name: Option<String>) -> Self {
+		self.config.mdns = true;
+		self.config.mdns_name = name;
+		self
+	}
+
+	/// Prints a QR code of each URL others on the network can open once listening.
+	pub const fn qr(mut self, qr: bool) -> Self {
+		self.config.qr = qr;
+		self
+	}
+
+	/// Puts the URL others on the network can open, or else the local one, on the clipboard once
+	/// listening.
+	pub const fn copy_url(mut self, copy_url: bool) -> Self {
+		self.config.copy_url = copy_url;
+		self
+	}
+
+	/// Gives responses being sent `grace` to finish once the server is told to stop.
+	pub const fn shutdown_grace(mut self, grace: Duration) -> Self {
+		self.config.shutdown_seconds = grace.as_secs();
+		self
+	}
+
+	/// Stops the server once it has run for `timeout`.
+	pub const fn timeout(mut self, timeout: Duration) -> Self {
+		self.config.timeout = Some(Seconds(timeout.as_secs()));
+		self
+	}
+
+	/// Stops the server once no request has come in for `idle`.
+	pub const fn exit_after_idle(mut self, idle: Duration) -> Self {
+		self.config.exit_after_idle = Some(Seconds(idle.as_secs()));
+		self
+	}
+
+	/// Handles connections on `workers` threads instead of one per CPU core.
+	pub const fn workers(mut self, workers: usize) -> Self {
+		self.config.workers = Some(workers);
+		self
+	}
+
+	/// Keeps idle connections open for `keep_alive`, or closes each after its response when
+	/// zero.
+	pub const fn keep_alive(mut self, keep_alive: Duration) -> Self {
+		self.config.keep_alive = Some(Seconds(keep_alive.as_secs()));
+		self
+	}
+
+	/// Gives clients `timeout` to send a request's headers, or waits indefinitely when zero.
+	pub const fn client_request_timeout(mut self, timeout: Duration) -> Self {
+		self.config.client_request_timeout = Some(Seconds(timeout.as_secs()));
+		self
+	}
+
+	/// Gives connections `timeout` to close cleanly after their last response.
+	pub const fn client_disconnect_timeout(mut self, timeout: Duration) -> Self {
+		self.config.client_disconnect_timeout = Some(Seconds(timeout.as_secs()));
+		self
+	}
+
+	/// Stops the server once `downloads` files have been downloaded in full.
+	pub const fn max_downloads(mut self, downloads: u64) -> Self {
+		self.config.max_downloads = Some(downloads);
+		self
+	}
+
+	/// Listens at `listener`'s address and port, over TLS or not, instead of at the port and
+	/// [`Self::bind`] addresses; may be called repeatedly.
+	pub fn listen(mut self, listener: Listener) -> Self {
+		self.config.listen.push(listener);
+		self
+	}
+
+	/// Requires HTTP Basic authentication; may be called repeatedly to allow several users.
+	pub fn auth(mut self, user: &str, password: &str) -> Self {
+		self.config.auth.push(format!("{user}:{password}"));
+		self
+	}
+
+	/// Requires a bearer token, accepted as a header, query parameter or cookie.
+	pub fn token(mut self, token: impl Into<String>) -> Self {
+		self.config.token = Some(token.into());
+		self
+	}
+
+	/// Serves HTTPS using a PEM certificate chain and private key.
+	pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+		self.config.tls_cert = Some(cert.into());
+		self.config.tls_key = Some(key.into());
+		self
+	}
+
+	/// Serves HTTPS with a certificate generated at startup.
+	pub const fn tls_self_signed(mut self, enable: bool) -> Self {
+		self.config.tls_self_signed = enable;
+		self
+	}
+
+	pub const fn allow_upload(mut self, allow: bool) -> Self {
+		self.config.allow_upload = allow;
+		self
+	}
+
+	pub const fn allow_delete(mut self, allow: bool) -> Self {
+		self.config.allow_delete = allow;
+		self
+	}
+
+	pub const fn webdav(mut self, enable: bool) -> Self {
+		self.config.webdav = enable;
+		self
+	}
+
+	/// Runs the files in `dir`, relative to the root, as CGI scripts instead of serving them.
+	pub fn cgi(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.config.cgi_dir = Some(dir.into());
+		self
+	}
+
+	/// Renders listings and error pages from the templates in `dir` that replace the built-in ones.
+	pub fn template_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.config.template_dir = Some(dir.into());
+		self
+	}
+
+	/// Styles listings with the file at `path` too, after the built-in styles.
+	pub fn custom_css(mut self, path: impl Into<PathBuf>) -> Self {
+		self.config.custom_css = Some(path.into());
+		self
+	}
+
+	/// Runs the file at `path` in listings too, after the built-in scripts.
+	pub fn custom_js(mut self, path: impl Into<PathBuf>) -> Self {
+		self.config.custom_js = Some(path.into());
+		self
+	}
+
+	/// Rejects uploaded files larger than `bytes`.
+	pub const fn max_upload_size(mut self, bytes: u64) -> Self {
+		self.config.max_upload_size = Some(ByteSize(bytes));
+		self
+	}
+
+	/// Exposes Prometheus metrics at `/_metrics`.
+	pub const fn metrics(mut self, enable: bool) -> Self {
+		self.config.metrics = enable;
+		self
+	}
+
+	/// Serves a live statistics dashboard at `/_admin` and a settings API at `/_admin/api`; requires
+	/// `auth` or `token`.
+	pub const fn admin(mut self, enable: bool) -> Self {
+		self.config.admin = enable;
+		self
+	}
+
+	/// Reports how long each request spent resolving paths, reading metadata and preparing the
+	/// response in a `Server-Timing` header.
+	pub const fn server_timing(mut self, enable: bool) -> Self {
+		self.config.server_timing = enable;
+		self
+	}
+
+	/// Serves the whole file instead of `416 Range Not Satisfiable` when no requested range lies
+	/// within it, for older clients that give up on the error.
+	pub const fn lenient_ranges(mut self, enable: bool) -> Self {
+		self.config.lenient_ranges = enable;
+		self
+	}
+
+	/// Reads streamed files `bytes` at a time.
+	pub const fn chunk_size(mut self, bytes: u64) -> Self {
+		self.config.chunk_size = ByteSize(bytes);
+		self
+	}
+
+	/// Keeps up to `chunks` chunks read ahead of the one being sent; `0` disables reading ahead.
+	pub const fn read_ahead(mut self, chunks: usize) -> Self {
+		self.config.read_ahead = chunks;
+		self
+	}
+
+	/// Sends files of at least `min_size` bytes from a memory map instead of reading them in
+	/// chunks, when they are on a read-only filesystem.
+	pub const fn mmap(mut self, min_size: u64) -> Self {
+		self.config.mmap_min_size = Some(ByteSize(min_size));
+		self
+	}
+
+	/// Sends each response at no more than `rate` bytes per second.
+	pub const fn limit_rate(mut self, rate: u64) -> Self {
+		self.config.limit_rate = Some(ByteSize(rate));
+		self
+	}
+
+	/// Sends all responses together at no more than `rate` bytes per second.
+	pub const fn limit_rate_total(mut self, rate: u64) -> Self {
+		self.config.limit_rate_total = Some(ByteSize(rate));
+		self
+	}
+
+	/// Allows each client IP `per_second` requests on average and `burst` at once, answering
+	/// `429 Too Many Requests` beyond that.
+	pub const fn rate_limit(mut self, per_second: f64, burst: u32) -> Self {
+		self.config.rate_limit = Some(per_second);
+		self.config.rate_limit_burst = burst;
+		self
+	}
+
+	/// Caps the connections each client IP may have open at once.
+	pub const fn max_connections_per_ip(mut self, max: usize) -> Self {
+		self.config.max_connections_per_ip = Some(max);
+		self
+	}
+
+	/// Only lets in clients within `range`; anyone may connect until one is given.
+	pub fn allow_ip(mut self, range: IpRange) -> Self {
+		self.config.allow_ip.push(range);
+		self
+	}
+
+	/// Turns away clients within `range`, even those [`Self::allow_ip`] lets in.
+	pub fn deny_ip(mut self, range: IpRange) -> Self {
+		self.config.deny_ip.push(range);
+		self
+	}
+
+	/// Answers requests for `host`, which may be `*` for any or start with a dot for its
+	/// subdomains too. Until one is given only `localhost` and the address listened on are.
+	pub fn allowed_host(mut self, host: impl Into<String>) -> Self {
+		self.config.allowed_hosts.push(host.into());
+		self
+	}
+
+	/// Believes `X-Forwarded-For` from this many reverse proxies in front of the server when
+	/// telling who the client is.
+	pub const fn trusted_proxies(mut self, proxies: usize) -> Self {
+		self.config.trusted_proxies = proxies;
+		self
+	}
+
+	/// Bans clients for `seconds` once they fail to authenticate `failures` times.
+	pub const fn ban_after(mut self, failures: u32, seconds: u64) -> Self {
+		self.config.ban_after = Some(failures);
+		self.config.ban_seconds = seconds;
+		self
+	}
+
+	/// Sends `Strict-Transport-Security` for `max_age` seconds when serving HTTPS.
+	pub const fn hsts(mut self, max_age: u64, include_subdomains: bool) -> Self {
+		self.config.hsts = true;
+		self.config.hsts_max_age = max_age;
+		self.config.hsts_include_subdomains = include_subdomains;
+		self
+	}
+
+	/// Also listens for plain HTTP on `port`, redirecting every request to HTTPS.
+	pub const fn redirect_http(mut self, port: u16) -> Self {
+		self.config.redirect_http_port = Some(port);
+		self
+	}
+
+	/// Serves symlinks wherever they point rather than only those resolving beneath the root.
+	pub const fn follow_symlinks(mut self, follow: bool) -> Self {
+		self.config.follow_symlinks = follow;
+		self
+	}
+
+	/// Lists and serves dotfiles instead of hiding them.
+	pub const fn hidden(mut self, show: bool) -> Self {
+		self.config.hidden = show;
+		self
+	}
+
+	/// Keeps paths matching `pattern`, written as in a `.gitignore`, from clients.
+	pub fn ignore(mut self, pattern: impl Into<String>) -> Self {
+		self.config.ignore.push(pattern.into());
+		self
+	}
+
+	/// Also keeps what the root's `.gitignore` lists from clients.
+	pub const fn use_gitignore(mut self, enable: bool) -> Self {
+		self.config.use_gitignore = enable;
+		self
+	}
+
+	/// Serves the first of `names` found in a directory instead of listing it; directories are
+	/// always listed when `names` is empty.
+	pub fn index_files<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+		self.config.index_files = names.into_iter().map(Into::into).collect();
+		self
+	}
+
+	/// Answers requests for missing paths without an extension with the root's index file.
+	pub const fn spa(mut self, enable: bool) -> Self {
+		self.config.spa = enable;
+		self
+	}
+
+	/// Lists directories without an index file; otherwise they are answered as if missing.
+	pub const fn dirlist(mut self, enable: bool) -> Self {
+		self.config.dirlist = enable;
+		self
+	}
+
+	/// Shows browsers the file at `page` instead of the built-in page for `status` errors.
+	pub fn error_page(mut self, status: u16, page: impl Into<PathBuf>) -> Self {
+		self.config.error_pages.insert(ErrorStatus(status), page.into());
+		self
+	}
+
+	/// Serves files with `extension` as `mime` instead of the built-in guess.
+	pub fn mime_type(mut self, extension: impl Into<String>, mime: impl Into<String>) -> Self {
+		self.config.mime_types.insert(extension.into(), mime.into());
+		self
+	}
+
+	/// Reads further extension mappings from an `/etc/mime.types`-style file at startup.
+	pub fn mime_types_file(mut self, path: impl Into<PathBuf>) -> Self {
+		self.config.mime_types_file = Some(path.into());
+		self
+	}
+
+	/// Serves files without an extension as `mime` instead of `application/octet-stream`.
+	pub fn default_mime_type(mut self, mime: impl Into<String>) -> Self {
+		self.config.default_mime_type = Some(mime.into());
+		self
+	}
+
+	/// Sends text files with `charset` instead of the one sniffed from each.
+	pub fn charset(mut self, charset: impl Into<String>) -> Self {
+		self.config.charset = Some(charset.into());
+		self
+	}
+
+	/// Always sends files with `extension` as downloads rather than showing them.
+	pub fn force_download_ext(mut self, extension: impl Into<String>) -> Self {
+		self.config.force_download.push(extension.into());
+		self
+	}
+
+	/// Mounts every route beneath `prefix`, such as `/files`, for serving behind a reverse proxy
+	/// that forwards only that path.
+	pub fn route_prefix(mut self, prefix: impl Into<String>) -> Self {
+		self.config.route_prefix = prefix.into();
+		self
+	}
+
+	/// Also serves `mount.root` beneath `mount.path`, inside the route prefix.
+	pub fn mount(mut self, mount: Mount) -> Self {
+		self.config.mounts.push(mount);
+		self
+	}
+
+	/// Forwards requests beneath `path`, inside the route prefix, to the server at `upstream`.
+	pub fn proxy(mut self, path: impl Into<String>, upstream: impl Into<String>) -> Self {
+		self.config.proxies.push(Proxy {
+			path: path.into(),
+			upstream: upstream.into(),
+		});
+		self
+	}
+
+	/// Serves `root` instead of the server's root, and without the mounts, to requests for `host`.
+	pub fn virtual_host(mut self, host: impl Into<String>, root: impl Into<PathBuf>) -> Self {
+		self.config.hosts.insert(host.into(), root.into());
+		self
+	}
+
+	/// Sends requests for `from` to `to` with `status`. A trailing `*` on `from` matches the rest
+	/// of the path, which replaces a trailing `*` on `to`.
+	pub fn redirect(mut self, from: impl Into<String>, to: impl Into<String>, status: u16) -> Self {
+		self.config.redirects.push(Redirect {
+			from: from.into(),
+			to: to.into(),
+			status,
+		});
+		self
+	}
+
+	/// Routes paths matching the regex `pattern` as if `to`, with its `$1` and the like expanded,
+	/// had been asked for.
+	pub fn rewrite(mut self, pattern: impl Into<String>, to: impl Into<String>) -> Self {
+		self.config.rewrites.push(Rewrite {
+			pattern: pattern.into(),
+			to: to.into(),
+		});
+		self
+	}
+
+	/// Adds `X-Content-Type-Options`, `X-Frame-Options`, `Referrer-Policy` and
+	/// `Content-Security-Policy` headers to responses.
+	pub const fn security_headers(mut self, enable: bool) -> Self {
+		self.config.security_headers = enable;
+		self
+	}
+
+	/// Replaces the default `Content-Security-Policy` sent with [`Self::security_headers`].
+	pub fn content_security_policy(mut self, policy: impl Into<String>) -> Self {
+		self.config.content_security_policy = policy.into();
+		self
+	}
+
+	/// Sets the header `name` on every response, or with `path`, a glob as for `--ignore`, only on
+	/// those for matching paths.
+	pub fn header(mut self, path: Option<&str>, name: impl Into<String>, value: impl Into<String>) -> Self {
+		self.config.headers.push(CustomHeader {
+			path: path.map(str::to_string),
+			name: name.into(),
+			value: value.into(),
+		});
+		self
+	}
+
+	/// Selects how requests are logged to stdout, or turns logging off.
+	pub const fn log_format(mut self, format: LogFormat) -> Self {
+		self.config.log_format = format;
+		self
+	}
+
+	/// Registers a [`Hook`]; hooks run in the order they were added, and the first one to answer a
+	/// request wins.
+	pub fn hook(mut self, hook: impl Hook) -> Self {
+		self.hooks.push(hook);
+		self
+	}
+
+	/// Reloads the credentials, mounts, headers, redirects, rewrites and ignore patterns from
+	/// what `load` returns on SIGHUP or a `POST` to `/_admin/reload`. Requests already being
+	/// handled finish under the configuration they started with.
+	pub fn reload_with(mut self, load: impl Fn() -> std::io::Result<Config> + Send + Sync + 'static) -> Self {
+		self.reload = Some(Box::new(load));
+		self
+	}
+
+	/// Binds the listener and serves requests until the server is stopped. Must be awaited on an
+	/// actix runtime, e.g. from `#[actix_web::main]`.
+	pub async fn run(self) -> std::io::Result<()> {
+		let mut config = self.config;
+		if config.share_secret.is_empty() {
+			config.share_secret = auth::generate_token();
+		}
+		if config.token.as_deref() == Some("") {
+			config.token = Some(auth::generate_token());
+		}
+		config.root = std::fs::canonicalize(&config.root)?;
+		if !config.root.is_dir() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("{} is not a directory", config.root.display()),
+			));
+		}
+		if let Some(dir) = config.template_dir.as_ref().filter(|dir| !dir.is_dir()) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Template directory {} is not a directory", dir.display()),
+			));
+		}
+		for file in [&config.custom_css, &config.custom_js].into_iter().flatten() {
+			if !file.is_file() {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					format!("{} is not a file", file.display()),
+				));
+			}
+		}
+
+		config.load_mime_types()?;
+		config.route_prefix = normalize_prefix(&config.route_prefix).ok_or_else(|| {
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Invalid route prefix {:?}", config.route_prefix),
+			)
+		})?;
+
+		if config.workers == Some(0) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"--workers must be at least 1",
+			));
+		}
+
+		if config.h2c && !config.http2 {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"h2c cannot be combined with http2 = false",
+			));
+		}
+
+		if config.admin && config.auth.is_empty() && config.token.is_none() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"--admin requires --auth or --token",
+			));
+		}
+
+		let mut listening = listeners::from_config(&config);
+		let sockets = listeners::bind(&mut listening, config.port_retry)?;
+		let addresses = listeners::addresses(&listening);
+		let tls_config = tls::from_config(&config, &addresses)?;
+		let secure = |listener: &Listener| listener.tls.unwrap_or(tls_config.is_some());
+		if let Some(listener) = listening
+			.iter()
+			.find(|listener| secure(listener) && tls_config.is_none())
+		{
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Listening on {} over HTTPS requires TLS", listener.address),
+			));
+		}
+		let secure_listeners: Vec<_> = listening.iter().copied().filter(secure).collect();
+		let redirect = match (config.redirect_http_port, secure_listeners.first()) {
+			(Some(_), None) => {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					"--redirect-http requires TLS",
+				));
+			},
+			(Some(http_port), Some(https)) => Some(tls::redirect_server(
+				&listeners::addresses(&secure_listeners),
+				http_port,
+				https.address.port(),
+			)?),
+			(None, _) => None,
+		};
+		let hsts = (tls_config.is_some() && config.hsts).then(|| tls::hsts_header(&config));
+
+		let settings = reload::Settings::new(config.clone(), false, None)?;
+		for mount in &settings.mounts.0 {
+			tracing::info!(
+				"Serving {} at {}{}/",
+				mount.config.root.display(),
+				mount.host.as_deref().unwrap_or_default(),
+				mount.config.route_prefix
+			);
+		}
+		// What a QR code is printed for below each address, mounts of their own host aside.
+		let qr_paths: Vec<_> = if config.qr {
+			std::iter::once(config.route_prefix.clone())
+				.chain(
+					settings
+						.mounts
+						.0
+						.iter()
+						.filter(|mount| mount.host.is_none())
+						.map(|mount| mount.config.route_prefix.clone()),
+				)
+				.collect()
+		} else {
+			Vec::new()
+		};
+		let upstreams: Vec<_> = proxy::Upstream::from_config(&config)?
+			.into_iter()
+			.map(web::Data::new)
+			.collect();
+		for upstream in &upstreams {
+			tracing::info!("Proxying {}/ to {}", upstream.path, upstream.url);
+		}
+		let cgi = cgi::Cgi::from_config(&config)?.map(web::Data::new);
+		if let Some(cgi) = &cgi {
+			tracing::info!("Running CGI scripts at {}/", cgi.path);
+		}
+		let allowed_hosts = host_filter::AllowedHosts::from_config(&config, &addresses).map(web::Data::new);
+		let content_index = if config.index_content {
+			Some(web::Data::new(index::ContentIndex::start(&config)?))
+		} else {
+			None
+		};
+
+		let server_timing = config.server_timing;
+		let compress = config.compress;
+		// The admin API may bring in rate limits where there were none.
+		let throttle = config.limit_rate.is_some() || config.limit_rate_total.is_some() || config.admin;
+		let limiter = (config.rate_limit.is_some() || config.max_connections_per_ip.is_some() || config.admin)
+			.then(|| web::Data::new(limits::Limiter::new(&config)));
+		let connection_limiter = limiter.clone();
+		let authenticates = !config.auth.is_empty()
+			|| config.token.is_some()
+			|| config.metrics_auth.is_some()
+			|| config.mounts.iter().any(|mount| !mount.auth.is_empty());
+		let bans = config
+			.ban_after
+			.filter(|_| authenticates)
+			.map(|failures| web::Data::new(bans::Bans::new(failures, Duration::from_secs(config.ban_seconds))));
+		let security_headers = config.security_headers;
+		let csp = config.content_security_policy.clone();
+		let filter_ips = !config.allow_ip.is_empty() || !config.deny_ip.is_empty();
+		let prefix = config.route_prefix.clone();
+		let root = config.root.clone();
+		let token = config.token.clone();
+		let unix_socket = config.unix_socket.clone();
+		let unix_socket_mode = config.unix_socket_mode;
+		let h2c = config.h2c;
+		let http2 = config.http2;
+		// The path of the URL put on the clipboard, if any is.
+		let copied_path = config.copy_url.then(|| config.route_prefix.clone());
+		let grace = Duration::from_secs(config.shutdown_seconds);
+		let timeout = config.timeout.map(Seconds::duration);
+		let idle = config.exit_after_idle.map(Seconds::duration);
+		let workers = config.workers;
+		let keep_alive = config.keep_alive.map(|keep_alive| match keep_alive {
+			Seconds(0) => KeepAlive::Disabled,
+			keep_alive => KeepAlive::Timeout(keep_alive.duration()),
+		});
+		let client_request_timeout = config.client_request_timeout.map(Seconds::duration);
+		let client_disconnect_timeout = config.client_disconnect_timeout.map(Seconds::duration);
+		let (transfers, downloads_done) = shutdown::Transfers::new(config.max_downloads);
+		let transfers = web::Data::new(transfers);
+		let server_transfers = transfers.clone();
+		let mdns = listening.first().filter(|_| config.mdns).map(|listener| {
+			mdns::Service::new(
+				config.mdns_name.as_deref(),
+				listener,
+				secure(listener),
+				&config.route_prefix,
+			)
+		});
+		// Reloading may bring in rules and headers where there were none.
+		let apply_rules = settings.rules.is_some() || self.reload.is_some();
+		let add_headers = settings.custom_headers.is_some() || self.reload.is_some();
+		let reloader = web::Data::new(reload::Reloader::new(settings, self.reload));
+		#[cfg(unix)]
+		if reloader.reloads() {
+			actix_web::rt::spawn(reload::reload_on_hangup(reloader.clone()));
+		}
+		let locks = web::Data::new(webdav::LockManager::default());
+		let checksums = web::Data::new(checksum::ChecksumCache::default());
+		let stats = config.admin.then(|| web::Data::new(stats::Stats::default()));
+		let mut hooks = self.hooks;
+		if let Some(stats) = &stats {
+			hooks.push(stats::DownloadCounter {
+				stats: stats.clone(),
+				root: config.root.clone(),
+			});
+		}
+		if config.max_downloads.is_some() {
+			hooks.push(shutdown::DownloadMarker);
+		}
+		let hooks = web::Data::new(hooks);
+		let metrics = config.metrics.then(|| web::Data::new(metrics::Metrics::default()));
+		let connection_metrics = metrics.clone();
+		let app = move || {
+			App::new()
+				.app_data(reloader.clone())
+				.app_data(locks.clone())
+				.app_data(checksums.clone())
+				.app_data(hooks.clone())
+				.app_data(server_transfers.clone())
+				.configure(|cfg| {
+					if let Some(content_index) = &content_index {
+						cfg.app_data(content_index.clone());
+					}
+					if let Some(metrics) = &metrics {
+						cfg.app_data(metrics.clone());
+					}
+					if let Some(stats) = &stats {
+						cfg.app_data(stats.clone());
+					}
+					if let Some(limiter) = &limiter {
+						cfg.app_data(limiter.clone());
+					}
+					if let Some(bans) = &bans {
+						cfg.app_data(bans.clone());
+					}
+					if let Some(allowed_hosts) = &allowed_hosts {
+						cfg.app_data(allowed_hosts.clone());
+					}
+				})
+				.wrap(middleware::from_fn(mounts::use_mount))
+				.wrap(middleware::from_fn(hooks::run_request_hooks))
+				.wrap(middleware::from_fn(auth::require_auth))
+				.wrap(middleware::Condition::new(
+					apply_rules,
+					middleware::from_fn(rules::apply_rules),
+				))
+				.wrap(middleware::Condition::new(
+					compress,
+					middleware::from_fn(compression::compress),
+				))
+				.wrap(middleware::Condition::new(
+					throttle,
+					middleware::from_fn(throttle::limit_rate),
+				))
+				.wrap(middleware::Condition::new(
+					server_timing,
+					middleware::from_fn(timing::add_server_timing),
+				))
+				.wrap(middleware::Condition::new(
+					limiter.is_some(),
+					middleware::from_fn(limits::limit_requests),
+				))
+				.wrap(middleware::Condition::new(
+					hsts.is_some(),
+					middleware::DefaultHeaders::new()
+						.add((header::STRICT_TRANSPORT_SECURITY, hsts.clone().unwrap_or_default())),
+				))
+				.wrap(middleware::Condition::new(
+					security_headers,
+					middleware::DefaultHeaders::new()
+						.add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+						.add((header::X_FRAME_OPTIONS, "DENY"))
+						.add((header::REFERRER_POLICY, "no-referrer"))
+						.add((header::CONTENT_SECURITY_POLICY, csp.clone())),
+				))
+				.wrap(middleware::Condition::new(
+					add_headers,
+					middleware::from_fn(headers::add_headers),
+				))
+				.wrap(middleware::Condition::new(
+					allowed_hosts.is_some(),
+					middleware::from_fn(host_filter::filter_hosts),
+				))
+				.wrap(middleware::Condition::new(
+					filter_ips,
+					middleware::from_fn(ip_filter::filter_ips),
+				))
+				.wrap(middleware::from_fn(errors::render_errors))
+				.wrap(middleware::from_fn(access_log::log_requests))
+				.wrap(middleware::from_fn(reload::use_settings))
+				.configure(|cfg| {
+					for upstream in &upstreams {
+						cfg.service(
+							web::scope(&upstream.path)
+								.app_data(upstream.clone())
+								.default_service(web::to(proxy::forward)),
+						);
+					}
+					cfg.service(
+						routes(mounts::SCOPE).guard(guard::fn_guard(|ctx| ctx.req_data().contains::<mounts::Routed>())),
+					);
+					// After the virtual hosts, which have roots of their own.
+					if let Some(cgi) = &cgi {
+						cfg.service(
+							web::scope(&cgi.path)
+								.app_data(cgi.clone())
+								.default_service(web::to(cgi::run)),
+						);
+					}
+				})
+				.service(routes(&prefix))
+		};
+		let on_connect = move |extensions: &mut Extensions| {
+			if let Some(metrics) = &connection_metrics {
+				extensions.insert(metrics::ConnectionGuard::new(metrics.clone()));
+			}
+			if connection_limiter
+				.as_ref()
+				.is_some_and(|limiter| limiter.limits_connections())
+			{
+				extensions.insert(limits::Connection::default());
+			}
+		};
+		let connections = tls::Connections {
+			workers,
+			keep_alive,
+			client_request_timeout,
+			client_disconnect_timeout,
+			shutdown_timeout: grace,
+		};
+		let server = HttpServer::new(app.clone())
+			.on_connect({
+				let on_connect = on_connect.clone();
+				move |_, extensions| on_connect(extensions)
+			})
+			.disable_signals()
+			.shutdown_timeout(grace.as_secs());
+		let server = match connections.workers {
+			Some(workers) => server.workers(workers),
+			None => server,
+		};
+		let server = match connections.keep_alive {
+			Some(keep_alive) => server.keep_alive(keep_alive),
+			None => server,
+		};
+		let server = match connections.client_request_timeout {
+			Some(timeout) => server.client_request_timeout(timeout),
+			None => server,
+		};
+		let server = match connections.client_disconnect_timeout {
+			Some(timeout) => server.client_disconnect_timeout(timeout),
+			None => server,
+		};
+
+		// Every listener shares the one app, whether it speaks TLS or not.
+		let mut server = server;
+		let mut urls = Vec::with_capacity(listening.len());
+		let mut lan_urls = Vec::new();
+		let mut http1_sockets = Vec::new();
+		// Only run when some listener is left to it, as actix-server panics on none.
+		let mut bound = false;
+		let all: Vec<_> = listening.iter().map(|listener| listener.address).collect();
+		for (listener, socket) in listening.iter().zip(sockets) {
+			let address = listener.address;
+			let scheme = if tls_config.is_some() && secure(listener) {
+				"https"
+			} else {
+				"http"
+			};
+			for reachable in listeners::reachable(address, &all) {
+				let url = format!("{scheme}://{reachable}");
+				if !urls.contains(&url) {
+					urls.push(url);
+				}
+			}
+			if let Some(ip) = listeners::lan_address(address.ip()) {
+				let url = format!("{scheme}://{}", SocketAddr::new(ip, address.port()));
+				if !lan_urls.contains(&url) {
+					lan_urls.push(url);
+				}
+			}
+			server = match &tls_config {
+				Some(_) if secure(listener) && !http2 => {
+					http1_sockets.push(socket);
+					server
+				},
+				Some(tls_config) if secure(listener) => {
+					bound = true;
+					server.listen_rustls_0_23(socket, tls_config.clone())?
+				},
+				_ => {
+					bound = true;
+					if h2c {
+						server.listen_auto_h2c(socket)?
+					} else {
+						server.listen(socket)?
+					}
+				},
+			};
+		}
+		#[cfg(unix)]
+		let _socket_file = match &unix_socket {
+			Some(path) => {
+				let (socket, file) = listeners::unix(path, unix_socket_mode)?;
+				if h2c {
+					tracing::warn!("The Unix socket takes HTTP/1.1 only; h2c applies to TCP listeners");
+				}
+				server = server.listen_uds(socket)?;
+				bound = true;
+				urls.push(format!("unix:{}", path.display()));
+				Some(file)
+			},
+			None => None,
+		};
+		#[cfg(not(unix))]
+		if unix_socket.is_some() || unix_socket_mode.is_some() {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::Unsupported,
+				"Unix sockets are not supported on this platform",
+			));
+		}
+		let _mdns = match mdns {
+			Some(service) => {
+				let host = service.host();
+				let responder = mdns::Responder::start(service)
+					.map_err(|e| std::io::Error::new(e.kind(), format!("Cannot advertise over mDNS: {e}")))?;
+				tracing::info!("Advertising on the local network as {host}");
+				if listening
+					.first()
+					.is_some_and(|listener| listener.address.ip().is_loopback())
+				{
+					tracing::warn!("Only listening on localhost, which others on the network cannot reach; see --bind");
+				}
+				Some(responder)
+			},
+			_ => None,
+		};
+		for url in &urls {
+			tracing::info!("Serving {} at {url}", root.display());
+		}
+		if let (Some(token), Some(url)) = (&token, urls.first()) {
+			tracing::info!("Access with token: {url}/?token={token}");
+		}
+		if let (Some(path), Some(url)) = (&copied_path, lan_urls.first().or(urls.first())) {
+			let url = match &token {
+				Some(token) => format!("{url}{path}/?token={token}"),
+				None => format!("{url}{path}/"),
+			};
+			match clipboard::copy(&url) {
+				Ok(()) => tracing::info!("Copied {url} to the clipboard"),
+				Err(e) => tracing::warn!("Cannot copy {url} to the clipboard: {e}"),
+			}
+		}
+		if !qr_paths.is_empty() && lan_urls.is_empty() {
+			tracing::warn!("Only listening on localhost, so there is no URL for a QR code; see --bind");
+		}
+		for url in &lan_urls {
+			for path in &qr_paths {
+				let url = match &token {
+					Some(token) => format!("{url}{path}/?token={token}"),
+					None => format!("{url}{path}/"),
+				};
+				match qr::QrCode::new(url.as_bytes()) {
+					// Printed as it is, as log lines would break the code up.
+					Some(code) => eprintln!("\n{}\n{url}\n", code.to_lines().join("\n")),
+					None => tracing::warn!("{url} is too long for a QR code"),
+				}
+			}
+		}
+
+		let http1 = match &tls_config {
+			Some(tls_config) if !http1_sockets.is_empty() => Some(tls::http1_server(
+				app,
+				on_connect,
+				http1_sockets,
+				tls_config,
+				&connections,
+			)?),
+			_ => None,
+		};
+		let server = bound.then(|| server.run());
+		let handles = server
+			.as_ref()
+			.map(|server| server.handle())
+			.into_iter()
+			.chain(redirect.as_ref().map(|redirect| redirect.handle()))
+			.chain(http1.as_ref().map(|http1| http1.handle()))
+			.collect();
+		actix_web::rt::spawn(shutdown::stop_when(
+			handles,
+			transfers.clone(),
+			grace,
+			timeout,
+			idle,
+			downloads_done,
+		));
+		let result = futures::future::try_join_all(server.into_iter().chain(redirect).chain(http1))
+			.await
+			.map(|_| ());
+		transfers.log_summary();
+		result
+	}
+}
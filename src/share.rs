@@ -0,0 +1,99 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::auth;
+use crate::config::Config;
+use crate::helpers::{encode_url_path, resolve_path};
+
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShareCheck {
+	/// The request carries no share signature.
+	Unsigned,
+	Valid,
+	Expired,
+	Invalid,
+}
+
+#[derive(Deserialize)]
+pub struct ShareQuery {
+	path: String,
+	expires_in: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ShareLink {
+	url: String,
+	expires: u64,
+}
+
+pub fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub fn signature(secret: &str, path: &str, expires: u64) -> String {
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+	mac.update(path.as_bytes());
+	mac.update(b"\n");
+	mac.update(expires.to_string().as_bytes());
+	BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// Checks the `expires`/`sig` query parameters of `req` against its (decoded) URL path.
+pub fn verify(config: &Config, req: &HttpRequest) -> ShareCheck {
+	let Ok(query) = web::Query::<Vec<(String, String)>>::from_query(req.query_string()) else {
+		return ShareCheck::Unsigned;
+	};
+	let find = |name: &str| {
+		query
+			.iter()
+			.find_map(|(key, value)| (key == name).then_some(value.as_str()))
+	};
+
+	let (expires, sig) = match (find("expires"), find("sig")) {
+		(Some(expires), Some(sig)) => (expires, sig),
+		(None, None) => return ShareCheck::Unsigned,
+		_ => return ShareCheck::Invalid,
+	};
+	let Ok(expires) = expires.parse::<u64>() else {
+		return ShareCheck::Invalid;
+	};
+
+	let path = percent_decode_str(req.path()).decode_utf8_lossy();
+	let expected = signature(&config.share_secret, &path, expires);
+	if !auth::constant_time_eq(expected.as_bytes(), sig.as_bytes()) {
+		ShareCheck::Invalid
+	} else if expires < now() {
+		ShareCheck::Expired
+	} else {
+		ShareCheck::Valid
+	}
+}
+
+#[get("/_api/share")]
+async fn create_share_link(query: web::Query<ShareQuery>, config: web::Data<Config>) -> Result<HttpResponse> {
+	let relative = query.path.trim_start_matches('/');
+	let Some(final_path) = resolve_path(&config.root, Path::new(relative)) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	if !final_path.is_file() {
+		return Ok(HttpResponse::NotFound().body("File not found"));
+	}
+
+	// Signed with the prefix, so the link is good for this mount only.
+	let path = format!("{}/{relative}", config.route_prefix);
+	let Some(expires) = now().checked_add(query.expires_in.unwrap_or(DEFAULT_TTL_SECS)) else {
+		return Ok(HttpResponse::BadRequest().body("Expiry is too far in the future"));
+	};
+	let sig = signature(&config.share_secret, &path, expires);
+	let url = format!("{}?expires={expires}&sig={sig}", encode_url_path(&path));
+
+	Ok(HttpResponse::Ok().json(ShareLink { url, expires }))
+}
@@ -0,0 +1,208 @@
+use actix_web::dev::ServerHandle;
+use actix_web::rt::signal;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::hooks::Hook;
+
+/// Counts of what the server sent, kept for the summary printed once it stops.
+pub struct Transfers {
+	started: Instant,
+	requests: AtomicU64,
+	sent: AtomicU64,
+	active: AtomicU64,
+	cut_off: AtomicU64,
+	/// When a response last started or ended, in milliseconds since `started`.
+	last_active: AtomicU64,
+	/// Files sent in full.
+	downloads: AtomicU64,
+	max_downloads: Option<u64>,
+	/// Told once `max_downloads` files have been sent.
+	downloads_done: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl Transfers {
+	/// Counts that tell the returned receiver once `max_downloads` files have been sent, if it is
+	/// set.
+	pub fn new(max_downloads: Option<u64>) -> (Self, oneshot::Receiver<()>) {
+		let (done, downloads_done) = oneshot::channel();
+		let transfers = Self {
+			started: Instant::now(),
+			requests: AtomicU64::new(0),
+			sent: AtomicU64::new(0),
+			active: AtomicU64::new(0),
+			cut_off: AtomicU64::new(0),
+			last_active: AtomicU64::new(0),
+			downloads: AtomicU64::new(0),
+			max_downloads,
+			downloads_done: Mutex::new(Some(done)),
+		};
+		(transfers, downloads_done)
+	}
+
+	/// A response body started being sent.
+	pub fn start(&self) {
+		self.requests.fetch_add(1, Ordering::Relaxed);
+		self.active.fetch_add(1, Ordering::Relaxed);
+		self.touch();
+	}
+
+	fn touch(&self) {
+		self.last_active
+			.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+	}
+
+	/// How long nothing has been sent for, or `None` while something is.
+	fn idle_for(&self) -> Option<Duration> {
+		if self.active.load(Ordering::Relaxed) > 0 {
+			return None;
+		}
+		let last = Duration::from_millis(self.last_active.load(Ordering::Relaxed));
+		Some(self.started.elapsed().saturating_sub(last))
+	}
+
+	pub fn add_sent(&self, bytes: u64) {
+		self.sent.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	/// A response body is done with, `complete` unless the client went away or the server
+	/// stopped first.
+	pub fn finish(&self, complete: bool) {
+		self.active.fetch_sub(1, Ordering::Relaxed);
+		self.touch();
+		if !complete {
+			self.cut_off.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// A file was sent in full.
+	pub fn downloaded(&self) {
+		let downloads = self.downloads.fetch_add(1, Ordering::Relaxed) + 1;
+		if self.max_downloads.is_some_and(|max| downloads >= max) {
+			if let Some(done) = self.downloads_done.lock().unwrap().take() {
+				let _ = done.send(());
+			}
+		}
+	}
+
+	/// Logs how many requests were answered and how much was sent while the server ran.
+	pub fn log_summary(&self) {
+		let cut_off = self.cut_off.load(Ordering::Relaxed) + self.active.load(Ordering::Relaxed);
+		tracing::info!(
+			"Served {} in {}s, sending {}{}",
+			count(self.requests.load(Ordering::Relaxed), "request"),
+			self.started.elapsed().as_secs(),
+			humansize::format_size(self.sent.load(Ordering::Relaxed), humansize::BINARY),
+			match cut_off {
+				0 => String::new(),
+				n => format!("; {} not sent in full", count(n, "response")),
+			}
+		);
+	}
+}
+
+fn count(n: u64, thing: &str) -> String {
+	match n {
+		1 => format!("1 {thing}"),
+		n => format!("{n} {thing}s"),
+	}
+}
+
+/// Marks a request as a download of the whole of a file.
+pub struct Download;
+
+/// Marks requests for files with [`Download`], for counting them once sent.
+pub struct DownloadMarker;
+
+impl Hook for DownloadMarker {
+	fn before_send_file(&self, req: &HttpRequest, _path: &Path) -> Option<HttpResponse> {
+		req.extensions_mut().insert(Download);
+		None
+	}
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix.
+async fn signalled() {
+	#[cfg(unix)]
+	{
+		if let Ok(mut terminate) = signal::unix::signal(signal::unix::SignalKind::terminate()) {
+			future::select(Box::pin(signal::ctrl_c()), Box::pin(terminate.recv())).await;
+			return;
+		}
+	}
+	let _ = signal::ctrl_c().await;
+}
+
+/// Resolves once `transfers` have been idle for `limit`.
+async fn idle(transfers: &Transfers, limit: Duration) {
+	loop {
+		let wait = match transfers.idle_for() {
+			Some(idle) if idle >= limit => return,
+			Some(idle) => limit - idle,
+			None => limit,
+		};
+		actix_web::rt::time::sleep(wait).await;
+	}
+}
+
+/// Stops `servers` once signalled, after `timeout`, once idle for `idle_limit` or once
+/// `downloads_done`: they stop accepting connections at once, and those open get `grace` to
+/// finish what they are sending. A signal while stopping exits the process without waiting, as
+/// servers already stopping gracefully cannot be told to hurry.
+pub async fn stop_when(
+	servers: Vec<ServerHandle>,
+	transfers: actix_web::web::Data<Transfers>,
+	grace: Duration,
+	timeout: Option<Duration>,
+	idle_limit: Option<Duration>,
+	downloads_done: oneshot::Receiver<()>,
+) {
+	let timed_out = async {
+		match timeout {
+			Some(timeout) => {
+				actix_web::rt::time::sleep(timeout).await;
+				tracing::info!("Stopping after running for {}s", timeout.as_secs());
+			},
+			None => future::pending().await,
+		}
+	};
+	let went_idle = async {
+		match idle_limit {
+			Some(limit) => {
+				idle(&transfers, limit).await;
+				tracing::info!("Stopping after {}s without requests", limit.as_secs());
+			},
+			None => future::pending().await,
+		}
+	};
+	let downloaded = async {
+		match downloads_done.await {
+			Ok(()) => tracing::info!(
+				"Stopping after {}",
+				count(transfers.downloads.load(Ordering::Relaxed), "download")
+			),
+			Err(_) => future::pending().await,
+		}
+	};
+	future::select(
+		future::select(Box::pin(signalled()), Box::pin(downloaded)),
+		future::select(Box::pin(timed_out), Box::pin(went_idle)),
+	)
+	.await;
+	tracing::info!(
+		"Shutting down, waiting up to {}s for {} being sent; interrupt again to stop at once",
+		grace.as_secs(),
+		count(transfers.active.load(Ordering::Relaxed), "response")
+	);
+	let graceful = future::join_all(servers.iter().map(|server| server.stop(true)));
+	if let Either::Right(_) = future::select(Box::pin(graceful), Box::pin(signalled())).await {
+		tracing::warn!("Stopping without waiting for responses being sent");
+		transfers.log_summary();
+		std::process::exit(130);
+	}
+}
@@ -0,0 +1,243 @@
+use actix_web::http::header::ContentEncoding;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::hooks::Hook;
+
+const ADMIN_HTML: &str = include_str!("../templates/admin.html");
+const RECENT_REQUESTS: usize = 50;
+const TOP_DOWNLOADS: usize = 10;
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Transfer {
+	method: String,
+	path: String,
+	peer: Option<String>,
+	started: Instant,
+	sent: Arc<AtomicU64>,
+}
+
+#[derive(Serialize, Clone)]
+struct Finished {
+	time: chrono::DateTime<chrono::Local>,
+	method: String,
+	path: String,
+	peer: Option<String>,
+	status: u16,
+	bytes: u64,
+	duration_ms: f64,
+}
+
+#[derive(Default)]
+struct Inner {
+	next_id: u64,
+	active: HashMap<u64, Transfer>,
+	recent: VecDeque<Finished>,
+	downloads: HashMap<String, u64>,
+	finished_bytes: u64,
+}
+
+/// In-memory statistics behind the admin dashboard, fed by the access log middleware and a
+/// download hook.
+pub struct Stats {
+	started: Instant,
+	inner: Mutex<Inner>,
+}
+
+/// A response body being sent, reported to [`Stats`] until dropped.
+pub struct ActiveTransfer {
+	stats: web::Data<Stats>,
+	id: u64,
+	sent: Arc<AtomicU64>,
+	status: u16,
+}
+
+impl ActiveTransfer {
+	pub fn add_sent(&self, bytes: u64) {
+		self.sent.fetch_add(bytes, Ordering::Relaxed);
+	}
+}
+
+impl Drop for ActiveTransfer {
+	fn drop(&mut self) {
+		self.stats.finish(self.id, self.status);
+	}
+}
+
+#[derive(Serialize)]
+struct ActiveView {
+	method: String,
+	path: String,
+	peer: Option<String>,
+	sent: u64,
+	elapsed_secs: f64,
+}
+
+#[derive(Serialize)]
+struct Download {
+	path: String,
+	count: u64,
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+	uptime_secs: u64,
+	total_bytes: u64,
+	active: Vec<ActiveView>,
+	/// Newest first.
+	recent: Vec<Finished>,
+	top_downloads: Vec<Download>,
+}
+
+impl Default for Stats {
+	fn default() -> Self {
+		Self {
+			started: Instant::now(),
+			inner: Mutex::new(Inner::default()),
+		}
+	}
+}
+
+impl Stats {
+	pub fn start(stats: web::Data<Self>, method: &str, path: &str, peer: Option<&str>, status: u16) -> ActiveTransfer {
+		let sent = Arc::new(AtomicU64::new(0));
+		let id = {
+			let mut inner = stats.inner.lock().unwrap();
+			let id = inner.next_id;
+			inner.next_id += 1;
+			inner.active.insert(
+				id,
+				Transfer {
+					method: method.to_string(),
+					path: path.to_string(),
+					peer: peer.map(str::to_string),
+					started: Instant::now(),
+					sent: sent.clone(),
+				},
+			);
+			id
+		};
+		ActiveTransfer {
+			stats,
+			id,
+			sent,
+			status,
+		}
+	}
+
+	fn finish(&self, id: u64, status: u16) {
+		let mut inner = self.inner.lock().unwrap();
+		let Some(transfer) = inner.active.remove(&id) else {
+			return;
+		};
+		let bytes = transfer.sent.load(Ordering::Relaxed);
+		inner.finished_bytes += bytes;
+		if inner.recent.len() == RECENT_REQUESTS {
+			inner.recent.pop_back();
+		}
+		inner.recent.push_front(Finished {
+			time: chrono::Local::now(),
+			method: transfer.method,
+			path: transfer.path,
+			peer: transfer.peer,
+			status,
+			bytes,
+			duration_ms: transfer.started.elapsed().as_secs_f64() * 1000.0,
+		});
+	}
+
+	fn snapshot(&self) -> Snapshot {
+		let inner = self.inner.lock().unwrap();
+		let active: Vec<ActiveView> = inner
+			.active
+			.values()
+			.map(|transfer| ActiveView {
+				method: transfer.method.clone(),
+				path: transfer.path.clone(),
+				peer: transfer.peer.clone(),
+				sent: transfer.sent.load(Ordering::Relaxed),
+				elapsed_secs: transfer.started.elapsed().as_secs_f64(),
+			})
+			.collect();
+
+		let mut top_downloads: Vec<Download> = inner
+			.downloads
+			.iter()
+			.map(|(path, count)| Download {
+				path: path.clone(),
+				count: *count,
+			})
+			.collect();
+		top_downloads.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+		top_downloads.truncate(TOP_DOWNLOADS);
+
+		Snapshot {
+			uptime_secs: self.started.elapsed().as_secs(),
+			total_bytes: inner.finished_bytes + active.iter().map(|transfer| transfer.sent).sum::<u64>(),
+			active,
+			recent: inner.recent.iter().cloned().collect(),
+			top_downloads,
+		}
+	}
+}
+
+/// Counts file downloads for the dashboard. Range requests only count when they start at the
+/// beginning, so seeking through a video is not counted as many downloads.
+pub struct DownloadCounter {
+	pub stats: web::Data<Stats>,
+	pub root: PathBuf,
+}
+
+impl Hook for DownloadCounter {
+	fn before_send_file(&self, req: &HttpRequest, path: &Path) -> Option<HttpResponse> {
+		let from_start = req
+			.headers()
+			.get("range")
+			.and_then(|v| v.to_str().ok())
+			.is_none_or(|range| range.trim().starts_with("bytes=0-"));
+		if from_start {
+			let relative = path.strip_prefix(&self.root).unwrap_or(path);
+			let key = format!("/{}", relative.to_string_lossy());
+			*self.stats.inner.lock().unwrap().downloads.entry(key).or_default() += 1;
+		}
+		None
+	}
+}
+
+#[get("/_admin")]
+async fn admin_page(stats: Option<web::Data<Stats>>) -> HttpResponse {
+	if stats.is_none() {
+		return HttpResponse::NotFound().body("The admin dashboard is disabled");
+	}
+	HttpResponse::Ok().content_type("text/html").body(ADMIN_HTML)
+}
+
+/// Server-sent events carrying a JSON [`Snapshot`] every second.
+#[get("/_admin/events")]
+async fn admin_events(stats: Option<web::Data<Stats>>) -> HttpResponse {
+	let Some(stats) = stats else {
+		return HttpResponse::NotFound().body("The admin dashboard is disabled");
+	};
+	let interval = actix_web::rt::time::interval(UPDATE_INTERVAL);
+	let events = futures::stream::unfold((stats, interval), |(stats, mut interval)| async move {
+		interval.tick().await;
+		let json = serde_json::to_string(&stats.snapshot()).unwrap_or_default();
+		Some((
+			Ok::<_, actix_web::Error>(Bytes::from(format!("data: {json}\n\n"))),
+			(stats, interval),
+		))
+	});
+
+	// Compression would hold events back until enough of them piled up.
+	HttpResponse::Ok()
+		.content_type("text/event-stream")
+		.insert_header(("Cache-Control", "no-cache"))
+		.insert_header(ContentEncoding::Identity)
+		.streaming(events)
+}
@@ -1,35 +1,742 @@
 use actix_web::Result;
 use askama::Template;
-use bytes::Bytes;
-use clap::Parser;
-use futures::stream::Stream;
-use serde::Serialize;
+use bytes::{BufMut, Bytes, BytesMut};
+use clap::{Parser, Subcommand, ValueHint};
+use futures::channel::mpsc;
+use futures::{SinkExt, Stream, StreamExt};
+use memmap2::{Mmap, MmapOptions};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Seek, SeekFrom};
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::pin::Pin;
+use tokio::io::{AsyncReadExt, Take};
+use tokio_util::io::poll_read_buf;
 
+use crate::access_log::LogFormat;
+use crate::buffers;
+use crate::compression::Algorithm;
+use crate::config::{
+	parse_error_page, parse_mime_mapping, parse_mode, parse_quota, ByteSize, CustomHeader, ErrorStatus, IpRange,
+	Listener, Mount, Proxy, Seconds,
+};
+use crate::logging::LogRotation;
 use crate::CHUNK_SIZE;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Simple HTTP file server")]
+#[command(
+	name = "http-server",
+	author,
+	version,
+	about = "Simple HTTP file server",
+	args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+
+	#[command(flatten)]
+	pub serve: Args,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+	/// Serve a directory, as when no subcommand is given
+	Serve(Box<Args>),
+	/// Print a completion script for a shell
+	Completions {
+		#[arg(value_enum)]
+		shell: Shell,
+	},
+	/// Print a commented configuration file with every setting at its default
+	GenConfig,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Shell {
+	Bash,
+	Zsh,
+	Fish,
+}
+
+#[derive(clap::Args, Debug)]
 pub struct Args {
-	#[arg(short, long, default_value_t = 8080)]
-	pub port: u16,
+	#[arg(value_hint = ValueHint::DirPath, help = "Directory to serve [default: .]")]
+	pub directory: Option<PathBuf>,
+
+	#[arg(short, long, help = "Port to listen on, or 0 for any that is free [default: 8080]")]
+	pub port: Option<u16>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		num_args = 0..=1,
+		default_missing_value = "100",
+		help = "Try up to N ports above --port in turn while it is in use [default N: 100]"
+	)]
+	pub port_retry: Option<u16>,
+
+	#[arg(
+		short,
+		long,
+		value_name = "ADDR",
+		help = "Listen on ADDR, e.g. 0.0.0.0 for all IPv4 interfaces, :: for all IPv4 and IPv6 ones, or one interface's IP (repeatable) [default: 127.0.0.1]"
+	)]
+	pub bind: Vec<IpAddr>,
+
+	#[arg(
+		long,
+		value_name = "[SCHEME://]ADDR:PORT",
+		help = "Listen on ADDR:PORT instead, over HTTPS for https:// and plain HTTP for http:// [default: HTTPS when TLS is configured] (repeatable)"
+	)]
+	pub listen: Vec<Listener>,
+
+	#[arg(
+		long,
+		help = "Also take HTTP/2 without TLS on TCP listeners from clients that start with it (h2c prior knowledge; Upgrade: h2c is answered over HTTP/1.1)"
+	)]
+	pub h2c: bool,
+
+	#[arg(
+		long,
+		conflicts_with = "h2c",
+		help = "Only speak HTTP/1.1, also over HTTPS, which otherwise offers HTTP/2"
+	)]
+	pub no_http2: bool,
+
+	#[arg(
+		long,
+		value_name = "PATH",
+		help = "Listen on a Unix socket at PATH, e.g. for a reverse proxy, instead of the TCP port unless --bind or --listen are given"
+	)]
+	pub unix_socket: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "MODE",
+		value_parser = parse_mode,
+		help = "Permissions of the --unix-socket in octal, e.g. 660 [default: as the umask allows]"
+	)]
+	pub unix_socket_mode: Option<u32>,
+
+	#[arg(
+		long,
+		help = "Advertise the server on the local network over mDNS/Bonjour, as <hostname>.local"
+	)]
+	pub mdns: bool,
+
+	#[arg(
+		long,
+		value_name = "NAME",
+		help = "Name the server is advertised under with --mdns [default: the host name]"
+	)]
+	pub mdns_name: Option<String>,
+
+	#[arg(
+		long,
+		help = "Print a QR code of each URL others on the network can open, for scanning with a phone"
+	)]
+	pub qr: bool,
+
+	#[arg(
+		long,
+		help = "Copy the URL others on the network can open, or else the local one, to the clipboard"
+	)]
+	pub copy_url: bool,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		help = "How long downloads in progress may take to finish on Ctrl+C or SIGTERM; a second Ctrl+C stops at once [default: 30]"
+	)]
+	pub shutdown_grace: Option<u64>,
+
+	#[arg(
+		long,
+		value_name = "DURATION",
+		help = "Stop after DURATION, in seconds or with an s/m/h/d suffix, e.g. 2h"
+	)]
+	pub timeout: Option<Seconds>,
+
+	#[arg(
+		long,
+		value_name = "DURATION",
+		help = "Stop once no request has come in for DURATION, e.g. 10m"
+	)]
+	pub exit_after_idle: Option<Seconds>,
+
+	#[arg(long, help = "Stop once a file has been downloaded in full")]
+	pub once: bool,
+
+	#[arg(
+		long,
+		value_name = "N",
+		conflicts_with = "once",
+		help = "Stop once N files have been downloaded in full"
+	)]
+	pub max_downloads: Option<u64>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Handle connections on N worker threads [default: one per CPU core]"
+	)]
+	pub workers: Option<usize>,
+
+	#[arg(
+		long,
+		value_name = "DURATION",
+		help = "Keep idle connections open for DURATION, or 0 to close each after its response [default: 5s]"
+	)]
+	pub keep_alive: Option<Seconds>,
+
+	#[arg(
+		long,
+		value_name = "DURATION",
+		help = "Answer 408 to clients taking longer than DURATION to send a request's headers, or 0 to wait [default: 5s]"
+	)]
+	pub client_request_timeout: Option<Seconds>,
+
+	#[arg(
+		long,
+		value_name = "DURATION",
+		help = "Give connections DURATION to close cleanly after their last response [default: 1s]"
+	)]
+	pub client_disconnect_timeout: Option<Seconds>,
+
+	#[arg(
+		short,
+		long,
+		value_name = "FILE",
+		help = "Load settings from a TOML file, reloading credentials, mounts, headers, rules and ignore patterns from it on SIGHUP"
+	)]
+	pub config: Option<PathBuf>,
+
+	#[arg(long, value_name = "FILE", help = "PEM certificate chain to serve HTTPS with")]
+	pub tls_cert: Option<PathBuf>,
+
+	#[arg(long, value_name = "FILE", help = "PEM private key matching --tls-cert")]
+	pub tls_key: Option<PathBuf>,
+
+	#[arg(
+		long,
+		help = "Serve HTTPS with an ephemeral self-signed certificate when no cert/key is given"
+	)]
+	pub tls_self_signed: bool,
+
+	#[arg(
+		long,
+		value_name = "DOMAIN",
+		help = "Obtain certificates for DOMAIN via ACME (repeatable)"
+	)]
+	pub acme: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "EMAIL",
+		help = "Contact address for the ACME account (repeatable)"
+	)]
+	pub acme_email: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "DIR",
+		help = "Directory to persist ACME accounts and certificates in"
+	)]
+	pub acme_cache: Option<PathBuf>,
+
+	#[arg(long, help = "Use the Let's Encrypt staging environment")]
+	pub acme_staging: bool,
+
+	#[arg(long, help = "Send Strict-Transport-Security when serving HTTPS")]
+	pub hsts: bool,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		help = "How long browsers should stick to HTTPS with --hsts [default: 31536000]"
+	)]
+	pub hsts_max_age: Option<u64>,
+
+	#[arg(long, help = "Extend --hsts to all subdomains")]
+	pub hsts_include_subdomains: bool,
+
+	#[arg(
+		long,
+		value_name = "PORT",
+		help = "Also listen for plain HTTP on PORT, redirecting every request to HTTPS"
+	)]
+	pub redirect_http: Option<u16>,
+
+	#[arg(
+		short,
+		long,
+		value_name = "USER:PASS",
+		help = "Require HTTP Basic auth (repeatable for multiple users)"
+	)]
+	pub auth: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "SECRET",
+		num_args = 0..=1,
+		default_missing_value = "",
+		help = "Require a bearer token or ?token= parameter (generated when SECRET is omitted)"
+	)]
+	pub token: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "SECRET",
+		help = "Key for signing share links (random per run if omitted, invalidating links on restart)"
+	)]
+	pub share_secret: Option<String>,
+
+	#[arg(
+		long,
+		overrides_with = "no_follow_symlinks",
+		help = "Serve symlinks wherever they point, even outside the served directory"
+	)]
+	pub follow_symlinks: bool,
+
+	#[arg(
+		long,
+		overrides_with = "follow_symlinks",
+		help = "Only serve symlinks that resolve inside the served directory (default)"
+	)]
+	pub no_follow_symlinks: bool,
+
+	#[arg(
+		long,
+		help = "List and serve dotfiles; otherwise only authenticated users asking with ?hidden=true see them"
+	)]
+	pub hidden: bool,
+
+	#[arg(
+		long,
+		value_name = "GLOB",
+		help = "Keep paths matching GLOB (e.g. node_modules/ or *.log) out of listings, search, archives and requests (repeatable)"
+	)]
+	pub ignore: Vec<String>,
+
+	#[arg(long, help = "Also ignore what the served directory's .gitignore lists")]
+	pub use_gitignore: bool,
+
+	#[arg(
+		long,
+		value_name = "NAME",
+		help = "Serve NAME instead of listing directories that contain it (repeatable, first found wins) [default: index.html]"
+	)]
+	pub index: Vec<String>,
+
+	#[arg(
+		long,
+		conflicts_with = "index",
+		help = "Always list directories, even those with an index file"
+	)]
+	pub no_index: bool,
+
+	#[arg(
+		long,
+		help = "Serve the root index file, with a 200, for missing paths without an extension, for client-side routed apps"
+	)]
+	pub spa: bool,
+
+	#[arg(
+		long,
+		help = "Answer directories without an index file with 404 instead of listing them, and disable the tree and search APIs"
+	)]
+	pub no_dirlist: bool,
+
+	#[arg(
+		long,
+		value_name = "STATUS=FILE",
+		value_parser = parse_error_page,
+		help = "Show browsers FILE instead of the built-in page for STATUS errors, e.g. 404=public/404.html (repeatable)"
+	)]
+	pub error_page: Vec<(ErrorStatus, PathBuf)>,
+
+	#[arg(
+		long,
+		value_name = "EXT=TYPE[,EXT=TYPE]...",
+		value_delimiter = ',',
+		value_parser = parse_mime_mapping,
+		help = "Serve files with extension EXT as TYPE, e.g. wasm=application/wasm,vtt=text/vtt (repeatable)"
+	)]
+	pub mime_map: Vec<(String, String)>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "Read extension mappings from an /etc/mime.types-style FILE; --mime-map entries take precedence"
+	)]
+	pub mime_types: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "TYPE",
+		help = "Content type for files without an extension [default: application/octet-stream]"
+	)]
+	pub default_mime: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "CHARSET",
+		help = "Charset sent with text files, e.g. windows-1252 [default: sniffed from each file]"
+	)]
+	pub charset: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "EXT[,EXT]...",
+		value_delimiter = ',',
+		help = "Always send files with these extensions as downloads, e.g. html,svg so untrusted pages are never rendered (repeatable)"
+	)]
+	pub force_download_ext: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "PREFIX",
+		help = "Mount every route, and prefix every generated link, under PREFIX (e.g. /files) for serving behind a reverse proxy path"
+	)]
+	pub route_prefix: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "PATH=DIR[,OPTION]...",
+		help = "Also serve DIR beneath PATH, e.g. /music=/mnt/music,read-only; options are read-only and auth=USER:PASS, which replaces --auth there (repeatable)"
+	)]
+	pub mount: Vec<Mount>,
+
+	#[arg(
+		long,
+		value_name = "PATH=URL",
+		help = "Forward requests beneath PATH to the server at URL, e.g. /api=http://localhost:3000 (repeatable)"
+	)]
+	pub proxy: Vec<Proxy>,
+
+	#[arg(long, help = "Allow uploading files into listed directories")]
+	pub allow_upload: bool,
+
+	#[arg(long, help = "Allow deleting files and empty directories")]
+	pub allow_delete: bool,
+
+	#[arg(
+		long,
+		help = "Enable WebDAV (PROPFIND, PROPPATCH, LOCK, UNLOCK) for mounting as a network drive"
+	)]
+	pub webdav: bool,
+
+	#[arg(
+		long,
+		value_name = "DIR",
+		help = "Run the files in DIR, a directory beneath the root such as cgi-bin, as CGI scripts instead of serving them"
+	)]
+	pub cgi: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "SIZE",
+		help = "Reject uploaded files larger than SIZE (e.g. 512M)"
+	)]
+	pub max_upload_size: Option<ByteSize>,
+
+	#[arg(
+		long,
+		value_name = "DIR=SIZE",
+		value_parser = parse_quota,
+		help = "Limit the total size of DIR (relative to the root) for uploads (repeatable)"
+	)]
+	pub quota: Vec<(PathBuf, ByteSize)>,
+
+	#[arg(long, help = "Maintain a full-text index of text files for content search")]
+	pub index_content: bool,
+
+	#[arg(
+		long,
+		value_name = "DIR",
+		help = "Where to keep the content index (defaults to a per-root directory under ~/.cache)"
+	)]
+	pub index_dir: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_enum,
+		value_name = "FORMAT",
+		help = "Access log format [default: common]"
+	)]
+	pub log_format: Option<LogFormat>,
+
+	#[arg(
+		long,
+		value_name = "LEVEL",
+		help = "Log level or RUST_LOG-style filter, e.g. debug [default: $RUST_LOG, then info]"
+	)]
+	pub log_level: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		help = "Also write access entries, warnings and errors to FILE as JSON"
+	)]
+	pub log_file: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "WHEN",
+		help = "Rotate --log-file daily, hourly, never or at a size such as 100M [default: daily]"
+	)]
+	pub log_rotate: Option<LogRotation>,
+
+	#[arg(long, value_name = "N", help = "Rotated log files to keep [default: 7]")]
+	pub log_keep: Option<usize>,
+
+	#[arg(long, help = "Expose Prometheus metrics at /_metrics")]
+	pub metrics: bool,
+
+	#[arg(
+		long,
+		value_name = "USER:PASS",
+		help = "Protect /_metrics with these credentials instead of the regular ones"
+	)]
+	pub metrics_auth: Option<String>,
+
+	#[arg(
+		long,
+		help = "Serve a live statistics dashboard at /_admin and a settings API at /_admin/api (requires --auth or --token)"
+	)]
+	pub admin: bool,
+
+	#[arg(long, help = "Report per-request timings in a Server-Timing header")]
+	pub server_timing: bool,
+
+	#[arg(long, help = "Serve the whole file instead of 416 for unsatisfiable ranges")]
+	pub lenient_ranges: bool,
+
+	#[arg(
+		long,
+		value_name = "SIZE",
+		help = "Read streamed files in chunks of SIZE [default: 64K]"
+	)]
+	pub chunk_size: Option<ByteSize>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Chunks to read ahead of the one being sent [default: 1]"
+	)]
+	pub read_ahead: Option<usize>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Split directory listings into pages of N entries (?page= and ?limit= work regardless)"
+	)]
+	pub page_size: Option<usize>,
+
+	#[arg(
+		long,
+		value_enum,
+		value_name = "ORDER",
+		help = "Order listings are shown in unless ?sort= picks another [default: natural]"
+	)]
+	pub sort: Option<ListingOrder>,
+
+	#[arg(
+		long,
+		value_enum,
+		value_name = "PRESET",
+		help = "Colors listings are drawn in, in light or dark as the browser prefers or the toggle picks [default: default]"
+	)]
+	pub theme: Option<Theme>,
+
+	#[arg(
+		long,
+		value_name = "DIR",
+		value_hint = ValueHint::DirPath,
+		help = "Render listings and error pages from directory.html and error.html in DIR, read on each request, using the built-in ones for those missing"
+	)]
+	pub template_dir: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		value_hint = ValueHint::FilePath,
+		help = "Style listings with FILE too, loaded after the built-in styles"
+	)]
+	pub custom_css: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "FILE",
+		value_hint = ValueHint::FilePath,
+		help = "Run FILE in listings too, after the built-in scripts"
+	)]
+	pub custom_js: Option<PathBuf>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Read the metadata of up to N directory entries at once [default: 16]"
+	)]
+	pub stat_parallelism: Option<usize>,
+
+	#[arg(long, help = "Send responses uncompressed")]
+	pub no_compress: bool,
+
+	#[arg(
+		long,
+		value_enum,
+		value_delimiter = ',',
+		value_name = "ALGORITHMS",
+		help = "Compression algorithms to offer, in order of preference [default: br,zstd,gzip]"
+	)]
+	pub compress_algorithms: Vec<Algorithm>,
+
+	#[arg(
+		long,
+		value_name = "LEVEL",
+		help = "Compression level, clamped to each algorithm's range [default: a fast level]"
+	)]
+	pub compress_level: Option<u32>,
+
+	#[arg(
+		long,
+		value_name = "TYPE",
+		help = "Also leave TYPE (e.g. text/csv or model/*) uncompressed; media and archives always are (repeatable)"
+	)]
+	pub compress_exclude: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "SIZE",
+		help = "Send files of at least SIZE on read-only filesystems from a memory map (best on fast local disks)"
+	)]
+	pub mmap: Option<ByteSize>,
+
+	#[arg(
+		long,
+		value_name = "RATE",
+		help = "Send each response at no more than RATE bytes per second (e.g. 500K)"
+	)]
+	pub limit_rate: Option<ByteSize>,
+
+	#[arg(
+		long,
+		value_name = "RATE",
+		help = "Send all responses together at no more than RATE bytes per second"
+	)]
+	pub limit_rate_total: Option<ByteSize>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Allow each client IP N requests per second on average, answering 429 beyond that"
+	)]
+	pub rate_limit: Option<f64>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Requests a client IP may make in a burst above --rate-limit [default: 20]"
+	)]
+	pub rate_limit_burst: Option<u32>,
+
+	#[arg(long, value_name = "N", help = "Connections each client IP may have open at once")]
+	pub max_connections_per_ip: Option<usize>,
+
+	#[arg(
+		long,
+		value_name = "CIDR",
+		help = "Only let in clients from CIDR (e.g. 192.168.1.0/24) or a single address (repeatable)"
+	)]
+	pub allow_ip: Vec<IpRange>,
+
+	#[arg(
+		long,
+		value_name = "CIDR",
+		help = "Turn away clients from CIDR or a single address, even if allowed (repeatable)"
+	)]
+	pub deny_ip: Vec<IpRange>,
+
+	#[arg(
+		long,
+		value_name = "HOST[,HOST]...",
+		value_delimiter = ',',
+		help = "Only answer requests for these host names, .example.com for its subdomains too, or * for any [default: localhost and the address listened on]"
+	)]
+	pub allowed_hosts: Vec<String>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Trust X-Forwarded-For from N reverse proxies in front of the server [default: 0]"
+	)]
+	pub trusted_proxies: Option<usize>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		help = "Ban clients for --ban-time after N failed authentication attempts"
+	)]
+	pub ban_after: Option<u32>,
+
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		help = "How long bans last and failed attempts are remembered [default: 600]"
+	)]
+	pub ban_time: Option<u64>,
 
-	#[arg(short = 'o', long = "open", help = "Listen on all interfaces (0.0.0.0)")]
-	pub open: bool,
+	#[arg(
+		long,
+		help = "Send nosniff, X-Frame-Options, Referrer-Policy and Content-Security-Policy headers"
+	)]
+	pub security_headers: bool,
+
+	#[arg(
+		long,
+		value_name = "POLICY",
+		help = "Content-Security-Policy sent with --security-headers [default: same-origin only]"
+	)]
+	pub csp: Option<String>,
+
+	#[arg(
+		long,
+		value_name = "[GLOB ]NAME: VALUE",
+		help = "Set a header on every response, or with a glob such as \"*.wasm Cross-Origin-Resource-Policy: same-origin\" only on matching paths (repeatable)"
+	)]
+	pub header: Vec<CustomHeader>,
 }
 
-#[allow(dead_code)]
+/// Streams the bytes from `start` to `end` inclusive of a file. Reads go through tokio's blocking
+/// pool, so a slow disk holds up only this response rather than the whole worker.
 pub struct VideoStream {
-	file: File,
-	start: u64,
-	end: u64,
-	current_pos: u64,
+	inner: Chunks,
+}
+
+enum Chunks {
+	Direct(FileChunks),
+	/// Filled by a task that keeps reading while earlier chunks are still being sent.
+	ReadAhead(mpsc::Receiver<std::io::Result<Bytes>>),
+	Mapped(MappedChunks),
 }
 
 impl VideoStream {
-	pub fn new(mut file: File, start: u64, end: u64) -> std::io::Result<Self> {
+	pub fn new(file: File, start: u64, end: u64) -> std::io::Result<Self> {
+		Self::with_buffering(file, start, end, CHUNK_SIZE, 0)
+	}
+
+	/// Reads `chunk_size` bytes at a time, with up to `read_ahead` chunks waiting to be sent.
+	/// Reading ahead spawns a task, so it needs to run inside the actix runtime.
+	pub fn with_buffering(
+		mut file: File,
+		start: u64,
+		end: u64,
+		chunk_size: usize,
+		read_ahead: usize,
+	) -> std::io::Result<Self> {
 		if start > end {
 			return Err(std::io::Error::new(
 				std::io::ErrorKind::InvalidInput,
@@ -38,49 +745,363 @@ impl VideoStream {
 		}
 
 		file.seek(SeekFrom::Start(start))?;
+		let file = tokio::fs::File::from_std(file).take(end - start + 1);
+		let chunk_size = chunk_size.max(1);
+		let mut chunks = FileChunks {
+			file,
+			buffer: buffers::take(chunk_size),
+			chunk_size,
+		};
+		if read_ahead == 0 {
+			return Ok(Self {
+				inner: Chunks::Direct(chunks),
+			});
+		}
 
+		let (mut sender, receiver) = mpsc::channel(read_ahead - 1);
+		actix_web::rt::spawn(async move {
+			while let Some(chunk) = chunks.next().await {
+				// The response was dropped, so there is no one left to read for.
+				if sender.send(chunk).await.is_err() {
+					break;
+				}
+			}
+		});
 		Ok(Self {
-			file,
-			start,
-			end,
-			current_pos: start,
+			inner: Chunks::ReadAhead(receiver),
+		})
+	}
+}
+
+impl VideoStream {
+	/// Copies chunks straight out of a memory map of the span instead of reading them, saving a
+	/// system call per chunk. Page faults are served on the worker itself, so this suits large
+	/// files on fast local disks, not network filesystems. Only files that [`unchanging`] allows
+	/// are mapped.
+	pub fn mapped(file: File, start: u64, end: u64, chunk_size: usize) -> std::io::Result<Self> {
+		if start > end {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"Start position must be less than or equal to end position",
+			));
+		}
+		if !unchanging(&file) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"Only files on read-only filesystems are memory-mapped",
+			));
+		}
+
+		let len = usize::try_from(end - start + 1).map_err(std::io::Error::other)?;
+		// SAFETY: the map is only read from, and the file is on a filesystem mounted read-only,
+		// so nothing can truncate it under the map, which would raise `SIGBUS` on the pages it
+		// removed. A read-only mount can still be remounted writable while the map is alive,
+		// which is why this is behind `--mmap` and off by default.
+		let map = unsafe { MmapOptions::new().offset(start).len(len).map(&file)? };
+		Ok(Self {
+			inner: Chunks::Mapped(MappedChunks {
+				map,
+				pos: 0,
+				chunk_size: chunk_size.max(1),
+			}),
 		})
 	}
 }
 
+/// Whether `file` is on a filesystem mounted read-only, the only files that are memory-mapped:
+/// checking the length before each chunk of others would still race a truncation.
+pub fn unchanging(file: &File) -> bool {
+	#[cfg(unix)]
+	{
+		use std::os::fd::AsRawFd;
+
+		let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+		// SAFETY: the buffer has room for the whole struct, which is only read once it is filled.
+		unsafe {
+			libc::fstatvfs(file.as_raw_fd(), stats.as_mut_ptr()) == 0
+				&& stats.assume_init().f_flag & libc::ST_RDONLY != 0
+		}
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = file;
+		false
+	}
+}
+
 impl Stream for VideoStream {
 	type Item = Result<Bytes, std::io::Error>;
 
-	fn poll_next(self: Pin<&mut Self>, _cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
-		let this = self.get_mut();
-
-		if this.current_pos > this.end {
-			return std::task::Poll::Ready(None);
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+		match &mut self.inner {
+			Chunks::Direct(chunks) => Pin::new(chunks).poll_next(cx),
+			Chunks::ReadAhead(receiver) => Pin::new(receiver).poll_next(cx),
+			Chunks::Mapped(chunks) => std::task::Poll::Ready(chunks.next_chunk()),
 		}
+	}
+}
+
+/// Reads a file chunk by chunk into a buffer borrowed from the pool. Sent chunks share its
+/// allocation until they are dropped, after which it is reused for the next read.
+struct FileChunks {
+	file: Take<tokio::fs::File>,
+	buffer: BytesMut,
+	chunk_size: usize,
+}
 
-		let remaining = usize::try_from(this.end - this.current_pos + 1).expect("Value got truncated");
-		let to_read = remaining.min(CHUNK_SIZE);
-		let mut buffer = vec![0; to_read];
+impl Stream for FileChunks {
+	type Item = Result<Bytes, std::io::Error>;
 
-		match this.file.read(&mut buffer) {
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+		let this = &mut *self;
+		this.buffer.reserve(this.chunk_size);
+		let read = poll_read_buf(
+			Pin::new(&mut this.file),
+			cx,
+			&mut (&mut this.buffer).limit(this.chunk_size),
+		);
+		match std::task::ready!(read) {
 			Ok(0) => std::task::Poll::Ready(None),
-			Ok(n) => {
-				this.current_pos += n as u64;
-				buffer.truncate(n);
-				std::task::Poll::Ready(Some(Ok(Bytes::from(buffer))))
-			},
+			Ok(_) => std::task::Poll::Ready(Some(Ok(this.buffer.split().freeze()))),
 			Err(e) => std::task::Poll::Ready(Some(Err(e))),
 		}
 	}
 }
 
+impl Drop for FileChunks {
+	fn drop(&mut self) {
+		buffers::give_back(std::mem::take(&mut self.buffer));
+	}
+}
+
+/// A memory-mapped span of a file, copied out a chunk at a time.
+struct MappedChunks {
+	map: Mmap,
+	pos: usize,
+	chunk_size: usize,
+}
+
+impl MappedChunks {
+	fn next_chunk(&mut self) -> Option<std::io::Result<Bytes>> {
+		if self.pos >= self.map.len() {
+			return None;
+		}
+		let end = self.map.len().min(self.pos + self.chunk_size);
+		let chunk = Bytes::copy_from_slice(&self.map[self.pos..end]);
+		self.pos = end;
+		Some(Ok(chunk))
+	}
+}
+
 #[derive(Template)]
 #[template(path = "directory.html")]
 pub struct DirectoryTemplate {
+	/// Prepended to every link, as all routes are mounted beneath it.
+	pub prefix: String,
 	pub current_path: String,
-	pub parent_path: String,
-	pub has_parent: bool,
+	pub allow_upload: bool,
+	pub allow_delete: bool,
+	pub allow_rename: bool,
+	/// Set when listing the inside of a zip file, which can be browsed but not archived or shared.
+	pub in_archive: bool,
+	/// Whether the search box can also search file contents (`--index-content`).
+	pub content_search: bool,
+	pub quota_remaining: Option<String>,
 	pub entries: Vec<DirEntry>,
+	/// Set when only one page of the directory is shown.
+	pub pagination: Option<Pagination>,
+	pub sort: Sort,
+	pub theme: Theme,
+	/// Whether `--custom-css` and `--custom-js` are given, for the page to load them.
+	pub custom_css: bool,
+	pub custom_js: bool,
+}
+
+/// One of the directories leading to the one listed, linked to at `href` beneath the prefix.
+pub struct Breadcrumb {
+	pub name: String,
+	pub href: String,
+}
+
+impl DirectoryTemplate {
+	/// The directories from the root down to the one listed, which comes last.
+	pub fn breadcrumbs(&self) -> Vec<Breadcrumb> {
+		let mut path = String::new();
+		self.current_path
+			.split('/')
+			.filter(|name| !name.is_empty())
+			.map(|name| {
+				if !path.is_empty() {
+					path.push('/');
+				}
+				path.push_str(name);
+				let mut href = crate::helpers::encode_url_path(&path);
+				// An archive's own path downloads it, so its listing is reached with a trailing slash.
+				if self.in_archive && name.to_lowercase().ends_with(".zip") {
+					href.push('/');
+				}
+				Breadcrumb {
+					name: name.to_string(),
+					href,
+				}
+			})
+			.collect()
+	}
+}
+
+/// The built-in page shown to browsers for error responses, carrying the handler's message.
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorTemplate {
+	pub prefix: String,
+	pub status: u16,
+	pub reason: &'static str,
+	pub message: String,
+}
+
+/// Where a page sits in a listing split up with `?page=` and `?limit=`.
+pub struct Pagination {
+	/// Counted from 1.
+	pub page: usize,
+	pub pages: usize,
+	pub limit: usize,
+	/// Entries in the whole directory.
+	pub total: usize,
+}
+
+/// The palette listings are drawn in, each with a light and a dark variant.
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+	#[default]
+	Default,
+	Solarized,
+	Nord,
+	HighContrast,
+}
+
+impl Theme {
+	/// The `data-preset` the listing's stylesheet picks the palette by.
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Default => "default",
+			Self::Solarized => "solarized",
+			Self::Nord => "nord",
+			Self::HighContrast => "high-contrast",
+		}
+	}
+}
+
+/// The column a listing is sorted by, as `?sort=` names it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+	Name,
+	Size,
+	Modified,
+}
+
+impl SortKey {
+	pub fn parse(name: &str) -> Option<Self> {
+		match name {
+			"name" => Some(Self::Name),
+			"size" => Some(Self::Size),
+			"mtime" => Some(Self::Modified),
+			_ => None,
+		}
+	}
+
+	pub const fn as_str(self) -> &'static str {
+		match self {
+			Self::Name => "name",
+			Self::Size => "size",
+			Self::Modified => "mtime",
+		}
+	}
+}
+
+/// How listings are ordered unless `?sort=` says otherwise.
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingOrder {
+	/// By name, regardless of case and accents and with numbers compared by value, so
+	/// `file2.png` comes before `file10.png`.
+	#[default]
+	Natural,
+	/// By name, case-insensitively and character by character.
+	Lexicographic,
+	/// Newest first, with names compared as for `natural`.
+	Mtime,
+}
+
+/// How a listing is ordered, as asked for with `?sort=`, `?order=` and `?dirs_first=`.
+#[derive(Clone, Copy)]
+pub struct Sort {
+	pub key: SortKey,
+	pub descending: bool,
+	/// Whether directories come before files, whichever way the rest is sorted.
+	pub dirs_first: bool,
+	/// Whether names are compared naturally rather than lexicographically.
+	pub natural: bool,
+}
+
+impl Sort {
+	/// The order of listings the request does not pick one for.
+	pub const fn default_for(order: ListingOrder) -> Self {
+		let by_mtime = matches!(order, ListingOrder::Mtime);
+		Self {
+			key: if by_mtime { SortKey::Modified } else { SortKey::Name },
+			descending: by_mtime,
+			dirs_first: true,
+			natural: !matches!(order, ListingOrder::Lexicographic),
+		}
+	}
+
+	fn is_by(&self, key: &str) -> bool {
+		self.key.as_str() == key
+	}
+
+	/// The query of the header link for the `key` column, which sorts by it or, if the listing
+	/// already is, the other way round.
+	pub fn link(&self, key: &str) -> String {
+		let order = if self.is_by(key) && !self.descending {
+			"desc"
+		} else {
+			"asc"
+		};
+		let dirs_first = if self.dirs_first { "" } else { "&dirs_first=false" };
+		format!("?sort={key}&order={order}{dirs_first}")
+	}
+
+	/// The arrow shown next to the `key` column if the listing is sorted by it.
+	pub fn indicator(&self, key: &str) -> &'static str {
+		match (self.is_by(key), self.descending) {
+			(false, _) => "",
+			(true, false) => "▲",
+			(true, true) => "▼",
+		}
+	}
+
+	/// The `aria-sort` of the `key` column.
+	pub fn aria(&self, key: &str) -> &'static str {
+		match (self.is_by(key), self.descending) {
+			(false, _) => "none",
+			(true, false) => "ascending",
+			(true, true) => "descending",
+		}
+	}
+
+	/// What to add to the query of links to other pages of the listing to keep it in this order.
+	pub fn query(&self) -> String {
+		let mut query = format!(
+			"&sort={}&order={}",
+			self.key.as_str(),
+			if self.descending { "desc" } else { "asc" }
+		);
+		if !self.dirs_first {
+			query.push_str("&dirs_first=false");
+		}
+		query
+	}
 }
 
 #[derive(Serialize)]
@@ -88,6 +1109,30 @@ pub struct DirEntry {
 	pub name: String,
 	pub path: String,
 	pub is_dir: bool,
-	pub size: String,
-	pub modified: String,
+	/// Size in bytes, or `None` for directories.
+	pub size: Option<u64>,
+	pub modified: chrono::DateTime<chrono::Local>,
+}
+
+impl DirEntry {
+	/// `path` escaped for use in a link.
+	pub fn href(&self) -> String {
+		crate::helpers::encode_url_path(&self.path)
+	}
+
+	/// The symbol of the icons sprite the entry is listed with.
+	pub fn icon(&self) -> &'static str {
+		crate::icons::icon(&self.name, self.is_dir)
+	}
+
+	pub fn display_size(&self) -> String {
+		self.size.map_or_else(
+			|| "-".to_string(),
+			|size| humansize::format_size(size, humansize::BINARY),
+		)
+	}
+
+	pub fn display_modified(&self) -> String {
+		self.modified.format("%Y-%m-%d %H:%M:%S").to_string()
+	}
 }
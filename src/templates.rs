@@ -0,0 +1,451 @@
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::structs::{DirectoryTemplate, ErrorTemplate};
+
+/// A page of the built-in UI that a file of the same name in `--template-dir` replaces.
+///
+/// Such files are read on every render, so they can be edited while the server runs, and are
+/// written in a small subset of the built-in templates' syntax: `{{ entry.name }}` (escaped, or
+/// as it is with `|safe`), `{% if a.b %}`, `{% if not a.b %}`, `{% else %}`, `{% endif %}`,
+/// `{% for entry in entries %}` with `loop.index`, `loop.first` and `loop.last` inside, `{% endfor %}`
+/// and `{# comments #}`. Names that are not part of the page's [`context`](Self::context) come out
+/// empty and false.
+pub trait Overridable: askama::Template {
+	/// The name of the file taking the built-in template's place.
+	const NAME: &'static str;
+
+	/// What the file is rendered with.
+	fn context(&self) -> Value;
+}
+
+impl Overridable for DirectoryTemplate {
+	const NAME: &'static str = "directory.html";
+
+	fn context(&self) -> Value {
+		let entries: Vec<_> = self
+			.entries
+			.iter()
+			.map(|entry| {
+				json!({
+					"name": entry.name,
+					"path": entry.path,
+					"href": entry.href(),
+					"is_dir": entry.is_dir,
+					"size": entry.size,
+					"display_size": entry.display_size(),
+					"modified": entry.modified.to_rfc3339(),
+					"display_modified": entry.display_modified(),
+					"icon": entry.icon(),
+				})
+			})
+			.collect();
+		let breadcrumbs: Vec<_> = self
+			.breadcrumbs()
+			.into_iter()
+			.map(|crumb| json!({ "name": crumb.name, "href": crumb.href }))
+			.collect();
+		let pagination = self.pagination.as_ref().map(|page| {
+			json!({
+				"page": page.page,
+				"pages": page.pages,
+				"limit": page.limit,
+				"total": page.total,
+				"previous": (page.page > 1).then(|| page.page - 1),
+				"next": (page.page < page.pages).then(|| page.page + 1),
+			})
+		});
+		let columns = ["name", "size", "mtime"];
+		let by_column = |f: &dyn Fn(&str) -> Value| {
+			columns
+				.iter()
+				.map(|&column| (column.to_string(), f(column)))
+				.collect::<serde_json::Map<_, _>>()
+		};
+		json!({
+			"prefix": self.prefix,
+			"current_path": self.current_path,
+			"breadcrumbs": breadcrumbs,
+			"allow_upload": self.allow_upload,
+			"allow_delete": self.allow_delete,
+			"allow_rename": self.allow_rename,
+			"in_archive": self.in_archive,
+			"content_search": self.content_search,
+			"quota_remaining": self.quota_remaining,
+			"entries": entries,
+			"pagination": pagination,
+			"sort": {
+				"key": self.sort.key.as_str(),
+				"descending": self.sort.descending,
+				"links": by_column(&|column| self.sort.link(column).into()),
+				"indicators": by_column(&|column| self.sort.indicator(column).into()),
+				"query": self.sort.query(),
+			},
+			"theme": self.theme.as_str(),
+			"custom_css": self.custom_css,
+			"custom_js": self.custom_js,
+		})
+	}
+}
+
+impl Overridable for ErrorTemplate {
+	const NAME: &'static str = "error.html";
+
+	fn context(&self) -> Value {
+		json!({
+			"prefix": self.prefix,
+			"status": self.status,
+			"reason": self.reason,
+			"message": self.message,
+		})
+	}
+}
+
+/// Renders `template`, or the file of the same name in `config.template_dir` in its place if
+/// there is one. A file that cannot be read or parsed is logged, and the built-in page shown.
+pub async fn render<T: Overridable>(config: Option<&Config>, template: &T) -> askama::Result<String> {
+	if let Some(dir) = config.and_then(|config| config.template_dir.as_deref()) {
+		let path = dir.join(T::NAME);
+		match tokio::fs::read_to_string(&path).await {
+			Ok(source) => match render_source(&source, &template.context()) {
+				Ok(html) => return Ok(html),
+				Err(e) => tracing::warn!("Cannot parse template {}, using the built-in one: {e}", path.display()),
+			},
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+			Err(e) => tracing::warn!("Cannot read template {}, using the built-in one: {e}", path.display()),
+		}
+	}
+	template.render()
+}
+
+fn render_source(source: &str, context: &Value) -> Result<String, String> {
+	let nodes = parse(source)?;
+	let mut html = String::with_capacity(source.len());
+	let mut scope = Scope {
+		root: context,
+		locals: Vec::new(),
+	};
+	write(&nodes, &mut scope, &mut html);
+	Ok(html)
+}
+
+enum Node {
+	Text(String),
+	Value {
+		path: Vec<String>,
+		escape: bool,
+	},
+	If {
+		negated: bool,
+		path: Vec<String>,
+		then: Vec<Self>,
+		otherwise: Vec<Self>,
+	},
+	For {
+		name: String,
+		path: Vec<String>,
+		body: Vec<Self>,
+	},
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, String> {
+	let mut rest = source;
+	match block(&mut rest)? {
+		(nodes, None) => Ok(nodes),
+		(_, Some(tag)) => Err(format!("{{% {tag} %}} without a matching opening tag")),
+	}
+}
+
+/// A dotted name such as `entry.name`.
+fn path(expression: &str) -> Result<Vec<String>, String> {
+	let parts: Vec<String> = expression.split('.').map(str::to_string).collect();
+	if parts
+		.iter()
+		.any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+	{
+		return Err(format!("Invalid name {expression:?}"));
+	}
+	Ok(parts)
+}
+
+/// The nodes up to the end of `rest` or the first `else`, `endif` or `endfor`, which is returned
+/// with them.
+fn block(rest: &mut &str) -> Result<(Vec<Node>, Option<&'static str>), String> {
+	let mut nodes = Vec::new();
+	loop {
+		let Some((start, open)) = ["{{", "{%", "{#"]
+			.into_iter()
+			.filter_map(|open| rest.find(open).map(|start| (start, open)))
+			.min()
+		else {
+			if !rest.is_empty() {
+				nodes.push(Node::Text(rest.to_string()));
+			}
+			*rest = "";
+			return Ok((nodes, None));
+		};
+		if start > 0 {
+			nodes.push(Node::Text(rest[..start].to_string()));
+		}
+		let close = match open {
+			"{{" => "}}",
+			"{%" => "%}",
+			_ => "#}",
+		};
+		let inner_start = start + open.len();
+		let Some(length) = rest[inner_start..].find(close) else {
+			return Err(format!("{open} without a matching {close}"));
+		};
+		let inner = rest[inner_start..inner_start + length].trim();
+		*rest = &rest[inner_start + length + close.len()..];
+
+		match open {
+			"{{" => {
+				let mut parts = inner.split('|').map(str::trim);
+				let path = path(parts.next().unwrap_or_default())?;
+				let mut escape = true;
+				for filter in parts {
+					match filter {
+						"safe" => escape = false,
+						_ => return Err(format!("Unknown filter {filter:?}")),
+					}
+				}
+				nodes.push(Node::Value { path, escape });
+			},
+			"{%" => {
+				let words: Vec<&str> = inner.split_whitespace().collect();
+				match words.as_slice() {
+					["if", condition @ ..] => {
+						let (negated, expression) = match condition {
+							["not", expression] => (true, *expression),
+							[expression] => match expression.strip_prefix('!') {
+								Some(expression) => (true, expression),
+								None => (false, *expression),
+							},
+							_ => return Err(format!("Invalid condition {:?}", condition.join(" "))),
+						};
+						let path = path(expression)?;
+						let (then, mut end) = block(rest)?;
+						let mut otherwise = Vec::new();
+						if end == Some("else") {
+							(otherwise, end) = block(rest)?;
+						}
+						if end != Some("endif") {
+							return Err(format!("{{% if {expression} %}} without a matching {{% endif %}}"));
+						}
+						nodes.push(Node::If {
+							negated,
+							path,
+							then,
+							otherwise,
+						});
+					},
+					["for", name, "in", expression] => {
+						let path = path(expression)?;
+						let (body, end) = block(rest)?;
+						if end != Some("endfor") {
+							return Err(format!(
+								"{{% for {name} in {expression} %}} without a matching {{% endfor %}}"
+							));
+						}
+						nodes.push(Node::For {
+							name: (*name).to_string(),
+							path,
+							body,
+						});
+					},
+					["else"] => return Ok((nodes, Some("else"))),
+					["endif"] => return Ok((nodes, Some("endif"))),
+					["endfor"] => return Ok((nodes, Some("endfor"))),
+					_ => return Err(format!("Unknown tag {{% {inner} %}}")),
+				}
+			},
+			_ => {},
+		}
+	}
+}
+
+/// The context a file is rendered with, and the loop variables in effect.
+struct Scope<'a> {
+	root: &'a Value,
+	locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+	fn lookup(&self, path: &[String]) -> Option<&Value> {
+		let (first, rest) = path.split_first()?;
+		let mut value = self
+			.locals
+			.iter()
+			.rev()
+			.find(|(name, _)| name == first)
+			.map(|(_, value)| value)
+			.or_else(|| self.root.get(first))?;
+		for key in rest {
+			value = match key.parse::<usize>() {
+				Ok(index) => value.get(index),
+				Err(_) => value.get(key),
+			}?;
+		}
+		Some(value)
+	}
+}
+
+fn truthy(value: Option<&Value>) -> bool {
+	match value {
+		None | Some(Value::Null) => false,
+		Some(Value::Bool(value)) => *value,
+		Some(Value::Number(number)) => number.as_f64() != Some(0.0),
+		Some(Value::String(string)) => !string.is_empty(),
+		Some(Value::Array(array)) => !array.is_empty(),
+		Some(Value::Object(object)) => !object.is_empty(),
+	}
+}
+
+fn escape_into(text: &str, out: &mut String) {
+	for c in text.chars() {
+		match c {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			'\'' => out.push_str("&#x27;"),
+			c => out.push(c),
+		}
+	}
+}
+
+fn write(nodes: &[Node], scope: &mut Scope, out: &mut String) {
+	for node in nodes {
+		match node {
+			Node::Text(text) => out.push_str(text),
+			Node::Value { path, escape } => {
+				let text = match scope.lookup(path) {
+					None | Some(Value::Null) => String::new(),
+					Some(Value::String(string)) => string.clone(),
+					Some(value) => value.to_string(),
+				};
+				if *escape {
+					escape_into(&text, out);
+				} else {
+					out.push_str(&text);
+				}
+			},
+			Node::If {
+				negated,
+				path,
+				then,
+				otherwise,
+			} => {
+				let branch = if truthy(scope.lookup(path)) == *negated {
+					otherwise
+				} else {
+					then
+				};
+				write(branch, scope, out);
+			},
+			Node::For { name, path, body } => {
+				let items = scope
+					.lookup(path)
+					.and_then(Value::as_array)
+					.cloned()
+					.unwrap_or_default();
+				let count = items.len();
+				for (i, item) in items.into_iter().enumerate() {
+					scope.locals.push((name.clone(), item));
+					scope.locals.push((
+						"loop".to_string(),
+						json!({ "index": i + 1, "first": i == 0, "last": i + 1 == count }),
+					));
+					write(body, scope, out);
+					scope.locals.truncate(scope.locals.len() - 2);
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn render(source: &str, context: Value) -> Result<String, String> {
+		render_source(source, &context)
+	}
+
+	#[test]
+	fn values_are_escaped_unless_safe() {
+		let context = json!({ "name": "<a href='x'>&\"</a>", "count": 3, "missing": null });
+		assert_eq!(
+			render(
+				"{{ name }}|{{name|safe}}|{{ count }}|{{ missing }}{{ nowhere.at.all }}",
+				context
+			)
+			.unwrap(),
+			"&lt;a href=&#x27;x&#x27;&gt;&amp;&quot;&lt;/a&gt;|<a href='x'>&\"</a>|3|"
+		);
+	}
+
+	#[test]
+	fn conditions_follow_truthiness() {
+		let context = json!({ "yes": true, "empty": "", "zero": 0, "list": [1], "map": {} });
+		let source = "{% if yes %}a{% endif %}{% if empty %}b{% else %}c{% endif %}{% if not zero %}d{% endif %}\
+		              {% if !list %}e{% else %}f{% endif %}{% if map %}g{% endif %}{# {{ yes }} #}";
+		assert_eq!(render(source, context).unwrap(), "acdf");
+	}
+
+	#[test]
+	fn loops_nest_and_know_their_place() {
+		let context = json!({
+			"rows": [
+				{ "name": "a", "cells": [1, 2] },
+				{ "name": "b", "cells": [] },
+				{ "name": "c", "cells": [3] },
+			],
+			"loop": "outside",
+		});
+		let source = "{% for row in rows %}{{ loop.index }}{{ row.name }}\
+		              {% if loop.first %}^{% endif %}{% if loop.last %}${% endif %}\
+		              [{% for cell in row.cells %}{% if not loop.first %},{% endif %}{{ cell }}\
+		              {% if loop.last %}{{ row.name }}{% endif %}{% endfor %}]\
+		              {% if not row.cells %}-{% endif %}{% endfor %}{{ loop }}{{ row.name }}";
+		assert_eq!(render(source, context).unwrap(), "1a^[1,2a]2b[]-3c$[3c]outside");
+	}
+
+	#[test]
+	fn indexes_and_missing_lists() {
+		let context = json!({ "items": ["x", "y"], "not_a_list": "z" });
+		assert_eq!(
+			render(
+				"{{ items.1 }}{% for item in not_a_list %}!{% endfor %}{% for item in nothing %}!{% endfor %}",
+				context
+			)
+			.unwrap(),
+			"y"
+		);
+	}
+
+	#[test]
+	fn unbalanced_tags_are_refused() {
+		for source in [
+			"{{ name",
+			"{% if a %}",
+			"{% if a %}{% else %}",
+			"{% if a %}{% endfor %}",
+			"{% for a in b %}",
+			"{% for a in b %}{% endif %}",
+			"{% for a in b %}{% if a %}{% endfor %}{% endif %}",
+			"{% endif %}",
+			"{% else %}",
+			"{% endfor %}",
+			"{# never closed",
+			"{% while true %}{% endwhile %}",
+			"{{ name|upper }}",
+			"{{ a..b }}",
+			"{{ }}",
+			"{% if a b %}{% endif %}",
+		] {
+			assert!(render(source, json!({})).is_err(), "{source}");
+		}
+	}
+}
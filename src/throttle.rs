@@ -0,0 +1,136 @@
+use actix_web::body::{BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::rt::time::{sleep, Sleep};
+use actix_web::{web, Error};
+use bytes::Bytes;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+/// A token bucket refilled at `rate` bytes per second and holding at most a second's worth, so
+/// an idle stream can burst briefly but not save up.
+pub struct Bucket {
+	rate: f64,
+	state: Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+	pub fn new(rate: u64) -> Self {
+		let rate = rate.max(1) as f64;
+		Self {
+			rate,
+			state: Mutex::new((rate, Instant::now())),
+		}
+	}
+
+	/// Spends `len` bytes' worth of tokens, going into debt when there are not enough, and
+	/// returns how long to wait until the debt is paid off. Streams sharing a bucket each wait
+	/// for what they took, which splits the rate between them.
+	fn take(&self, len: usize) -> Duration {
+		let mut state = self.state.lock().unwrap();
+		let (tokens, last) = &mut *state;
+		let now = Instant::now();
+		*tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+		*last = now;
+		*tokens -= len as f64;
+		if *tokens >= 0.0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(-*tokens / self.rate)
+		}
+	}
+}
+
+/// A body sent no faster than its own bucket and the shared one allow. Chunks are split into
+/// pieces of a tenth of a second's worth, so slow rates come out steadily rather than in bursts
+/// a whole chunk apart.
+pub struct Throttled {
+	body: BoxBody,
+	own: Option<Bucket>,
+	shared: Option<web::Data<Bucket>>,
+	piece: usize,
+	pending: Bytes,
+	delayed: Option<(Bytes, Pin<Box<Sleep>>)>,
+}
+
+impl MessageBody for Throttled {
+	type Error = Box<dyn std::error::Error>;
+
+	fn size(&self) -> BodySize {
+		self.body.size()
+	}
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+		let this = self.get_mut();
+		if let Some((_, delay)) = &mut this.delayed {
+			ready!(delay.as_mut().poll(cx));
+			let (piece, _) = this.delayed.take().unwrap();
+			return Poll::Ready(Some(Ok(piece)));
+		}
+
+		if this.pending.is_empty() {
+			match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+				Some(Ok(chunk)) => this.pending = chunk,
+				other => return Poll::Ready(other),
+			}
+		}
+		let piece = this.pending.split_to(this.piece.min(this.pending.len()));
+		let wait = [this.own.as_ref(), this.shared.as_ref().map(|shared| &***shared)]
+			.into_iter()
+			.flatten()
+			.map(|bucket| bucket.take(piece.len()))
+			.max()
+			.unwrap_or_default();
+		if wait.is_zero() {
+			return Poll::Ready(Some(Ok(piece)));
+		}
+
+		let mut delay = Box::pin(sleep(wait));
+		if delay.as_mut().poll(cx).is_ready() {
+			return Poll::Ready(Some(Ok(piece)));
+		}
+		this.delayed = Some((piece, delay));
+		Poll::Pending
+	}
+}
+
+/// Middleware holding response bodies to `limit_rate` each and to `limit_rate_total` across
+/// all of them, the latter through the [`Bucket`] in the app data.
+pub async fn limit_rate(
+	req: ServiceRequest,
+	next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<Throttled, BoxBody>>, Error> {
+	let rate = req.app_data::<web::Data<Config>>().and_then(|config| config.limit_rate);
+	let shared = req.app_data::<web::Data<Bucket>>().cloned();
+
+	let res = next.call(req).await?;
+	let slowest = [
+		rate.map(|rate| rate.0),
+		shared.as_ref().map(|bucket| bucket.rate as u64),
+	]
+	.into_iter()
+	.flatten()
+	.min();
+	let Some(slowest) = slowest else {
+		return Ok(res.map_body(|_, body| EitherBody::right(body.boxed())));
+	};
+
+	let piece = usize::try_from(slowest / 10)
+		.unwrap_or(usize::MAX)
+		.clamp(1024, crate::CHUNK_SIZE);
+	Ok(res.map_body(|_, body| {
+		EitherBody::left(Throttled {
+			body: body.boxed(),
+			own: rate.map(|rate| Bucket::new(rate.0)),
+			shared,
+			piece,
+			pending: Bytes::new(),
+			delayed: None,
+		})
+	}))
+}
@@ -0,0 +1,52 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest};
+use std::time::{Duration, Instant};
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Phases measured while handling one request, present in the request extensions only while
+/// `--server-timing` is on.
+#[derive(Default)]
+struct Timings(Vec<(&'static str, Duration)>);
+
+/// Runs `f`, recording how long it took as the phase `name` when timings are being collected.
+pub fn measure<T>(req: &HttpRequest, name: &'static str, f: impl FnOnce() -> T) -> T {
+	if !req.extensions().contains::<Timings>() {
+		return f();
+	}
+	let started = Instant::now();
+	let result = f();
+	if let Some(timings) = req.extensions_mut().get_mut::<Timings>() {
+		timings.0.push((name, started.elapsed()));
+	}
+	result
+}
+
+/// Middleware reporting the measured phases, plus the time until the response was ready to be
+/// sent, in a `Server-Timing` header that browser dev tools display per request.
+pub async fn add_server_timing(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+	let started = Instant::now();
+	req.extensions_mut().insert(Timings::default());
+
+	let mut res = next.call(req).await?;
+	let first_byte = started.elapsed();
+	let mut timings = res.request().extensions_mut().remove::<Timings>().unwrap_or_default();
+	timings.0.push(("first-byte", first_byte));
+
+	let value = timings
+		.0
+		.iter()
+		.map(|(name, duration)| format!("{name};dur={:.3}", duration.as_secs_f64() * 1000.0))
+		.collect::<Vec<_>>()
+		.join(", ");
+	if let Ok(value) = HeaderValue::from_str(&value) {
+		res.headers_mut().insert(SERVER_TIMING, value);
+	}
+	Ok(res)
+}
@@ -0,0 +1,326 @@
+pub mod acme;
+
+use actix_http::{HttpService, Request};
+use actix_service::{map_config, IntoServiceFactory, ServiceFactory, ServiceFactoryExt};
+use actix_web::body::MessageBody;
+use actix_web::dev::{AppConfig, Extensions, Response, Server};
+use actix_web::http::header::LOCATION;
+use actix_web::http::KeepAlive;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::helpers::hostname;
+use crate::listeners;
+
+fn invalid_data(message: impl Into<String>) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+pub fn load_certs(path: &Path) -> std::io::Result<Vec<CertificateDer<'static>>> {
+	let mut reader = BufReader::new(File::open(path)?);
+	let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+	if certs.is_empty() {
+		return Err(invalid_data(format!("No certificates found in {}", path.display())));
+	}
+	Ok(certs)
+}
+
+pub fn load_key(path: &Path) -> std::io::Result<PrivateKeyDer<'static>> {
+	let mut reader = BufReader::new(File::open(path)?);
+	rustls_pemfile::private_key(&mut reader)?
+		.ok_or_else(|| invalid_data(format!("No private key found in {}", path.display())))
+}
+
+pub fn server_config(
+	certs: Vec<CertificateDer<'static>>,
+	key: PrivateKeyDer<'static>,
+) -> std::io::Result<rustls::ServerConfig> {
+	rustls::ServerConfig::builder()
+		.with_no_client_auth()
+		.with_single_cert(certs, key)
+		.map_err(|e| invalid_data(e.to_string()))
+}
+
+pub fn load_server_config(cert_path: &Path, key_path: &Path) -> std::io::Result<rustls::ServerConfig> {
+	server_config(load_certs(cert_path)?, load_key(key_path)?)
+}
+
+/// Generates an ephemeral self-signed certificate valid for `names`, returning the server config
+/// together with the SHA-256 fingerprint of the certificate so it can be verified by clients.
+pub fn self_signed_server_config(names: Vec<String>) -> std::io::Result<(rustls::ServerConfig, String)> {
+	let rcgen::CertifiedKey { cert, key_pair } =
+		rcgen::generate_simple_self_signed(names).map_err(|e| invalid_data(e.to_string()))?;
+
+	let fingerprint = Sha256::digest(cert.der())
+		.iter()
+		.map(|b| format!("{b:02X}"))
+		.collect::<Vec<_>>()
+		.join(":");
+
+	let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+	Ok((server_config(vec![cert.der().clone()], key)?, fingerprint))
+}
+
+/// Resolves the TLS settings of `config` into a rustls server config, or `None` for plain HTTP.
+pub fn from_config(config: &Config, addresses: &[IpAddr]) -> std::io::Result<Option<rustls::ServerConfig>> {
+	if !config.acme.is_empty() {
+		if config.tls_cert.is_some() || config.tls_key.is_some() || config.tls_self_signed {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"acme cannot be combined with tls_cert, tls_key or tls_self_signed",
+			));
+		}
+		return Ok(Some(acme::server_config(config)));
+	}
+
+	let mut server_config = match (&config.tls_cert, &config.tls_key) {
+		(Some(cert), Some(key)) => load_server_config(cert, key)?,
+		(None, None) if config.tls_self_signed => {
+			let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string(), "::1".to_string()];
+			for address in addresses.iter().filter(|address| !address.is_unspecified()) {
+				if !names.contains(&address.to_string()) {
+					names.push(address.to_string());
+				}
+			}
+			let (server_config, fingerprint) = self_signed_server_config(names)?;
+			tracing::info!(%fingerprint, "Generated self-signed certificate");
+			server_config
+		},
+		(None, None) => return Ok(None),
+		_ => {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"tls_cert and tls_key must be provided together",
+			))
+		},
+	};
+	server_config.alpn_protocols = alpn_protocols(config);
+	Ok(Some(server_config))
+}
+
+/// The protocols offered through ALPN, most preferred first.
+pub fn alpn_protocols(config: &Config) -> Vec<Vec<u8>> {
+	if config.http2 {
+		vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+	} else {
+		vec![b"http/1.1".to_vec()]
+	}
+}
+
+/// The `Strict-Transport-Security` value for `config`.
+pub fn hsts_header(config: &Config) -> String {
+	let mut value = format!("max-age={}", config.hsts_max_age);
+	if config.hsts_include_subdomains {
+		value.push_str("; includeSubDomains");
+	}
+	value
+}
+
+fn redirect(req: &HttpRequest, https_port: u16) -> HttpResponse {
+	let port = if https_port == 443 {
+		String::new()
+	} else {
+		format!(":{https_port}")
+	};
+	let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+	let location = format!("https://{}{port}{path}", hostname(req.connection_info().host()));
+	HttpResponse::PermanentRedirect()
+		.insert_header((LOCATION, location))
+		.finish()
+}
+
+/// A plain HTTP server on `http_port` at each of `addresses` sending every request to the same
+/// URL over HTTPS on `https_port`.
+pub fn redirect_server(addresses: &[IpAddr], http_port: u16, https_port: u16) -> std::io::Result<Server> {
+	let mut server = HttpServer::new(move || {
+		App::new().default_service(web::to(
+			move |req: HttpRequest| async move { redirect(&req, https_port) },
+		))
+	})
+	.workers(1)
+	// Stopped along with the server it redirects to.
+	.disable_signals();
+	let all: Vec<_> = addresses
+		.iter()
+		.map(|&address| SocketAddr::new(address, http_port))
+		.collect();
+	for &address in &all {
+		server = server.listen(listeners::tcp(address, &all)?)?;
+	}
+	for address in server.addrs() {
+		tracing::info!("Redirecting http://{address} to HTTPS");
+	}
+	Ok(server.run())
+}
+
+/// How the connections of the main server are kept, for [`http1_server`] to keep its own alike.
+/// Unset values are actix-web's defaults.
+#[derive(Default)]
+pub struct Connections {
+	pub workers: Option<usize>,
+	pub keep_alive: Option<KeepAlive>,
+	pub client_request_timeout: Option<Duration>,
+	pub client_disconnect_timeout: Option<Duration>,
+	pub shutdown_timeout: Duration,
+}
+
+/// A server answering HTTPS on `sockets` over HTTP/1.1 only, for `--no-http2`. The TLS listeners
+/// of actix-web put h2 first among the ALPN protocols whatever `tls_config` says, so these are
+/// built from the HTTP/1.1 service of actix-http instead, with the same app and connection hook.
+pub fn http1_server<F, I, S, B, C>(
+	factory: F,
+	on_connect: C,
+	sockets: Vec<TcpListener>,
+	tls_config: &rustls::ServerConfig,
+	connections: &Connections,
+) -> std::io::Result<Server>
+where
+	F: Fn() -> I + Send + Clone + 'static,
+	I: IntoServiceFactory<S, Request>,
+	S: ServiceFactory<Request, Config = AppConfig> + 'static,
+	S::Error: Into<actix_web::Error>,
+	S::InitError: std::fmt::Debug,
+	S::Response: Into<Response<B>>,
+	B: MessageBody + 'static,
+	C: Fn(&mut Extensions) + Send + Clone + 'static,
+{
+	let keep_alive = connections.keep_alive.unwrap_or_default();
+	let request_timeout = connections.client_request_timeout.unwrap_or(Duration::from_secs(5));
+	let disconnect_timeout = connections.client_disconnect_timeout.unwrap_or(Duration::from_secs(1));
+	let mut server = Server::build()
+		.disable_signals()
+		.shutdown_timeout(connections.shutdown_timeout.as_secs());
+	if let Some(workers) = connections.workers {
+		server = server.workers(workers);
+	}
+	for socket in sockets {
+		let address = socket.local_addr()?;
+		let (factory, on_connect, tls_config) = (factory.clone(), on_connect.clone(), tls_config.clone());
+		server = server.listen(format!("http1-{address}"), socket, move || {
+			let on_connect = on_connect.clone();
+			let app = factory().into_factory().map_err(|e| e.into().error_response());
+			// What actix-web's own listeners give apps, whose constructor it keeps to itself.
+			let app_config = AppConfig::__priv_test_new(true, address.to_string(), address);
+			HttpService::build()
+				.keep_alive(keep_alive)
+				.client_request_timeout(request_timeout)
+				.client_disconnect_timeout(disconnect_timeout)
+				.on_connect_ext(move |_: &_, extensions: &mut Extensions| on_connect(extensions))
+				.h1(map_config(app, move |()| app_config.clone()))
+				.rustls_0_23(tls_config.clone())
+		})?;
+	}
+	Ok(server.run())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::TcpStream;
+	use std::sync::Arc;
+
+	/// A TLS config loaded as `--tls-cert` and `--tls-key` would be, with the roots trusting it.
+	fn tls_config(http2: bool) -> (rustls::ServerConfig, rustls::RootCertStore) {
+		let rcgen::CertifiedKey { cert, key_pair } =
+			rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+		let dir = std::env::temp_dir().join(format!("http-server-rs-tls-{http2}-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("cert.pem"), cert.pem()).unwrap();
+		std::fs::write(dir.join("key.pem"), key_pair.serialize_pem()).unwrap();
+		let config = Config {
+			tls_cert: Some(dir.join("cert.pem")),
+			tls_key: Some(dir.join("key.pem")),
+			http2,
+			..Config::default()
+		};
+		let server_config = from_config(&config, &[]).unwrap().unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		let mut roots = rustls::RootCertStore::empty();
+		roots.add(cert.der().clone()).unwrap();
+		(server_config, roots)
+	}
+
+	/// The protocol agreed on with the server at `address` by a client offering `offered`, or
+	/// `None` if the handshake fails.
+	async fn negotiate(address: SocketAddr, roots: rustls::RootCertStore, offered: &[&[u8]]) -> Option<Vec<u8>> {
+		let mut client_config = rustls::ClientConfig::builder()
+			.with_root_certificates(roots)
+			.with_no_client_auth();
+		client_config.alpn_protocols = offered.iter().map(|protocol| protocol.to_vec()).collect();
+		web::block(move || {
+			let name = "localhost".try_into().unwrap();
+			let mut connection = rustls::ClientConnection::new(Arc::new(client_config), name).unwrap();
+			let mut socket = TcpStream::connect(address).unwrap();
+			while connection.is_handshaking() {
+				connection.complete_io(&mut socket).ok()?;
+			}
+			connection.alpn_protocol().map(<[u8]>::to_vec)
+		})
+		.await
+		.unwrap()
+	}
+
+	fn socket() -> (TcpListener, SocketAddr) {
+		let socket = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = socket.local_addr().unwrap();
+		(socket, address)
+	}
+
+	#[actix_web::test]
+	async fn https_offers_http2() {
+		let (tls_config, roots) = tls_config(true);
+		let (socket, address) = socket();
+		let server = HttpServer::new(App::new)
+			.workers(1)
+			.disable_signals()
+			.listen_rustls_0_23(socket, tls_config)
+			.unwrap()
+			.run();
+		let handle = server.handle();
+		actix_web::rt::spawn(server);
+		let both: &[&[u8]] = &[b"h2", b"http/1.1"];
+		assert_eq!(
+			negotiate(address, roots.clone(), both).await.as_deref(),
+			Some(&b"h2"[..])
+		);
+		assert_eq!(
+			negotiate(address, roots, &[b"http/1.1"]).await.as_deref(),
+			Some(&b"http/1.1"[..])
+		);
+		handle.stop(false).await;
+	}
+
+	#[actix_web::test]
+	async fn http1_server_never_agrees_to_http2() {
+		let (tls_config, roots) = tls_config(false);
+		let (socket, address) = socket();
+		let connections = Connections {
+			workers: Some(1),
+			..Connections::default()
+		};
+		let server = http1_server(
+			App::new,
+			|_: &mut Extensions| {},
+			vec![socket],
+			&tls_config,
+			&connections,
+		)
+		.unwrap();
+		let handle = server.handle();
+		actix_web::rt::spawn(server);
+		let both: &[&[u8]] = &[b"h2", b"http/1.1"];
+		assert_eq!(
+			negotiate(address, roots.clone(), both).await.as_deref(),
+			Some(&b"http/1.1"[..])
+		);
+		assert_eq!(negotiate(address, roots, &[b"h2"]).await, None);
+		handle.stop(false).await;
+	}
+}
@@ -0,0 +1,48 @@
+use futures::StreamExt;
+use rustls_acme::caches::DirCache;
+use rustls_acme::AcmeConfig;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::helpers::cache_dir;
+
+/// Default location for persisted ACME accounts and certificates, kept outside of the served
+/// tree so private keys are never exposed by the file server itself.
+pub fn default_cache_dir() -> Option<PathBuf> {
+	Some(cache_dir()?.join("acme"))
+}
+
+/// Builds a rustls config whose certificates are obtained and renewed through ACME using the
+/// TLS-ALPN-01 challenge. The ordering/renewal state machine is driven by a background task on the
+/// current actix runtime for as long as the server runs.
+pub fn server_config(config: &Config) -> rustls::ServerConfig {
+	let cache = config.acme_cache.clone().or_else(default_cache_dir);
+	if let Some(dir) = &cache {
+		tracing::info!("Persisting ACME certificates to {}", dir.display());
+	}
+
+	let mut state = AcmeConfig::new(&config.acme)
+		.contact(config.acme_email.iter().map(|email| format!("mailto:{email}")))
+		.cache_option(cache.map(DirCache::new))
+		.directory_lets_encrypt(!config.acme_staging)
+		.state();
+
+	let mut server_config = rustls::ServerConfig::builder()
+		.with_no_client_auth()
+		.with_cert_resolver(state.resolver());
+	server_config.alpn_protocols = super::alpn_protocols(config);
+	server_config
+		.alpn_protocols
+		.push(rustls_acme::acme::ACME_TLS_ALPN_NAME.to_vec());
+
+	actix_web::rt::spawn(async move {
+		while let Some(event) = state.next().await {
+			match event {
+				Ok(event) => tracing::info!(?event, "ACME"),
+				Err(err) => tracing::error!(?err, "ACME error"),
+			}
+		}
+	});
+
+	server_config
+}
@@ -0,0 +1,251 @@
+use actix_multipart::Multipart;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{http::header, post, put, web, HttpRequest, HttpResponse, Result};
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use rand::distributions::{Alphanumeric, DistString};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::helpers::{encode_url_path, parent_within_root, resolve_path, sanitize_filename, Visibility};
+use crate::hooks::Hooks;
+use crate::metrics::Metrics;
+use crate::paths;
+use crate::webdav::{locked_response, LockManager};
+
+#[allow(clippy::future_not_send)]
+#[post("/{path:.*}")]
+async fn upload_files(
+	req: HttpRequest,
+	mut payload: Multipart,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+	hooks: web::Data<Hooks>,
+	metrics: Option<web::Data<Metrics>>,
+) -> Result<HttpResponse> {
+	if !config.allow_upload {
+		return Ok(HttpResponse::MethodNotAllowed().body("Uploads are disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(dir) = resolve_path(&config.root, &path) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	if !dir.is_dir() || !visibility.allows(&path) {
+		return Ok(HttpResponse::NotFound().body("Directory not found"));
+	}
+
+	while let Some(mut field) = payload.try_next().await? {
+		let Some(filename) = field
+			.content_disposition()
+			.and_then(|cd| cd.get_filename())
+			.and_then(sanitize_filename)
+		else {
+			continue;
+		};
+
+		let target = dir.join(&filename);
+		// A symlinked directory on the way may lead out of the root, which is never written to.
+		if !parent_within_root(&config.root, &target) || !visibility.allows(&path.join(&filename)) {
+			return Ok(HttpResponse::Forbidden().body("Access denied"));
+		}
+		if !locks.may_modify(&req, &target, false) {
+			return Ok(locked_response());
+		}
+		if tokio::fs::symlink_metadata(&target).await.is_ok() {
+			return Ok(HttpResponse::Conflict().body(format!("{filename} already exists")));
+		}
+
+		let limit = upload_limit(&config, &dir).await.map_err(ErrorInternalServerError)?;
+		match write_atomically(&target, &mut field, limit).await {
+			Ok(written) => {
+				if let Some(metrics) = &metrics {
+					metrics.record_upload(written);
+				}
+			},
+			Err(e) if e.kind() == std::io::ErrorKind::FileTooLarge => return Ok(too_large(&filename)),
+			Err(e) => return Err(ErrorInternalServerError(e)),
+		}
+		hooks.after_upload(&req, &target);
+	}
+
+	let location = format!("{}/{}", config.route_prefix, encode_url_path(&path.to_string_lossy()));
+	Ok(HttpResponse::SeeOther()
+		.insert_header((header::LOCATION, location))
+		.finish())
+}
+
+/// Total size of the files below `dir`, without following symlinks.
+pub async fn dir_size(dir: &Path) -> std::io::Result<u64> {
+	let mut total = 0;
+	let mut pending = vec![dir.to_path_buf()];
+
+	while let Some(dir) = pending.pop() {
+		let mut read_dir = tokio::fs::read_dir(&dir).await?;
+		while let Some(entry) = read_dir.next_entry().await? {
+			let metadata = tokio::fs::symlink_metadata(entry.path()).await?;
+			if metadata.is_dir() {
+				pending.push(entry.path());
+			} else {
+				total += metadata.len();
+			}
+		}
+	}
+
+	Ok(total)
+}
+
+/// Space left in `dir` under the tightest quota configured for it or any of its ancestors.
+pub async fn remaining_quota(config: &Config, dir: &Path) -> std::io::Result<Option<u64>> {
+	quota_left(config, dir, None).await
+}
+
+/// Like [`remaining_quota`], but only under the quotas `from` is not already counted against, as
+/// moving it from there into `dir` takes up no new space in those.
+pub async fn remaining_quota_from(config: &Config, from: &Path, dir: &Path) -> std::io::Result<Option<u64>> {
+	quota_left(config, dir, Some(from)).await
+}
+
+async fn quota_left(config: &Config, dir: &Path, from: Option<&Path>) -> std::io::Result<Option<u64>> {
+	let mut remaining: Option<u64> = None;
+
+	for (quota_dir, limit) in &config.quotas {
+		let quota_root = config.root.join(quota_dir);
+		if !dir.starts_with(&quota_root) || from.is_some_and(|from| from.starts_with(&quota_root)) {
+			continue;
+		}
+		let left = limit.0.saturating_sub(dir_size(&quota_root).await?);
+		remaining = Some(remaining.map_or(left, |r| r.min(left)));
+	}
+
+	Ok(remaining)
+}
+
+/// Largest number of bytes a single upload into `dir` may write.
+async fn upload_limit(config: &Config, dir: &Path) -> std::io::Result<Option<u64>> {
+	let quota = remaining_quota(config, dir).await?;
+	let max = config.max_upload_size.map(|size| size.0);
+	Ok(match (quota, max) {
+		(Some(a), Some(b)) => Some(a.min(b)),
+		(a, b) => a.or(b),
+	})
+}
+
+fn too_large(name: &str) -> HttpResponse {
+	HttpResponse::PayloadTooLarge().body(format!("{name} exceeds the upload size limit or quota"))
+}
+
+/// Streams `body` into a temporary sibling of `target` and renames it into place once complete, so
+/// readers never observe a partially written file. Fails with `FileTooLarge` as soon as more than
+/// `limit` bytes arrive, without buffering the rest of the body.
+async fn write_atomically<E: std::fmt::Display>(
+	target: &Path,
+	body: impl Stream<Item = Result<Bytes, E>>,
+	limit: Option<u64>,
+) -> std::io::Result<u64> {
+	let name = target.file_name().unwrap_or_default().to_string_lossy();
+	let suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+	let temp = target.with_file_name(format!(".{name}.{suffix}.part"));
+
+	let written: std::io::Result<u64> = async {
+		let mut file = tokio::fs::File::create(&temp).await?;
+		let mut body = std::pin::pin!(body);
+		let mut total = 0u64;
+		while let Some(chunk) = body
+			.try_next()
+			.await
+			.map_err(|e| std::io::Error::other(e.to_string()))?
+		{
+			total += chunk.len() as u64;
+			if limit.is_some_and(|limit| total > limit) {
+				return Err(std::io::ErrorKind::FileTooLarge.into());
+			}
+			file.write_all(&chunk).await?;
+		}
+		file.sync_all().await?;
+		tokio::fs::rename(&temp, target).await?;
+		Ok(total)
+	}
+	.await;
+
+	if written.is_err() {
+		let _ = tokio::fs::remove_file(&temp).await;
+	}
+	written
+}
+
+#[allow(clippy::future_not_send)]
+#[put("/{path:.*}")]
+async fn put_file(
+	req: HttpRequest,
+	payload: web::Payload,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+	hooks: web::Data<Hooks>,
+	metrics: Option<web::Data<Metrics>>,
+) -> Result<HttpResponse> {
+	if !config.allow_upload {
+		return Ok(HttpResponse::MethodNotAllowed().body("Uploads are disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(target) = resolve_path(&config.root, &path).filter(|target| *target != config.root) else {
+		return Ok(HttpResponse::NotFound().body("Access denied"));
+	};
+	if target.is_dir() {
+		return Ok(HttpResponse::Conflict().body("Target is a directory"));
+	}
+	if !target.parent().is_some_and(Path::is_dir) {
+		return Ok(HttpResponse::Conflict().body("Parent directory does not exist"));
+	}
+	// A symlinked directory on the way may lead out of the root, which is never written to.
+	if !parent_within_root(&config.root, &target) {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	}
+	// What is kept from listings cannot be overwritten by naming it either.
+	if !Visibility::of(&config, &req).allows(&path) {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	}
+	if !locks.may_modify(&req, &target, false) {
+		return Ok(locked_response());
+	}
+
+	let name = path.to_string_lossy();
+	let dir = target.parent().unwrap_or(&config.root);
+	let limit = upload_limit(&config, dir).await.map_err(ErrorInternalServerError)?;
+	let declared = req
+		.headers()
+		.get(header::CONTENT_LENGTH)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.parse::<u64>().ok());
+	if let (Some(limit), Some(declared)) = (limit, declared) {
+		if declared > limit {
+			return Ok(too_large(&name));
+		}
+	}
+
+	let existed = target.exists();
+	match write_atomically(&target, payload, limit).await {
+		Ok(written) => {
+			if let Some(metrics) = &metrics {
+				metrics.record_upload(written);
+			}
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::FileTooLarge => return Ok(too_large(&name)),
+		Err(e) => return Err(ErrorInternalServerError(e)),
+	}
+	hooks.after_upload(&req, &target);
+
+	if existed {
+		Ok(HttpResponse::NoContent().finish())
+	} else {
+		Ok(HttpResponse::Created()
+			.insert_header((
+				header::LOCATION,
+				format!("{}/{}", config.route_prefix, encode_url_path(&path.to_string_lossy())),
+			))
+			.finish())
+	}
+}
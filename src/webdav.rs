@@ -0,0 +1,734 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::{header, StatusCode};
+use actix_web::{route, web, HttpRequest, HttpResponse, Result};
+use bytes::Bytes;
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+use rand::RngCore;
+use std::fmt::Write;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::Config;
+use crate::helpers::{encode_url_path, mime_type, parent_within_root, resolve_path, within_root, Visibility};
+use crate::paths;
+
+const DAV_NS: &str = "DAV:";
+const MS_NS: &str = "urn:schemas-microsoft-com:";
+const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 60 * 60;
+const MAX_LOCK_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Live properties reported for every resource.
+const LIVE_PROPS: &[&str] = &[
+	"displayname",
+	"resourcetype",
+	"getcontentlength",
+	"getcontenttype",
+	"getlastmodified",
+	"creationdate",
+	"supportedlock",
+	"lockdiscovery",
+];
+
+/// An element of a parsed XML request body, flattened in document order.
+struct XmlElement {
+	depth: usize,
+	ns: String,
+	name: String,
+	/// Byte range of the raw content between the start and end tags.
+	inner: Option<std::ops::Range<usize>>,
+}
+
+fn parse_xml(body: &str) -> Option<Vec<XmlElement>> {
+	let mut reader = NsReader::from_str(body);
+	let mut elements = Vec::new();
+	let mut open = Vec::new();
+	let mut last_pos = 0;
+
+	loop {
+		let (ns, event) = reader.read_resolved_event().ok()?;
+		let ns = namespace(&ns);
+		let pos = usize::try_from(reader.buffer_position()).ok()?;
+		match event {
+			Event::Start(e) => {
+				open.push((elements.len(), pos));
+				elements.push(XmlElement {
+					depth: open.len(),
+					ns: ns.clone(),
+					name: String::from_utf8_lossy(e.local_name().as_ref()).into_owned(),
+					inner: None,
+				});
+			},
+			Event::Empty(e) => elements.push(XmlElement {
+				depth: open.len() + 1,
+				ns,
+				name: String::from_utf8_lossy(e.local_name().as_ref()).into_owned(),
+				inner: None,
+			}),
+			Event::End(_) => {
+				let (index, start) = open.pop()?;
+				elements[index].inner = Some(start..last_pos);
+			},
+			Event::Eof => break,
+			_ => (),
+		}
+		last_pos = pos;
+	}
+
+	Some(elements)
+}
+
+fn namespace(ns: &ResolveResult) -> String {
+	match ns {
+		ResolveResult::Bound(ns) => String::from_utf8_lossy(ns.as_ref()).into_owned(),
+		_ => String::new(),
+	}
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct PropName {
+	ns: String,
+	name: String,
+}
+
+impl PropName {
+	fn dav(name: &str) -> Self {
+		Self {
+			ns: DAV_NS.to_string(),
+			name: name.to_string(),
+		}
+	}
+
+	/// Renders the property as an element wrapping `value`, self-closing when there is none.
+	fn element(&self, value: Option<&str>) -> String {
+		let (open, close) = if self.ns == DAV_NS {
+			(format!("D:{}", self.name), format!("D:{}", self.name))
+		} else {
+			(
+				format!("X:{} xmlns:X=\"{}\"", self.name, escape(self.ns.as_str())),
+				format!("X:{}", self.name),
+			)
+		};
+		match value {
+			Some(value) => format!("<{open}>{value}</{close}>"),
+			None => format!("<{open}/>"),
+		}
+	}
+}
+
+enum PropfindRequest {
+	AllProp,
+	PropName,
+	Props(Vec<PropName>),
+}
+
+fn parse_propfind(body: &str) -> Option<PropfindRequest> {
+	if body.trim().is_empty() {
+		return Some(PropfindRequest::AllProp);
+	}
+
+	let elements = parse_xml(body)?;
+	let mut request = None;
+	let mut props = Vec::new();
+	let mut in_prop = false;
+
+	for element in elements {
+		match element.depth {
+			1 if element.name != "propfind" => return None,
+			2 => {
+				in_prop = element.name == "prop";
+				match element.name.as_str() {
+					"allprop" => request = Some(PropfindRequest::AllProp),
+					"propname" => request = Some(PropfindRequest::PropName),
+					_ => (),
+				}
+			},
+			3 if in_prop => props.push(PropName {
+				ns: element.ns,
+				name: element.name,
+			}),
+			_ => (),
+		}
+	}
+
+	request.or(Some(PropfindRequest::Props(props)))
+}
+
+struct ActiveLock {
+	root: PathBuf,
+	href: String,
+	token: String,
+	owner: Option<String>,
+	exclusive: bool,
+	infinite: bool,
+	timeout_secs: u64,
+	expires: Instant,
+}
+
+impl ActiveLock {
+	fn covers(&self, path: &Path) -> bool {
+		self.root == path || (self.infinite && path.starts_with(&self.root))
+	}
+
+	fn to_xml(&self) -> String {
+		let scope = if self.exclusive { "exclusive" } else { "shared" };
+		let depth = if self.infinite { "infinity" } else { "0" };
+		let owner = self
+			.owner
+			.as_ref()
+			.map(|owner| format!("<D:owner>{owner}</D:owner>"))
+			.unwrap_or_default();
+		format!(
+			"<D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:{scope}/></D:lockscope><D:depth>{depth}</D:depth>{owner}<D:timeout>Second-{}</D:timeout><D:locktoken><D:href>{}</D:href></D:locktoken><D:lockroot><D:href>{}</D:href></D:lockroot></D:activelock>",
+			self.timeout_secs,
+			self.token,
+			escape(self.href.as_str())
+		)
+	}
+}
+
+/// In-memory WebDAV lock table shared by all workers.
+#[derive(Default)]
+pub struct LockManager {
+	locks: Mutex<Vec<ActiveLock>>,
+}
+
+impl LockManager {
+	fn active(&self) -> MutexGuard<'_, Vec<ActiveLock>> {
+		let mut locks = self.locks.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+		let now = Instant::now();
+		locks.retain(|lock| lock.expires > now);
+		locks
+	}
+
+	/// Returns whether `path` may be modified by `req`: every lock covering it (or, with
+	/// `descendants`, any lock below it) must have its token submitted in the `If` header.
+	pub fn may_modify(&self, req: &HttpRequest, path: &Path, descendants: bool) -> bool {
+		let tokens = submitted_tokens(req);
+		self.active()
+			.iter()
+			.filter(|lock| lock.covers(path) || (descendants && lock.root.starts_with(path)))
+			.all(|lock| tokens.contains(&lock.token))
+	}
+
+	/// Drops every lock rooted at or below `path`, e.g. after it was deleted or moved away.
+	pub fn release_under(&self, path: &Path) {
+		self.active().retain(|lock| !lock.root.starts_with(path));
+	}
+
+	fn lockdiscovery(&self, path: &Path) -> String {
+		let locks: String = self
+			.active()
+			.iter()
+			.filter(|lock| lock.covers(path))
+			.map(ActiveLock::to_xml)
+			.collect();
+		format!("<D:lockdiscovery>{locks}</D:lockdiscovery>")
+	}
+}
+
+/// Collects the lock tokens a client presented via the `If` or `Lock-Token` headers.
+fn submitted_tokens(req: &HttpRequest) -> Vec<String> {
+	let mut tokens = Vec::new();
+	for name in ["if", "lock-token"] {
+		let Some(value) = req.headers().get(name).and_then(|v| v.to_str().ok()) else {
+			continue;
+		};
+		let mut rest = value;
+		while let Some(start) = rest.find('<') {
+			let Some(len) = rest[start..].find('>') else {
+				break;
+			};
+			let token = &rest[start + 1..start + len];
+			if token.starts_with("opaquelocktoken:") {
+				tokens.push(token.to_string());
+			}
+			rest = &rest[start + len..];
+		}
+	}
+	tokens
+}
+
+pub fn locked_response() -> HttpResponse {
+	HttpResponse::build(StatusCode::LOCKED).body("Resource is locked")
+}
+
+fn generate_lock_token() -> String {
+	let mut bytes = [0u8; 16];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+	format!(
+		"opaquelocktoken:{}-{}-{}-{}-{}",
+		&hex[0..8],
+		&hex[8..12],
+		&hex[12..16],
+		&hex[16..20],
+		&hex[20..32]
+	)
+}
+
+fn http_date(time: SystemTime) -> String {
+	chrono::DateTime::<chrono::Utc>::from(time)
+		.format("%a, %d %b %Y %H:%M:%S GMT")
+		.to_string()
+}
+
+fn href_for(config: &Config, path: &Path, is_dir: bool) -> String {
+	let relative = path.strip_prefix(&config.root).unwrap_or(path).to_string_lossy();
+	let mut href = format!("{}/{}", config.route_prefix, encode_url_path(&relative));
+	if is_dir && !href.ends_with('/') {
+		href.push('/');
+	}
+	href
+}
+
+struct Resource {
+	path: PathBuf,
+	href: String,
+	mime: String,
+	metadata: Metadata,
+}
+
+impl Resource {
+	fn live_prop(&self, name: &str, locks: &LockManager) -> Option<String> {
+		let is_dir = self.metadata.is_dir();
+		let value = match name {
+			"displayname" => escape(self.path.file_name().unwrap_or_default().to_string_lossy()).into_owned(),
+			"resourcetype" if is_dir => "<D:collection/>".to_string(),
+			"resourcetype" => String::new(),
+			"getcontentlength" if !is_dir => self.metadata.len().to_string(),
+			"getcontenttype" if !is_dir => self.mime.clone(),
+			"getlastmodified" => http_date(self.metadata.modified().ok()?),
+			"creationdate" => {
+				let created = self.metadata.created().or_else(|_| self.metadata.modified()).ok()?;
+				chrono::DateTime::<chrono::Utc>::from(created).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+			},
+			"supportedlock" => concat!(
+				"<D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>",
+				"<D:lockentry><D:lockscope><D:shared/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>"
+			)
+			.to_string(),
+			"lockdiscovery" => {
+				let discovery = locks.lockdiscovery(&self.path);
+				return Some(discovery);
+			},
+			_ => return None,
+		};
+		Some(PropName::dav(name).element(Some(&value)))
+	}
+
+	fn write_response(&self, out: &mut String, request: &PropfindRequest, locks: &LockManager) {
+		let mut found = String::new();
+		let mut missing = String::new();
+
+		match request {
+			PropfindRequest::AllProp => {
+				for name in LIVE_PROPS {
+					if let Some(prop) = self.live_prop(name, locks) {
+						found.push_str(&prop);
+					}
+				}
+			},
+			PropfindRequest::PropName => {
+				for name in LIVE_PROPS {
+					if self.live_prop(name, locks).is_some() {
+						found.push_str(&PropName::dav(name).element(None));
+					}
+				}
+			},
+			PropfindRequest::Props(props) => {
+				for prop in props {
+					let value = (prop.ns == DAV_NS).then(|| self.live_prop(&prop.name, locks)).flatten();
+					match value {
+						Some(value) => found.push_str(&value),
+						None => missing.push_str(&prop.element(None)),
+					}
+				}
+			},
+		}
+
+		let _ = write!(out, "<D:response><D:href>{}</D:href>", escape(self.href.as_str()));
+		if !found.is_empty() || missing.is_empty() {
+			let _ = write!(
+				out,
+				"<D:propstat><D:prop>{found}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>"
+			);
+		}
+		if !missing.is_empty() {
+			let _ = write!(
+				out,
+				"<D:propstat><D:prop>{missing}</D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>"
+			);
+		}
+		out.push_str("</D:response>");
+	}
+}
+
+fn multistatus(body: &str) -> HttpResponse {
+	HttpResponse::build(StatusCode::MULTI_STATUS)
+		.content_type(XML_CONTENT_TYPE)
+		.body(format!(
+			"<?xml version=\"1.0\" encoding=\"utf-8\"?><D:multistatus xmlns:D=\"DAV:\">{body}</D:multistatus>"
+		))
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "OPTIONS")]
+async fn options(config: web::Data<Config>) -> HttpResponse {
+	let mut methods = vec!["OPTIONS", "GET", "HEAD"];
+	if config.allow_upload {
+		methods.extend(["POST", "PUT", "MKCOL", "MOVE", "COPY"]);
+	}
+	if config.allow_delete {
+		methods.push("DELETE");
+	}
+
+	let mut response = HttpResponse::Ok();
+	if config.webdav {
+		methods.extend(["PROPFIND", "PROPPATCH", "LOCK", "UNLOCK"]);
+		response.insert_header(("DAV", "1, 2"));
+		response.insert_header(("MS-Author-Via", "DAV"));
+	}
+	response.insert_header((header::ALLOW, methods.join(", "))).finish()
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "PROPFIND")]
+async fn propfind(
+	req: HttpRequest,
+	body: Bytes,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+) -> Result<HttpResponse> {
+	if !config.webdav {
+		return Ok(HttpResponse::MethodNotAllowed().body("WebDAV is disabled"));
+	}
+
+	let Some(target) = resolve_path(&config.root, &paths::request_path(&req, &config.root)) else {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	};
+	let visibility = Visibility::of(&config, &req);
+	let Ok(metadata) = tokio::fs::metadata(&target).await else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+	if !within_root(&config, &target) || !visibility.allows(&paths::request_path(&req, &config.root)) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+
+	let depth = req.headers().get("depth").and_then(|v| v.to_str().ok()).unwrap_or("1");
+	let children = match depth {
+		"0" => false,
+		"1" => true,
+		_ => {
+			return Ok(HttpResponse::Forbidden().content_type(XML_CONTENT_TYPE).body(
+				"<?xml version=\"1.0\" encoding=\"utf-8\"?><D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>",
+			));
+		},
+	};
+
+	let Some(request) = std::str::from_utf8(&body).ok().and_then(parse_propfind) else {
+		return Ok(HttpResponse::BadRequest().body("Malformed PROPFIND body"));
+	};
+
+	let mut resources = vec![Resource {
+		href: href_for(&config, &target, metadata.is_dir()),
+		mime: mime_type(&config, &target).to_string(),
+		path: target.clone(),
+		metadata,
+	}];
+
+	if children && resources[0].metadata.is_dir() {
+		let mut read_dir = tokio::fs::read_dir(&target).await.map_err(ErrorInternalServerError)?;
+		while let Some(entry) = read_dir.next_entry().await.map_err(ErrorInternalServerError)? {
+			let path = entry.path();
+			// Symlinks leading out of the root are left out, as they are of listings.
+			if !visibility.allows(path.strip_prefix(&config.root).unwrap_or(&path)) || !within_root(&config, &path) {
+				continue;
+			}
+			let Ok(metadata) = tokio::fs::metadata(&path).await else {
+				continue;
+			};
+			resources.push(Resource {
+				href: href_for(&config, &path, metadata.is_dir()),
+				mime: mime_type(&config, &path).to_string(),
+				path,
+				metadata,
+			});
+		}
+	}
+
+	let mut out = String::new();
+	for resource in &resources {
+		resource.write_response(&mut out, &request, &locks);
+	}
+	Ok(multistatus(&out))
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "PROPPATCH")]
+async fn proppatch(
+	req: HttpRequest,
+	body: Bytes,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+) -> Result<HttpResponse> {
+	if !config.webdav {
+		return Ok(HttpResponse::MethodNotAllowed().body("WebDAV is disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(target) = resolve_path(&config.root, &path) else {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	};
+	let Ok(metadata) = tokio::fs::metadata(&target).await else {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	};
+	if !Visibility::of(&config, &req).allows(&path) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	if !locks.may_modify(&req, &target, false) {
+		return Ok(locked_response());
+	}
+
+	let Some(elements) = std::str::from_utf8(&body).ok().and_then(parse_xml) else {
+		return Ok(HttpResponse::BadRequest().body("Malformed PROPPATCH body"));
+	};
+	let props: Vec<PropName> = elements
+		.into_iter()
+		.filter(|element| element.depth == 4)
+		.map(|element| PropName {
+			ns: element.ns,
+			name: element.name,
+		})
+		.collect();
+
+	// Dead properties are not stored. Windows insists on setting its timestamp properties, so
+	// those are acknowledged; anything else is refused, which fails the whole (atomic) update.
+	let all_accepted = props.iter().all(|prop| prop.ns == MS_NS);
+	let mut accepted = String::new();
+	let mut refused = String::new();
+	for prop in &props {
+		if prop.ns == MS_NS {
+			accepted.push_str(&prop.element(None));
+		} else {
+			refused.push_str(&prop.element(None));
+		}
+	}
+
+	let accepted_status = if all_accepted {
+		"200 OK"
+	} else {
+		"424 Failed Dependency"
+	};
+	let mut out = format!(
+		"<D:response><D:href>{}</D:href>",
+		escape(href_for(&config, &target, metadata.is_dir()).as_str())
+	);
+	if !accepted.is_empty() {
+		let _ = write!(
+			out,
+			"<D:propstat><D:prop>{accepted}</D:prop><D:status>HTTP/1.1 {accepted_status}</D:status></D:propstat>"
+		);
+	}
+	if !refused.is_empty() {
+		let _ = write!(
+			out,
+			"<D:propstat><D:prop>{refused}</D:prop><D:status>HTTP/1.1 403 Forbidden</D:status></D:propstat>"
+		);
+	}
+	out.push_str("</D:response>");
+
+	Ok(multistatus(&out))
+}
+
+fn lock_timeout(req: &HttpRequest) -> u64 {
+	req.headers()
+		.get("timeout")
+		.and_then(|v| v.to_str().ok())
+		.and_then(|value| {
+			value.split(',').map(str::trim).find_map(|part| {
+				if part.eq_ignore_ascii_case("infinite") {
+					Some(MAX_LOCK_TIMEOUT_SECS)
+				} else {
+					part.strip_prefix("Second-")?.parse::<u64>().ok()
+				}
+			})
+		})
+		.unwrap_or(DEFAULT_LOCK_TIMEOUT_SECS)
+		.min(MAX_LOCK_TIMEOUT_SECS)
+}
+
+fn lock_response(status: StatusCode, discovery: &str, token: Option<&str>) -> HttpResponse {
+	let mut response = HttpResponse::build(status);
+	if let Some(token) = token {
+		response.insert_header(("Lock-Token", format!("<{token}>")));
+	}
+	response.content_type(XML_CONTENT_TYPE).body(format!(
+		"<?xml version=\"1.0\" encoding=\"utf-8\"?><D:prop xmlns:D=\"DAV:\">{discovery}</D:prop>"
+	))
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "LOCK")]
+async fn lock_resource(
+	req: HttpRequest,
+	body: Bytes,
+	config: web::Data<Config>,
+	locks: web::Data<LockManager>,
+) -> Result<HttpResponse> {
+	if !config.webdav {
+		return Ok(HttpResponse::MethodNotAllowed().body("WebDAV is disabled"));
+	}
+
+	let path = paths::request_path(&req, &config.root);
+	let Some(target) = resolve_path(&config.root, &path) else {
+		return Ok(HttpResponse::Forbidden().body("Access denied"));
+	};
+	if target != config.root && !parent_within_root(&config.root, &target) {
+		return Ok(HttpResponse::Conflict().body("Parent directory does not exist"));
+	}
+	if !Visibility::of(&config, &req).allows(&path) {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+	let timeout_secs = lock_timeout(&req);
+	let Ok(body) = std::str::from_utf8(&body) else {
+		return Ok(HttpResponse::BadRequest().body("Malformed LOCK body"));
+	};
+
+	// An empty body refreshes an existing lock identified through the If header.
+	if body.trim().is_empty() {
+		let tokens = submitted_tokens(&req);
+		let refreshed = {
+			let mut active = locks.active();
+			let lock = active
+				.iter_mut()
+				.find(|lock| lock.covers(&target) && tokens.contains(&lock.token));
+			lock.map(|lock| {
+				lock.timeout_secs = timeout_secs;
+				lock.expires = Instant::now() + Duration::from_secs(timeout_secs);
+				lock.to_xml()
+			})
+		};
+		return Ok(match refreshed {
+			Some(xml) => lock_response(
+				StatusCode::OK,
+				&format!("<D:lockdiscovery>{xml}</D:lockdiscovery>"),
+				None,
+			),
+			None => HttpResponse::PreconditionFailed().body("No matching lock to refresh"),
+		});
+	}
+
+	let Some(elements) = parse_xml(body) else {
+		return Ok(HttpResponse::BadRequest().body("Malformed LOCK body"));
+	};
+	let mut exclusive = true;
+	let mut owner = None;
+	let mut in_scope = false;
+	for element in &elements {
+		match element.depth {
+			2 => {
+				in_scope = element.name == "lockscope";
+				if element.name == "owner" {
+					owner = element.inner.clone().map(|range| body[range].trim().to_string());
+				}
+			},
+			3 if in_scope => exclusive = element.name != "shared",
+			_ => (),
+		}
+	}
+	let infinite = !matches!(req.headers().get("depth").and_then(|v| v.to_str().ok()), Some("0"));
+
+	let mut existed = tokio::fs::symlink_metadata(&target).await.is_ok();
+	let metadata_is_dir = target.is_dir();
+	let href = href_for(&config, &target, metadata_is_dir);
+	let token = generate_lock_token();
+	let conflicts = |active: &[ActiveLock]| {
+		active.iter().any(|lock| {
+			(lock.covers(&target) || (infinite && lock.root.starts_with(&target))) && (lock.exclusive || exclusive)
+		})
+	};
+
+	if conflicts(&locks.active()) {
+		return Ok(locked_response());
+	}
+	let mut created = false;
+	if !existed {
+		// Locking an unmapped URL reserves it by creating an empty file (RFC 4918 §7.3).
+		if !config.allow_upload {
+			return Ok(HttpResponse::NotFound().body("Not found"));
+		}
+		let file = tokio::fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&target)
+			.await;
+		match file {
+			Ok(_) => created = true,
+			Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => existed = true,
+			Err(e) => return Err(ErrorInternalServerError(e)),
+		}
+	}
+
+	// Another lock may have been taken while the file was being created.
+	let conflict = {
+		let mut active = locks.active();
+		let conflict = conflicts(&active);
+		if !conflict {
+			active.push(ActiveLock {
+				root: target.clone(),
+				href,
+				token: token.clone(),
+				owner,
+				exclusive,
+				infinite,
+				timeout_secs,
+				expires: Instant::now() + Duration::from_secs(timeout_secs),
+			});
+		}
+		conflict
+	};
+	if conflict {
+		if created {
+			let _ = tokio::fs::remove_file(&target).await;
+		}
+		return Ok(locked_response());
+	}
+
+	let status = if existed { StatusCode::OK } else { StatusCode::CREATED };
+	Ok(lock_response(status, &locks.lockdiscovery(&target), Some(&token)))
+}
+
+#[allow(clippy::future_not_send)]
+#[route("/{path:.*}", method = "UNLOCK")]
+async fn unlock_resource(req: HttpRequest, config: web::Data<Config>, locks: web::Data<LockManager>) -> HttpResponse {
+	if !config.webdav {
+		return HttpResponse::MethodNotAllowed().body("WebDAV is disabled");
+	}
+
+	let Some(target) = resolve_path(&config.root, &paths::request_path(&req, &config.root)) else {
+		return HttpResponse::Forbidden().body("Access denied");
+	};
+	let Some(token) = req
+		.headers()
+		.get("lock-token")
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+	else {
+		return HttpResponse::BadRequest().body("Missing Lock-Token header");
+	};
+
+	let mut active = locks.active();
+	let before = active.len();
+	active.retain(|lock| !(lock.token == token && lock.covers(&target)));
+	if active.len() == before {
+		HttpResponse::Conflict().body("Lock token does not match the request URI")
+	} else {
+		HttpResponse::NoContent().finish()
+	}
+}
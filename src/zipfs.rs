@@ -0,0 +1,228 @@
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{self, EntityTag, TryIntoHeaderValue};
+use actix_web::{HttpRequest, HttpResponse, Result};
+use async_zip::tokio::read::seek::ZipFileReader;
+use async_zip::{Compression, ZipDateTime};
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::config::Config;
+use crate::helpers::{
+	attachment, file_etag, insert_validators, is_download, listing_sort, mime_type, not_modified, paginate, sort_order,
+	wants_json, Visibility, TOTAL_COUNT,
+};
+use crate::hooks::Hooks;
+use crate::ranges;
+use crate::structs::{DirEntry, DirectoryTemplate};
+use crate::templates;
+
+/// Size of the fixed part of a local file header, before the name and extra field.
+const LOCAL_HEADER_LEN: u64 = 30;
+
+fn is_zip(path: &Path) -> bool {
+	path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) && path.is_file()
+}
+
+/// Splits a path that reaches into a `.zip` file into the archive and the slash-separated path of
+/// the member inside it. A path naming the archive itself only counts when `trailing_slash` is set,
+/// so that `/photos.zip` still downloads the file while `/photos.zip/` browses it.
+pub fn locate(root: &Path, path: &Path, trailing_slash: bool) -> Option<(PathBuf, String)> {
+	if path.exists() {
+		return (trailing_slash && is_zip(path)).then(|| (path.to_path_buf(), String::new()));
+	}
+
+	let archive = path
+		.ancestors()
+		.skip(1)
+		.take_while(|ancestor| ancestor.starts_with(root) && *ancestor != root)
+		.find(|ancestor| is_zip(ancestor))?;
+	let inner = path.strip_prefix(archive).ok()?;
+	let inner = inner
+		.components()
+		.map(|c| c.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/");
+	Some((archive.to_path_buf(), inner))
+}
+
+/// Zip timestamps carry no time zone, so they are taken to be in the server's local time.
+fn local_date(date: &ZipDateTime) -> chrono::DateTime<chrono::Local> {
+	chrono::NaiveDate::from_ymd_opt(date.year(), date.month(), date.day())
+		.and_then(|day| day.and_hms_opt(date.hour(), date.minute(), date.second()))
+		.and_then(|time| time.and_local_timezone(chrono::Local).earliest())
+		.unwrap_or_else(chrono::Local::now)
+}
+
+/// Offset of a stored member's data, found by reading the name and extra field lengths from its
+/// local header since they may differ from the copies in the central directory.
+async fn data_offset(file: &mut tokio::fs::File, header_offset: u64) -> std::io::Result<u64> {
+	let mut header = [0; LOCAL_HEADER_LEN as usize];
+	file.seek(SeekFrom::Start(header_offset)).await?;
+	file.read_exact(&mut header).await?;
+	let name_len = u16::from_le_bytes([header[26], header[27]]);
+	let extra_len = u16::from_le_bytes([header[28], header[29]]);
+	Ok(header_offset + LOCAL_HEADER_LEN + u64::from(name_len) + u64::from(extra_len))
+}
+
+/// Lists or streams `inner` from the zip file at `archive`. `url_path` is the request path relative
+/// to the root, used to build links in the listing, which is split into pages like a directory's.
+/// Members are shown and served as `visibility` would files at the same paths.
+#[allow(clippy::future_not_send)]
+pub async fn serve(
+	req: &HttpRequest,
+	hooks: &Hooks,
+	visibility: &Visibility,
+	url_path: &Path,
+	archive: &Path,
+	inner: &str,
+	config: &Config,
+) -> Result<HttpResponse> {
+	let file = tokio::fs::File::open(archive).await?;
+	let archive_etag = file_etag(&file.metadata().await?);
+	let Ok(zip) = ZipFileReader::with_tokio(tokio::io::BufReader::new(file)).await else {
+		return Ok(HttpResponse::UnprocessableEntity().body("Not a readable zip archive"));
+	};
+
+	let names: Vec<Option<&str>> = zip
+		.file()
+		.entries()
+		.iter()
+		.map(|entry| entry.filename().as_str().ok().map(|name| name.trim_start_matches('/')))
+		.collect();
+
+	if !inner.is_empty() {
+		let found = names.iter().position(|name| *name == Some(inner));
+		if let Some(index) = found.filter(|_| visibility.allows(url_path)) {
+			// Hooks see members as if the archive were a directory.
+			if let Some(response) = hooks.before_send_file(req, &archive.join(inner)) {
+				return Ok(response);
+			}
+			return serve_member(req, config, zip, index, inner, &archive_etag).await;
+		}
+	}
+
+	if !config.dirlist {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+
+	let current_path = url_path.to_string_lossy().trim_end_matches('/').to_string();
+	let prefix = if inner.is_empty() {
+		String::new()
+	} else {
+		format!("{inner}/")
+	};
+	let mut entries = BTreeMap::new();
+	let mut found = false;
+	for (entry, name) in zip.file().entries().iter().zip(&names) {
+		let Some(rest) = name.and_then(|name| name.strip_prefix(&prefix)) else {
+			continue;
+		};
+		let (child, is_dir) = match rest.split_once('/') {
+			Some((child, _)) => (child, true),
+			None => (rest, false),
+		};
+		if child.is_empty() {
+			continue;
+		}
+		found = true;
+		// Members are not on disk, so only the archive knows whether one is a directory.
+		if !visibility.allows_new(&url_path.join(child), is_dir) {
+			continue;
+		}
+
+		entries.entry(child.to_string()).or_insert_with(|| DirEntry {
+			name: child.to_string(),
+			path: format!("{current_path}/{child}"),
+			is_dir,
+			size: (!is_dir).then_some(entry.uncompressed_size()),
+			modified: local_date(entry.last_modification_date()),
+		});
+	}
+
+	if !found && !inner.is_empty() {
+		return Ok(HttpResponse::NotFound().body("Not found"));
+	}
+
+	let mut entries: Vec<DirEntry> = entries.into_values().collect();
+	let sort = listing_sort(req, config.sort);
+	entries.sort_by(|a, b| sort_order(&sort, a, b));
+	let mut response = HttpResponse::Ok();
+	response.insert_header((TOTAL_COUNT, entries.len()));
+	let pagination = paginate(req, &mut entries, config.page_size);
+
+	if wants_json(req) {
+		return Ok(response.json(entries));
+	}
+
+	let template = DirectoryTemplate {
+		prefix: config.route_prefix.clone(),
+		current_path,
+		allow_upload: false,
+		allow_delete: false,
+		allow_rename: false,
+		in_archive: true,
+		content_search: false,
+		quota_remaining: None,
+		entries,
+		pagination,
+		sort,
+		theme: config.theme,
+		custom_css: config.custom_css.is_some(),
+		custom_js: config.custom_js.is_some(),
+	};
+	let html = templates::render(Some(config), &template)
+		.await
+		.map_err(ErrorInternalServerError)?;
+	Ok(response.content_type("text/html").body(html))
+}
+
+/// Streams one member. Stored members are read straight out of the archive, which also makes
+/// byte ranges possible; compressed ones have to be inflated from the start. Members share the
+/// archive's validator, narrowed down by their checksum.
+#[allow(clippy::future_not_send)]
+async fn serve_member(
+	req: &HttpRequest,
+	config: &Config,
+	zip: ZipFileReader<tokio::io::BufReader<tokio::fs::File>>,
+	index: usize,
+	inner: &str,
+	archive_etag: &EntityTag,
+) -> Result<HttpResponse> {
+	let stored = &zip.file().entries()[index];
+	let mime_type = mime_type(config, Path::new(inner)).to_string();
+	let size = stored.uncompressed_size();
+	let etag = EntityTag::new_strong(format!("{}:{:x}", archive_etag.tag(), stored.crc32()));
+	let modified = Some(local_date(stored.last_modification_date()).into());
+
+	if not_modified(req, &etag, modified) {
+		let mut response = HttpResponse::NotModified();
+		insert_validators(&mut response, etag, modified);
+		return Ok(response.finish());
+	}
+
+	let mut response = if stored.compression() == Compression::Stored {
+		let header_offset = stored.header_offset();
+		let mut file = zip.into_inner().into_inner().into_inner();
+		let offset = data_offset(&mut file, header_offset).await?;
+		let file = file.into_std().await;
+		ranges::respond(req, file, offset, size, mime_type, etag, modified)?
+	} else {
+		let reader = zip.into_entry(index).await.map_err(ErrorInternalServerError)?;
+		let mut response = HttpResponse::Ok();
+		insert_validators(&mut response, etag, modified);
+		response
+			.content_type(mime_type)
+			.body(ranges::body(req, size, ReaderStream::new(reader.compat())))
+	};
+	if is_download(config, req, Path::new(inner)) {
+		let name = inner.rsplit('/').next().unwrap_or(inner);
+		response
+			.headers_mut()
+			.insert(header::CONTENT_DISPOSITION, attachment(name).try_into_value()?);
+	}
+	Ok(response)
+}
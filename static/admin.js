@@ -0,0 +1,88 @@
+function formatSize(bytes) {
+    const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let size = bytes;
+    let unit = 0;
+    while (size >= 1024 && unit < units.length - 1) {
+        size /= 1024;
+        unit++;
+    }
+    return unit === 0 ? `${size} B` : `${size.toFixed(2)} ${units[unit]}`;
+}
+
+function formatDuration(seconds) {
+    const s = Math.floor(seconds);
+    const days = Math.floor(s / 86400);
+    const hours = Math.floor((s % 86400) / 3600);
+    const minutes = Math.floor((s % 3600) / 60);
+    const parts = [];
+    if (days) parts.push(`${days}d`);
+    if (days || hours) parts.push(`${hours}h`);
+    if (days || hours || minutes) parts.push(`${minutes}m`);
+    parts.push(`${s % 60}s`);
+    return parts.join(" ");
+}
+
+// Builds a table row from plain text cells, so paths are never interpreted as HTML.
+function row(cells) {
+    const tr = document.createElement("tr");
+    for (const cell of cells) {
+        const td = document.createElement("td");
+        td.textContent = cell;
+        tr.appendChild(td);
+    }
+    return tr;
+}
+
+function fill(id, rows) {
+    document.getElementById(id).replaceChildren(...rows);
+}
+
+function render(stats) {
+    document.getElementById("uptime").textContent = formatDuration(stats.uptime_secs);
+    document.getElementById("total-bytes").textContent = formatSize(stats.total_bytes);
+    document.getElementById("active-count").textContent = stats.active.length;
+
+    fill(
+        "active",
+        stats.active.map((t) =>
+            row([
+                `${t.method} ${t.path}`,
+                t.peer || "-",
+                formatSize(t.sent),
+                formatDuration(t.elapsed_secs),
+            ]),
+        ),
+    );
+    fill(
+        "downloads",
+        stats.top_downloads.map((d) => row([d.path, d.count])),
+    );
+    fill(
+        "recent",
+        stats.recent.map((r) =>
+            row([
+                new Date(r.time).toLocaleTimeString(),
+                `${r.method} ${r.path}`,
+                r.status,
+                formatSize(r.bytes),
+                `${r.duration_ms.toFixed(1)} ms`,
+                r.peer || "-",
+            ]),
+        ),
+    );
+}
+
+const status = document.getElementById("status");
+const events = new EventSource("_admin/events");
+events.onopen = () => (status.textContent = "Live");
+events.onerror = () => (status.textContent = "Reconnecting…");
+events.onmessage = (event) => render(JSON.parse(event.data));
+
+const theme =
+    localStorage.getItem("theme") ??
+    (window.matchMedia("(prefers-color-scheme: dark)").matches
+        ? "dark"
+        : "light");
+if (theme !== "dark") {
+    document.documentElement.removeAttribute("data-theme");
+}
@@ -0,0 +1,36 @@
+// Narrows the listing down to the entries whose names contain what is typed into the filter
+// box, without asking the server.
+document.addEventListener("DOMContentLoaded", () => {
+    const filter = document.querySelector(".filter-input");
+    if (!filter) return;
+    const count = document.querySelector(".filter-count");
+    const rows = Array.from(document.querySelectorAll(".listing tr"));
+    const names = rows.map((row) => (row.dataset.name ?? "").toLowerCase());
+
+    function apply() {
+        const needle = filter.value.trim().toLowerCase();
+        let shown = 0;
+        rows.forEach((row, index) => {
+            row.hidden = needle !== "" && !names[index].includes(needle);
+            if (!row.hidden) shown++;
+        });
+        count.textContent = needle ? `${shown} of ${rows.length} shown` : "";
+    }
+
+    filter.addEventListener("input", apply);
+    filter.addEventListener("keydown", (event) => {
+        if (event.key === "Escape") {
+            filter.value = "";
+            apply();
+            filter.blur();
+        }
+    });
+    document.addEventListener("keydown", (event) => {
+        if (event.key === "/" && event.target.tagName !== "INPUT") {
+            event.preventDefault();
+            filter.focus();
+        }
+    });
+    // Browsers fill the box in again when coming back to the page.
+    apply();
+});
@@ -0,0 +1,44 @@
+// Switches the listing between rows and a grid of cards, as last chosen, before the page is
+// drawn. Cards show pictures and the first frames of videos, loaded once they come into view.
+(() => {
+    const html = document.documentElement;
+    html.dataset.view = localStorage.getItem("view") ?? "list";
+
+    function apply(view) {
+        html.dataset.view = view;
+        const toggle = document.querySelector(".view-toggle");
+        if (toggle) {
+            toggle.setAttribute("aria-pressed", String(view === "grid"));
+            toggle.querySelector(".view-icon").textContent =
+                view === "grid" ? "☰" : "▦";
+            toggle.querySelector(".view-text").textContent =
+                view === "grid" ? "List view" : "Grid view";
+        }
+    }
+
+    document.addEventListener("DOMContentLoaded", () => {
+        apply(html.dataset.view);
+        document
+            .querySelector(".view-toggle")
+            ?.addEventListener("click", () => {
+                const view = html.dataset.view === "grid" ? "list" : "grid";
+                localStorage.setItem("view", view);
+                apply(view);
+            });
+
+        // Thumbnails are only drawn as cards, so hidden ones in the list never come into view.
+        const observer = new IntersectionObserver(
+            (entries) => {
+                entries.forEach(({ isIntersecting, target }) => {
+                    if (!isIntersecting) return;
+                    target.src = target.dataset.src;
+                    observer.unobserve(target);
+                });
+            },
+            { rootMargin: "200px" },
+        );
+        document
+            .querySelectorAll(".thumbnail[data-src]")
+            .forEach((thumbnail) => observer.observe(thumbnail));
+    });
+})();
@@ -0,0 +1,516 @@
+// Where the server is mounted, when it sits behind a proxy under a path of its own.
+const prefix = document.documentElement.dataset.prefix ?? "";
+
+// A path below the root as it goes in a URL, each of its names escaped.
+const encodePath = (path) => path.split("/").map(encodeURIComponent).join("/");
+
+document.addEventListener("DOMContentLoaded", () => {
+    const form = document.querySelector(".mkdir-form");
+    if (!form) return;
+
+    form.addEventListener("submit", async (event) => {
+        event.preventDefault();
+        const name = form.elements.name.value.trim();
+        if (!name || /[\\/]/.test(name)) {
+            alert("Folder names cannot contain slashes");
+            return;
+        }
+
+        const base = window.location.pathname.replace(/\/$/, "");
+        const response = await fetch(
+            `${base}/${encodeURIComponent(name)}`,
+            { method: "MKCOL" },
+        );
+        if (response.ok) {
+            window.location.reload();
+        } else {
+            alert(`Could not create folder: ${await response.text()}`);
+        }
+    });
+});
+
+document.addEventListener("DOMContentLoaded", () => {
+    const zone = document.querySelector(".upload-zone");
+    if (!zone) return;
+
+    const status = zone.querySelector(".upload-status");
+
+    async function uploadFiles(files) {
+        const data = new FormData();
+        for (const file of files) {
+            data.append("file", file, file.name);
+        }
+        status.textContent = `Uploading ${files.length} file(s)...`;
+
+        const response = await fetch(window.location.pathname, {
+            method: "POST",
+            body: data,
+        });
+        if (response.ok) {
+            window.location.reload();
+        } else {
+            status.textContent = `Upload failed: ${await response.text()}`;
+        }
+    }
+
+    ["dragenter", "dragover"].forEach((type) =>
+        document.addEventListener(type, (event) => {
+            event.preventDefault();
+            zone.classList.add("dragover");
+        }),
+    );
+    document.addEventListener("dragleave", (event) => {
+        if (event.relatedTarget === null) {
+            zone.classList.remove("dragover");
+        }
+    });
+    document.addEventListener("drop", (event) => {
+        event.preventDefault();
+        zone.classList.remove("dragover");
+        if (event.dataTransfer.files.length > 0) {
+            uploadFiles(event.dataTransfer.files);
+        }
+    });
+});
+
+document.addEventListener("DOMContentLoaded", () => {
+    const modal = document.querySelector(".modal");
+    const modalContent = document.querySelector(".modal-content");
+    const closeButton = document.querySelector(".modal-close");
+    const videoPlayer = videojs("my-video");
+
+    async function findSubtitles(videoPath) {
+        const basePath = videoPath.substring(
+            0,
+            videoPath.lastIndexOf("."),
+        );
+        const subtitles = [];
+
+        for (const ext of [".srt", ".vtt"]) {
+            try {
+                const response = await fetch(`${basePath}${ext}`);
+                if (response.ok) {
+                    subtitles.push({
+                        kind: "captions",
+                        label: `Subtitles (${ext.substring(1)})`,
+                        src: `${basePath}${ext}`,
+                        srclang: "en",
+                    });
+                }
+            } catch (e) {
+                console.log(`No ${ext} subtitles found`);
+            }
+        }
+
+        return subtitles;
+    }
+
+    async function openVideoModal(videoSrc) {
+        try {
+            const subtitles = await findSubtitles(videoSrc);
+
+            videoPlayer.src({
+                type: "video/mp4",
+                src: videoSrc,
+                withCredentials: false,
+                useBrowserNativeControls: false,
+            });
+
+            videoPlayer.options({
+                html5: {
+                    nativeTextTracks: false,
+                    useNativeSeek: true,
+                    preload: "metadata",
+                },
+            });
+
+            while (videoPlayer.textTracks().length > 0) {
+                videoPlayer.removeRemoteTextTrack(
+                    videoPlayer.textTracks()[0],
+                );
+            }
+
+            subtitles.forEach((subtitle) => {
+                videoPlayer.addRemoteTextTrack(
+                    {
+                        kind: subtitle.kind,
+                        src: subtitle.src,
+                        srclang: subtitle.srclang,
+                        label: subtitle.label,
+                        default: false,
+                    },
+                    false,
+                );
+            });
+
+            modal.style.display = "flex";
+            videoPlayer.play();
+        } catch (error) {
+            console.error("Error loading video:", error);
+        }
+    }
+
+    function closeVideoModal() {
+        modal.style.display = "none";
+        videoPlayer.pause();
+        videoPlayer.currentTime(0);
+    }
+
+    const links = document.querySelectorAll("a");
+    links.forEach((link) => {
+        const fileName = link.textContent.trim();
+        if (fileName.match(/\.(mp4|webm|mkv)$/i)) {
+            link.addEventListener("click", function (event) {
+                event.preventDefault();
+                openVideoModal(link.getAttribute("href"));
+            });
+        }
+    });
+
+    closeButton.onclick = closeVideoModal;
+
+    async function copyShareLink(path) {
+        const response = await fetch(
+            `${prefix}/_api/share?path=${encodeURIComponent(path)}`,
+        );
+        if (!response.ok) {
+            alert(`Could not create share link: ${response.status}`);
+            return;
+        }
+        const { url } = await response.json();
+        const link = `${window.location.origin}${url}`;
+        try {
+            await navigator.clipboard.writeText(link);
+            alert("Share link copied to clipboard");
+        } catch (e) {
+            prompt("Share link", link);
+        }
+    }
+
+    async function deleteEntry(path, name) {
+        if (!confirm(`Delete "${name}"?`)) return;
+
+        const response = await fetch(`${prefix}/${encodePath(path)}`, {
+            method: "DELETE",
+        });
+        if (response.ok) {
+            window.location.reload();
+        } else {
+            alert(`Could not delete "${name}": ${await response.text()}`);
+        }
+    }
+
+    async function renameEntry(path, name) {
+        const parent = path.includes("/")
+            ? path.substring(0, path.lastIndexOf("/") + 1)
+            : "";
+        const target = prompt(
+            `Rename or move "${name}" to:`,
+            `/${parent}${name}`,
+        );
+        if (!target || target === `/${path}`) return;
+
+        const destination = prefix + encodePath(target);
+        let response = await fetch(`${prefix}/${encodePath(path)}`, {
+            method: "MOVE",
+            headers: { Destination: destination, Overwrite: "F" },
+        });
+        if (
+            response.status === 412 &&
+            confirm(`${await response.text()}. Overwrite?`)
+        ) {
+            response = await fetch(`${prefix}/${encodePath(path)}`, {
+                method: "MOVE",
+                headers: { Destination: destination, Overwrite: "T" },
+            });
+        }
+        if (response.ok) {
+            window.location.reload();
+        } else if (response.status !== 412) {
+            alert(`Could not move "${name}": ${await response.text()}`);
+        }
+    }
+
+    document.querySelectorAll(".rename-button").forEach((button) => {
+        button.addEventListener("click", (event) => {
+            event.stopPropagation();
+            renameEntry(button.dataset.path, button.dataset.name);
+        });
+    });
+
+    document.querySelectorAll(".delete-button").forEach((button) => {
+        button.addEventListener("click", (event) => {
+            event.stopPropagation();
+            deleteEntry(button.dataset.path, button.dataset.name);
+        });
+    });
+
+    function formatSize(bytes) {
+        const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let size = bytes;
+        let unit = 0;
+        while (size >= 1024 && unit < units.length - 1) {
+            size /= 1024;
+            unit++;
+        }
+        return unit === 0
+            ? `${size} ${units[0]}`
+            : `${size.toFixed(2)} ${units[unit]}`;
+    }
+
+    function typeIcon(name) {
+        const ns = "http://www.w3.org/2000/svg";
+        const svg = document.createElementNS(ns, "svg");
+        svg.setAttribute("class", `icon icon-${name}`);
+        svg.setAttribute("aria-hidden", "true");
+        const use = document.createElementNS(ns, "use");
+        use.setAttribute("href", `${prefix}/_static/icons.svg#${name}`);
+        svg.append(use);
+        return svg;
+    }
+
+    function renderSearchResults({ results, truncated }) {
+        const body = document.querySelector(".search-results");
+        body.replaceChildren();
+        for (const entry of results) {
+            const row = body.insertRow();
+            const link = document.createElement("a");
+            link.href = `${prefix}/${encodePath(entry.path)}`;
+            link.append(typeIcon(entry.is_dir ? "folder" : "file"), entry.path);
+            row.insertCell().append(link);
+            row.insertCell().textContent =
+                entry.size === null ? "-" : formatSize(entry.size);
+            const modified = row.insertCell();
+            modified.className = "modified-column";
+            modified.textContent = new Date(
+                entry.modified,
+            ).toLocaleString();
+        }
+        if (results.length === 0 || truncated) {
+            const cell = body.insertRow().insertCell();
+            cell.colSpan = 3;
+            cell.textContent =
+                results.length === 0
+                    ? "No matches"
+                    : `Showing the first ${results.length} matches`;
+        }
+    }
+
+    const searchForm = document.querySelector(".search-form");
+    if (searchForm) {
+        const listing = document.querySelector(".listing");
+        const searchResults =
+            document.querySelector(".search-results");
+        searchForm.addEventListener("submit", async (event) => {
+            event.preventDefault();
+            const q = searchForm.q.value.trim();
+            if (!q) {
+                listing.hidden = false;
+                searchResults.hidden = true;
+                return;
+            }
+            const params = new URLSearchParams({
+                q,
+                path: decodeURIComponent(window.location.pathname).slice(
+                    prefix.length,
+                ),
+            });
+            if (searchForm.content?.checked) {
+                params.set("content", "true");
+            }
+            const response = await fetch(`${prefix}/_api/search?${params}`);
+            if (!response.ok) {
+                alert(`Search failed: ${await response.text()}`);
+                return;
+            }
+            renderSearchResults(await response.json());
+            listing.hidden = true;
+            searchResults.hidden = false;
+        });
+        searchForm.q.addEventListener("input", () => {
+            if (!searchForm.q.value) {
+                listing.hidden = false;
+                searchResults.hidden = true;
+            }
+        });
+    }
+
+    const downloadSelected = document.querySelector(
+        "#selection-form button",
+    );
+    document.querySelectorAll(".select-entry").forEach((box) => {
+        box.addEventListener("change", () => {
+            downloadSelected.disabled = !document.querySelector(
+                ".select-entry:checked",
+            );
+        });
+    });
+
+    document.querySelectorAll(".share-button").forEach((button) => {
+        button.addEventListener("click", (event) => {
+            event.stopPropagation();
+            copyShareLink(button.dataset.path);
+        });
+    });
+
+    modal.addEventListener("click", (event) => {
+        if (event.target === modal) {
+            closeVideoModal();
+        }
+    });
+
+    document.addEventListener("keydown", (event) => {
+        if (
+            event.key === "Escape" &&
+            modal.style.display === "flex"
+        ) {
+            closeVideoModal();
+        }
+    });
+    document.addEventListener("keydown", (event) => {
+        if (modal.style.display === "flex") {
+            switch (event.key.toLowerCase()) {
+                case " ":
+                case "p":
+                    event.preventDefault();
+                    videoPlayer.paused()
+                        ? videoPlayer.play()
+                        : videoPlayer.pause();
+                    break;
+                case "f":
+                    event.preventDefault();
+                    videoPlayer.isFullscreen()
+                        ? videoPlayer.exitFullscreen()
+                        : videoPlayer.requestFullscreen();
+                    break;
+                case "m":
+                    event.preventDefault();
+                    videoPlayer.muted(!videoPlayer.muted());
+                    break;
+            }
+        }
+    });
+});
+
+document.addEventListener("DOMContentLoaded", () => {
+    let currentFocusIndex = -1;
+    const rows = Array.from(
+        document.querySelectorAll("table tr"),
+    ).slice(1);
+
+    function focusRow(index) {
+        rows.forEach((row) => (row.style.backgroundColor = ""));
+
+        if (index >= 0 && index < rows.length) {
+            currentFocusIndex = index;
+            const row = rows[currentFocusIndex];
+            row.style.backgroundColor = "var(--hover-color)";
+            row.scrollIntoView({
+                block: "nearest",
+                behavior: "smooth",
+            });
+        }
+    }
+
+    // The next row from `index` in the direction of `step` that the filter has not hidden.
+    function nextVisible(index, step) {
+        for (let i = index + step; i >= 0 && i < rows.length; i += step) {
+            if (!rows[i].hidden) return i;
+        }
+        return index;
+    }
+
+    function navigateToParent() {
+        const currentPath = window.location.pathname;
+        const normalizedPath = currentPath.endsWith("/")
+            ? currentPath.slice(0, -1)
+            : currentPath;
+
+        const pathSegments = normalizedPath.split("/");
+
+        if (pathSegments.length <= 1 || normalizedPath === prefix) return;
+
+        pathSegments.pop();
+        const parentPath = pathSegments.join("/") || "/";
+
+        window.location.href = parentPath;
+    }
+
+    function handleRowSelection() {
+        if (
+            currentFocusIndex >= 0 &&
+            currentFocusIndex < rows.length
+        ) {
+            const link = rows[currentFocusIndex].querySelector("a");
+            if (link) {
+                const fileName = link.textContent.trim();
+                if (fileName.match(/\.(mp4|webm|ogg)$/i)) {
+                    const event = new MouseEvent("click", {
+                        bubbles: true,
+                        cancelable: true,
+                        view: window,
+                    });
+                    link.dispatchEvent(event);
+                } else {
+                    window.location.href = link.href;
+                }
+            }
+        }
+    }
+
+    document.addEventListener("keydown", (event) => {
+        if (event.target.tagName === "INPUT") return;
+
+        switch (event.key) {
+            case "ArrowDown":
+            case "j":
+                event.preventDefault();
+                focusRow(nextVisible(currentFocusIndex, 1));
+                break;
+
+            case "ArrowUp":
+            case "k":
+                event.preventDefault();
+                focusRow(
+                    nextVisible(
+                        currentFocusIndex === -1
+                            ? rows.length
+                            : currentFocusIndex,
+                        -1,
+                    ),
+                );
+                break;
+
+            case "Enter":
+            case " ":
+                event.preventDefault();
+                handleRowSelection();
+                break;
+
+            case "Backspace":
+                event.preventDefault();
+                navigateToParent();
+                break;
+
+            case "Home":
+                event.preventDefault();
+                focusRow(nextVisible(-1, 1));
+                break;
+
+            case "End":
+                event.preventDefault();
+                focusRow(nextVisible(rows.length, -1));
+                break;
+        }
+    });
+
+    rows.forEach((row, index) => {
+        row.addEventListener("mouseenter", () => {
+            focusRow(index);
+        });
+    });
+
+    if (rows.length > 0) {
+        focusRow(0);
+    }
+});
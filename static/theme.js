@@ -0,0 +1,33 @@
+// Picks the light or dark theme before the page is drawn: the one last chosen with the toggle,
+// or else the one the system prefers, followed as it changes.
+(() => {
+    const html = document.documentElement;
+    const system = window.matchMedia("(prefers-color-scheme: dark)");
+    const chosen = () =>
+        localStorage.getItem("theme") ?? (system.matches ? "dark" : "light");
+
+    function apply(theme) {
+        html.dataset.theme = theme;
+        const icon = document.querySelector(".theme-icon");
+        const text = document.querySelector(".theme-text");
+        if (icon && text) {
+            icon.textContent = theme === "dark" ? "☀️" : "🌙";
+            text.textContent =
+                theme === "dark" ? "Light Mode" : "Dark Mode";
+        }
+    }
+
+    apply(chosen());
+    system.addEventListener("change", () => apply(chosen()));
+    document.addEventListener("DOMContentLoaded", () => {
+        apply(chosen());
+        document
+            .querySelector(".theme-toggle")
+            ?.addEventListener("click", () => {
+                const theme =
+                    html.dataset.theme === "dark" ? "light" : "dark";
+                localStorage.setItem("theme", theme);
+                apply(theme);
+            });
+    });
+})();